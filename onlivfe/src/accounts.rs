@@ -9,6 +9,7 @@ crate::platform_id!(
 
 crate::platform_enum!(
 	/// Details of a platform account
+	#[derive(Debug)]
 	PlatformAccount {
 		Box<vrc::model::AnyUser>,
 		Box<chilloutvr::model::UserDetails>,
@@ -23,7 +24,7 @@ crate::platform_enum_id!(PlatformAccountId, PlatformAccount {
 
 crate::platform_enum!(
 	/// Details of a platform account friend
-	#[derive(Eq)]
+	#[derive(Debug, Eq)]
 	PlatformFriend {
 		Box<vrc::model::Friend>,
 		Box<chilloutvr::model::Friend>,
@@ -35,3 +36,97 @@ crate::platform_enum_id!(PlatformAccountId, PlatformFriend {
 	v.data.base.id.clone(),
 	v.data.id.clone()
 } v);
+
+impl PlatformAccount {
+	/// Gets the name that the account is shown with on the platform
+	#[must_use]
+	pub fn display_name(&self) -> &str {
+		match self {
+			Self::VRChat(v) => &v.data.as_user().base.display_name,
+			Self::ChilloutVR(v) => &v.data.base.name,
+			Self::Resonite(v) => &v.data.username,
+		}
+	}
+
+	/// Gets the links that the account has in its bio.
+	///
+	/// Empty on CVR & Resonite, as they don't have bio links.
+	#[must_use]
+	pub fn bio_links(&self) -> &[String] {
+		match self {
+			Self::VRChat(v) => &v.data.as_user().base.bio_links,
+			Self::ChilloutVR(_) | Self::Resonite(_) => &[],
+		}
+	}
+
+	/// Gets the link to the thumbnail of the account's current avatar, if the
+	/// platform has one.
+	///
+	/// Always `None` on Resonite, as it doesn't share avatars' thumbnails.
+	#[must_use]
+	pub fn avatar_thumbnail_url(&self) -> Option<&str> {
+		let url = match self {
+			Self::VRChat(v) => {
+				v.data.as_user().base.current_avatar_thumbnail_image_url.as_str()
+			}
+			Self::ChilloutVR(v) => v.data.base.image_url.as_str(),
+			Self::Resonite(_) => return None,
+		};
+		Some(url).filter(|url| !url.is_empty())
+	}
+}
+
+impl PlatformFriend {
+	/// Gets the name that the friend is shown with on the platform
+	#[must_use]
+	pub fn display_name(&self) -> &str {
+		match self {
+			Self::VRChat(v) => &v.data.base.display_name,
+			Self::ChilloutVR(v) => &v.data.base.name,
+			Self::Resonite(v) => &v.data.username,
+		}
+	}
+
+	/// Gets the links that the friend has in their bio.
+	///
+	/// Empty on CVR & Resonite, as they don't have bio links.
+	#[must_use]
+	pub fn bio_links(&self) -> &[String] {
+		match self {
+			Self::VRChat(v) => &v.data.base.bio_links,
+			Self::ChilloutVR(_) | Self::Resonite(_) => &[],
+		}
+	}
+
+	/// Gets the link to the thumbnail of the friend's current avatar, if the
+	/// platform has one.
+	///
+	/// Always `None` on Resonite, as it doesn't share avatars' thumbnails.
+	#[must_use]
+	pub fn avatar_thumbnail_url(&self) -> Option<&str> {
+		let url = match self {
+			Self::VRChat(v) => {
+				v.data.base.current_avatar_thumbnail_image_url.as_str()
+			}
+			Self::ChilloutVR(v) => v.data.base.image_url.as_str(),
+			Self::Resonite(_) => return None,
+		};
+		Some(url).filter(|url| !url.is_empty())
+	}
+
+	/// If the friend is online, as of when the data was fetched.
+	///
+	/// `None` if the platform doesn't tell it along with the friend, which is
+	/// the case with CVR & Resonite, as their online statuses are only
+	/// sent over their real-time connections.
+	#[must_use]
+	pub fn is_online(&self) -> Option<bool> {
+		match self {
+			Self::VRChat(v) => Some(!matches!(
+				v.data.user_or_friend.location,
+				vrc::id::OfflineOrPrivateOr::Offline
+			)),
+			Self::ChilloutVR(_) | Self::Resonite(_) => None,
+		}
+	}
+}
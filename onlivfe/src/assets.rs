@@ -9,6 +9,7 @@ crate::platform_id!(
 
 crate::platform_enum!(
 	/// The platform specific instance/session.
+	#[derive(Debug)]
 	World {
 		Box<vrc::model::World>,
 		Box<chilloutvr::model::WorldDetails>,
@@ -32,6 +33,7 @@ crate::platform_id!(
 
 crate::platform_enum!(
 	/// The platform specific avatar.
+	#[derive(Debug)]
 	Avatar {
 		Box<vrc::model::Avatar>,
 		Box<chilloutvr::model::AvatarDetails>,
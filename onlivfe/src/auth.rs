@@ -16,16 +16,21 @@ pub enum LoginError {
 }
 
 impl Display for LoginError {
-		fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-				match self {
-					Self::Error(v) => write!(f, "{v}"),
-					Self::RequiresAdditionalFactor(id) => write!(f, "2FA is required for account with ID '{}'", id.id_as_string())
-				}
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Error(v) => write!(f, "{v}"),
+			Self::RequiresAdditionalFactor(id) => {
+				write!(f, "2FA is required for account with ID '{}'", id.id_as_string())
+			}
 		}
+	}
 }
 
 crate::platform_enum!(
 	/// Credentials for a platform
+	///
+	/// The `Debug` output leaves out the tokens, but serializing keeps them, as
+	/// that's what storage backends use.
 	#[derive(Eq)]
 	Authentication {
 		Box<vrc::query::Authentication>,
@@ -39,10 +44,18 @@ crate::platform_enum_id!(PlatformAccountId, Authentication {
 	v.data.user_id.clone()
 } v);
 
+// Manually implemented to keep the tokens out of logs and traces
+impl std::fmt::Debug for Authentication {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Authentication")
+			.field("id", &self.id())
+			.field("updated_at", &self.metadata().updated_at)
+			.finish_non_exhaustive()
+	}
+}
+
 // Can't use platform enum due to not knowing user IDs before auth has completed
-#[derive(
-	Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
-)]
+#[derive(Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "platform", content = "data")]
 /// Required for trying to create a platform authentication
 pub enum LoginCredentials {
@@ -55,6 +68,16 @@ pub enum LoginCredentials {
 }
 crate::platform_specific!(LoginCredentials);
 
+// Manually implemented to keep the passwords and such out of logs and traces
+impl std::fmt::Debug for LoginCredentials {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("LoginCredentials")
+			.field("platform", &self.platform())
+			.field("identifier", &self.identifier())
+			.finish_non_exhaustive()
+	}
+}
+
 impl LoginCredentials {
 	/// Sets the inner username/email/userid as a string
 	///
@@ -78,9 +101,7 @@ impl LoginCredentials {
 
 							Ok(())
 						}
-						Err(e) => {
-							Err(std::io::Error::new(std::io::ErrorKind::Other, e.to_owned()))
-						}
+						Err(e) => Err(std::io::Error::other(e.to_owned())),
 					}
 				}
 			},
@@ -141,8 +162,7 @@ impl LoginCredentials {
 					Ok(())
 				}
 				crate::vrchat::LoginRequestPart::SecondFactor((_user_id, _)) => {
-					Err(std::io::Error::new(
-						std::io::ErrorKind::Other,
+					Err(std::io::Error::other(
 						"VRChat auth second factor stage doesn't have a primary secret"
 							.to_owned(),
 					))
@@ -203,9 +223,8 @@ impl LoginCredentials {
 	) -> Result<(), std::io::Error> {
 		match self {
 			Self::VRChat(login_request_part) => match &mut **login_request_part {
-				crate::vrchat::LoginRequestPart::LoginRequest(authenticating) => {
-					Err(std::io::Error::new(
-						std::io::ErrorKind::Other,
+				crate::vrchat::LoginRequestPart::LoginRequest(_) => {
+					Err(std::io::Error::other(
 						"VRChat auth first stage doesn't have a secondary secret"
 							.to_owned(),
 					))
@@ -213,21 +232,25 @@ impl LoginCredentials {
 				crate::vrchat::LoginRequestPart::SecondFactor((
 					_user_id,
 					second_factor,
-				)) => value.map_or_else(|| Err(std::io::Error::new(
-						std::io::ErrorKind::Other,
-						"VRChat second factor stage requires a secondary secret".to_owned(),
-					)), |value| match second_factor {
-							vrc::query::VerifySecondFactor::Email(code)
-							| vrc::query::VerifySecondFactor::Code(code)
-							| vrc::query::VerifySecondFactor::Recovery(code) => {
-								*code = value;
-
-								Ok(())
+				)) => value.map_or_else(
+					|| {
+						Err(std::io::Error::other(
+							"VRChat second factor stage requires a secondary secret"
+								.to_owned(),
+						))
+					},
+					|value| match second_factor {
+						vrc::query::VerifySecondFactor::Email(code)
+						| vrc::query::VerifySecondFactor::Code(code)
+						| vrc::query::VerifySecondFactor::Recovery(code) => {
+							*code = value;
+
+							Ok(())
 						}
-					}),
+					},
+				),
 			},
-			Self::ChilloutVR(login_credentials) => Err(std::io::Error::new(
-				std::io::ErrorKind::Other,
+			Self::ChilloutVR(_) => Err(std::io::Error::other(
 				"ChilloutVR doesn't support a secondary secret".to_owned(),
 			)),
 			Self::Resonite(user_session_query_with_headers) => {
@@ -259,3 +282,72 @@ impl LoginCredentials {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::PlatformDataAndMetadata;
+
+	const PASSWORD: &str = "hunter2-password";
+	const TOKEN: &str = "authcookie_secret-token";
+	const SECOND_FACTOR_TOKEN: &str = "twoFactorAuth_secret-token";
+	const CODE: &str = "123456";
+
+	fn vrc_user_id() -> vrc::id::User {
+		"usr_c1644b5b-3ca4-45b4-97c6-a2a0de70d469".parse().unwrap()
+	}
+
+	fn assert_redacted(formatted: &str, secrets: &[&str]) {
+		for secret in secrets {
+			assert!(
+				!formatted.contains(secret),
+				"`{secret}` was not redacted from `{formatted}`"
+			);
+		}
+	}
+
+	#[test]
+	fn authentication_debug_redacts_tokens() {
+		let auth = Authentication::VRChat(PlatformDataAndMetadata::new_now(
+			Box::new(vrc::query::Authentication {
+				token: TOKEN.to_owned(),
+				second_factor_token: Some(SECOND_FACTOR_TOKEN.to_owned()),
+			}),
+			vrc_user_id(),
+		));
+
+		for formatted in [format!("{auth:?}"), format!("{auth:#?}")] {
+			assert_redacted(&formatted, &[TOKEN, SECOND_FACTOR_TOKEN]);
+			assert!(formatted.contains(vrc_user_id().as_ref()));
+		}
+	}
+
+	#[test]
+	fn login_credentials_debug_redacts_password() {
+		let credentials = LoginCredentials::VRChat(Box::new(
+			crate::vrchat::LoginRequestPart::LoginRequest(
+				vrc::query::Authenticating {
+					username: "username".to_owned(),
+					password: PASSWORD.to_owned(),
+				},
+			),
+		));
+
+		for formatted in [format!("{credentials:?}"), format!("{credentials:#?}")] {
+			assert_redacted(&formatted, &[PASSWORD]);
+			assert!(formatted.contains("username"));
+		}
+	}
+
+	#[test]
+	fn login_request_part_debug_redacts_second_factor() {
+		let part = crate::vrchat::LoginRequestPart::SecondFactor((
+			vrc_user_id(),
+			vrc::query::VerifySecondFactor::Code(CODE.to_owned()),
+		));
+
+		for formatted in [format!("{part:?}"), format!("{part:#?}")] {
+			assert_redacted(&formatted, &[CODE]);
+		}
+	}
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{PlatformAccountId, ProfileId};
+
+/// An ID of a circle
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircleId(uuid::Uuid);
+
+impl CircleId {
+	/// Creates a new circle ID
+	#[must_use]
+	// New and default have slightly different semantics, as the new will be
+	// different each time unlike what would assume for default.
+	#[allow(clippy::new_without_default)]
+	#[cfg(feature = "rand_util")]
+	pub fn new() -> Self { Self(uuid::Uuid::new_v4()) }
+}
+
+impl std::fmt::Display for CircleId {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl std::str::FromStr for CircleId {
+	type Err = uuid::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		uuid::Uuid::parse_str(s).map(Self)
+	}
+}
+
+/// An RGB color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+	/// The red component
+	pub red: u8,
+	/// The green component
+	pub green: u8,
+	/// The blue component
+	pub blue: u8,
+}
+
+/// Formatted as `#rrggbb`
+impl std::fmt::Display for Color {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+	}
+}
+
+impl std::str::FromStr for Color {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("Invalid color '{s}', expected #rrggbb");
+		let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+		// Parsing the components alone would also accept signs like `+f`
+		if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+			return Err(invalid());
+		}
+		let component =
+			|i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+
+		Ok(Self { red: component(0)?, green: component(2)?, blue: component(4)? })
+	}
+}
+
+impl TryFrom<String> for Color {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> { value.parse() }
+}
+
+impl From<Color> for String {
+	fn from(value: Color) -> Self { value.to_string() }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// A named group of peeps across the platforms, such as "Close friends".
+///
+/// The members are stored separately, see
+/// [`OnlivfeStore::circle_members`](crate::storage::OnlivfeStore::circle_members).
+pub struct Circle {
+	/// The ID of the circle
+	pub id: CircleId,
+	/// The name of the circle
+	pub name: String,
+	/// The color that the circle is shown with
+	pub color: Option<Color>,
+	/// Where the circle is shown in the list of circles, lower ones first
+	pub position: u32,
+}
+
+impl Circle {
+	/// Creates a new circle with a name
+	#[must_use]
+	#[cfg(feature = "rand_util")]
+	pub fn new(name: impl Into<String>) -> Self {
+		Self { id: CircleId::new(), name: name.into(), color: None, position: 0 }
+	}
+}
+
+/// A member of a circle, either a whole profile or a single account
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "camelCase")]
+pub enum CircleMember {
+	/// A profile, including all of the accounts linked to it
+	Profile(ProfileId),
+	/// A platform account
+	Account(PlatformAccountId),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn colors_parse_from_hex() {
+		for (text, color) in [
+			("#ff8000", Color { red: 255, green: 128, blue: 0 }),
+			("#00aAfF", Color { red: 0, green: 170, blue: 255 }),
+			("#000000", Color { red: 0, green: 0, blue: 0 }),
+		] {
+			assert_eq!(text.parse::<Color>(), Ok(color), "Parsing {text}");
+		}
+	}
+
+	#[test]
+	fn colors_round_trip_through_text() {
+		let color = Color { red: 18, green: 52, blue: 86 };
+		assert_eq!(color.to_string(), "#123456");
+		assert_eq!(color.to_string().parse::<Color>(), Ok(color));
+		assert_eq!(
+			serde_json::to_value(color).expect("serializing to succeed"),
+			serde_json::json!("#123456")
+		);
+	}
+
+	#[test]
+	fn invalid_colors_are_rejected() {
+		for text in [
+			"", "#", "ff8000", "#ff800", "#ff80000", "#fff", "#gg8000", "#ff 800",
+			"#+f8000", "#-f8000", "#ff+f00", "#ff80-0", "#ff80é",
+		] {
+			assert!(text.parse::<Color>().is_err(), "Parsing {text:?}");
+		}
+		assert!(serde_json::from_str::<Color>(r##""#+f+f+f""##).is_err());
+	}
+}
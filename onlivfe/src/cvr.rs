@@ -20,3 +20,74 @@ impl From<super::PlatformDataMetadata<chilloutvr::id::User>>
 		Self { updated_at: value.updated_at, updated_by: value.updated_by.into() }
 	}
 }
+
+/// Reads a `ChilloutVR` instance piece by piece.
+///
+/// The model has the world both in itself and in its flattened base, which
+/// makes serde serialize the key twice and fail to deserialize it at all.
+///
+/// # Errors
+///
+/// If the value isn't a valid instance
+pub fn instance_from_value(
+	value: &serde_json::Value,
+) -> serde_json::Result<chilloutvr::model::ExtendedInstanceDetails> {
+	use serde::Deserialize;
+
+	/// The fields on top of the base details
+	#[derive(Deserialize)]
+	#[serde(rename_all = "camelCase")]
+	struct Extended {
+		instance_setting_privacy: chilloutvr::model::InstancePrivacy,
+		author: chilloutvr::model::UserBase,
+		owner: chilloutvr::model::UserDetails,
+		world: chilloutvr::model::AssetBaseWithTags,
+	}
+
+	let extended = Extended::deserialize(value)?;
+	Ok(chilloutvr::model::ExtendedInstanceDetails {
+		base: chilloutvr::model::InstanceDetails::deserialize(value)?,
+		instance_setting_privacy: extended.instance_setting_privacy,
+		author: extended.author,
+		owner: extended.owner,
+		world: extended.world,
+	})
+}
+
+/// Stores `ChilloutVR` instances with the world only once,
+/// see [`instance_from_value`]
+pub(crate) mod stored_instance {
+	use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
+
+	use crate::PlatformDataAndMetadata;
+
+	type Stored = PlatformDataAndMetadata<
+		chilloutvr::model::ExtendedInstanceDetails,
+		chilloutvr::id::User,
+	>;
+
+	pub fn serialize<S: Serializer>(
+		stored: &Stored, serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		let mut data =
+			serde_json::to_value(&stored.data).map_err(ser::Error::custom)?;
+		data["world"] =
+			serde_json::to_value(&stored.data.world).map_err(ser::Error::custom)?;
+		PlatformDataAndMetadata { data, metadata: stored.metadata.clone() }
+			.serialize(serializer)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Stored, D::Error> {
+		let stored = PlatformDataAndMetadata::<
+			serde_json::Value,
+			chilloutvr::id::User,
+		>::deserialize(deserializer)?;
+		Ok(PlatformDataAndMetadata {
+			data: super::instance_from_value(&stored.data)
+				.map_err(de::Error::custom)?,
+			metadata: stored.metadata,
+		})
+	}
+}
@@ -0,0 +1,339 @@
+//! Encryption of stored authentications, for storage backends to use
+//!
+//! Authentications contain session tokens and such, which shouldn't be stored
+//! in cleartext. The storage backends store [`KeyParams`] next to the
+//! [`Sealed`] authentications, which are then used to unlock an
+//! [`AuthCipher`] with the user provided [`AuthKey`].
+
+use std::path::PathBuf;
+
+use chacha20poly1305::{
+	XChaCha20Poly1305,
+	XNonce,
+	aead::{Aead, AeadCore, KeyInit, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::Authentication;
+
+/// Length of the encryption key in bytes
+const KEY_LENGTH: usize = 32;
+/// Length of the salt used for deriving a key from a passphrase
+const SALT_LENGTH: usize = 16;
+/// The known plaintext that is used to check if the key is correct
+const CHECK_PLAINTEXT: &[u8] = b"onlivfe";
+
+/// An error with the encryption of authentications
+#[derive(Debug)]
+pub enum EncryptionError {
+	/// The key doesn't match the one that the data was encrypted with
+	WrongKey,
+	/// Reading or writing the key file failed
+	KeyFile(std::io::Error),
+	/// The key file or the encrypted data is not valid
+	Invalid(String),
+}
+
+impl std::fmt::Display for EncryptionError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::WrongKey => write!(
+				f,
+				"Wrong passphrase or key file for the encrypted authentications"
+			),
+			Self::KeyFile(e) => write!(f, "Key file error: {e}"),
+			Self::Invalid(e) => write!(f, "Invalid encrypted data: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for EncryptionError {}
+
+/// What the encryption key is derived from
+#[derive(Clone)]
+pub enum AuthKey {
+	/// A passphrase from the user
+	Passphrase(String),
+	/// A file containing a random key
+	KeyFile(PathBuf),
+}
+
+impl std::fmt::Debug for AuthKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Passphrase(_) => f.write_str("Passphrase(<redacted>)"),
+			Self::KeyFile(path) => f.debug_tuple("KeyFile").field(path).finish(),
+		}
+	}
+}
+
+impl AuthKey {
+	/// Writes a new random key into a file, to be used with
+	/// [`AuthKey::KeyFile`]
+	///
+	/// # Errors
+	///
+	/// If the file already exists or writing it fails
+	pub fn generate_key_file(
+		path: impl Into<PathBuf>,
+	) -> Result<Self, EncryptionError> {
+		use std::io::Write;
+
+		let path = path.into();
+		let mut key = [0u8; KEY_LENGTH];
+		OsRng.fill_bytes(&mut key);
+		let mut file = std::fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&path)
+			.map_err(EncryptionError::KeyFile)?;
+		file.write_all(&key).map_err(EncryptionError::KeyFile)?;
+		file.sync_all().map_err(EncryptionError::KeyFile)?;
+
+		Ok(Self::KeyFile(path))
+	}
+
+	fn derive(
+		&self, kdf: Option<&PassphraseKdf>,
+	) -> Result<[u8; KEY_LENGTH], EncryptionError> {
+		let mut key = [0u8; KEY_LENGTH];
+		match (self, kdf) {
+			(Self::Passphrase(passphrase), Some(kdf)) => {
+				let params = argon2::Params::new(
+					kdf.m_cost,
+					kdf.t_cost,
+					kdf.p_cost,
+					Some(KEY_LENGTH),
+				)
+				.map_err(|e| EncryptionError::Invalid(e.to_string()))?;
+				argon2::Argon2::new(
+					argon2::Algorithm::Argon2id,
+					argon2::Version::V0x13,
+					params,
+				)
+				.hash_password_into(passphrase.as_bytes(), &kdf.salt, &mut key)
+				.map_err(|e| EncryptionError::Invalid(e.to_string()))?;
+			}
+			(Self::KeyFile(path), None) => {
+				let bytes = std::fs::read(path).map_err(EncryptionError::KeyFile)?;
+				if bytes.len() != KEY_LENGTH {
+					return Err(EncryptionError::Invalid(format!(
+						"Key file should be {KEY_LENGTH} bytes long, was {}",
+						bytes.len()
+					)));
+				}
+				key.copy_from_slice(&bytes);
+			}
+			// The data was encrypted with a different kind of a key
+			_ => return Err(EncryptionError::WrongKey),
+		}
+
+		Ok(key)
+	}
+}
+
+/// Parameters for deriving a key from a passphrase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PassphraseKdf {
+	salt: Vec<u8>,
+	m_cost: u32,
+	t_cost: u32,
+	p_cost: u32,
+}
+
+impl PassphraseKdf {
+	fn generate() -> Self {
+		let mut salt = vec![0u8; SALT_LENGTH];
+		OsRng.fill_bytes(&mut salt);
+		let defaults = argon2::Params::default();
+
+		Self {
+			salt,
+			m_cost: defaults.m_cost(),
+			t_cost: defaults.t_cost(),
+			p_cost: defaults.p_cost(),
+		}
+	}
+}
+
+/// Data encrypted by an [`AuthCipher`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sealed {
+	nonce: Vec<u8>,
+	ciphertext: Vec<u8>,
+}
+
+/// Parameters needed to unlock the key again, which are stored next to the
+/// encrypted data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyParams {
+	/// `None` if the key is read from a key file
+	passphrase_kdf: Option<PassphraseKdf>,
+	/// A known value encrypted with the key, used for detecting wrong keys
+	check: Sealed,
+}
+
+/// Encrypts & decrypts authentications with a key
+pub struct AuthCipher {
+	cipher: XChaCha20Poly1305,
+	params: KeyParams,
+}
+
+impl std::fmt::Debug for AuthCipher {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("AuthCipher")
+			.field("params", &self.params)
+			.finish_non_exhaustive()
+	}
+}
+
+impl AuthCipher {
+	/// Creates a cipher with new key parameters, for encrypting data that
+	/// wasn't encrypted before or for changing the key
+	///
+	/// # Errors
+	///
+	/// If the key file couldn't be read
+	pub fn create(key: &AuthKey) -> Result<Self, EncryptionError> {
+		let passphrase_kdf = match key {
+			AuthKey::Passphrase(_) => Some(PassphraseKdf::generate()),
+			AuthKey::KeyFile(_) => None,
+		};
+		let cipher =
+			XChaCha20Poly1305::new(&key.derive(passphrase_kdf.as_ref())?.into());
+		let check = seal_with(&cipher, CHECK_PLAINTEXT)?;
+
+		Ok(Self { cipher, params: KeyParams { passphrase_kdf, check } })
+	}
+
+	/// Unlocks the cipher that previously stored data was encrypted with
+	///
+	/// # Errors
+	///
+	/// [`EncryptionError::WrongKey`] if the key is not the one that was used
+	/// with the parameters, or if the key file couldn't be read
+	pub fn unlock(
+		key: &AuthKey, params: &KeyParams,
+	) -> Result<Self, EncryptionError> {
+		let cipher = XChaCha20Poly1305::new(
+			&key.derive(params.passphrase_kdf.as_ref())?.into(),
+		);
+		match open_with(&cipher, &params.check) {
+			Ok(plaintext) if plaintext == CHECK_PLAINTEXT => {}
+			_ => return Err(EncryptionError::WrongKey),
+		}
+
+		Ok(Self { cipher, params: params.clone() })
+	}
+
+	/// The parameters that need to be stored for unlocking the cipher again
+	#[must_use]
+	pub const fn params(&self) -> &KeyParams { &self.params }
+
+	/// Encrypts an authentication
+	///
+	/// # Errors
+	///
+	/// If serializing or encrypting the authentication failed
+	pub fn seal(&self, auth: &Authentication) -> Result<Sealed, EncryptionError> {
+		let plaintext = serde_json::to_vec(auth)
+			.map_err(|e| EncryptionError::Invalid(e.to_string()))?;
+		seal_with(&self.cipher, &plaintext)
+	}
+
+	/// Decrypts an authentication
+	///
+	/// # Errors
+	///
+	/// If the data was not encrypted with this cipher, or if it's not a valid
+	/// authentication
+	pub fn open(
+		&self, sealed: &Sealed,
+	) -> Result<Authentication, EncryptionError> {
+		let plaintext = open_with(&self.cipher, sealed)?;
+		serde_json::from_slice(&plaintext)
+			.map_err(|e| EncryptionError::Invalid(e.to_string()))
+	}
+}
+
+fn seal_with(
+	cipher: &XChaCha20Poly1305, plaintext: &[u8],
+) -> Result<Sealed, EncryptionError> {
+	let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+	let ciphertext = cipher
+		.encrypt(&nonce, plaintext)
+		.map_err(|_| EncryptionError::Invalid("Encryption failed".to_owned()))?;
+
+	Ok(Sealed { nonce: nonce.to_vec(), ciphertext })
+}
+
+fn open_with(
+	cipher: &XChaCha20Poly1305, sealed: &Sealed,
+) -> Result<Vec<u8>, EncryptionError> {
+	if sealed.nonce.len() != 24 {
+		return Err(EncryptionError::Invalid("Invalid nonce length".to_owned()));
+	}
+	cipher
+		.decrypt(XNonce::from_slice(&sealed.nonce), sealed.ciphertext.as_ref())
+		.map_err(|_| EncryptionError::WrongKey)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn auth_key_debug_redacts_passphrase() {
+		let key = AuthKey::Passphrase("correct horse battery staple".to_owned());
+
+		assert!(!format!("{key:?}").contains("correct horse"));
+		assert!(!format!("{key:#?}").contains("correct horse"));
+	}
+
+	fn authentication() -> Authentication {
+		Authentication::VRChat(crate::PlatformDataAndMetadata::new_now(
+			Box::new(vrc::query::Authentication {
+				token: "authcookie_secret-token".to_owned(),
+				second_factor_token: None,
+			}),
+			"usr_c1644b5b-3ca4-45b4-97c6-a2a0de70d469".parse().unwrap(),
+		))
+	}
+
+	#[test]
+	fn sealed_authentication_opens_with_same_key() {
+		let key = AuthKey::Passphrase("correct horse battery staple".to_owned());
+		let auth = authentication();
+		let cipher = AuthCipher::create(&key).unwrap();
+		let sealed = cipher.seal(&auth).unwrap();
+		let serialized = serde_json::to_string(&sealed).unwrap();
+		assert!(!serialized.contains("authcookie_secret-token"));
+
+		let reunlocked = AuthCipher::unlock(&key, cipher.params()).unwrap();
+		let opened = reunlocked.open(&sealed).unwrap();
+		assert_eq!(
+			serde_json::to_value(&opened).unwrap(),
+			serde_json::to_value(&auth).unwrap()
+		);
+	}
+
+	#[test]
+	fn wrong_passphrase_is_rejected() {
+		let key = AuthKey::Passphrase("correct horse battery staple".to_owned());
+		let wrong_key = AuthKey::Passphrase("incorrect horse".to_owned());
+		let cipher = AuthCipher::create(&key).unwrap();
+		let sealed = cipher.seal(&authentication()).unwrap();
+
+		assert!(matches!(
+			AuthCipher::unlock(&wrong_key, cipher.params()),
+			Err(EncryptionError::WrongKey)
+		));
+		let other_cipher = AuthCipher::create(&wrong_key).unwrap();
+		assert!(matches!(
+			other_cipher.open(&sealed),
+			Err(EncryptionError::WrongKey)
+		));
+	}
+}
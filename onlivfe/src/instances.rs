@@ -9,9 +9,10 @@ crate::platform_id!(
 
 crate::platform_enum!(
 	/// The platform specific instance/session.
-	#[derive(Eq)]
+	#[derive(Debug, Eq)]
 	Instance {
 		vrc::model::Instance,
+		#[serde(with = "crate::cvr::stored_instance")]
 		chilloutvr::model::ExtendedInstanceDetails,
 		resonite::model::SessionInfo
 	}
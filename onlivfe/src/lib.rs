@@ -15,6 +15,8 @@
 // Not much can be done about it :/
 #![allow(clippy::multiple_crate_versions)]
 
+use std::collections::{BTreeMap, BTreeSet};
+
 use serde::{Deserialize, Serialize};
 use strum::{
 	AsRefStr,
@@ -24,9 +26,11 @@ use strum::{
 	FromRepr,
 	IntoEnumIterator,
 };
-use time::OffsetDateTime;
+use time::{Month, OffsetDateTime};
 
 pub mod cvr;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod resonite;
 pub mod storage;
 pub mod vrchat;
@@ -39,6 +43,18 @@ mod instances;
 pub use instances::*;
 mod assets;
 pub use assets::*;
+mod circles;
+pub use circles::*;
+mod pictures;
+pub use pictures::*;
+mod revisions;
+pub use revisions::*;
+mod sharing;
+pub use sharing::*;
+mod suggestions;
+pub use suggestions::*;
+mod trash;
+pub use trash::*;
 
 /// The type of the platform/service/game/etc
 #[derive(
@@ -134,17 +150,21 @@ pub(crate) use platform_id;
 
 macro_rules! platform_enum {
 	($(#[$meta:meta])*
-	$name:ident { $vrc:ty, $cvr:ty, $resonite:ty }) => {
+	$name:ident { $vrc:ty, $(#[$cvr_meta:meta])* $cvr:ty, $resonite:ty }) => {
 		$(#[$meta])*
 		// Wrong warning
 		#[allow(clippy::derive_partial_eq_without_eq)]
-		#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+		// Debug is left out, as some of the data is secret
+		#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 		#[serde(tag = "platform", content = "data")]
 		pub enum $name {
 			/// VRC variant
 			VRChat(crate::PlatformDataAndMetadata<$vrc, vrc::id::User>),
 			/// CVR variant
-			ChilloutVR(crate::PlatformDataAndMetadata<$cvr, chilloutvr::id::User>),
+			ChilloutVR(
+				$(#[$cvr_meta])*
+				crate::PlatformDataAndMetadata<$cvr, chilloutvr::id::User>
+			),
 			/// Resonite variant
 			Resonite(crate::PlatformDataAndMetadata<$resonite, resonite::id::User>),
 		}
@@ -196,7 +216,93 @@ impl ProfileId {
 	pub fn new() -> Self { Self(uuid::Uuid::new_v4()) }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl std::fmt::Display for ProfileId {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl std::str::FromStr for ProfileId {
+	type Err = uuid::Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		uuid::Uuid::parse_str(s).map(Self)
+	}
+}
+
+/// A birthday, without the year if it's not known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Birthday {
+	year: Option<i32>,
+	month: Month,
+	day: u8,
+}
+
+impl Birthday {
+	/// Creates a birthday, if the day exists
+	#[must_use]
+	pub fn new(year: Option<i32>, month: Month, day: u8) -> Option<Self> {
+		// Any leap year works for checking birthdays without a year
+		time::Date::from_calendar_date(year.unwrap_or(2000), month, day).ok()?;
+		Some(Self { year, month, day })
+	}
+
+	/// The year of birth, if it's known
+	#[must_use]
+	pub const fn year(&self) -> Option<i32> { self.year }
+
+	/// The month of the birthday
+	#[must_use]
+	pub const fn month(&self) -> Month { self.month }
+
+	/// The day of the month of the birthday
+	#[must_use]
+	pub const fn day(&self) -> u8 { self.day }
+}
+
+/// Formatted as `YYYY-MM-DD`, or `--MM-DD` without the year as in ISO 8601
+impl std::fmt::Display for Birthday {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.year {
+			Some(year) => write!(f, "{year:04}-")?,
+			None => f.write_str("--")?,
+		}
+		write!(f, "{:02}-{:02}", u8::from(self.month), self.day)
+	}
+}
+
+impl std::str::FromStr for Birthday {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("Invalid birthday '{s}'");
+		let (year, month_day) = if let Some(month_day) = s.strip_prefix("--") {
+			(None, month_day)
+		} else {
+			let (year, month_day) = s.split_once('-').ok_or_else(invalid)?;
+			(Some(year.parse().map_err(|_| invalid())?), month_day)
+		};
+		let (month, day) = month_day.split_once('-').ok_or_else(invalid)?;
+		let month: u8 = month.parse().map_err(|_| invalid())?;
+		let month = Month::try_from(month).map_err(|_| invalid())?;
+		let day = day.parse().map_err(|_| invalid())?;
+
+		Self::new(year, month, day).ok_or_else(invalid)
+	}
+}
+
+impl TryFrom<String> for Birthday {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> { value.parse() }
+}
+
+impl From<Birthday> for String {
+	fn from(value: Birthday) -> Self { value.to_string() }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 /// A profile of "this is someone".
 pub struct Profile {
@@ -208,6 +314,31 @@ pub struct Profile {
 	pub notes: Option<String>,
 	/// A custom profile picture about the peep
 	pub pfp_url: Option<String>,
+	/// Tags for finding & grouping peeps, such as "met at a meetup"
+	#[serde(default)]
+	pub tags: BTreeSet<String>,
+	/// Free-form details about the peep, such as their Discord username
+	#[serde(default)]
+	pub custom_fields: BTreeMap<String, String>,
+	/// The pronouns of the peep
+	#[serde(default)]
+	pub pronouns: Option<String>,
+	/// The birthday of the peep
+	#[serde(default)]
+	pub birthday: Option<Birthday>,
+	/// The IANA name of the time zone of the peep, such as "Europe/Helsinki"
+	#[serde(default)]
+	pub time_zone: Option<String>,
+	/// If the peep is a favorite
+	#[serde(default)]
+	pub favorite: bool,
+	/// When the profile was created,
+	/// defaulting to the current time for profiles from before it was tracked
+	#[serde(default = "OffsetDateTime::now_utc")]
+	pub created_at: OffsetDateTime,
+	/// When the profile was last updated
+	#[serde(default = "OffsetDateTime::now_utc")]
+	pub updated_at: OffsetDateTime,
 }
 
 impl Profile {
@@ -218,13 +349,100 @@ impl Profile {
 	#[allow(clippy::new_without_default)]
 	#[cfg(feature = "rand_util")]
 	pub fn new() -> Self {
+		let now = OffsetDateTime::now_utc();
 		Self {
 			sharing_id: ProfileId::new(),
 			nick: None,
 			notes: None,
 			pfp_url: None,
+			tags: BTreeSet::new(),
+			custom_fields: BTreeMap::new(),
+			pronouns: None,
+			birthday: None,
+			time_zone: None,
+			favorite: false,
+			created_at: now,
+			updated_at: now,
 		}
 	}
+
+	/// If the profile has the tag, ignoring ASCII case
+	#[must_use]
+	pub fn has_tag(&self, tag: &str) -> bool {
+		self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+	}
+
+	/// Fills in the details that this profile doesn't have from another copy of
+	/// it, keeping the existing ones as is.
+	///
+	/// Tags and custom fields that are missing are added, and the profile is
+	/// considered to have been created at the earlier of the two times.
+	///
+	/// Returns if anything was filled in.
+	pub fn fill_missing_from(&mut self, other: &Self) -> bool {
+		let mut filled = false;
+		for (field, other_field) in [
+			(&mut self.nick, &other.nick),
+			(&mut self.notes, &other.notes),
+			(&mut self.pfp_url, &other.pfp_url),
+			(&mut self.pronouns, &other.pronouns),
+			(&mut self.time_zone, &other.time_zone),
+		] {
+			if field.is_none() && other_field.is_some() {
+				field.clone_from(other_field);
+				filled = true;
+			}
+		}
+		if self.birthday.is_none() && other.birthday.is_some() {
+			self.birthday = other.birthday;
+			filled = true;
+		}
+		for tag in &other.tags {
+			filled |= self.tags.insert(tag.clone());
+		}
+		for (key, value) in &other.custom_fields {
+			if !self.custom_fields.contains_key(key) {
+				self.custom_fields.insert(key.clone(), value.clone());
+				filled = true;
+			}
+		}
+		if other.created_at < self.created_at {
+			self.created_at = other.created_at;
+			filled = true;
+		}
+		if filled {
+			self.updated_at = self.updated_at.max(other.updated_at);
+		}
+
+		filled
+	}
+
+	/// Merges another profile of the same peep into this one.
+	///
+	/// Details that are missing are filled in from the other profile like with
+	/// [`fill_missing_from`](Self::fill_missing_from), and conflicting ones
+	/// are kept as is, except for:
+	/// - notes, which are joined together
+	/// - custom fields, whose conflicting values are joined together
+	/// - being a favorite, which is kept if either of them is
+	pub fn merge(&mut self, other: &Self) {
+		if let (Some(notes), Some(other_notes)) = (&mut self.notes, &other.notes) {
+			if notes != other_notes {
+				notes.push_str("\n\n");
+				notes.push_str(other_notes);
+			}
+		}
+		for (key, other_value) in &other.custom_fields {
+			if let Some(value) = self.custom_fields.get_mut(key) {
+				if value != other_value {
+					value.push_str(", ");
+					value.push_str(other_value);
+				}
+			}
+		}
+		self.favorite |= other.favorite;
+		self.fill_missing_from(other);
+	}
 }
 
 /// Metadata about the data from a platform
@@ -279,3 +497,197 @@ impl<T, Id> From<PlatformDataAndMetadata<T, Id>>
 		(value.data, value.metadata)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn birthday(year: Option<i32>, month: Month, day: u8) -> Birthday {
+		Birthday::new(year, month, day).expect("the birthday to exist")
+	}
+
+	#[test]
+	fn birthdays_round_trip_through_strings() {
+		for (text, birthday) in [
+			("1999-04-01", birthday(Some(1999), Month::April, 1)),
+			("--12-24", birthday(None, Month::December, 24)),
+			("2000-02-29", birthday(Some(2000), Month::February, 29)),
+			("--02-29", birthday(None, Month::February, 29)),
+		] {
+			assert_eq!(text.parse::<Birthday>(), Ok(birthday));
+			assert_eq!(birthday.to_string(), text);
+		}
+		assert_eq!(birthday(Some(987), Month::May, 6).to_string(), "0987-05-06");
+	}
+
+	#[test]
+	fn invalid_birthdays_are_rejected() {
+		for text in [
+			"",
+			"--",
+			"1999-02-29",
+			"--02-30",
+			"--13-01",
+			"--00-10",
+			"--04-31",
+			"1999-04",
+			"--04",
+			"year-04-01",
+			"1999-April-01",
+			"1999-04-01-01",
+		] {
+			assert!(text.parse::<Birthday>().is_err(), "'{text}' should be invalid");
+		}
+		assert_eq!(Birthday::new(Some(2001), Month::February, 29), None);
+		assert_eq!(Birthday::new(None, Month::January, 0), None);
+	}
+
+	#[test]
+	fn filling_keeps_existing_details() {
+		let earlier = OffsetDateTime::UNIX_EPOCH;
+		let mut profile = Profile {
+			nick: Some("Peep".to_owned()),
+			tags: ["Friend".to_owned()].into(),
+			custom_fields: [("Discord".to_owned(), "peep".to_owned())].into(),
+			..Profile::new()
+		};
+		let other = Profile {
+			nick: Some("Other".to_owned()),
+			notes: Some("Likes cats".to_owned()),
+			birthday: Some(birthday(None, Month::June, 2)),
+			tags: ["Friend".to_owned(), "Meetup".to_owned()].into(),
+			custom_fields: [
+				("Discord".to_owned(), "other".to_owned()),
+				("Country".to_owned(), "Finland".to_owned()),
+			]
+			.into(),
+			favorite: true,
+			created_at: earlier,
+			..Profile::new()
+		};
+		let sharing_id = profile.sharing_id.clone();
+
+		assert!(profile.fill_missing_from(&other));
+		assert_eq!(profile.sharing_id, sharing_id);
+		assert_eq!(profile.nick.as_deref(), Some("Peep"));
+		assert_eq!(profile.notes.as_deref(), Some("Likes cats"));
+		assert_eq!(profile.birthday, other.birthday);
+		assert_eq!(profile.tags, other.tags);
+		assert_eq!(
+			profile.custom_fields,
+			[
+				("Discord".to_owned(), "peep".to_owned()),
+				("Country".to_owned(), "Finland".to_owned()),
+			]
+			.into()
+		);
+		assert!(!profile.favorite, "Favorites are not filled in");
+		assert_eq!(profile.created_at, earlier);
+		assert_eq!(profile.updated_at, other.updated_at.max(profile.updated_at));
+
+		let filled = profile.clone();
+		assert!(
+			!profile.fill_missing_from(&other),
+			"Nothing should be left to fill"
+		);
+		assert_eq!(profile, filled);
+	}
+
+	#[test]
+	fn filling_from_an_empty_profile_changes_nothing() {
+		let mut profile =
+			Profile { nick: Some("Peep".to_owned()), ..Profile::new() };
+		let unchanged = profile.clone();
+		let mut empty = Profile::new();
+		empty.created_at = profile.created_at;
+
+		assert!(!profile.fill_missing_from(&empty));
+		assert_eq!(profile, unchanged);
+	}
+
+	fn with_notes(notes: Option<&str>) -> Profile {
+		Profile { notes: notes.map(str::to_owned), ..Profile::new() }
+	}
+
+	fn with_fields(fields: &[(&str, &str)]) -> Profile {
+		let custom_fields = fields
+			.iter()
+			.map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+			.collect();
+		Profile { custom_fields, ..Profile::new() }
+	}
+
+	#[test]
+	fn merging_joins_different_notes() {
+		let mut profile = with_notes(Some("Met at a meetup"));
+		profile.merge(&with_notes(Some("Likes cats")));
+		assert_eq!(profile.notes.as_deref(), Some("Met at a meetup\n\nLikes cats"));
+
+		let mut profile = with_notes(Some("Likes cats"));
+		profile.merge(&with_notes(Some("Likes cats")));
+		assert_eq!(profile.notes.as_deref(), Some("Likes cats"));
+
+		let mut profile = with_notes(None);
+		profile.merge(&with_notes(Some("Likes cats")));
+		assert_eq!(profile.notes.as_deref(), Some("Likes cats"));
+
+		let mut profile = with_notes(Some("Likes cats"));
+		profile.merge(&with_notes(None));
+		assert_eq!(profile.notes.as_deref(), Some("Likes cats"));
+	}
+
+	#[test]
+	fn merging_joins_conflicting_custom_fields() {
+		let mut profile =
+			with_fields(&[("Discord", "peep"), ("Twitter", "@peep"), ("Age", "20")]);
+		profile.merge(&with_fields(&[
+			("Discord", "peep2"),
+			("Twitter", "@peep"),
+			("Country", "Finland"),
+		]));
+
+		assert_eq!(
+			profile.custom_fields,
+			with_fields(&[
+				("Discord", "peep, peep2"),
+				("Twitter", "@peep"),
+				("Age", "20"),
+				("Country", "Finland"),
+			])
+			.custom_fields
+		);
+	}
+
+	#[test]
+	fn merging_keeps_favorite_from_either() {
+		for (favorite, other_favorite) in
+			[(false, false), (true, false), (false, true), (true, true)]
+		{
+			let mut profile = Profile { favorite, ..Profile::new() };
+			profile.merge(&Profile { favorite: other_favorite, ..Profile::new() });
+			assert_eq!(profile.favorite, favorite || other_favorite);
+		}
+	}
+
+	#[test]
+	fn merging_keeps_conflicting_details_and_fills_missing_ones() {
+		let mut profile = Profile {
+			nick: Some("Peep".to_owned()),
+			tags: ["Friend".to_owned()].into(),
+			..Profile::new()
+		};
+		let other = Profile {
+			nick: Some("Other".to_owned()),
+			pronouns: Some("they/them".to_owned()),
+			tags: ["Meetup".to_owned()].into(),
+			..Profile::new()
+		};
+		let sharing_id = profile.sharing_id.clone();
+		profile.merge(&other);
+
+		assert_eq!(profile.sharing_id, sharing_id);
+		assert_eq!(profile.nick.as_deref(), Some("Peep"));
+		assert_eq!(profile.pronouns.as_deref(), Some("they/them"));
+		assert_eq!(profile.tags, ["Friend".to_owned(), "Meetup".to_owned()].into());
+	}
+}
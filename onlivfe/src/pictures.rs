@@ -0,0 +1,214 @@
+use time::OffsetDateTime;
+
+use crate::ProfileId;
+
+/// The scheme of the URIs that point to locally stored profile pictures,
+/// see [`LocalPfpUri`]
+pub const LOCAL_PFP_SCHEME: &str = "onlivfe-pfp";
+/// The largest picture that is accepted, in bytes
+pub const MAX_PICTURE_BYTES: usize = 10 * 1024 * 1024;
+/// The largest width & height of a picture that is accepted, in pixels
+pub const MAX_PICTURE_DIMENSION: u32 = 8192;
+/// The most memory that decoding a picture can use, in bytes
+pub const MAX_PICTURE_ALLOCATION: u64 = 256 * 1024 * 1024;
+/// The width & height that thumbnails are scaled to fit in
+pub const THUMBNAIL_SIZE: u32 = 128;
+/// The content type of the thumbnails
+pub const THUMBNAIL_CONTENT_TYPE: &str = "image/png";
+
+/// An error with creating a profile picture
+#[derive(Debug)]
+pub enum PictureError {
+	/// The picture is larger than [`MAX_PICTURE_BYTES`]
+	TooLarge(usize),
+	/// The bytes aren't in a known image format
+	UnknownFormat,
+	/// The image couldn't be decoded or the thumbnail encoded
+	Invalid(String),
+}
+
+impl std::fmt::Display for PictureError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::TooLarge(len) => write!(
+				f,
+				"Picture is {len} bytes, while at most {MAX_PICTURE_BYTES} are allowed"
+			),
+			Self::UnknownFormat => write!(f, "Picture is not in a known format"),
+			Self::Invalid(e) => write!(f, "Invalid picture: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for PictureError {}
+
+/// A profile picture that is stored locally, so that it works offline and
+/// doesn't break when the platforms change their links
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProfilePicture {
+	/// The profile that the picture is of
+	pub profile_id: ProfileId,
+	/// The MIME type of the picture, such as `image/png`
+	pub content_type: String,
+	/// The picture itself
+	pub bytes: Vec<u8>,
+	/// A smaller version of the picture, in [`THUMBNAIL_CONTENT_TYPE`]
+	pub thumbnail: Vec<u8>,
+	/// When the picture was stored
+	pub stored_at: OffsetDateTime,
+}
+
+impl std::fmt::Debug for ProfilePicture {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ProfilePicture")
+			.field("profile_id", &self.profile_id)
+			.field("content_type", &self.content_type)
+			.field("bytes", &self.bytes.len())
+			.field("thumbnail", &self.thumbnail.len())
+			.field("stored_at", &self.stored_at)
+			.finish()
+	}
+}
+
+impl ProfilePicture {
+	/// Creates a profile picture from the bytes of an image, generating its
+	/// thumbnail
+	///
+	/// # Errors
+	///
+	/// If the image is too large, or not in a supported format.
+	/// The dimensions are limited separately from the size in bytes, as a small
+	/// file can still decode into a huge image.
+	#[cfg(feature = "thumbnails")]
+	pub fn new(
+		profile_id: ProfileId, bytes: Vec<u8>,
+	) -> Result<Self, PictureError> {
+		if bytes.len() > MAX_PICTURE_BYTES {
+			return Err(PictureError::TooLarge(bytes.len()));
+		}
+		let format =
+			image::guess_format(&bytes).map_err(|_| PictureError::UnknownFormat)?;
+		let mut limits = image::Limits::default();
+		limits.max_image_width = Some(MAX_PICTURE_DIMENSION);
+		limits.max_image_height = Some(MAX_PICTURE_DIMENSION);
+		limits.max_alloc = Some(MAX_PICTURE_ALLOCATION);
+		let mut reader =
+			image::ImageReader::with_format(std::io::Cursor::new(&bytes), format);
+		reader.limits(limits);
+		let image =
+			reader.decode().map_err(|e| PictureError::Invalid(e.to_string()))?;
+
+		let mut thumbnail = std::io::Cursor::new(vec![]);
+		image
+			.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+			.write_to(&mut thumbnail, image::ImageFormat::Png)
+			.map_err(|e| PictureError::Invalid(e.to_string()))?;
+
+		Ok(Self {
+			profile_id,
+			content_type: format.to_mime_type().to_owned(),
+			bytes,
+			thumbnail: thumbnail.into_inner(),
+			stored_at: OffsetDateTime::now_utc(),
+		})
+	}
+
+	/// The URI that points to the picture, for using as the
+	/// [`Profile::pfp_url`](crate::Profile::pfp_url)
+	#[must_use]
+	pub fn uri(&self) -> LocalPfpUri {
+		LocalPfpUri { profile_id: self.profile_id.clone(), thumbnail: false }
+	}
+}
+
+/// A URI of a locally stored profile picture, like
+/// `onlivfe-pfp://<profile id>` or `onlivfe-pfp://<profile id>/thumbnail`
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct LocalPfpUri {
+	/// The profile that the picture is of
+	pub profile_id: ProfileId,
+	/// If the URI points to the thumbnail instead of the full picture
+	pub thumbnail: bool,
+}
+
+impl std::fmt::Display for LocalPfpUri {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{LOCAL_PFP_SCHEME}://{}", self.profile_id)?;
+		if self.thumbnail {
+			write!(f, "/thumbnail")?;
+		}
+		Ok(())
+	}
+}
+
+impl std::str::FromStr for LocalPfpUri {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("Invalid local profile picture URI '{s}'");
+		let path = s
+			.strip_prefix(LOCAL_PFP_SCHEME)
+			.and_then(|rest| rest.strip_prefix("://"))
+			.ok_or_else(invalid)?;
+		let (profile_id, thumbnail) = path
+			.strip_suffix("/thumbnail")
+			.map_or((path, false), |profile_id| (profile_id, true));
+
+		Ok(Self {
+			profile_id: profile_id.parse().map_err(|_| invalid())?,
+			thumbnail,
+		})
+	}
+}
+
+#[cfg(all(test, feature = "thumbnails"))]
+mod tests {
+	use super::*;
+
+	fn png(width: u32, height: u32) -> Vec<u8> {
+		let mut bytes = std::io::Cursor::new(vec![]);
+		image::GrayImage::new(width, height)
+			.write_to(&mut bytes, image::ImageFormat::Png)
+			.expect("encoding a PNG to succeed");
+		bytes.into_inner()
+	}
+
+	#[test]
+	fn pictures_get_thumbnails() {
+		let picture = ProfilePicture::new(ProfileId::new(), png(512, 256))
+			.expect("the picture to be valid");
+
+		assert_eq!(picture.content_type, "image/png");
+		let thumbnail = image::load_from_memory(&picture.thumbnail)
+			.expect("the thumbnail to be valid");
+		assert_eq!(
+			(thumbnail.width(), thumbnail.height()),
+			(THUMBNAIL_SIZE, THUMBNAIL_SIZE / 2)
+		);
+	}
+
+	#[test]
+	fn too_large_dimensions_are_rejected() {
+		for (width, height) in
+			[(MAX_PICTURE_DIMENSION + 1, 1), (1, MAX_PICTURE_DIMENSION + 1)]
+		{
+			let bytes = png(width, height);
+			assert!(bytes.len() < MAX_PICTURE_BYTES);
+			assert!(
+				matches!(
+					ProfilePicture::new(ProfileId::new(), bytes),
+					Err(PictureError::Invalid(_))
+				),
+				"A {width}x{height} picture"
+			);
+		}
+	}
+
+	#[test]
+	fn unknown_formats_are_rejected() {
+		assert!(matches!(
+			ProfilePicture::new(ProfileId::new(), b"not a picture".to_vec()),
+			Err(PictureError::UnknownFormat)
+		));
+	}
+}
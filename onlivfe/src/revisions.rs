@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{PlatformAccountId, Profile, ProfileId};
+
+/// What changed about a profile in a [`ProfileRevision`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+// Most of the changes are of the details anyways
+#[allow(clippy::large_enum_variant)]
+pub enum ProfileChange {
+	/// The details of the profile changed,
+	/// with `None` meaning that it didn't exist
+	Details {
+		/// The profile before the change
+		old: Option<Profile>,
+		/// The profile after the change
+		new: Option<Profile>,
+	},
+	/// The accounts that are linked to the profile changed
+	Accounts {
+		/// The accounts before the change
+		old: Vec<PlatformAccountId>,
+		/// The accounts after the change
+		new: Vec<PlatformAccountId>,
+	},
+}
+
+impl ProfileChange {
+	/// A change of the details, if they actually changed
+	#[must_use]
+	pub fn details(old: Option<Profile>, new: Option<Profile>) -> Option<Self> {
+		(old != new).then_some(Self::Details { old, new })
+	}
+
+	/// A change of the linked accounts, if they changed other than by their
+	/// order
+	#[must_use]
+	pub fn accounts(
+		old: Vec<PlatformAccountId>, new: Vec<PlatformAccountId>,
+	) -> Option<Self> {
+		let changed =
+			old.iter().collect::<HashSet<_>>() != new.iter().collect::<HashSet<_>>();
+		changed.then_some(Self::Accounts { old, new })
+	}
+}
+
+/// A recorded change to a profile or the accounts linked to it, see
+/// [`OnlivfeStore::profile_revisions`](crate::storage::OnlivfeStore::profile_revisions)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileRevision {
+	/// The profile that changed
+	pub profile_id: ProfileId,
+	/// The number of the revision, starting from 1 and increasing with each
+	/// revision of the profile
+	pub number: u64,
+	/// When the change was made
+	pub recorded_at: OffsetDateTime,
+	/// What changed
+	pub change: ProfileChange,
+}
+
+/// The details and accounts of a profile at some point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileState {
+	/// The details of the profile, `None` if it didn't exist
+	pub profile: Option<Profile>,
+	/// The accounts that were linked to the profile
+	pub account_ids: Vec<PlatformAccountId>,
+}
+
+/// Works out what a profile was like right after a revision, from its current
+/// state and its revisions in the order that they were made.
+///
+/// Each change after the revision has what came before it, and anything that
+/// didn't change after the revision is as it currently is.
+#[must_use]
+pub fn profile_at_revision(
+	current: ProfileState, revisions: &[ProfileRevision], number: u64,
+) -> ProfileState {
+	let mut profile = None;
+	let mut account_ids = None;
+	for revision in revisions.iter().filter(|revision| revision.number > number) {
+		match &revision.change {
+			ProfileChange::Details { old, .. } => {
+				profile.get_or_insert_with(|| old.clone());
+			}
+			ProfileChange::Accounts { old, .. } => {
+				account_ids.get_or_insert_with(|| old.clone());
+			}
+		}
+	}
+
+	ProfileState {
+		profile: profile.unwrap_or(current.profile),
+		account_ids: account_ids.unwrap_or(current.account_ids),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::PlatformType;
+
+	fn account_id() -> PlatformAccountId {
+		serde_json::from_value(serde_json::json!({
+			"platform": PlatformType::Resonite,
+			"id": "U-onlivfe",
+		}))
+		.expect("the platform account ID to be valid")
+	}
+
+	fn named(profile: &Profile, nick: &str) -> Profile {
+		Profile { nick: Some(nick.to_owned()), ..profile.clone() }
+	}
+
+	fn revisions(
+		profile_id: &ProfileId, changes: Vec<ProfileChange>,
+	) -> Vec<ProfileRevision> {
+		(1..)
+			.zip(changes)
+			.map(|(number, change)| ProfileRevision {
+				profile_id: profile_id.clone(),
+				number,
+				recorded_at: OffsetDateTime::UNIX_EPOCH,
+				change,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn details_are_as_they_were_after_the_revision() {
+		let profile = Profile::new();
+		let [first, second, third] =
+			["First", "Second", "Third"].map(|nick| named(&profile, nick));
+		let revisions = revisions(
+			&profile.sharing_id,
+			vec![
+				ProfileChange::Details { old: None, new: Some(first.clone()) },
+				ProfileChange::Details {
+					old: Some(first.clone()),
+					new: Some(second.clone()),
+				},
+				ProfileChange::Details {
+					old: Some(second.clone()),
+					new: Some(third.clone()),
+				},
+			],
+		);
+		let current =
+			ProfileState { profile: Some(third.clone()), account_ids: vec![] };
+
+		for (number, expected) in [(1, first), (2, second), (3, third)] {
+			let state = profile_at_revision(current.clone(), &revisions, number);
+			assert_eq!(state.profile, Some(expected), "At revision {number}");
+		}
+	}
+
+	#[test]
+	fn unchanged_parts_are_kept_as_they_are() {
+		let profile = Profile::new();
+		let renamed = named(&profile, "Renamed");
+		let revisions = revisions(
+			&profile.sharing_id,
+			vec![
+				ProfileChange::Details { old: None, new: Some(profile.clone()) },
+				ProfileChange::Accounts { old: vec![], new: vec![account_id()] },
+				ProfileChange::Details {
+					old: Some(profile.clone()),
+					new: Some(renamed.clone()),
+				},
+			],
+		);
+		let current =
+			ProfileState { profile: Some(renamed), account_ids: vec![account_id()] };
+
+		assert_eq!(
+			profile_at_revision(current.clone(), &revisions, 1),
+			ProfileState { profile: Some(profile.clone()), account_ids: vec![] }
+		);
+		assert_eq!(
+			profile_at_revision(current.clone(), &revisions, 2),
+			ProfileState { profile: Some(profile), account_ids: vec![account_id()] }
+		);
+		assert_eq!(profile_at_revision(current.clone(), &revisions, 3), current);
+	}
+
+	#[test]
+	fn deleted_profiles_can_be_rolled_back() {
+		let profile = Profile::new();
+		let revisions = revisions(
+			&profile.sharing_id,
+			vec![
+				ProfileChange::Details { old: None, new: Some(profile.clone()) },
+				ProfileChange::Accounts { old: vec![], new: vec![account_id()] },
+				ProfileChange::Accounts { old: vec![account_id()], new: vec![] },
+				ProfileChange::Details { old: Some(profile.clone()), new: None },
+			],
+		);
+		let current = ProfileState { profile: None, account_ids: vec![] };
+
+		assert_eq!(
+			profile_at_revision(current.clone(), &revisions, 2),
+			ProfileState { profile: Some(profile), account_ids: vec![account_id()] }
+		);
+		assert_eq!(profile_at_revision(current.clone(), &revisions, 4), current);
+	}
+
+	#[test]
+	fn only_actual_changes_are_revisions() {
+		let profile = Profile::new();
+		assert!(
+			ProfileChange::details(Some(profile.clone()), Some(profile.clone()))
+				.is_none()
+		);
+		assert!(ProfileChange::details(None, Some(profile)).is_some());
+		assert!(
+			ProfileChange::accounts(vec![account_id()], vec![account_id()]).is_none()
+		);
+		assert!(ProfileChange::accounts(vec![], vec![account_id()]).is_some());
+	}
+}
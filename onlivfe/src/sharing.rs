@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{PlatformAccountId, Profile, ProfileId};
+
+/// The version of the profile bundle format that is written, older ones can
+/// still be parsed
+pub const PROFILE_BUNDLE_VERSION: u32 = 1;
+
+/// An error with parsing a [`ProfileBundle`]
+#[derive(Debug)]
+pub enum ProfileBundleError {
+	/// The bundle is from a newer version of onlivfe
+	UnsupportedVersion(u32),
+	/// The bundle is not valid
+	Invalid(String),
+}
+
+impl std::fmt::Display for ProfileBundleError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::UnsupportedVersion(version) => write!(
+				f,
+				"Profile bundle version {version} is newer than the supported \
+				 version {PROFILE_BUNDLE_VERSION}"
+			),
+			Self::Invalid(e) => write!(f, "Invalid profile bundle: {e}"),
+		}
+	}
+}
+
+impl std::error::Error for ProfileBundleError {}
+
+/// A profile along with the accounts that are linked to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedProfile {
+	/// The profile itself
+	#[serde(flatten)]
+	pub profile: Profile,
+	/// The accounts that the profile is linked to
+	#[serde(default)]
+	pub account_ids: Vec<PlatformAccountId>,
+}
+
+/// Just the version, to check it before trying to read the rest
+#[derive(Deserialize)]
+struct ProfileBundleHeader {
+	version: u32,
+}
+
+/// Profiles that are shared between people, telling who is who.
+///
+/// The profiles keep their [`sharing_id`](Profile::sharing_id)s, so that
+/// importing the same profile again merges into the previously imported one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileBundle {
+	version: u32,
+	/// The shared profiles
+	pub profiles: Vec<SharedProfile>,
+}
+
+impl ProfileBundle {
+	/// Creates a bundle of the current version from profiles
+	#[must_use]
+	pub const fn new(profiles: Vec<SharedProfile>) -> Self {
+		Self { version: PROFILE_BUNDLE_VERSION, profiles }
+	}
+
+	/// The version of the format that the bundle was created with
+	#[must_use]
+	pub const fn version(&self) -> u32 { self.version }
+
+	/// Serializes the bundle into text that can be shared
+	///
+	/// # Errors
+	///
+	/// If serializing some of the profiles failed
+	pub fn serialize(&self) -> Result<String, ProfileBundleError> {
+		serde_json::to_string(self)
+			.map_err(|e| ProfileBundleError::Invalid(e.to_string()))
+	}
+
+	/// Parses a bundle from [`serialize`](Self::serialize)d text.
+	///
+	/// Multiple copies of the same profile are merged into one.
+	///
+	/// # Errors
+	///
+	/// If the bundle is from a newer version or not valid
+	pub fn parse(text: &str) -> Result<Self, ProfileBundleError> {
+		let ProfileBundleHeader { version } = serde_json::from_str(text)
+			.map_err(|e| ProfileBundleError::Invalid(e.to_string()))?;
+		if version > PROFILE_BUNDLE_VERSION {
+			return Err(ProfileBundleError::UnsupportedVersion(version));
+		}
+		let bundle: Self = serde_json::from_str(text)
+			.map_err(|e| ProfileBundleError::Invalid(e.to_string()))?;
+
+		let mut profiles: Vec<SharedProfile> =
+			Vec::with_capacity(bundle.profiles.len());
+		let mut indexes: HashMap<ProfileId, usize> = HashMap::new();
+		for shared in bundle.profiles {
+			let Some(&index) = indexes.get(&shared.profile.sharing_id) else {
+				indexes.insert(shared.profile.sharing_id.clone(), profiles.len());
+				profiles.push(shared);
+				continue;
+			};
+			let existing = &mut profiles[index];
+			existing.profile.fill_missing_from(&shared.profile);
+			for account_id in shared.account_ids {
+				if !existing.account_ids.contains(&account_id) {
+					existing.account_ids.push(account_id);
+				}
+			}
+		}
+
+		Ok(Self { version: bundle.version, profiles })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::PlatformType;
+
+	fn account_id(id: &str) -> PlatformAccountId {
+		serde_json::from_value(serde_json::json!({
+			"platform": PlatformType::Resonite,
+			"id": id,
+		}))
+		.expect("the platform account ID to be valid")
+	}
+
+	fn shared(nick: &str, account_ids: Vec<PlatformAccountId>) -> SharedProfile {
+		SharedProfile {
+			profile: Profile {
+				nick: Some(nick.to_owned()),
+				tags: [nick.to_owned()].into(),
+				..Profile::new()
+			},
+			account_ids,
+		}
+	}
+
+	#[test]
+	fn parsing_a_serialized_bundle_round_trips() {
+		let bundle = ProfileBundle::new(vec![
+			shared("First", vec![account_id("U-first")]),
+			shared("Second", vec![]),
+		]);
+
+		let text = bundle.serialize().expect("serializing to succeed");
+		assert_eq!(
+			ProfileBundle::parse(&text).expect("parsing to succeed"),
+			bundle
+		);
+	}
+
+	#[test]
+	fn malformed_bundles_are_rejected() {
+		for text in [
+			"",
+			"not a bundle",
+			r#"{"profiles": []}"#,
+			r#"{"version": 1}"#,
+			r#"{"version": 1, "profiles": [{"sharingId": "not a UUID"}]}"#,
+		] {
+			assert!(
+				matches!(
+					ProfileBundle::parse(text),
+					Err(ProfileBundleError::Invalid(_))
+				),
+				"Parsing {text:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn newer_versions_are_rejected() {
+		let newer = PROFILE_BUNDLE_VERSION + 1;
+		let text = format!(r#"{{"version": {newer}, "somethingNew": true}}"#);
+
+		assert!(matches!(
+			ProfileBundle::parse(&text),
+			Err(ProfileBundleError::UnsupportedVersion(version)) if version == newer
+		));
+	}
+
+	#[test]
+	fn copies_of_the_same_profile_are_merged() {
+		let first = shared("First", vec![account_id("U-first")]);
+		let mut copy =
+			shared("Copy", vec![account_id("U-first"), account_id("U-copy")]);
+		copy.profile.sharing_id = first.profile.sharing_id.clone();
+		copy.profile.notes = Some("Only in the copy".to_owned());
+		let text = ProfileBundle::new(vec![first.clone(), copy])
+			.serialize()
+			.expect("serializing to succeed");
+
+		let bundle = ProfileBundle::parse(&text).expect("parsing to succeed");
+		let [merged] = bundle.profiles.as_slice() else {
+			panic!("Expected one profile, got {:?}", bundle.profiles);
+		};
+		assert_eq!(merged.profile.nick, first.profile.nick);
+		assert_eq!(merged.profile.notes.as_deref(), Some("Only in the copy"));
+		assert_eq!(
+			merged.profile.tags,
+			["Copy".to_owned(), "First".to_owned()].into()
+		);
+		assert_eq!(
+			merged.account_ids,
+			vec![account_id("U-first"), account_id("U-copy")]
+		);
+	}
+}
@@ -1,9 +1,30 @@
 //! The storage interface that core will use
 
+mod batch;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub use batch::{BatchUpdated, StoreBatch};
+mod changes;
+pub use changes::{
+	ChangeKind,
+	ChangeNotifier,
+	ChangeStream,
+	EntityId,
+	StoreChange,
+};
+mod layered;
+pub use layered::{LayeredError, LayeredStore, WritePolicy};
+pub mod search;
+pub use search::SearchHit;
+
 use crate::{
+	AccountLink,
 	Authentication,
 	Avatar,
 	AvatarId,
+	Circle,
+	CircleId,
+	CircleMember,
 	Instance,
 	InstanceId,
 	PlatformAccount,
@@ -11,6 +32,9 @@ use crate::{
 	PlatformFriend,
 	Profile,
 	ProfileId,
+	ProfilePicture,
+	ProfileRevision,
+	TrashedProfile,
 	World,
 	WorldId,
 };
@@ -21,6 +45,105 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	/// The error type for operations using this storage backend
 	type Err: std::error::Error;
 
+	/// Subscribes to the changes that are made to the store after this,
+	/// or `None` if the store doesn't support that.
+	///
+	/// The changes are sent after the writes have succeeded.
+	fn watch(&self) -> Option<ChangeStream> { None }
+
+	/// Updates or stores everything in the batch, so that either all of it or
+	/// none of it is applied, returning the IDs of the updated entries.
+	///
+	/// The default implementation stores the entries one by one, so it's not
+	/// atomic, and should be overridden by storage backends that can be.
+	/// Those that update the mappings, circle members or profiles through this
+	/// need to override it.
+	async fn apply_batch(
+		&self, batch: StoreBatch,
+	) -> Result<BatchUpdated, Self::Err> {
+		/// Updates the entries one by one, collecting the IDs of the ones that
+		/// existed before the batch
+		macro_rules! update_each {
+			($entries:expr, $update:ident, $id:expr) => {{
+				let mut seen = std::collections::HashSet::new();
+				let mut ids = vec![];
+				for entry in $entries {
+					let id = $id(&entry);
+					// Later entries with the same ID replace the earlier ones
+					let first = seen.insert(id.clone());
+					if self.$update(entry).await? && first {
+						ids.push(id);
+					}
+				}
+				ids
+			}};
+		}
+
+		let updated = BatchUpdated {
+			accounts: update_each!(
+				batch.accounts,
+				update_account,
+				PlatformAccount::id
+			),
+			friends: update_each!(batch.friends, update_friend, PlatformFriend::id),
+			instances: update_each!(batch.instances, update_instance, Instance::id),
+			worlds: update_each!(batch.worlds, update_world, World::id),
+			avatars: update_each!(batch.avatars, update_avatar, Avatar::id),
+			profiles: update_each!(batch.profiles, update_profile, |p: &Profile| {
+				p.sharing_id.clone()
+			}),
+			authentications: update_each!(
+				batch.authentications,
+				update_authentication,
+				Authentication::id
+			),
+		};
+		for (profile_id, account_ids) in batch.profile_account_ids {
+			self.update_profile_account_ids(profile_id, account_ids).await?;
+		}
+		for (circle_id, members) in batch.circle_members {
+			self.update_circle_members(circle_id, members).await?;
+		}
+		for profile_id in batch.deleted_profiles {
+			self.delete_profile(profile_id).await?;
+		}
+
+		Ok(updated)
+	}
+
+	/// Searches the profiles' nicks & notes and the display names of the
+	/// friends & accounts, returning at most `max` hits from the best match to
+	/// the worst.
+	///
+	/// The default implementation goes through all of them, so storage backends
+	/// with an index should override it.
+	async fn search(
+		&self, query: &str, max: usize,
+	) -> Result<Vec<SearchHit>, Self::Err> {
+		let terms = search::terms(query);
+		if terms.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let profiles = self.profiles(usize::MAX).await?;
+		let friends = self.friends(usize::MAX).await?;
+		let accounts = self.accounts(usize::MAX).await?;
+		let hits = profiles
+			.iter()
+			.filter_map(|profile| SearchHit::profile(&terms, profile))
+			.chain(
+				friends.iter().filter_map(|friend| SearchHit::friend(&terms, friend)),
+			)
+			.chain(
+				accounts
+					.iter()
+					.filter_map(|account| SearchHit::account(&terms, account)),
+			)
+			.collect();
+
+		Ok(search::rank(hits, max))
+	}
+
 	/// Retrieves a list of account ids
 	async fn account_ids(
 		&self, max: usize,
@@ -33,7 +156,7 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 
 		let account_ids = self.account_ids(max).await?;
 
-		let accounts = stream::iter(account_ids.into_iter())
+		let accounts = stream::iter(account_ids)
 			.then(|account_id| async move { self.account(account_id).await })
 			.try_collect()
 			.await?;
@@ -62,16 +185,9 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	async fn update_accounts(
 		&self, accounts: Vec<PlatformAccount>,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
-		let mut ids = vec![];
-		// TODO: Proper async loop
-		for account in accounts {
-			let id = account.id();
-			if self.update_account(account).await? {
-				ids.push(id);
-			}
-		}
+		let batch = StoreBatch { accounts, ..StoreBatch::default() };
 
-		Ok(ids)
+		Ok(self.apply_batch(batch).await?.accounts)
 	}
 
 	/// Retrieves the profiles for an account
@@ -82,7 +198,7 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 
 		let profile_ids = self.account_profile_ids(account_id).await?;
 
-		let profiles = stream::iter(profile_ids.into_iter())
+		let profiles = stream::iter(profile_ids)
 			.then(|profile_id| async move { self.profile(profile_id).await })
 			.try_collect()
 			.await?;
@@ -100,7 +216,7 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 
 		let instance_ids = self.instance_ids(max).await?;
 
-		let instances = stream::iter(instance_ids.into_iter())
+		let instances = stream::iter(instance_ids)
 			.then(|instance_id| async move { self.instance(instance_id).await })
 			.try_collect()
 			.await?;
@@ -121,16 +237,9 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	async fn update_instances(
 		&self, instances: Vec<Instance>,
 	) -> Result<Vec<InstanceId>, Self::Err> {
-		let mut ids = vec![];
-		// TODO: Proper async loop
-		for instance in instances {
-			let id = instance.id();
-			if self.update_instance(instance).await? {
-				ids.push(id);
-			}
-		}
+		let batch = StoreBatch { instances, ..StoreBatch::default() };
 
-		Ok(ids)
+		Ok(self.apply_batch(batch).await?.instances)
 	}
 
 	/// Retrieves a list of world ids
@@ -141,7 +250,7 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 
 		let world_ids = self.world_ids(max).await?;
 
-		let worlds = stream::iter(world_ids.into_iter())
+		let worlds = stream::iter(world_ids)
 			.then(|world_id| async move { self.world(world_id).await })
 			.try_collect()
 			.await?;
@@ -158,16 +267,9 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	async fn update_worlds(
 		&self, worlds: Vec<World>,
 	) -> Result<Vec<WorldId>, Self::Err> {
-		let mut ids = vec![];
-		// TODO: Proper async loop
-		for world in worlds {
-			let id = world.id();
-			if self.update_world(world).await? {
-				ids.push(id);
-			}
-		}
+		let batch = StoreBatch { worlds, ..StoreBatch::default() };
 
-		Ok(ids)
+		Ok(self.apply_batch(batch).await?.worlds)
 	}
 
 	/// Retrieves a list of avatar ids
@@ -178,7 +280,7 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 
 		let avatar_ids = self.avatar_ids(max).await?;
 
-		let avatars = stream::iter(avatar_ids.into_iter())
+		let avatars = stream::iter(avatar_ids)
 			.then(|avatar_id| async move { self.avatar(avatar_id).await })
 			.try_collect()
 			.await?;
@@ -195,16 +297,9 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	async fn update_avatars(
 		&self, avatars: Vec<Avatar>,
 	) -> Result<Vec<AvatarId>, Self::Err> {
-		let mut ids = vec![];
-		// TODO: Proper async loop
-		for avatar in avatars {
-			let id = avatar.id();
-			if self.update_avatar(avatar).await? {
-				ids.push(id);
-			}
-		}
+		let batch = StoreBatch { avatars, ..StoreBatch::default() };
 
-		Ok(ids)
+		Ok(self.apply_batch(batch).await?.avatars)
 	}
 
 	/// Retrieves a list of friend ids
@@ -219,7 +314,7 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 
 		let friend_ids = self.friend_ids(max).await?;
 
-		let friends = stream::iter(friend_ids.into_iter())
+		let friends = stream::iter(friend_ids)
 			.then(|friend_id| async move { self.friend(friend_id).await })
 			.try_collect()
 			.await?;
@@ -240,18 +335,42 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	async fn update_friends(
 		&self, friends: Vec<PlatformFriend>,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
-		let mut ids = vec![];
-		// TODO: Proper async loop
-		for friend in friends {
-			let id = friend.id();
-			if self.update_friend(friend).await? {
-				ids.push(id);
-			}
-		}
+		let batch = StoreBatch { friends, ..StoreBatch::default() };
 
-		Ok(ids)
+		Ok(self.apply_batch(batch).await?.friends)
 	}
 
+	/// Retrieves a list of profile ids
+	async fn profile_ids(&self, max: usize) -> Result<Vec<ProfileId>, Self::Err>;
+	/// Retrieves a list of profiles
+	async fn profiles(&self, max: usize) -> Result<Vec<Profile>, Self::Err> {
+		use futures::prelude::*;
+
+		let profile_ids = self.profile_ids(max).await?;
+
+		let profiles = stream::iter(profile_ids)
+			.then(|profile_id| async move { self.profile(profile_id).await })
+			.try_collect()
+			.await?;
+
+		Ok(profiles)
+	}
+	/// Retrieves a list of ids of the profiles that have a tag, ignoring ASCII
+	/// case
+	async fn tagged_profile_ids(
+		&self, tag: &str, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		Ok(
+			self
+				.profiles(usize::MAX)
+				.await?
+				.into_iter()
+				.filter(|profile| profile.has_tag(tag))
+				.map(|profile| profile.sharing_id)
+				.take(max)
+				.collect(),
+		)
+	}
 	/// Retrieves the details for a profile
 	async fn profile(&self, profile_id: ProfileId) -> Result<Profile, Self::Err>;
 	/// Retrieves the account IDs for a profile
@@ -270,19 +389,137 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	async fn update_profiles(
 		&self, profiles: Vec<Profile>,
 	) -> Result<Vec<ProfileId>, Self::Err> {
-		let mut ids = vec![];
-		// TODO: Proper async loop
-		for profile in profiles {
-			let id = profile.sharing_id.clone();
-			if self.update_profile(profile).await? {
-				ids.push(id);
+		let batch = StoreBatch { profiles, ..StoreBatch::default() };
+
+		Ok(self.apply_batch(batch).await?.profiles)
+	}
+	/// Deletes a profile for good, along with its account mappings and circle
+	/// memberships.
+	///
+	/// A copy of it in the trash is left as is.
+	async fn delete_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err>;
+	/// Moves a profile to the trash, from where it can be restored with
+	/// [`restore_profile`](Self::restore_profile)
+	async fn trash_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<TrashedProfile, Self::Err> {
+		let profile = self.profile(profile_id.clone()).await?;
+		let account_ids = self.profile_account_ids(profile_id.clone()).await?;
+		let circle_ids =
+			self.member_circle_ids(CircleMember::Profile(profile_id.clone())).await?;
+		let trashed = TrashedProfile {
+			profile,
+			account_ids,
+			circle_ids,
+			trashed_at: time::OffsetDateTime::now_utc(),
+		};
+		// Stored first, so that the profile isn't lost if deleting it fails
+		self.update_trashed_profile(trashed.clone()).await?;
+		self.delete_profile(profile_id).await?;
+
+		Ok(trashed)
+	}
+	/// Restores a profile from the trash, along with its account mappings and
+	/// circle memberships.
+	///
+	/// The profile is added to the end of the circles that still exist.
+	async fn restore_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<Profile, Self::Err> {
+		let TrashedProfile { profile, account_ids, circle_ids, .. } =
+			self.trashed_profile(profile_id.clone()).await?;
+		self.update_profile(profile.clone()).await?;
+
+		// The accounts might've been linked again while it was in the trash
+		let mut linked = self.profile_account_ids(profile_id.clone()).await?;
+		for account_id in account_ids {
+			if !linked.contains(&account_id) {
+				linked.push(account_id);
+			}
+		}
+		self.update_profile_account_ids(profile_id.clone(), linked).await?;
+
+		let existing_circle_ids = self.circle_ids(usize::MAX).await?;
+		for circle_id in circle_ids {
+			if !existing_circle_ids.contains(&circle_id) {
+				continue;
 			}
+			let mut members = self.circle_members(circle_id.clone()).await?;
+			members.push(CircleMember::Profile(profile_id.clone()));
+			self.update_circle_members(circle_id, members).await?;
 		}
 
-		Ok(ids)
+		self.delete_trashed_profile(profile_id).await?;
+		Ok(profile)
 	}
-	/// Deletes a profile
-	async fn delete_profile(
+	/// Retrieves a list of the IDs of the profiles in the trash
+	async fn trashed_profile_ids(
+		&self, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err>;
+	/// Retrieves a list of the profiles in the trash
+	async fn trashed_profiles(
+		&self, max: usize,
+	) -> Result<Vec<TrashedProfile>, Self::Err> {
+		use futures::prelude::*;
+
+		let profile_ids = self.trashed_profile_ids(max).await?;
+
+		let profiles = stream::iter(profile_ids)
+			.then(|profile_id| async move { self.trashed_profile(profile_id).await })
+			.try_collect()
+			.await?;
+
+		Ok(profiles)
+	}
+	/// Retrieves a profile in the trash
+	async fn trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<TrashedProfile, Self::Err>;
+	/// Update or store a profile in the trash,
+	/// returning if an existing one was updated
+	async fn update_trashed_profile(
+		&self, trashed: TrashedProfile,
+	) -> Result<bool, Self::Err>;
+	/// Removes a profile from the trash for good
+	async fn delete_trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err>;
+	/// Retrieves the recorded changes to a profile and its account mappings,
+	/// oldest first.
+	///
+	/// Changes are recorded by the store whenever a profile or its mappings are
+	/// updated or deleted, and are kept after the profile is deleted until
+	/// they're removed with
+	/// [`delete_profile_revisions`](Self::delete_profile_revisions).
+	/// Stores may only keep some of the latest revisions.
+	async fn profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<Vec<ProfileRevision>, Self::Err>;
+	/// Replaces the recorded changes to a profile, such as with ones restored
+	/// from a backup
+	async fn update_profile_revisions(
+		&self, profile_id: ProfileId, revisions: Vec<ProfileRevision>,
+	) -> Result<(), Self::Err>;
+	/// Removes the recorded changes to a profile for good
+	async fn delete_profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err>;
+	/// Retrieves the locally stored picture of a profile
+	async fn profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<ProfilePicture, Self::Err>;
+	/// Update or store the picture of a profile,
+	/// returning if an existing one was replaced.
+	///
+	/// Pictures are kept when their profile is deleted, so that the ones of
+	/// trashed profiles are there when they're restored.
+	async fn update_profile_picture(
+		&self, picture: ProfilePicture,
+	) -> Result<bool, Self::Err>;
+	/// Removes the picture of a profile
+	async fn delete_profile_picture(
 		&self, profile_id: ProfileId,
 	) -> Result<(), Self::Err>;
 	/// Retrieves the accounts for a profile
@@ -293,7 +530,7 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 
 		let account_ids = self.profile_account_ids(profile_id).await?;
 
-		let accounts = stream::iter(account_ids.into_iter())
+		let accounts = stream::iter(account_ids)
 			.then(|account_id| async move { self.account(account_id).await })
 			.try_collect()
 			.await?;
@@ -301,6 +538,47 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 		Ok(accounts)
 	}
 
+	/// Retrieves a list of circle ids
+	async fn circle_ids(&self, max: usize) -> Result<Vec<CircleId>, Self::Err>;
+	/// Retrieves a list of circles
+	async fn circles(&self, max: usize) -> Result<Vec<Circle>, Self::Err> {
+		use futures::prelude::*;
+
+		let circle_ids = self.circle_ids(max).await?;
+
+		let circles = stream::iter(circle_ids)
+			.then(|circle_id| async move { self.circle(circle_id).await })
+			.try_collect()
+			.await?;
+
+		Ok(circles)
+	}
+	/// Retrieves the details for a circle
+	async fn circle(&self, circle_id: CircleId) -> Result<Circle, Self::Err>;
+	/// Update or store a new circle,
+	/// returning if an existing one was updated
+	async fn update_circle(&self, circle: Circle) -> Result<bool, Self::Err>;
+	/// Deletes a circle along with its members
+	async fn delete_circle(&self, circle_id: CircleId) -> Result<(), Self::Err>;
+	/// Retrieves the members of a circle, in their order within it
+	async fn circle_members(
+		&self, circle_id: CircleId,
+	) -> Result<Vec<CircleMember>, Self::Err>;
+	/// Replaces the members of a circle, keeping their order
+	async fn update_circle_members(
+		&self, circle_id: CircleId, members: Vec<CircleMember>,
+	) -> Result<(), Self::Err>;
+	/// Retrieves the IDs of the circles that something is a member of
+	async fn member_circle_ids(
+		&self, member: CircleMember,
+	) -> Result<Vec<CircleId>, Self::Err>;
+
+	/// Retrieves the account links that were rejected from being suggested
+	async fn rejected_links(&self) -> Result<Vec<AccountLink>, Self::Err>;
+	/// Stores a rejected account link so that it isn't suggested again,
+	/// returning if it was rejected already
+	async fn reject_link(&self, link: AccountLink) -> Result<bool, Self::Err>;
+
 	/// Retrieves platform authentications
 	async fn authentications(&self) -> Result<Vec<Authentication>, Self::Err>;
 	/// Update or store a platform's authentication,
@@ -318,15 +596,8 @@ pub trait OnlivfeStore: Send + Sync + std::fmt::Debug {
 	async fn update_authentications(
 		&self, authentications: Vec<Authentication>,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
-		let mut ids = vec![];
-		// TODO: Proper async loop
-		for authentication in authentications {
-			let id = authentication.id();
-			if self.update_authentication(authentication).await? {
-				ids.push(id);
-			}
-		}
+		let batch = StoreBatch { authentications, ..StoreBatch::default() };
 
-		Ok(ids)
+		Ok(self.apply_batch(batch).await?.authentications)
 	}
 }
@@ -0,0 +1,131 @@
+use crate::{
+	Authentication,
+	Avatar,
+	AvatarId,
+	CircleId,
+	CircleMember,
+	Instance,
+	InstanceId,
+	PlatformAccount,
+	PlatformAccountId,
+	PlatformFriend,
+	Profile,
+	ProfileId,
+	World,
+	WorldId,
+};
+
+/// Updates that are applied all at once, see
+/// [`OnlivfeStore::apply_batch`](super::OnlivfeStore::apply_batch)
+///
+/// Usually the results of a refresh from a platform, so that a failure
+/// halfway through doesn't leave the store partially updated.
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct StoreBatch {
+	/// Platform accounts to update or store
+	pub accounts: Vec<PlatformAccount>,
+	/// Friends to update or store
+	pub friends: Vec<PlatformFriend>,
+	/// Instances to update or store
+	pub instances: Vec<Instance>,
+	/// Worlds to update or store
+	pub worlds: Vec<World>,
+	/// Avatars to update or store
+	pub avatars: Vec<Avatar>,
+	/// Profiles to update or store
+	pub profiles: Vec<Profile>,
+	/// Authentications to update or store
+	pub authentications: Vec<Authentication>,
+	/// Accounts to link to profiles, replacing the ones linked to them before
+	pub profile_account_ids: Vec<(ProfileId, Vec<PlatformAccountId>)>,
+	/// Members to set for circles, replacing their previous members
+	pub circle_members: Vec<(CircleId, Vec<CircleMember>)>,
+	/// Profiles to delete along with their mappings and circle memberships,
+	/// after everything else in the batch has been applied
+	pub deleted_profiles: Vec<ProfileId>,
+}
+
+impl StoreBatch {
+	/// Creates an empty batch
+	pub fn new() -> Self { Self::default() }
+
+	/// If there's nothing to update
+	#[must_use]
+	pub const fn is_empty(&self) -> bool {
+		self.accounts.is_empty()
+			&& self.friends.is_empty()
+			&& self.instances.is_empty()
+			&& self.worlds.is_empty()
+			&& self.avatars.is_empty()
+			&& self.profiles.is_empty()
+			&& self.authentications.is_empty()
+			&& self.profile_account_ids.is_empty()
+			&& self.circle_members.is_empty()
+			&& self.deleted_profiles.is_empty()
+	}
+
+	/// The amount of entries to update
+	#[must_use]
+	pub const fn len(&self) -> usize {
+		self.accounts.len()
+			+ self.friends.len()
+			+ self.instances.len()
+			+ self.worlds.len()
+			+ self.avatars.len()
+			+ self.profiles.len()
+			+ self.authentications.len()
+			+ self.profile_account_ids.len()
+			+ self.circle_members.len()
+			+ self.deleted_profiles.len()
+	}
+
+	/// Splits the platform data, such as friends, from the rest of the batch,
+	/// which is about the user's own data
+	pub fn split_platform_data(self) -> (Self, Self) {
+		let Self {
+			accounts,
+			friends,
+			instances,
+			worlds,
+			avatars,
+			profiles,
+			authentications,
+			profile_account_ids,
+			circle_members,
+			deleted_profiles,
+		} = self;
+
+		(
+			Self { accounts, friends, instances, worlds, avatars, ..Self::default() },
+			Self {
+				profiles,
+				authentications,
+				profile_account_ids,
+				circle_members,
+				deleted_profiles,
+				..Self::default()
+			},
+		)
+	}
+}
+
+/// The IDs of the entries of a [`StoreBatch`] that replaced ones that existed
+/// before it, each only once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchUpdated {
+	/// Updated platform accounts
+	pub accounts: Vec<PlatformAccountId>,
+	/// Updated friends
+	pub friends: Vec<PlatformAccountId>,
+	/// Updated instances
+	pub instances: Vec<InstanceId>,
+	/// Updated worlds
+	pub worlds: Vec<WorldId>,
+	/// Updated avatars
+	pub avatars: Vec<AvatarId>,
+	/// Updated profiles
+	pub profiles: Vec<ProfileId>,
+	/// Updated authentications
+	pub authentications: Vec<PlatformAccountId>,
+}
@@ -0,0 +1,265 @@
+use std::{
+	collections::HashSet,
+	sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use futures::channel::mpsc;
+use serde::{Deserialize, Serialize};
+
+use super::{BatchUpdated, StoreBatch};
+use crate::{
+	AccountLink,
+	AvatarId,
+	CircleId,
+	InstanceId,
+	PlatformAccountId,
+	ProfileId,
+	WorldId,
+};
+
+/// The ID of something that's stored, along with what kind of a thing it is
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "camelCase")]
+pub enum EntityId {
+	/// A platform account
+	Account(PlatformAccountId),
+	/// A friend
+	Friend(PlatformAccountId),
+	/// An instance
+	Instance(InstanceId),
+	/// A world
+	World(WorldId),
+	/// An avatar
+	Avatar(AvatarId),
+	/// A profile
+	Profile(ProfileId),
+	/// The accounts that are linked to a profile
+	ProfileAccounts(ProfileId),
+	/// The profiles that an account is linked to
+	AccountProfiles(PlatformAccountId),
+	/// A platform authentication
+	Authentication(PlatformAccountId),
+	/// A circle
+	Circle(CircleId),
+	/// The members of a circle
+	CircleMembers(CircleId),
+	/// An account link that was rejected from being suggested
+	RejectedLink(AccountLink),
+	/// A profile in the trash
+	TrashedProfile(ProfileId),
+	/// The locally stored picture of a profile
+	ProfilePicture(ProfileId),
+}
+
+/// How something that's stored changed
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+	/// It didn't exist before
+	Created,
+	/// It replaced an existing one
+	Updated,
+	/// It was removed
+	Deleted,
+}
+
+/// A change to the data in a store, see
+/// [`OnlivfeStore::watch`](super::OnlivfeStore::watch)
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreChange {
+	/// What changed
+	pub entity: EntityId,
+	/// How it changed
+	pub kind: ChangeKind,
+}
+
+impl StoreChange {
+	/// A change of something that was created or updated
+	#[must_use]
+	pub const fn stored(entity: EntityId, existed: bool) -> Self {
+		let kind = if existed { ChangeKind::Updated } else { ChangeKind::Created };
+		Self { entity, kind }
+	}
+
+	/// The changes of replacing the accounts that are linked to a profile
+	#[must_use]
+	pub fn profile_accounts_replaced(
+		profile_id: &ProfileId, previous: &[PlatformAccountId],
+		current: &[PlatformAccountId],
+	) -> Vec<Self> {
+		let affected: HashSet<&PlatformAccountId> =
+			previous.iter().chain(current).collect();
+		let mut changes =
+			vec![Self::stored(EntityId::ProfileAccounts(profile_id.clone()), true)];
+		changes.extend(affected.into_iter().map(|account_id| {
+			Self::stored(EntityId::AccountProfiles(account_id.clone()), true)
+		}));
+
+		changes
+	}
+
+	/// The changes of replacing the profiles that an account is linked to
+	#[must_use]
+	pub fn account_profiles_replaced(
+		account_id: &PlatformAccountId, previous: &[ProfileId],
+		current: &[ProfileId],
+	) -> Vec<Self> {
+		let affected: HashSet<&ProfileId> =
+			previous.iter().chain(current).collect();
+		let mut changes =
+			vec![Self::stored(EntityId::AccountProfiles(account_id.clone()), true)];
+		changes.extend(affected.into_iter().map(|profile_id| {
+			Self::stored(EntityId::ProfileAccounts(profile_id.clone()), true)
+		}));
+
+		changes
+	}
+
+	/// The changes of deleting a circle along with its members
+	#[must_use]
+	pub fn circle_deleted(circle_id: &CircleId) -> Vec<Self> {
+		vec![
+			Self::stored(EntityId::CircleMembers(circle_id.clone()), true),
+			Self {
+				entity: EntityId::Circle(circle_id.clone()),
+				kind: ChangeKind::Deleted,
+			},
+		]
+	}
+
+	/// The changes of deleting a profile, given the accounts it was linked to
+	/// and the circles it was a member of
+	#[must_use]
+	pub fn profile_deleted(
+		profile_id: &ProfileId, account_ids: &[PlatformAccountId],
+		circle_ids: &[CircleId],
+	) -> Vec<Self> {
+		let mut changes =
+			Self::profile_accounts_replaced(profile_id, account_ids, &[]);
+		changes.extend(circle_ids.iter().map(|circle_id| {
+			Self::stored(EntityId::CircleMembers(circle_id.clone()), true)
+		}));
+		changes.push(Self {
+			entity: EntityId::Profile(profile_id.clone()),
+			kind: ChangeKind::Deleted,
+		});
+
+		changes
+	}
+}
+
+/// The changes made to a store, in the order they were made in
+pub type ChangeStream = mpsc::UnboundedReceiver<StoreChange>;
+
+impl StoreBatch {
+	/// The IDs of the entries that the batch updates or stores
+	#[must_use]
+	pub fn entity_ids(&self) -> Vec<EntityId> {
+		let mut ids = Vec::with_capacity(self.len());
+		ids.extend(self.accounts.iter().map(|v| EntityId::Account(v.id())));
+		ids.extend(self.friends.iter().map(|v| EntityId::Friend(v.id())));
+		ids.extend(self.instances.iter().map(|v| EntityId::Instance(v.id())));
+		ids.extend(self.worlds.iter().map(|v| EntityId::World(v.id())));
+		ids.extend(self.avatars.iter().map(|v| EntityId::Avatar(v.id())));
+		ids.extend(
+			self.profiles.iter().map(|v| EntityId::Profile(v.sharing_id.clone())),
+		);
+		ids.extend(
+			self.authentications.iter().map(|v| EntityId::Authentication(v.id())),
+		);
+
+		ids
+	}
+}
+
+impl BatchUpdated {
+	/// The IDs of everything that was updated
+	#[must_use]
+	pub fn entity_ids(&self) -> HashSet<EntityId> {
+		let mut ids = HashSet::new();
+		ids.extend(self.accounts.iter().cloned().map(EntityId::Account));
+		ids.extend(self.friends.iter().cloned().map(EntityId::Friend));
+		ids.extend(self.instances.iter().cloned().map(EntityId::Instance));
+		ids.extend(self.worlds.iter().cloned().map(EntityId::World));
+		ids.extend(self.avatars.iter().cloned().map(EntityId::Avatar));
+		ids.extend(self.profiles.iter().cloned().map(EntityId::Profile));
+		ids.extend(
+			self.authentications.iter().cloned().map(EntityId::Authentication),
+		);
+
+		ids
+	}
+}
+
+/// Sends the changes of a store to everyone watching it,
+/// for storage backends to use for implementing
+/// [`OnlivfeStore::watch`](super::OnlivfeStore::watch)
+#[derive(Debug, Default)]
+pub struct ChangeNotifier {
+	watchers: Mutex<Vec<mpsc::UnboundedSender<StoreChange>>>,
+}
+
+impl ChangeNotifier {
+	/// Creates a notifier without any watchers
+	#[must_use]
+	pub fn new() -> Self { Self::default() }
+
+	fn lock_watchers(
+		&self,
+	) -> MutexGuard<'_, Vec<mpsc::UnboundedSender<StoreChange>>> {
+		// Sending never leaves the list in an invalid state
+		self.watchers.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Adds a watcher, which gets the changes notified after this
+	pub fn watch(&self) -> ChangeStream {
+		let (sender, receiver) = mpsc::unbounded();
+		self.lock_watchers().push(sender);
+		receiver
+	}
+
+	/// If anyone is watching, for skipping the work of collecting the changes
+	#[must_use]
+	pub fn is_watched(&self) -> bool {
+		let mut watchers = self.lock_watchers();
+		watchers.retain(|watcher| !watcher.is_closed());
+		!watchers.is_empty()
+	}
+
+	/// Sends the changes to the watchers, forgetting the ones that have stopped
+	/// watching
+	pub fn notify(&self, changes: impl IntoIterator<Item = StoreChange>) {
+		let mut watchers = self.lock_watchers();
+		if watchers.is_empty() {
+			return;
+		}
+		for change in changes {
+			watchers.retain(|watcher| watcher.unbounded_send(change.clone()).is_ok());
+		}
+	}
+
+	/// Sends the change of something that was created or updated
+	pub fn notify_stored(&self, entity: EntityId, existed: bool) {
+		self.notify([StoreChange::stored(entity, existed)]);
+	}
+
+	/// Sends the changes of an applied batch, given the IDs of everything in it
+	pub fn notify_batch(
+		&self, entity_ids: Vec<EntityId>, updated: &BatchUpdated,
+	) {
+		if entity_ids.is_empty() {
+			return;
+		}
+		let updated = updated.entity_ids();
+		// Entries that are in the batch more than once are only notified once
+		let mut seen = HashSet::new();
+		self.notify(
+			entity_ids.into_iter().filter(|entity| seen.insert(entity.clone())).map(
+				|entity| {
+					let existed = updated.contains(&entity);
+					StoreChange::stored(entity, existed)
+				},
+			),
+		);
+	}
+}
@@ -0,0 +1,1426 @@
+//! A test suite for checking that an [`OnlivfeStore`] implementation behaves
+//! like the others do
+//!
+//! Storage backends can run it from their tests, with the `conformance`
+//! feature enabled:
+//!
+//! ```ignore
+//! use onlivfe::storage::conformance::{self, Samples};
+//!
+//! #[tokio::test]
+//! async fn conformance() {
+//! 	let store = MyStore::new();
+//! 	conformance::run(&store, &Samples::builtin()).await;
+//! }
+//! ```
+//!
+//! Platform data can't be made up without the platforms' responses, so its
+//! round-trips are checked with the [`Samples`] that the caller gives.
+//! The checks don't assume that the store is empty, and leave their data
+//! behind.
+
+use std::{collections::HashSet, fmt::Debug, hash::Hash};
+
+use time::Month;
+
+use super::{
+	ChangeKind,
+	ChangeStream,
+	EntityId,
+	OnlivfeStore,
+	StoreBatch,
+	StoreChange,
+};
+use crate::{
+	AccountLink,
+	Authentication,
+	Avatar,
+	Birthday,
+	Circle,
+	CircleMember,
+	Color,
+	Instance,
+	PlatformAccount,
+	PlatformAccountId,
+	PlatformDataAndMetadata,
+	PlatformFriend,
+	PlatformType,
+	Profile,
+	ProfileChange,
+	ProfileId,
+	ProfilePicture,
+	World,
+};
+
+/// The kinds of platform data that samples can be given for
+const SAMPLE_KINDS: [&str; 6] =
+	["account", "friend", "instance", "world", "avatar", "authentication"];
+
+/// Platform data to check the round-trips with, ideally with entries for all
+/// of the platforms
+#[derive(Debug, Clone, Default)]
+pub struct Samples {
+	/// Platform accounts to store
+	pub accounts: Vec<PlatformAccount>,
+	/// Friends to store
+	pub friends: Vec<PlatformFriend>,
+	/// Instances to store
+	pub instances: Vec<Instance>,
+	/// Worlds to store
+	pub worlds: Vec<World>,
+	/// Avatars to store
+	pub avatars: Vec<Avatar>,
+	/// Authentications to store and remove
+	pub authentications: Vec<Authentication>,
+}
+
+/// The platform responses that the built-in samples of a platform are made
+/// from, see the JSON files next to this module
+#[derive(serde::Deserialize)]
+struct PlatformSamples<Account, Friend, Instance, World, Avatar> {
+	account: Account,
+	friend: Friend,
+	instance: Instance,
+	world: World,
+	avatar: Avatar,
+}
+
+impl<Account, Friend, Instance, World, Avatar>
+	PlatformSamples<Account, Friend, Instance, World, Avatar>
+where
+	Self: serde::de::DeserializeOwned,
+{
+	/// Parses the samples of a platform
+	fn parse(platform: PlatformType, json: &str) -> Self {
+		serde_json::from_str(json).unwrap_or_else(|e| {
+			panic!("the built-in {platform:?} samples to be valid: {e}")
+		})
+	}
+}
+
+/// Wraps a built-in sample with its metadata
+fn sample<T, Id: Clone>(
+	updated_by: &Id, data: T,
+) -> PlatformDataAndMetadata<T, Id> {
+	PlatformDataAndMetadata::new_now(data, updated_by.clone())
+}
+
+impl Samples {
+	/// Samples of all the kinds of platform data, made from hand-written
+	/// platform responses
+	///
+	/// # Panics
+	///
+	/// If the samples don't match the platform crates' models anymore
+	#[must_use]
+	pub fn builtin() -> Self {
+		let vrchat =
+			PlatformSamples::<
+				vrc::model::AnyUser,
+				vrc::model::Friend,
+				vrc::model::Instance,
+				vrc::model::World,
+				vrc::model::Avatar,
+			>::parse(PlatformType::VRChat, include_str!("conformance/vrchat.json"));
+		// Without asset tags, as the crate writes them in lowercase but only
+		// reads them back capitalized
+		let chilloutvr = PlatformSamples::<
+			chilloutvr::model::UserDetails,
+			chilloutvr::model::Friend,
+			serde_json::Value,
+			chilloutvr::model::WorldDetails,
+			chilloutvr::model::AvatarDetails,
+		>::parse(
+			PlatformType::ChilloutVR,
+			include_str!("conformance/chilloutvr.json"),
+		);
+		let resonite = PlatformSamples::<
+			resonite::model::User,
+			resonite::model::Contact,
+			resonite::model::SessionInfo,
+			resonite::model::Record,
+			resonite::model::Record,
+		>::parse(
+			PlatformType::Resonite,
+			include_str!("conformance/resonite.json"),
+		);
+
+		let vrchat_id = vrchat.account.as_user().base.id.clone();
+		let chilloutvr_id = chilloutvr.account.base.id.clone();
+		let resonite_id = resonite.account.id.clone();
+		Self {
+			accounts: vec![
+				PlatformAccount::VRChat(sample(&vrchat_id, Box::new(vrchat.account))),
+				PlatformAccount::ChilloutVR(sample(
+					&chilloutvr_id,
+					Box::new(chilloutvr.account),
+				)),
+				PlatformAccount::Resonite(sample(
+					&resonite_id,
+					Box::new(resonite.account),
+				)),
+			],
+			friends: vec![
+				PlatformFriend::VRChat(sample(&vrchat_id, Box::new(vrchat.friend))),
+				PlatformFriend::ChilloutVR(sample(
+					&chilloutvr_id,
+					Box::new(chilloutvr.friend),
+				)),
+				PlatformFriend::Resonite(sample(
+					&resonite_id,
+					Box::new(resonite.friend),
+				)),
+			],
+			instances: vec![
+				Instance::VRChat(sample(&vrchat_id, vrchat.instance)),
+				Instance::ChilloutVR(sample(
+					&chilloutvr_id,
+					crate::cvr::instance_from_value(&chilloutvr.instance).unwrap_or_else(
+						|e| panic!("the built-in ChilloutVR instance to be valid: {e}"),
+					),
+				)),
+				Instance::Resonite(sample(&resonite_id, resonite.instance)),
+			],
+			worlds: vec![
+				World::VRChat(sample(&vrchat_id, Box::new(vrchat.world))),
+				World::ChilloutVR(sample(&chilloutvr_id, Box::new(chilloutvr.world))),
+				World::Resonite(sample(&resonite_id, Box::new(resonite.world))),
+			],
+			avatars: vec![
+				Avatar::VRChat(sample(&vrchat_id, Box::new(vrchat.avatar))),
+				Avatar::ChilloutVR(sample(&chilloutvr_id, Box::new(chilloutvr.avatar))),
+				Avatar::Resonite(sample(&resonite_id, Box::new(resonite.avatar))),
+			],
+			authentications: vec![
+				Authentication::VRChat(sample(
+					&vrchat_id,
+					Box::new(vrc::query::Authentication {
+						token: "authcookie_onlivfe-conformance".to_owned(),
+						second_factor_token: Some(
+							"twoFactorAuth_onlivfe-conformance".to_owned(),
+						),
+					}),
+				)),
+				Authentication::ChilloutVR(sample(
+					&chilloutvr_id,
+					Box::new(chilloutvr::query::SavedLoginCredentials {
+						username: "Conformance CVR".to_owned(),
+						access_key: "onlivfe-conformance".to_owned(),
+					}),
+				)),
+				Authentication::Resonite(sample(
+					&resonite_id,
+					Box::new(resonite::query::Authentication {
+						token: "onlivfe-conformance".to_owned(),
+						user_id: resonite_id.clone(),
+					}),
+				)),
+			],
+		}
+	}
+
+	/// The kinds of platform data that have no samples for a platform
+	#[must_use]
+	pub fn missing_platforms(&self) -> Vec<(&'static str, PlatformType)> {
+		let mut covered = HashSet::new();
+		covered.extend(self.accounts.iter().map(|v| ("account", v.platform())));
+		covered.extend(self.friends.iter().map(|v| ("friend", v.platform())));
+		covered.extend(self.instances.iter().map(|v| ("instance", v.platform())));
+		covered.extend(self.worlds.iter().map(|v| ("world", v.platform())));
+		covered.extend(self.avatars.iter().map(|v| ("avatar", v.platform())));
+		covered.extend(
+			self.authentications.iter().map(|v| ("authentication", v.platform())),
+		);
+
+		SAMPLE_KINDS
+			.into_iter()
+			.flat_map(|kind| {
+				crate::platforms().into_iter().map(move |platform| (kind, platform))
+			})
+			.filter(|key| !covered.contains(key))
+			.collect()
+	}
+}
+
+/// Creates a platform account ID that doesn't need any platform data
+fn account_id(platform: PlatformType, id: &str) -> PlatformAccountId {
+	serde_json::from_value(serde_json::json!({ "platform": platform, "id": id }))
+		.expect("the platform account ID to be valid")
+}
+
+/// An account ID for each of the platforms
+fn account_ids() -> [PlatformAccountId; 3] {
+	[
+		account_id(
+			PlatformType::VRChat,
+			"usr_c1644b5b-3ca4-45b4-97c6-a2a0de70d469",
+		),
+		account_id(
+			PlatformType::ChilloutVR,
+			"c1644b5b-3ca4-45b4-97c6-a2a0de70d469",
+		),
+		account_id(PlatformType::Resonite, "U-onlivfe-conformance"),
+	]
+}
+
+/// Asserts that the IDs are the same, ignoring their order
+fn assert_same_ids<T: Debug + Eq + Hash>(actual: &[T], expected: &[T]) {
+	assert_eq!(
+		actual.iter().collect::<HashSet<_>>(),
+		expected.iter().collect::<HashSet<_>>()
+	);
+	assert_eq!(actual.len(), expected.len(), "IDs should not be duplicated");
+}
+
+/// Runs all of the checks
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn run<S: OnlivfeStore>(store: &S, samples: &Samples) {
+	let missing = samples.missing_platforms();
+	if !missing.is_empty() {
+		tracing::warn!(
+			"Not checking round-trips of platform data without samples: {missing:?}"
+		);
+	}
+
+	profile_round_trip(store).await;
+	profile_update_returns_if_existed(store).await;
+	tagged_profiles_are_found(store).await;
+	mappings_are_consistent(store).await;
+	circles_round_trip(store).await;
+	rejected_links_round_trip(store).await;
+	delete_profile_cascades(store).await;
+	trash_and_restore(store).await;
+	revisions_are_recorded(store).await;
+	profile_pictures_round_trip(store).await;
+	platform_data_round_trips(store, samples).await;
+	authentication_removal(store, &samples.authentications).await;
+	batch_round_trip(store, samples).await;
+	batch_moves_profile_data(store).await;
+	watch_notifies_changes(store).await;
+	search_finds_names(store, samples).await;
+}
+
+/// Checks that a profile is stored as is
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn profile_round_trip<S: OnlivfeStore>(store: &S) {
+	let mut profile = Profile::new();
+	profile.nick = Some("Conformance".to_owned());
+	profile.notes =
+		Some("Multiline\nnotes with \"quotes\" and ünicode".to_owned());
+	profile.pfp_url = Some("https://example.com/pfp.png".to_owned());
+	profile.tags = ["VR friends".to_owned(), "Dev team".to_owned()].into();
+	profile.custom_fields =
+		[("Discord".to_owned(), "conformance".to_owned())].into();
+	profile.pronouns = Some("they/them".to_owned());
+	profile.birthday = Birthday::new(None, Month::February, 29);
+	profile.time_zone = Some("Europe/Helsinki".to_owned());
+	profile.favorite = true;
+
+	store
+		.update_profile(profile.clone())
+		.await
+		.expect("storing a profile to succeed");
+	let stored = store
+		.profile(profile.sharing_id.clone())
+		.await
+		.expect("the stored profile to be found");
+
+	assert_eq!(stored, profile);
+}
+
+/// Checks that profiles are found by their tags, ignoring ASCII case
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn tagged_profiles_are_found<S: OnlivfeStore>(store: &S) {
+	// Unique, so that earlier data doesn't match
+	let tag = format!("Conformance {}", ProfileId::new());
+	let mut tagged = Profile::new();
+	tagged.tags = [tag.clone(), "Other".to_owned()].into();
+	let mut untagged = Profile::new();
+	untagged.tags = ["Other".to_owned()].into();
+	for profile in [&tagged, &untagged] {
+		store
+			.update_profile(profile.clone())
+			.await
+			.expect("storing a profile to succeed");
+	}
+
+	let profile_ids = store
+		.tagged_profile_ids(&tag.to_lowercase(), 10)
+		.await
+		.expect("getting tagged profiles to succeed");
+	assert_eq!(profile_ids, vec![tagged.sharing_id.clone()]);
+
+	// Removing the tag should stop the profile from being found with it
+	tagged.tags.remove(&tag);
+	store
+		.update_profile(tagged.clone())
+		.await
+		.expect("updating a profile to succeed");
+	let profile_ids = store
+		.tagged_profile_ids(&tag, 10)
+		.await
+		.expect("getting tagged profiles to succeed");
+	assert!(profile_ids.is_empty(), "Removed tags should not be found");
+}
+
+/// Checks that updating a profile returns if it already existed
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn profile_update_returns_if_existed<S: OnlivfeStore>(store: &S) {
+	let mut profile = Profile::new();
+	let existed = store
+		.update_profile(profile.clone())
+		.await
+		.expect("storing a profile to succeed");
+	assert!(!existed, "Storing a new profile should return false");
+
+	profile.nick = Some("Updated".to_owned());
+	let existed = store
+		.update_profile(profile.clone())
+		.await
+		.expect("updating a profile to succeed");
+	assert!(existed, "Updating an existing profile should return true");
+
+	let stored = store
+		.profile(profile.sharing_id.clone())
+		.await
+		.expect("the updated profile to be found");
+	assert_eq!(stored, profile);
+}
+
+/// Checks that circles and their members are stored as is, in order
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn circles_round_trip<S: OnlivfeStore>(store: &S) {
+	let mut circle = Circle::new("Close friends");
+	circle.color = Some(Color { red: 0xff, green: 0x80, blue: 0x00 });
+	circle.position = 3;
+	let existed = store
+		.update_circle(circle.clone())
+		.await
+		.expect("storing a circle to succeed");
+	assert!(!existed, "Storing a new circle should return false");
+	let stored =
+		store.circle(circle.id.clone()).await.expect("getting a circle to succeed");
+	assert_eq!(stored, circle);
+
+	let profile = Profile::new();
+	let [vrchat, _, resonite] = account_ids();
+	let members = vec![
+		CircleMember::Account(resonite.clone()),
+		CircleMember::Profile(profile.sharing_id.clone()),
+		CircleMember::Account(vrchat.clone()),
+		CircleMember::Account(resonite.clone()),
+	];
+	store
+		.update_circle_members(circle.id.clone(), members.clone())
+		.await
+		.expect("storing circle members to succeed");
+	let stored = store
+		.circle_members(circle.id.clone())
+		.await
+		.expect("getting circle members to succeed");
+	assert_eq!(stored, members[..3], "Members should keep their first position");
+
+	let circle_ids = store
+		.member_circle_ids(CircleMember::Profile(profile.sharing_id.clone()))
+		.await
+		.expect("getting a member's circles to succeed");
+	assert_eq!(circle_ids, vec![circle.id.clone()]);
+
+	store.delete_circle(circle.id.clone()).await.expect("deleting to succeed");
+	assert!(
+		store.circle(circle.id.clone()).await.is_err(),
+		"Deleted circles should not be found"
+	);
+	let stored = store
+		.circle_members(circle.id.clone())
+		.await
+		.expect("getting circle members to succeed");
+	assert!(stored.is_empty(), "Deleting a circle should remove its members");
+	let circle_ids = store
+		.member_circle_ids(CircleMember::Profile(profile.sharing_id))
+		.await
+		.expect("getting a member's circles to succeed");
+	assert!(circle_ids.is_empty(), "Deleted circles should not be listed");
+}
+
+/// Checks that rejected links are stored once, regardless of the order of
+/// their accounts
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn rejected_links_round_trip<S: OnlivfeStore>(store: &S) {
+	let [vrchat, chilloutvr, _] = account_ids();
+	let link = AccountLink::new(vrchat.clone(), chilloutvr.clone());
+	// Could've been rejected by an earlier run
+	let was_rejected = store
+		.rejected_links()
+		.await
+		.expect("getting rejected links to succeed")
+		.contains(&link);
+
+	let existed = store
+		.reject_link(AccountLink::new(chilloutvr, vrchat))
+		.await
+		.expect("rejecting a link to succeed");
+	assert_eq!(existed, was_rejected);
+	let existed =
+		store.reject_link(link.clone()).await.expect("rejecting to succeed");
+	assert!(existed, "Rejecting the same link again should return true");
+
+	let rejected_links =
+		store.rejected_links().await.expect("getting rejected links to succeed");
+	assert_eq!(
+		rejected_links.iter().filter(|rejected| **rejected == link).count(),
+		1,
+		"Rejected links should be stored once"
+	);
+}
+
+/// Checks that the profile to account mappings look the same from both sides
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn mappings_are_consistent<S: OnlivfeStore>(store: &S) {
+	let [vrchat, chilloutvr, resonite] = account_ids();
+	let first = ProfileId::new();
+	let second = ProfileId::new();
+
+	store
+		.update_profile_account_ids(
+			first.clone(),
+			vec![vrchat.clone(), chilloutvr.clone()],
+		)
+		.await
+		.expect("updating profile's accounts to succeed");
+	let account_ids = store
+		.profile_account_ids(first.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert_same_ids(&account_ids, &[vrchat.clone(), chilloutvr.clone()]);
+	for account_id in [&vrchat, &chilloutvr] {
+		let profile_ids = store
+			.account_profile_ids(account_id.clone())
+			.await
+			.expect("getting account's profiles to succeed");
+		assert!(profile_ids.contains(&first), "{account_id:?} should be mapped");
+	}
+
+	// Mapping from the account's side should show up on the profiles' side too
+	store
+		.update_account_profile_ids(
+			resonite.clone(),
+			vec![first.clone(), second.clone()],
+		)
+		.await
+		.expect("updating account's profiles to succeed");
+	let account_ids = store
+		.profile_account_ids(first.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert_same_ids(
+		&account_ids,
+		&[vrchat.clone(), chilloutvr.clone(), resonite.clone()],
+	);
+	let account_ids = store
+		.profile_account_ids(second.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert_same_ids(&account_ids, std::slice::from_ref(&resonite));
+
+	// Replacing the mappings should remove the old ones from both sides
+	store
+		.update_profile_account_ids(first.clone(), vec![resonite.clone()])
+		.await
+		.expect("updating profile's accounts to succeed");
+	let account_ids = store
+		.profile_account_ids(first.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert_same_ids(&account_ids, std::slice::from_ref(&resonite));
+	for account_id in [&vrchat, &chilloutvr] {
+		let profile_ids = store
+			.account_profile_ids(account_id.clone())
+			.await
+			.expect("getting account's profiles to succeed");
+		assert!(
+			!profile_ids.contains(&first),
+			"{account_id:?} should not be mapped anymore"
+		);
+	}
+	let profile_ids = store
+		.account_profile_ids(resonite)
+		.await
+		.expect("getting account's profiles to succeed");
+	assert!(profile_ids.contains(&first) && profile_ids.contains(&second));
+}
+
+/// Checks that deleting a profile also removes its mappings, tags and circle
+/// memberships
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn delete_profile_cascades<S: OnlivfeStore>(store: &S) {
+	let [vrchat, _, resonite] = account_ids();
+	let mut profile = Profile::new();
+	// Unique, so that earlier data doesn't match
+	let tag = format!("Deleted {}", profile.sharing_id);
+	profile.tags = [tag.clone()].into();
+	let profile_id = profile.sharing_id.clone();
+	store.update_profile(profile).await.expect("storing a profile to succeed");
+	store
+		.update_profile_account_ids(
+			profile_id.clone(),
+			vec![vrchat.clone(), resonite.clone()],
+		)
+		.await
+		.expect("updating profile's accounts to succeed");
+	let circle = Circle::new("Deleted member");
+	store
+		.update_circle(circle.clone())
+		.await
+		.expect("storing a circle to succeed");
+	store
+		.update_circle_members(
+			circle.id.clone(),
+			vec![
+				CircleMember::Profile(profile_id.clone()),
+				CircleMember::Account(vrchat.clone()),
+			],
+		)
+		.await
+		.expect("storing circle members to succeed");
+
+	store
+		.delete_profile(profile_id.clone())
+		.await
+		.expect("deleting a profile to succeed");
+
+	assert!(
+		store.profile(profile_id.clone()).await.is_err(),
+		"Deleted profile should not be found"
+	);
+	let profile_ids =
+		store.profile_ids(usize::MAX).await.expect("listing profiles to succeed");
+	assert!(
+		!profile_ids.contains(&profile_id),
+		"Deleted profile should not be listed"
+	);
+	let profile_ids = store
+		.tagged_profile_ids(&tag, 10)
+		.await
+		.expect("getting tagged profiles to succeed");
+	assert!(profile_ids.is_empty(), "Deleted profile should not have tags");
+	let account_ids = store
+		.profile_account_ids(profile_id.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert!(account_ids.is_empty(), "Deleted profile should have no accounts");
+	for account_id in [vrchat.clone(), resonite] {
+		let profile_ids = store
+			.account_profile_ids(account_id.clone())
+			.await
+			.expect("getting account's profiles to succeed");
+		assert!(
+			!profile_ids.contains(&profile_id),
+			"{account_id:?} should not be mapped to the deleted profile"
+		);
+	}
+	let members = store
+		.circle_members(circle.id.clone())
+		.await
+		.expect("getting circle members to succeed");
+	assert_eq!(
+		members,
+		vec![CircleMember::Account(vrchat)],
+		"Deleted profile should be removed from circles, leaving the others"
+	);
+}
+
+/// Checks that a trashed profile is removed like a deleted one, and that
+/// restoring it brings back its accounts and circle memberships
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn trash_and_restore<S: OnlivfeStore>(store: &S) {
+	let [_, chilloutvr, _] = account_ids();
+	let mut profile = Profile::new();
+	profile.nick = Some("Trashed".to_owned());
+	let profile_id = profile.sharing_id.clone();
+	store
+		.update_profile(profile.clone())
+		.await
+		.expect("storing a profile to succeed");
+	store
+		.update_profile_account_ids(profile_id.clone(), vec![chilloutvr.clone()])
+		.await
+		.expect("updating profile's accounts to succeed");
+	let circle = Circle::new("Trashed member");
+	store
+		.update_circle(circle.clone())
+		.await
+		.expect("storing a circle to succeed");
+	store
+		.update_circle_members(
+			circle.id.clone(),
+			vec![CircleMember::Profile(profile_id.clone())],
+		)
+		.await
+		.expect("storing circle members to succeed");
+
+	let trashed = store
+		.trash_profile(profile_id.clone())
+		.await
+		.expect("trashing a profile to succeed");
+	assert_eq!(trashed.profile, profile);
+	assert!(
+		store.profile(profile_id.clone()).await.is_err(),
+		"Trashed profile should not be found"
+	);
+	let account_ids = store
+		.profile_account_ids(profile_id.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert!(account_ids.is_empty(), "Trashed profile should have no accounts");
+	let stored = store
+		.trashed_profile(profile_id.clone())
+		.await
+		.expect("getting a trashed profile to succeed");
+	assert_eq!(stored, trashed);
+	let trashed_ids = store
+		.trashed_profile_ids(usize::MAX)
+		.await
+		.expect("listing trashed profiles to succeed");
+	assert!(trashed_ids.contains(&profile_id), "Trash should be listed");
+
+	let restored = store
+		.restore_profile(profile_id.clone())
+		.await
+		.expect("restoring a profile to succeed");
+	assert_eq!(restored, profile);
+	assert_eq!(
+		store.profile(profile_id.clone()).await.expect("restored profile"),
+		profile
+	);
+	let account_ids = store
+		.profile_account_ids(profile_id.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert_eq!(account_ids, vec![chilloutvr]);
+	let members = store
+		.circle_members(circle.id.clone())
+		.await
+		.expect("getting circle members to succeed");
+	assert_eq!(members, vec![CircleMember::Profile(profile_id.clone())]);
+	assert!(
+		store.trashed_profile(profile_id.clone()).await.is_err(),
+		"Restored profile should not be in the trash"
+	);
+
+	// Deleting from the trash is for good
+	store.trash_profile(profile_id.clone()).await.expect("trashing to succeed");
+	store
+		.delete_trashed_profile(profile_id.clone())
+		.await
+		.expect("deleting a trashed profile to succeed");
+	assert!(
+		store.restore_profile(profile_id).await.is_err(),
+		"Profiles deleted from the trash should not be restorable"
+	);
+}
+
+/// Checks that changes to a profile and its accounts are recorded as
+/// revisions, that ones which don't change anything aren't, and that the
+/// revisions can be replaced and deleted
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn revisions_are_recorded<S: OnlivfeStore>(store: &S) {
+	let [_, _, resonite] = account_ids();
+	let mut profile = Profile::new();
+	profile.nick = Some("Revised".to_owned());
+	let profile_id = profile.sharing_id.clone();
+	store
+		.update_profile(profile.clone())
+		.await
+		.expect("storing a profile to succeed");
+	store
+		.update_profile(profile.clone())
+		.await
+		.expect("storing the same profile to succeed");
+	let mut renamed = profile.clone();
+	renamed.nick = Some("Revised again".to_owned());
+	store
+		.update_profile(renamed.clone())
+		.await
+		.expect("updating a profile to succeed");
+	store
+		.update_profile_account_ids(profile_id.clone(), vec![resonite.clone()])
+		.await
+		.expect("updating profile's accounts to succeed");
+	let mut profile_ids = store
+		.account_profile_ids(resonite.clone())
+		.await
+		.expect("getting account's profiles to succeed");
+	profile_ids.retain(|id| *id != profile_id);
+	store
+		.update_account_profile_ids(resonite.clone(), profile_ids)
+		.await
+		.expect("updating account's profiles to succeed");
+	store
+		.delete_profile(profile_id.clone())
+		.await
+		.expect("deleting a profile to succeed");
+
+	let revisions = store
+		.profile_revisions(profile_id.clone())
+		.await
+		.expect("getting profile's revisions to succeed");
+	let changes: Vec<ProfileChange> =
+		revisions.iter().map(|revision| revision.change.clone()).collect();
+	assert_eq!(
+		changes,
+		vec![
+			ProfileChange::Details { old: None, new: Some(profile.clone()) },
+			ProfileChange::Details { old: Some(profile), new: Some(renamed.clone()) },
+			ProfileChange::Accounts { old: vec![], new: vec![resonite.clone()] },
+			ProfileChange::Accounts { old: vec![resonite], new: vec![] },
+			ProfileChange::Details { old: Some(renamed), new: None },
+		]
+	);
+	for (number, revision) in (1..).zip(&revisions) {
+		assert_eq!(revision.profile_id, profile_id);
+		assert_eq!(revision.number, number, "Numbers should count up from 1");
+	}
+	assert!(
+		revisions.windows(2).all(|pair| pair[0].recorded_at <= pair[1].recorded_at),
+		"Revisions should be in the order that they were recorded in"
+	);
+
+	let restored = revisions[..2].to_vec();
+	store
+		.update_profile_revisions(profile_id.clone(), restored.clone())
+		.await
+		.expect("replacing profile's revisions to succeed");
+	let revisions = store
+		.profile_revisions(profile_id.clone())
+		.await
+		.expect("getting profile's revisions to succeed");
+	assert_eq!(revisions, restored, "Revisions should be replaced as is");
+
+	store
+		.delete_profile_revisions(profile_id.clone())
+		.await
+		.expect("deleting profile's revisions to succeed");
+	let revisions = store
+		.profile_revisions(profile_id)
+		.await
+		.expect("getting profile's revisions to succeed");
+	assert!(revisions.is_empty(), "Deleted revisions should not be found");
+}
+
+/// Checks that profile pictures are stored as is, and kept when their profile
+/// is deleted
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn profile_pictures_round_trip<S: OnlivfeStore>(store: &S) {
+	let profile = Profile::new();
+	let profile_id = profile.sharing_id.clone();
+	store.update_profile(profile).await.expect("storing a profile to succeed");
+	assert!(
+		store.profile_picture(profile_id.clone()).await.is_err(),
+		"A picture should not be found before storing it"
+	);
+
+	let picture = ProfilePicture {
+		profile_id: profile_id.clone(),
+		content_type: "image/png".to_owned(),
+		bytes: vec![0x89, b'P', b'N', b'G', 0, 1, 2, 3],
+		thumbnail: vec![0x89, b'P', b'N', b'G', 4, 5],
+		stored_at: time::macros::datetime!(2024-08-14 12:34:56.789 UTC),
+	};
+	let existed = store
+		.update_profile_picture(picture.clone())
+		.await
+		.expect("storing a picture to succeed");
+	assert!(!existed, "Storing a new picture should return false");
+	let stored = store
+		.profile_picture(profile_id.clone())
+		.await
+		.expect("getting a stored picture to succeed");
+	assert_eq!(stored, picture);
+
+	let mut replaced = picture.clone();
+	"image/webp".clone_into(&mut replaced.content_type);
+	replaced.bytes = vec![b'R', b'I', b'F', b'F'];
+	let existed = store
+		.update_profile_picture(replaced.clone())
+		.await
+		.expect("replacing a picture to succeed");
+	assert!(existed, "Replacing a picture should return true");
+	assert_eq!(
+		store.profile_picture(profile_id.clone()).await.expect("the picture"),
+		replaced
+	);
+
+	store
+		.delete_profile(profile_id.clone())
+		.await
+		.expect("deleting a profile to succeed");
+	assert_eq!(
+		store
+			.profile_picture(profile_id.clone())
+			.await
+			.expect("the picture of a deleted profile"),
+		replaced,
+		"Deleting a profile should keep its picture"
+	);
+
+	store
+		.delete_profile_picture(profile_id.clone())
+		.await
+		.expect("deleting a picture to succeed");
+	assert!(
+		store.profile_picture(profile_id).await.is_err(),
+		"A deleted picture should not be found"
+	);
+}
+
+/// Stores each sample checking that it's returned as is, and that updating
+/// returns if it already existed
+macro_rules! check_round_trips {
+	(
+		$store:ident, $samples:expr, $kind:literal,
+		$get:ident, $update:ident, $ids:ident
+	) => {
+		for sample in $samples {
+			let id = sample.id();
+			let existed = $store.$get(id.clone()).await.is_ok();
+			let updated = $store
+				.$update(sample.clone())
+				.await
+				.unwrap_or_else(|e| panic!("storing {} failed: {e:?}", $kind));
+			assert_eq!(
+				updated, existed,
+				"Storing a {} should return if it existed already",
+				$kind
+			);
+
+			let stored = $store
+				.$get(id.clone())
+				.await
+				.unwrap_or_else(|e| panic!("getting stored {} failed: {e:?}", $kind));
+			assert_eq!(&stored, sample);
+			let ids = $store
+				.$ids(usize::MAX)
+				.await
+				.unwrap_or_else(|e| panic!("listing {}s failed: {e:?}", $kind));
+			assert!(ids.contains(&id), "The {} should be listed", $kind);
+
+			let updated = $store
+				.$update(sample.clone())
+				.await
+				.unwrap_or_else(|e| panic!("updating {} failed: {e:?}", $kind));
+			assert!(updated, "Updating an existing {} should return true", $kind);
+		}
+	};
+}
+
+/// Checks the round-trips of the platform data samples
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn platform_data_round_trips<S: OnlivfeStore>(
+	store: &S, samples: &Samples,
+) {
+	check_round_trips!(
+		store,
+		&samples.accounts,
+		"account",
+		account,
+		update_account,
+		account_ids
+	);
+	check_round_trips!(
+		store,
+		&samples.friends,
+		"friend",
+		friend,
+		update_friend,
+		friend_ids
+	);
+	check_round_trips!(
+		store,
+		&samples.instances,
+		"instance",
+		instance,
+		update_instance,
+		instance_ids
+	);
+	check_round_trips!(
+		store,
+		&samples.worlds,
+		"world",
+		world,
+		update_world,
+		world_ids
+	);
+	check_round_trips!(
+		store,
+		&samples.avatars,
+		"avatar",
+		avatar,
+		update_avatar,
+		avatar_ids
+	);
+}
+
+/// Checks that authentications are stored, updated and removed
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn authentication_removal<S: OnlivfeStore>(
+	store: &S, samples: &[Authentication],
+) {
+	for sample in samples {
+		let id = sample.id();
+		let existed = store
+			.authentications()
+			.await
+			.expect("listing authentications to succeed")
+			.iter()
+			.any(|auth| auth.id() == id);
+		let updated = store
+			.update_authentication(sample.clone())
+			.await
+			.expect("storing an authentication to succeed");
+		assert_eq!(
+			updated, existed,
+			"Storing an authentication should return if it existed already"
+		);
+		let updated = store
+			.update_authentication(sample.clone())
+			.await
+			.expect("updating an authentication to succeed");
+		assert!(updated, "Updating an existing authentication should return true");
+		let authentications = store
+			.authentications()
+			.await
+			.expect("listing authentications to succeed");
+		assert!(authentications.contains(sample));
+
+		let removed = store
+			.remove_authentication(id.clone())
+			.await
+			.expect("removing an authentication to succeed");
+		assert!(removed, "Removing an existing authentication should return true");
+		let authentications = store
+			.authentications()
+			.await
+			.expect("listing authentications to succeed");
+		assert!(
+			!authentications.iter().any(|auth| auth.id() == id),
+			"The authentication should be removed"
+		);
+		let removed = store
+			.remove_authentication(id)
+			.await
+			.expect("removing a removed authentication to succeed");
+		assert!(!removed, "Removing a removed authentication should return false");
+	}
+
+	let removed = store
+		.remove_authentication(account_id(
+			PlatformType::Resonite,
+			"U-onlivfe-conformance-never-stored",
+		))
+		.await
+		.expect("removing a missing authentication to succeed");
+	assert!(!removed, "Removing a missing authentication should return false");
+}
+
+/// Checks that a batch is stored, and that it returns the IDs of the entries
+/// that already existed
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn batch_round_trip<S: OnlivfeStore>(store: &S, samples: &Samples) {
+	let existing = Profile::new();
+	store
+		.update_profile(existing.clone())
+		.await
+		.expect("storing a profile to succeed");
+	let batch = StoreBatch {
+		accounts: samples.accounts.clone(),
+		friends: samples.friends.clone(),
+		instances: samples.instances.clone(),
+		worlds: samples.worlds.clone(),
+		avatars: samples.avatars.clone(),
+		profiles: vec![existing.clone(), Profile::new()],
+		authentications: samples.authentications.clone(),
+		..StoreBatch::default()
+	};
+
+	let updated = store
+		.apply_batch(batch.clone())
+		.await
+		.expect("applying a batch to succeed");
+	assert_eq!(updated.profiles, vec![existing.sharing_id]);
+	for profile in &batch.profiles {
+		let stored = store
+			.profile(profile.sharing_id.clone())
+			.await
+			.expect("the profile from the batch to be found");
+		assert_eq!(&stored, profile);
+	}
+	let authentications =
+		store.authentications().await.expect("listing authentications to succeed");
+	for sample in &batch.authentications {
+		assert!(authentications.contains(sample));
+	}
+
+	// Everything exists now, so all of it should be updated
+	let updated = store
+		.apply_batch(batch.clone())
+		.await
+		.expect("applying a batch again to succeed");
+	assert_same_ids(
+		&updated.accounts,
+		&batch.accounts.iter().map(PlatformAccount::id).collect::<Vec<_>>(),
+	);
+	assert_same_ids(
+		&updated.friends,
+		&batch.friends.iter().map(PlatformFriend::id).collect::<Vec<_>>(),
+	);
+	assert_same_ids(
+		&updated.instances,
+		&batch.instances.iter().map(Instance::id).collect::<Vec<_>>(),
+	);
+	assert_same_ids(
+		&updated.worlds,
+		&batch.worlds.iter().map(World::id).collect::<Vec<_>>(),
+	);
+	assert_same_ids(
+		&updated.avatars,
+		&batch.avatars.iter().map(Avatar::id).collect::<Vec<_>>(),
+	);
+	assert_same_ids(
+		&updated.profiles,
+		&batch.profiles.iter().map(|p| p.sharing_id.clone()).collect::<Vec<_>>(),
+	);
+	assert_same_ids(
+		&updated.authentications,
+		&batch.authentications.iter().map(Authentication::id).collect::<Vec<_>>(),
+	);
+}
+
+/// Checks that the mappings, circle members and profile deletions of a batch
+/// are applied after its profiles, like when merging profiles
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn batch_moves_profile_data<S: OnlivfeStore>(store: &S) {
+	let [vrchat, chilloutvr, resonite] = account_ids();
+	let from = Profile::new();
+	store
+		.update_profile(from.clone())
+		.await
+		.expect("storing a profile to succeed");
+	store
+		.update_profile_account_ids(
+			from.sharing_id.clone(),
+			vec![vrchat.clone(), resonite.clone()],
+		)
+		.await
+		.expect("updating profile's accounts to succeed");
+	let circle = Circle::new("Batch members");
+	store
+		.update_circle(circle.clone())
+		.await
+		.expect("storing a circle to succeed");
+	store
+		.update_circle_members(
+			circle.id.clone(),
+			vec![CircleMember::Profile(from.sharing_id.clone())],
+		)
+		.await
+		.expect("storing circle members to succeed");
+
+	let into = Profile::new();
+	let into_id = into.sharing_id.clone();
+	let batch = StoreBatch {
+		profiles: vec![into.clone()],
+		profile_account_ids: vec![(
+			into_id.clone(),
+			vec![chilloutvr, vrchat.clone(), resonite.clone()],
+		)],
+		circle_members: vec![(
+			circle.id.clone(),
+			vec![
+				CircleMember::Profile(into_id.clone()),
+				CircleMember::Profile(into_id.clone()),
+			],
+		)],
+		deleted_profiles: vec![from.sharing_id.clone()],
+		..StoreBatch::default()
+	};
+	store.apply_batch(batch).await.expect("applying a batch to succeed");
+
+	assert_eq!(
+		store.profile(into_id.clone()).await.expect("the profile to be found"),
+		into
+	);
+	assert!(
+		store.profile(from.sharing_id.clone()).await.is_err(),
+		"Deleted profile should not be found"
+	);
+	let account_ids = store
+		.profile_account_ids(into_id.clone())
+		.await
+		.expect("getting profile's accounts to succeed");
+	assert_eq!(account_ids.len(), 3, "All of the accounts should be linked");
+	for account_id in [vrchat, resonite] {
+		let profile_ids = store
+			.account_profile_ids(account_id.clone())
+			.await
+			.expect("getting account's profiles to succeed");
+		assert!(
+			profile_ids.contains(&into_id) && !profile_ids.contains(&from.sharing_id),
+			"{account_id:?} should only be mapped to the profile it was moved to"
+		);
+	}
+	let members = store
+		.circle_members(circle.id.clone())
+		.await
+		.expect("getting circle members to succeed");
+	assert_eq!(
+		members,
+		vec![CircleMember::Profile(into_id)],
+		"Circle members from a batch should only be stored once"
+	);
+}
+
+/// Takes the changes that have been sent so far
+fn received_changes(changes: &mut ChangeStream) -> Vec<StoreChange> {
+	let mut received = vec![];
+	while let Ok(change) = changes.try_recv() {
+		received.push(change);
+	}
+
+	received
+}
+
+/// Checks that watching the store notifies of the changes, if the store
+/// supports watching
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn watch_notifies_changes<S: OnlivfeStore>(store: &S) {
+	let Some(mut changes) = store.watch() else {
+		tracing::warn!(
+			"Not checking change notifications, as watching isn't supported"
+		);
+		return;
+	};
+	let [vrchat, ..] = account_ids();
+	let mut profile = Profile::new();
+	let profile_id = profile.sharing_id.clone();
+
+	store
+		.update_profile(profile.clone())
+		.await
+		.expect("storing a profile to succeed");
+	profile.nick = Some("Watched".to_owned());
+	store.update_profile(profile).await.expect("updating a profile to succeed");
+	assert_eq!(
+		received_changes(&mut changes),
+		vec![
+			StoreChange {
+				entity: EntityId::Profile(profile_id.clone()),
+				kind: ChangeKind::Created,
+			},
+			StoreChange {
+				entity: EntityId::Profile(profile_id.clone()),
+				kind: ChangeKind::Updated,
+			},
+		]
+	);
+
+	store
+		.update_profile_account_ids(profile_id.clone(), vec![vrchat.clone()])
+		.await
+		.expect("updating profile's accounts to succeed");
+	let received = received_changes(&mut changes);
+	for entity in [
+		EntityId::ProfileAccounts(profile_id.clone()),
+		EntityId::AccountProfiles(vrchat.clone()),
+	] {
+		assert!(
+			received.contains(&StoreChange { entity, kind: ChangeKind::Updated }),
+			"Updating mappings should notify of both sides, got {received:?}"
+		);
+	}
+
+	store
+		.delete_profile(profile_id.clone())
+		.await
+		.expect("deleting a profile to succeed");
+	let received = received_changes(&mut changes);
+	assert!(
+		received.contains(&StoreChange {
+			entity: EntityId::Profile(profile_id),
+			kind: ChangeKind::Deleted,
+		}),
+		"Deleting a profile should notify of it, got {received:?}"
+	);
+	assert!(
+		received.contains(&StoreChange {
+			entity: EntityId::AccountProfiles(vrchat),
+			kind: ChangeKind::Updated,
+		}),
+		"Deleting a profile should notify of its accounts, got {received:?}"
+	);
+
+	// The later one replaces the earlier one, which created the profile
+	let profile = Profile::new();
+	let profile_id = profile.sharing_id.clone();
+	let batch = StoreBatch {
+		profiles: vec![
+			profile.clone(),
+			Profile { nick: Some("Twice".to_owned()), ..profile },
+		],
+		..StoreBatch::default()
+	};
+	let updated =
+		store.apply_batch(batch).await.expect("applying a batch to succeed");
+	assert_eq!(updated.profiles, vec![]);
+	assert_eq!(
+		received_changes(&mut changes),
+		vec![StoreChange {
+			entity: EntityId::Profile(profile_id),
+			kind: ChangeKind::Created,
+		}],
+		"Entries that are in a batch twice should be notified of once"
+	);
+
+	// Nothing changes, so there's nothing to notify of
+	store
+		.remove_authentication(account_id(
+			PlatformType::Resonite,
+			"U-onlivfe-conformance-never-stored",
+		))
+		.await
+		.expect("removing a missing authentication to succeed");
+	assert_eq!(received_changes(&mut changes), vec![]);
+}
+
+/// Checks that searching finds the profiles by their nicks & notes and the
+/// samples by their display names
+///
+/// # Panics
+///
+/// If the store doesn't behave as expected
+pub async fn search_finds_names<S: OnlivfeStore>(store: &S, samples: &Samples) {
+	// Unique, so that earlier data doesn't match
+	let word = format!("zz{}", ProfileId::new().to_string().replace('-', ""));
+	let prefix = &word[..10];
+	let mut nicked = Profile::new();
+	nicked.nick = Some(format!("{word} Conformance"));
+	let mut noted = Profile::new();
+	noted.notes = Some(format!("Met in a {word} themed world"));
+	let unrelated = Profile::new();
+	for profile in [&nicked, &noted, &unrelated] {
+		store
+			.update_profile(profile.clone())
+			.await
+			.expect("storing a profile to succeed");
+	}
+
+	let hits = store.search(prefix, 10).await.expect("searching to succeed");
+	let entities: Vec<&EntityId> = hits.iter().map(|hit| &hit.entity).collect();
+	assert_eq!(
+		entities,
+		vec![
+			&EntityId::Profile(nicked.sharing_id.clone()),
+			&EntityId::Profile(noted.sharing_id.clone())
+		],
+		"A match in a nick should rank higher than one in the notes"
+	);
+	assert!(hits.iter().all(|hit| hit.platform.is_none()));
+	assert_eq!(hits[0].name, nicked.nick);
+
+	let hits = store
+		.search(&format!("{word} met"), 10)
+		.await
+		.expect("searching to succeed");
+	assert_eq!(
+		hits.iter().map(|hit| &hit.entity).collect::<Vec<_>>(),
+		vec![&EntityId::Profile(noted.sharing_id)],
+		"All of the terms should need to match"
+	);
+	let hits = store.search(prefix, 1).await.expect("searching to succeed");
+	assert_eq!(hits.len(), 1, "The amount of hits should be limited");
+	let hits = store.search(" ", 10).await.expect("searching to succeed");
+	assert!(hits.is_empty(), "An empty query should match nothing");
+
+	store
+		.update_friends(samples.friends.clone())
+		.await
+		.expect("storing friends to succeed");
+	store
+		.update_accounts(samples.accounts.clone())
+		.await
+		.expect("storing accounts to succeed");
+	let expected = samples
+		.friends
+		.iter()
+		.map(|friend| {
+			(friend.display_name(), EntityId::Friend(friend.id()), friend.platform())
+		})
+		.chain(samples.accounts.iter().map(|account| {
+			(
+				account.display_name(),
+				EntityId::Account(account.id()),
+				account.platform(),
+			)
+		}));
+	// Names without any letters or numbers can't be searched for
+	for (name, entity, platform) in
+		expected.filter(|(name, ..)| !super::search::terms(name).is_empty())
+	{
+		let hits =
+			store.search(name, usize::MAX).await.expect("searching to succeed");
+		assert!(
+			hits
+				.iter()
+				.any(|hit| hit.entity == entity && hit.platform == Some(platform)),
+			"Searching for {name:?} should find {entity:?}"
+		);
+	}
+}
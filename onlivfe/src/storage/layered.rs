@@ -0,0 +1,916 @@
+use std::{
+	collections::HashMap,
+	sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use super::{
+	BatchUpdated,
+	ChangeKind,
+	ChangeNotifier,
+	ChangeStream,
+	EntityId,
+	OnlivfeStore,
+	SearchHit,
+	StoreBatch,
+	StoreChange,
+	search,
+};
+use crate::{
+	AccountLink,
+	Authentication,
+	Avatar,
+	AvatarId,
+	Circle,
+	CircleId,
+	CircleMember,
+	Instance,
+	InstanceId,
+	PlatformAccount,
+	PlatformAccountId,
+	PlatformFriend,
+	Profile,
+	ProfileId,
+	ProfilePicture,
+	ProfileRevision,
+	TrashedProfile,
+	World,
+	WorldId,
+};
+
+/// An error from one of the layers of a [`LayeredStore`]
+#[derive(Debug)]
+pub enum LayeredError<F, D> {
+	/// The fast layer failed
+	Fast(F),
+	/// The durable layer failed
+	Durable(D),
+}
+
+impl<F: std::fmt::Display, D: std::fmt::Display> std::fmt::Display
+	for LayeredError<F, D>
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Fast(e) => write!(f, "Fast storage layer error: {e}"),
+			Self::Durable(e) => write!(f, "Durable storage layer error: {e}"),
+		}
+	}
+}
+
+impl<F: std::error::Error, D: std::error::Error> std::error::Error
+	for LayeredError<F, D>
+{
+}
+
+/// How writes of platform data reach the durable layer of a [`LayeredStore`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+	/// Every write is stored in the durable layer before returning
+	#[default]
+	WriteThrough,
+	/// Writes are queued, and stored in the durable layer when
+	/// [`LayeredStore::flush`] is called or when there are `max_pending` of
+	/// them.
+	///
+	/// The queued writes are lost if the store is dropped without flushing
+	/// them, as flushing is async and can't be done when dropping.
+	/// Only platform data is queued, which is fetched from the platforms
+	/// again anyways.
+	Batched {
+		/// The amount of queued writes that triggers a flush
+		max_pending: usize,
+	},
+}
+
+/// Platform data writes that haven't reached the durable layer yet,
+/// deduplicated by the IDs
+#[derive(Debug, Default)]
+struct Pending {
+	accounts: HashMap<PlatformAccountId, PlatformAccount>,
+	friends: HashMap<PlatformAccountId, PlatformFriend>,
+	instances: HashMap<InstanceId, Instance>,
+	worlds: HashMap<WorldId, World>,
+	avatars: HashMap<AvatarId, Avatar>,
+}
+
+impl Pending {
+	fn len(&self) -> usize {
+		self.accounts.len()
+			+ self.friends.len()
+			+ self.instances.len()
+			+ self.worlds.len()
+			+ self.avatars.len()
+	}
+
+	/// Queues the platform data of a batch, replacing older writes
+	fn queue(&mut self, batch: StoreBatch) {
+		self.accounts.extend(batch.accounts.into_iter().map(|v| (v.id(), v)));
+		self.friends.extend(batch.friends.into_iter().map(|v| (v.id(), v)));
+		self.instances.extend(batch.instances.into_iter().map(|v| (v.id(), v)));
+		self.worlds.extend(batch.worlds.into_iter().map(|v| (v.id(), v)));
+		self.avatars.extend(batch.avatars.into_iter().map(|v| (v.id(), v)));
+	}
+
+	/// The queued writes as a batch, so that they're flushed all at once
+	fn to_batch(&self) -> StoreBatch {
+		StoreBatch {
+			accounts: self.accounts.values().cloned().collect(),
+			friends: self.friends.values().cloned().collect(),
+			instances: self.instances.values().cloned().collect(),
+			worlds: self.worlds.values().cloned().collect(),
+			avatars: self.avatars.values().cloned().collect(),
+			..StoreBatch::default()
+		}
+	}
+
+	/// Adds back writes that failed, without overwriting newer ones
+	fn merge_older(&mut self, older: Self) {
+		for (id, account) in older.accounts {
+			self.accounts.entry(id).or_insert(account);
+		}
+		for (id, friend) in older.friends {
+			self.friends.entry(id).or_insert(friend);
+		}
+		for (id, instance) in older.instances {
+			self.instances.entry(id).or_insert(instance);
+		}
+		for (id, world) in older.worlds {
+			self.worlds.entry(id).or_insert(world);
+		}
+		for (id, avatar) in older.avatars {
+			self.avatars.entry(id).or_insert(avatar);
+		}
+	}
+}
+
+/// A storage backend that keeps the platform data in a fast layer,
+/// such as the in-memory cache store, in front of a durable layer,
+/// such as the DB store
+///
+/// Platform data, such as friends, is read from the fast layer, falling back
+/// to the durable one if it's missing.
+/// Writes of platform data go to both, as dictated by the [`WritePolicy`].
+/// Profiles, circles, their mappings, rejected links, and authentications are
+/// only stored in the durable layer, as they're not looked up often and must
+/// not be lost.
+///
+/// Watching it notifies of the writes made through it, rather than of the
+/// ones that reach the layers, as batched writes reach the durable layer
+/// only when they're flushed.
+///
+/// Batches are applied to the layers one after the other, so
+/// [`apply_batch`](OnlivfeStore::apply_batch) is only atomic within each
+/// layer, not across them.
+/// If the fast layer fails after the durable one succeeded, the durable layer
+/// keeps the batch, and the fast layer catches up when the data is written or
+/// read through it again.
+#[derive(Debug)]
+pub struct LayeredStore<Fast: OnlivfeStore, Durable: OnlivfeStore> {
+	fast: Fast,
+	durable: Durable,
+	policy: WritePolicy,
+	pending: Mutex<Pending>,
+	changes: ChangeNotifier,
+}
+
+type LayeredResult<T, Fast, Durable> = Result<
+	T,
+	LayeredError<<Fast as OnlivfeStore>::Err, <Durable as OnlivfeStore>::Err>,
+>;
+
+impl<Fast: OnlivfeStore, Durable: OnlivfeStore> LayeredStore<Fast, Durable>
+where
+	Fast::Err: Send,
+	Durable::Err: Send,
+{
+	/// Layers the stores, filling the fast layer with the platform data that's
+	/// stored in the durable one
+	///
+	/// # Errors
+	///
+	/// If copying the platform data to the fast layer fails
+	pub async fn load(
+		fast: Fast, durable: Durable, policy: WritePolicy,
+	) -> LayeredResult<Self, Fast, Durable> {
+		let platform_data = StoreBatch {
+			accounts: durable
+				.accounts(usize::MAX)
+				.await
+				.map_err(LayeredError::Durable)?,
+			friends: durable
+				.friends(usize::MAX)
+				.await
+				.map_err(LayeredError::Durable)?,
+			instances: durable
+				.instances(usize::MAX)
+				.await
+				.map_err(LayeredError::Durable)?,
+			worlds: durable
+				.worlds(usize::MAX)
+				.await
+				.map_err(LayeredError::Durable)?,
+			avatars: durable
+				.avatars(usize::MAX)
+				.await
+				.map_err(LayeredError::Durable)?,
+			..StoreBatch::default()
+		};
+		fast.apply_batch(platform_data).await.map_err(LayeredError::Fast)?;
+
+		Ok(Self {
+			fast,
+			durable,
+			policy,
+			pending: Mutex::default(),
+			changes: ChangeNotifier::new(),
+		})
+	}
+
+	/// The fast layer
+	pub const fn fast(&self) -> &Fast { &self.fast }
+
+	/// The durable layer
+	pub const fn durable(&self) -> &Durable { &self.durable }
+
+	fn lock_pending(&self) -> MutexGuard<'_, Pending> {
+		// The pending writes are always left in a valid state
+		self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Stores the queued platform data writes in the durable layer.
+	///
+	/// Should be called periodically and before exiting when using
+	/// [`WritePolicy::Batched`], as the queued writes are lost otherwise.
+	///
+	/// # Errors
+	///
+	/// If storing into the durable layer fails, in which case the writes stay
+	/// queued
+	pub async fn flush(&self) -> LayeredResult<(), Fast, Durable> {
+		let pending = std::mem::take(&mut *self.lock_pending());
+		if pending.len() == 0 {
+			return Ok(());
+		}
+
+		if let Err(e) = self.durable.apply_batch(pending.to_batch()).await {
+			self.lock_pending().merge_older(pending);
+			return Err(LayeredError::Durable(e));
+		}
+
+		Ok(())
+	}
+
+	/// The accounts linked to a profile, if they're needed for notifying the
+	/// watchers of a change to them
+	async fn watched_account_ids(
+		&self, profile_id: &ProfileId,
+	) -> LayeredResult<Vec<PlatformAccountId>, Fast, Durable> {
+		if !self.changes.is_watched() {
+			return Ok(vec![]);
+		}
+
+		self
+			.durable
+			.profile_account_ids(profile_id.clone())
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	/// The circles that a profile is a member of, if they're needed for
+	/// notifying the watchers of a change to them
+	async fn watched_circle_ids(
+		&self, profile_id: &ProfileId,
+	) -> LayeredResult<Vec<CircleId>, Fast, Durable> {
+		if !self.changes.is_watched() {
+			return Ok(vec![]);
+		}
+
+		self
+			.durable
+			.member_circle_ids(CircleMember::Profile(profile_id.clone()))
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	/// Scores a hit from one of the layers again, as the layers' scores might
+	/// not be comparable with each other
+	async fn rescore(
+		&self, terms: &[String], hit: SearchHit,
+	) -> LayeredResult<SearchHit, Fast, Durable> {
+		let rescored = match &hit.entity {
+			EntityId::Profile(profile_id) => SearchHit::profile(
+				terms,
+				&self
+					.durable
+					.profile(profile_id.clone())
+					.await
+					.map_err(LayeredError::Durable)?,
+			),
+			EntityId::Account(account_id) => {
+				SearchHit::account(terms, &self.account(account_id.clone()).await?)
+			}
+			EntityId::Friend(friend_id) => {
+				SearchHit::friend(terms, &self.friend(friend_id.clone()).await?)
+			}
+			_ => None,
+		};
+
+		// The layer might match differently, for example ignoring diacritics
+		Ok(rescored.unwrap_or(SearchHit { score: 0.0, ..hit }))
+	}
+
+	/// The changes of the mappings, circle members and deletions of a batch,
+	/// if they're needed for notifying the watchers of them
+	async fn batch_changes(
+		&self, batch: &StoreBatch,
+	) -> LayeredResult<Vec<StoreChange>, Fast, Durable> {
+		let mut changes = vec![];
+		for (profile_id, account_ids) in &batch.profile_account_ids {
+			changes.extend(StoreChange::profile_accounts_replaced(
+				profile_id,
+				&self.watched_account_ids(profile_id).await?,
+				account_ids,
+			));
+		}
+		if self.changes.is_watched() {
+			changes.extend(batch.circle_members.iter().map(|(circle_id, _)| {
+				StoreChange::stored(EntityId::CircleMembers(circle_id.clone()), true)
+			}));
+		}
+		for profile_id in &batch.deleted_profiles {
+			changes.extend(StoreChange::profile_deleted(
+				profile_id,
+				&self.watched_account_ids(profile_id).await?,
+				&self.watched_circle_ids(profile_id).await?,
+			));
+		}
+
+		Ok(changes)
+	}
+
+	/// Flushes the queued writes if there are enough of them
+	async fn flush_if_full(&self) -> LayeredResult<(), Fast, Durable> {
+		let WritePolicy::Batched { max_pending } = self.policy else {
+			return Ok(());
+		};
+		if self.lock_pending().len() < max_pending {
+			return Ok(());
+		}
+
+		self.flush().await
+	}
+}
+
+/// Reads platform data from the fast layer,
+/// filling it from the durable layer if it's missing
+macro_rules! read_through {
+	($self:ident, $id:ident, $get:ident, $update:ident) => {{
+		if let Ok(data) = $self.fast.$get($id.clone()).await {
+			return Ok(data);
+		}
+		let data = $self.durable.$get($id).await.map_err(LayeredError::Durable)?;
+		$self.fast.$update(data.clone()).await.map_err(LayeredError::Fast)?;
+
+		Ok(data)
+	}};
+}
+
+/// Writes platform data to the fast layer, and to the durable one as dictated
+/// by the write policy
+macro_rules! write_platform_data {
+	(
+		$self:ident, $data:ident, $pending:ident, $update:ident, $entity:ident
+	) => {{
+		let id = $data.id();
+		match $self.policy {
+			WritePolicy::WriteThrough => {
+				let existed = $self
+					.durable
+					.$update($data.clone())
+					.await
+					.map_err(LayeredError::Durable)?;
+				$self.fast.$update($data).await.map_err(LayeredError::Fast)?;
+				$self.changes.notify_stored(EntityId::$entity(id), existed);
+
+				Ok(existed)
+			}
+			WritePolicy::Batched { .. } => {
+				let existed = $self
+					.fast
+					.$update($data.clone())
+					.await
+					.map_err(LayeredError::Fast)?;
+				$self.lock_pending().$pending.insert(id.clone(), $data);
+				$self.changes.notify_stored(EntityId::$entity(id), existed);
+				$self.flush_if_full().await?;
+
+				Ok(existed)
+			}
+		}
+	}};
+}
+
+#[async_trait::async_trait]
+impl<Fast: OnlivfeStore, Durable: OnlivfeStore> OnlivfeStore
+	for LayeredStore<Fast, Durable>
+where
+	Fast::Err: Send,
+	Durable::Err: Send,
+{
+	type Err = LayeredError<Fast::Err, Durable::Err>;
+
+	fn watch(&self) -> Option<ChangeStream> { Some(self.changes.watch()) }
+
+	async fn apply_batch(
+		&self, batch: StoreBatch,
+	) -> Result<BatchUpdated, Self::Err> {
+		let entity_ids =
+			if self.changes.is_watched() { batch.entity_ids() } else { vec![] };
+		let changes = self.batch_changes(&batch).await?;
+		let updated = match self.policy {
+			WritePolicy::WriteThrough => {
+				let (platform_data, _) = batch.clone().split_platform_data();
+				let updated = self
+					.durable
+					.apply_batch(batch)
+					.await
+					.map_err(LayeredError::Durable)?;
+				self
+					.fast
+					.apply_batch(platform_data)
+					.await
+					.map_err(LayeredError::Fast)?;
+
+				updated
+			}
+			WritePolicy::Batched { .. } => {
+				let (platform_data, durable_only) = batch.split_platform_data();
+				let durable_updated = self
+					.durable
+					.apply_batch(durable_only)
+					.await
+					.map_err(LayeredError::Durable)?;
+				let mut updated = self
+					.fast
+					.apply_batch(platform_data.clone())
+					.await
+					.map_err(LayeredError::Fast)?;
+				self.lock_pending().queue(platform_data);
+				updated.profiles = durable_updated.profiles;
+				updated.authentications = durable_updated.authentications;
+
+				updated
+			}
+		};
+
+		self.changes.notify_batch(entity_ids, &updated);
+		self.changes.notify(changes);
+		self.flush_if_full().await?;
+		Ok(updated)
+	}
+
+	async fn search(
+		&self, query: &str, max: usize,
+	) -> Result<Vec<SearchHit>, Self::Err> {
+		let terms = search::terms(query);
+		// Profiles are only stored in the durable layer,
+		// and the fast layer has the latest platform data
+		let profile_hits = self
+			.durable
+			.search(query, max)
+			.await
+			.map_err(LayeredError::Durable)?
+			.into_iter()
+			.filter(|hit| matches!(hit.entity, EntityId::Profile(_)));
+		let platform_hits = self
+			.fast
+			.search(query, max)
+			.await
+			.map_err(LayeredError::Fast)?
+			.into_iter()
+			.filter(|hit| !matches!(hit.entity, EntityId::Profile(_)));
+
+		let mut hits = vec![];
+		for hit in profile_hits.chain(platform_hits) {
+			hits.push(self.rescore(&terms, hit).await?);
+		}
+
+		Ok(search::rank(hits, max))
+	}
+
+	async fn account_ids(
+		&self, max: usize,
+	) -> Result<Vec<PlatformAccountId>, Self::Err> {
+		self.fast.account_ids(max).await.map_err(LayeredError::Fast)
+	}
+
+	async fn account(
+		&self, account_id: PlatformAccountId,
+	) -> Result<PlatformAccount, Self::Err> {
+		read_through!(self, account_id, account, update_account)
+	}
+
+	async fn account_profile_ids(
+		&self, account_id: PlatformAccountId,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		self
+			.durable
+			.account_profile_ids(account_id)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn update_account_profile_ids(
+		&self, account_id: PlatformAccountId, profile_ids: Vec<ProfileId>,
+	) -> Result<(), Self::Err> {
+		let previous_profile_ids = if self.changes.is_watched() {
+			self
+				.durable
+				.account_profile_ids(account_id.clone())
+				.await
+				.map_err(LayeredError::Durable)?
+		} else {
+			vec![]
+		};
+		self
+			.durable
+			.update_account_profile_ids(account_id.clone(), profile_ids.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify(StoreChange::account_profiles_replaced(
+			&account_id,
+			&previous_profile_ids,
+			&profile_ids,
+		));
+		Ok(())
+	}
+
+	async fn update_account(
+		&self, account: PlatformAccount,
+	) -> Result<bool, Self::Err> {
+		write_platform_data!(self, account, accounts, update_account, Account)
+	}
+
+	async fn friend_ids(
+		&self, max: usize,
+	) -> Result<Vec<PlatformAccountId>, Self::Err> {
+		self.fast.friend_ids(max).await.map_err(LayeredError::Fast)
+	}
+
+	async fn friend(
+		&self, friend_id: PlatformAccountId,
+	) -> Result<PlatformFriend, Self::Err> {
+		read_through!(self, friend_id, friend, update_friend)
+	}
+
+	async fn update_friend(
+		&self, friend: PlatformFriend,
+	) -> Result<bool, Self::Err> {
+		write_platform_data!(self, friend, friends, update_friend, Friend)
+	}
+
+	async fn instance_ids(
+		&self, max: usize,
+	) -> Result<Vec<InstanceId>, Self::Err> {
+		self.fast.instance_ids(max).await.map_err(LayeredError::Fast)
+	}
+
+	async fn instance(
+		&self, instance_id: InstanceId,
+	) -> Result<Instance, Self::Err> {
+		read_through!(self, instance_id, instance, update_instance)
+	}
+
+	async fn update_instance(
+		&self, instance: Instance,
+	) -> Result<bool, Self::Err> {
+		write_platform_data!(self, instance, instances, update_instance, Instance)
+	}
+
+	async fn world_ids(&self, max: usize) -> Result<Vec<WorldId>, Self::Err> {
+		self.fast.world_ids(max).await.map_err(LayeredError::Fast)
+	}
+
+	async fn world(&self, world_id: WorldId) -> Result<World, Self::Err> {
+		read_through!(self, world_id, world, update_world)
+	}
+
+	async fn update_world(&self, world: World) -> Result<bool, Self::Err> {
+		write_platform_data!(self, world, worlds, update_world, World)
+	}
+
+	async fn avatar_ids(&self, max: usize) -> Result<Vec<AvatarId>, Self::Err> {
+		self.fast.avatar_ids(max).await.map_err(LayeredError::Fast)
+	}
+
+	async fn avatar(&self, avatar_id: AvatarId) -> Result<Avatar, Self::Err> {
+		read_through!(self, avatar_id, avatar, update_avatar)
+	}
+
+	async fn update_avatar(&self, avatar: Avatar) -> Result<bool, Self::Err> {
+		write_platform_data!(self, avatar, avatars, update_avatar, Avatar)
+	}
+
+	async fn profile_ids(&self, max: usize) -> Result<Vec<ProfileId>, Self::Err> {
+		self.durable.profile_ids(max).await.map_err(LayeredError::Durable)
+	}
+
+	async fn tagged_profile_ids(
+		&self, tag: &str, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		self
+			.durable
+			.tagged_profile_ids(tag, max)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn profile(&self, profile_id: ProfileId) -> Result<Profile, Self::Err> {
+		self.durable.profile(profile_id).await.map_err(LayeredError::Durable)
+	}
+
+	async fn profile_account_ids(
+		&self, profile_id: ProfileId,
+	) -> Result<Vec<PlatformAccountId>, Self::Err> {
+		self
+			.durable
+			.profile_account_ids(profile_id)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn update_profile_account_ids(
+		&self, profile_id: ProfileId, account_ids: Vec<PlatformAccountId>,
+	) -> Result<(), Self::Err> {
+		let previous_account_ids = self.watched_account_ids(&profile_id).await?;
+		self
+			.durable
+			.update_profile_account_ids(profile_id.clone(), account_ids.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify(StoreChange::profile_accounts_replaced(
+			&profile_id,
+			&previous_account_ids,
+			&account_ids,
+		));
+		Ok(())
+	}
+
+	async fn update_profile(&self, profile: Profile) -> Result<bool, Self::Err> {
+		let profile_id = profile.sharing_id.clone();
+		let existed = self
+			.durable
+			.update_profile(profile)
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify_stored(EntityId::Profile(profile_id), existed);
+		Ok(existed)
+	}
+
+	async fn delete_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		let account_ids = self.watched_account_ids(&profile_id).await?;
+		let circle_ids = self.watched_circle_ids(&profile_id).await?;
+		self
+			.durable
+			.delete_profile(profile_id.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify(StoreChange::profile_deleted(
+			&profile_id,
+			&account_ids,
+			&circle_ids,
+		));
+		Ok(())
+	}
+
+	async fn trashed_profile_ids(
+		&self, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		self.durable.trashed_profile_ids(max).await.map_err(LayeredError::Durable)
+	}
+
+	async fn trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<TrashedProfile, Self::Err> {
+		self
+			.durable
+			.trashed_profile(profile_id)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn update_trashed_profile(
+		&self, trashed: TrashedProfile,
+	) -> Result<bool, Self::Err> {
+		let profile_id = trashed.profile.sharing_id.clone();
+		let existed = self
+			.durable
+			.update_trashed_profile(trashed)
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify_stored(EntityId::TrashedProfile(profile_id), existed);
+		Ok(existed)
+	}
+
+	async fn delete_trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		self
+			.durable
+			.delete_trashed_profile(profile_id.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify([StoreChange {
+			entity: EntityId::TrashedProfile(profile_id),
+			kind: ChangeKind::Deleted,
+		}]);
+		Ok(())
+	}
+
+	async fn profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<Vec<ProfileRevision>, Self::Err> {
+		self
+			.durable
+			.profile_revisions(profile_id)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn update_profile_revisions(
+		&self, profile_id: ProfileId, revisions: Vec<ProfileRevision>,
+	) -> Result<(), Self::Err> {
+		self
+			.durable
+			.update_profile_revisions(profile_id, revisions)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn delete_profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		self
+			.durable
+			.delete_profile_revisions(profile_id)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<ProfilePicture, Self::Err> {
+		self
+			.durable
+			.profile_picture(profile_id)
+			.await
+			.map_err(LayeredError::Durable)
+	}
+
+	async fn update_profile_picture(
+		&self, picture: ProfilePicture,
+	) -> Result<bool, Self::Err> {
+		let profile_id = picture.profile_id.clone();
+		let existed = self
+			.durable
+			.update_profile_picture(picture)
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify_stored(EntityId::ProfilePicture(profile_id), existed);
+		Ok(existed)
+	}
+
+	async fn delete_profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		self
+			.durable
+			.delete_profile_picture(profile_id.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify([StoreChange {
+			entity: EntityId::ProfilePicture(profile_id),
+			kind: ChangeKind::Deleted,
+		}]);
+		Ok(())
+	}
+
+	async fn circle_ids(&self, max: usize) -> Result<Vec<CircleId>, Self::Err> {
+		self.durable.circle_ids(max).await.map_err(LayeredError::Durable)
+	}
+
+	async fn circle(&self, circle_id: CircleId) -> Result<Circle, Self::Err> {
+		self.durable.circle(circle_id).await.map_err(LayeredError::Durable)
+	}
+
+	async fn update_circle(&self, circle: Circle) -> Result<bool, Self::Err> {
+		let circle_id = circle.id.clone();
+		let existed = self
+			.durable
+			.update_circle(circle)
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify_stored(EntityId::Circle(circle_id), existed);
+		Ok(existed)
+	}
+
+	async fn delete_circle(&self, circle_id: CircleId) -> Result<(), Self::Err> {
+		self
+			.durable
+			.delete_circle(circle_id.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify(StoreChange::circle_deleted(&circle_id));
+		Ok(())
+	}
+
+	async fn circle_members(
+		&self, circle_id: CircleId,
+	) -> Result<Vec<CircleMember>, Self::Err> {
+		self.durable.circle_members(circle_id).await.map_err(LayeredError::Durable)
+	}
+
+	async fn update_circle_members(
+		&self, circle_id: CircleId, members: Vec<CircleMember>,
+	) -> Result<(), Self::Err> {
+		self
+			.durable
+			.update_circle_members(circle_id.clone(), members)
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify_stored(EntityId::CircleMembers(circle_id), true);
+		Ok(())
+	}
+
+	async fn member_circle_ids(
+		&self, member: CircleMember,
+	) -> Result<Vec<CircleId>, Self::Err> {
+		self.durable.member_circle_ids(member).await.map_err(LayeredError::Durable)
+	}
+
+	async fn rejected_links(&self) -> Result<Vec<AccountLink>, Self::Err> {
+		self.durable.rejected_links().await.map_err(LayeredError::Durable)
+	}
+
+	async fn reject_link(&self, link: AccountLink) -> Result<bool, Self::Err> {
+		let existed = self
+			.durable
+			.reject_link(link.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		if !existed {
+			self.changes.notify_stored(EntityId::RejectedLink(link), false);
+		}
+		Ok(existed)
+	}
+
+	async fn authentications(&self) -> Result<Vec<Authentication>, Self::Err> {
+		self.durable.authentications().await.map_err(LayeredError::Durable)
+	}
+
+	async fn update_authentication(
+		&self, authentication: Authentication,
+	) -> Result<bool, Self::Err> {
+		let id = authentication.id();
+		let existed = self
+			.durable
+			.update_authentication(authentication)
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		self.changes.notify_stored(EntityId::Authentication(id), existed);
+		Ok(existed)
+	}
+
+	async fn remove_authentication(
+		&self, id: PlatformAccountId,
+	) -> Result<bool, Self::Err> {
+		let removed = self
+			.durable
+			.remove_authentication(id.clone())
+			.await
+			.map_err(LayeredError::Durable)?;
+
+		if removed {
+			self.changes.notify([StoreChange {
+				entity: EntityId::Authentication(id),
+				kind: ChangeKind::Deleted,
+			}]);
+		}
+		Ok(removed)
+	}
+}
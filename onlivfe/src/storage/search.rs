@@ -0,0 +1,127 @@
+//! Matching & ranking of search results, shared by the storage backends that
+//! don't have a search engine of their own
+
+use serde::{Deserialize, Serialize};
+
+use super::EntityId;
+use crate::{PlatformAccount, PlatformFriend, PlatformType, Profile};
+
+/// How much more a match in the nick of a profile is worth than one in its
+/// notes
+const NICK_WEIGHT: f64 = 2.0;
+
+/// Something that matched a search, see
+/// [`OnlivfeStore::search`](super::OnlivfeStore::search)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+	/// What matched, a profile, a friend or an account
+	pub entity: EntityId,
+	/// The platform of a friend or an account
+	pub platform: Option<PlatformType>,
+	/// The nick of a profile, or the display name of a friend or an account
+	pub name: Option<String>,
+	/// How well it matched, higher being better.
+	///
+	/// Only comparable with the scores of the same search.
+	pub score: f64,
+}
+
+impl SearchHit {
+	/// Matches a profile's nick & notes
+	#[must_use]
+	pub fn profile(terms: &[String], profile: &Profile) -> Option<Self> {
+		let nick_score = profile
+			.nick
+			.as_deref()
+			.and_then(|nick| match_score(terms, nick))
+			.map(|score| score * NICK_WEIGHT);
+		let notes_score =
+			profile.notes.as_deref().and_then(|notes| match_score(terms, notes));
+		let score = nick_score.into_iter().chain(notes_score).reduce(f64::max)?;
+
+		Some(Self {
+			entity: EntityId::Profile(profile.sharing_id.clone()),
+			platform: None,
+			name: profile.nick.clone(),
+			score,
+		})
+	}
+
+	/// Matches an account's display name
+	#[must_use]
+	pub fn account(terms: &[String], account: &PlatformAccount) -> Option<Self> {
+		let name = account.display_name();
+		Some(Self {
+			score: match_score(terms, name)?,
+			entity: EntityId::Account(account.id()),
+			platform: Some(account.platform()),
+			name: Some(name.to_owned()),
+		})
+	}
+
+	/// Matches a friend's display name
+	#[must_use]
+	pub fn friend(terms: &[String], friend: &PlatformFriend) -> Option<Self> {
+		let name = friend.display_name();
+		Some(Self {
+			score: match_score(terms, name)?,
+			entity: EntityId::Friend(friend.id()),
+			platform: Some(friend.platform()),
+			name: Some(name.to_owned()),
+		})
+	}
+}
+
+/// Splits text into lowercase words, which are what's matched
+#[must_use]
+pub fn terms(text: &str) -> Vec<String> {
+	text
+		.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(str::to_lowercase)
+		.collect()
+}
+
+/// Scores how well the text matches the search terms,
+/// or `None` if some of the terms don't match at all.
+///
+/// A term matching a whole word is worth more than it matching the start of a
+/// word, which is worth more than it matching elsewhere in a word.
+/// Shorter texts are preferred, as more of them matched.
+#[must_use]
+pub fn match_score(terms: &[String], text: &str) -> Option<f64> {
+	if terms.is_empty() {
+		return None;
+	}
+	let words = self::terms(text);
+
+	let mut score = 0.0;
+	for term in terms {
+		score += words
+			.iter()
+			.filter_map(|word| {
+				if word == term {
+					Some(3.0)
+				} else if word.starts_with(term.as_str()) {
+					Some(2.0)
+				} else if word.contains(term.as_str()) {
+					Some(1.0)
+				} else {
+					None
+				}
+			})
+			.reduce(f64::max)?;
+	}
+
+	#[allow(clippy::cast_precision_loss)]
+	Some(score + 1.0 / (words.len() as f64 + 1.0))
+}
+
+/// Orders the hits from the best match to the worst, keeping at most `max` of
+/// them
+#[must_use]
+pub fn rank(mut hits: Vec<SearchHit>, max: usize) -> Vec<SearchHit> {
+	hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+	hits.truncate(max);
+	hits
+}
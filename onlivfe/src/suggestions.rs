@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{PlatformAccount, PlatformAccountId, PlatformFriend};
+
+/// How similar the names need to be to count as a reason
+const MIN_NAME_SIMILARITY: f32 = 0.8;
+/// Names shorter than this are too generic to tell anything
+const MIN_NAME_LEN: usize = 3;
+
+/// Two accounts that could belong to the same person.
+///
+/// The order of the accounts doesn't matter, so that the same pair is always
+/// equal to itself.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "[PlatformAccountId; 2]", into = "[PlatformAccountId; 2]")]
+pub struct AccountLink {
+	first: PlatformAccountId,
+	second: PlatformAccountId,
+}
+
+impl AccountLink {
+	/// Creates a link between two accounts, in whichever order
+	#[must_use]
+	pub fn new(a: PlatformAccountId, b: PlatformAccountId) -> Self {
+		let key = |id: &PlatformAccountId| {
+			(id.platform().as_ref().to_owned(), id.id_as_string())
+		};
+		if key(&a) <= key(&b) {
+			Self { first: a, second: b }
+		} else {
+			Self { first: b, second: a }
+		}
+	}
+
+	/// The linked accounts
+	#[must_use]
+	pub const fn account_ids(&self) -> [&PlatformAccountId; 2] {
+		[&self.first, &self.second]
+	}
+
+	/// If the account is one of the linked ones
+	#[must_use]
+	pub fn contains(&self, account_id: &PlatformAccountId) -> bool {
+		&self.first == account_id || &self.second == account_id
+	}
+}
+
+impl From<[PlatformAccountId; 2]> for AccountLink {
+	fn from([a, b]: [PlatformAccountId; 2]) -> Self { Self::new(a, b) }
+}
+
+impl From<AccountLink> for [PlatformAccountId; 2] {
+	fn from(value: AccountLink) -> Self { [value.first, value.second] }
+}
+
+/// What is known about an account for suggesting links to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkCandidate {
+	/// The account
+	pub account_id: PlatformAccountId,
+	/// The names that the account has been seen with
+	pub display_names: Vec<String>,
+	/// The links in the account's bio
+	pub bio_links: Vec<String>,
+}
+
+impl LinkCandidate {
+	/// Creates a candidate without anything known about it
+	#[must_use]
+	pub const fn new(account_id: PlatformAccountId) -> Self {
+		Self { account_id, display_names: vec![], bio_links: vec![] }
+	}
+
+	/// Adds what's known from another candidate of the same account
+	pub fn merge(&mut self, other: Self) {
+		for name in other.display_names {
+			if !self.display_names.contains(&name) {
+				self.display_names.push(name);
+			}
+		}
+		for link in other.bio_links {
+			if !self.bio_links.contains(&link) {
+				self.bio_links.push(link);
+			}
+		}
+	}
+}
+
+impl From<&PlatformAccount> for LinkCandidate {
+	fn from(account: &PlatformAccount) -> Self {
+		Self {
+			account_id: account.id(),
+			display_names: vec![account.display_name().to_owned()],
+			bio_links: account.bio_links().to_vec(),
+		}
+	}
+}
+
+impl From<&PlatformFriend> for LinkCandidate {
+	fn from(friend: &PlatformFriend) -> Self {
+		Self {
+			account_id: friend.id(),
+			display_names: vec![friend.display_name().to_owned()],
+			bio_links: friend.bio_links().to_vec(),
+		}
+	}
+}
+
+/// Why two accounts are suggested to belong to the same person
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum LinkReason {
+	/// The accounts have similar names
+	SimilarName {
+		/// From 0 to 1, where 1 is the same name ignoring case and symbols
+		similarity: f32,
+	},
+	/// The accounts have the same links in their bios
+	SharedBioLinks {
+		/// The links that both of them have
+		links: Vec<String>,
+	},
+}
+
+impl LinkReason {
+	/// How likely the reason alone makes it that the accounts are the same
+	/// person's, from 0 to 1
+	#[must_use]
+	pub fn confidence(&self) -> f32 {
+		match self {
+			Self::SimilarName { similarity } => 0.6 * similarity,
+			Self::SharedBioLinks { .. } => 0.9,
+		}
+	}
+}
+
+/// A suggestion of two accounts on different platforms belonging to the same
+/// person
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkSuggestion {
+	/// The accounts that are suggested to be linked
+	pub link: AccountLink,
+	/// How likely it is that the accounts are the same person's, from 0 to 1
+	pub confidence: f32,
+	/// Why the accounts are suggested to be linked
+	pub reasons: Vec<LinkReason>,
+}
+
+/// Suggests links between accounts on different platforms, most confident
+/// first.
+///
+/// Candidates of the same account are merged together, and pairs without any
+/// reasons to link them are left out.
+#[must_use]
+pub fn suggest_links(
+	candidates: impl IntoIterator<Item = LinkCandidate>,
+) -> Vec<LinkSuggestion> {
+	let mut merged: Vec<LinkCandidate> = vec![];
+	let mut indexes: HashMap<PlatformAccountId, usize> = HashMap::new();
+	for candidate in candidates {
+		let Some(&index) = indexes.get(&candidate.account_id) else {
+			indexes.insert(candidate.account_id.clone(), merged.len());
+			merged.push(candidate);
+			continue;
+		};
+		merged[index].merge(candidate);
+	}
+
+	let mut suggestions = vec![];
+	for (i, a) in merged.iter().enumerate() {
+		for b in &merged[i + 1..] {
+			if a.account_id.platform() == b.account_id.platform() {
+				continue;
+			}
+			let reasons = link_reasons(a, b);
+			if reasons.is_empty() {
+				continue;
+			}
+			// Each reason is treated as independent evidence
+			let confidence = 1.0
+				- reasons
+					.iter()
+					.map(|reason| 1.0 - reason.confidence())
+					.product::<f32>();
+			suggestions.push(LinkSuggestion {
+				link: AccountLink::new(a.account_id.clone(), b.account_id.clone()),
+				confidence,
+				reasons,
+			});
+		}
+	}
+	suggestions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+	suggestions
+}
+
+fn link_reasons(a: &LinkCandidate, b: &LinkCandidate) -> Vec<LinkReason> {
+	let mut reasons = vec![];
+
+	let similarity = a
+		.display_names
+		.iter()
+		.flat_map(|a| b.display_names.iter().map(move |b| name_similarity(a, b)))
+		.fold(0.0, f32::max);
+	if similarity >= MIN_NAME_SIMILARITY {
+		reasons.push(LinkReason::SimilarName { similarity });
+	}
+
+	let a_links: HashSet<String> =
+		a.bio_links.iter().map(|link| normalize_link(link)).collect();
+	let links: Vec<String> = b
+		.bio_links
+		.iter()
+		.filter(|link| a_links.contains(&normalize_link(link)))
+		.cloned()
+		.collect();
+	if !links.is_empty() {
+		reasons.push(LinkReason::SharedBioLinks { links });
+	}
+
+	reasons
+}
+
+/// The similarity of names from 0 to 1, ignoring case and anything that's not
+/// a letter or a number
+// Names are short enough to not lose any precision
+#[allow(clippy::cast_precision_loss)]
+fn name_similarity(a: &str, b: &str) -> f32 {
+	let normalize = |name: &str| -> Vec<char> {
+		name
+			.chars()
+			.filter(|c| c.is_alphanumeric())
+			.flat_map(char::to_lowercase)
+			.collect()
+	};
+	let (a, b) = (normalize(a), normalize(b));
+	if a.len() < MIN_NAME_LEN || b.len() < MIN_NAME_LEN {
+		return 0.0;
+	}
+
+	// Levenshtein distance, keeping only the previous row
+	let mut previous: Vec<usize> = (0..=b.len()).collect();
+	for (i, a_char) in a.iter().enumerate() {
+		let mut current = vec![i + 1];
+		for (j, b_char) in b.iter().enumerate() {
+			let substitution = previous[j] + usize::from(a_char != b_char);
+			current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+		}
+		previous = current;
+	}
+	let distance = previous[b.len()];
+
+	1.0 - distance as f32 / a.len().max(b.len()) as f32
+}
+
+/// Strips the parts of a link that don't change where it points to
+fn normalize_link(link: &str) -> String {
+	let link = link.trim().to_lowercase();
+	let link = link
+		.strip_prefix("https://")
+		.or_else(|| link.strip_prefix("http://"))
+		.unwrap_or(&link);
+	let link = link.strip_prefix("www.").unwrap_or(link);
+
+	link.trim_end_matches('/').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::PlatformType;
+
+	fn account_id(platform: PlatformType, id: &str) -> PlatformAccountId {
+		serde_json::from_value(
+			serde_json::json!({ "platform": platform, "id": id }),
+		)
+		.expect("the platform account ID to be valid")
+	}
+
+	fn candidate(
+		platform: PlatformType, id: &str, name: &str, bio_links: &[&str],
+	) -> LinkCandidate {
+		LinkCandidate {
+			account_id: account_id(platform, id),
+			display_names: vec![name.to_owned()],
+			bio_links: bio_links.iter().map(|&link| link.to_owned()).collect(),
+		}
+	}
+
+	fn vrchat(name: &str, bio_links: &[&str]) -> LinkCandidate {
+		candidate(
+			PlatformType::VRChat,
+			"usr_c1644b5b-3ca4-45b4-97c6-a2a0de70d469",
+			name,
+			bio_links,
+		)
+	}
+
+	fn resonite(name: &str) -> LinkCandidate {
+		candidate(PlatformType::Resonite, "U-onlivfe", name, &[])
+	}
+
+	#[test]
+	fn links_are_normalized() {
+		assert_eq!(
+			normalize_link(" https://www.Example.com/onlivfe/ "),
+			"example.com/onlivfe"
+		);
+		assert_eq!(
+			normalize_link("http://example.com"),
+			normalize_link("example.com")
+		);
+		assert_ne!(
+			normalize_link("example.com/a"),
+			normalize_link("example.com/b")
+		);
+	}
+
+	#[test]
+	fn name_similarity_ignores_case_and_symbols() {
+		assert!(
+			(name_similarity("Onli_vfe", "onlivfe") - 1.0).abs() < f32::EPSILON
+		);
+		assert!((name_similarity("kitten", "sitting") - 4.0 / 7.0).abs() < 0.001);
+		assert!(name_similarity("onlivfe", "zzzzzzz") < MIN_NAME_SIMILARITY);
+	}
+
+	#[test]
+	fn short_names_are_not_similar() {
+		assert!(name_similarity("ab", "ab").abs() < f32::EPSILON);
+		assert!(name_similarity("a!b", "ab").abs() < f32::EPSILON);
+	}
+
+	#[test]
+	fn suggests_links_between_platforms_only() {
+		let same_platform = candidate(
+			PlatformType::VRChat,
+			"usr_5d7c3b1a-9e8f-4a2b-b6c5-d4e3f2a1b0c9",
+			"Onlivfe",
+			&[],
+		);
+		let unrelated =
+			candidate(PlatformType::ChilloutVR, "5d7c3b1a", "Somebody else", &[]);
+
+		let suggestions = suggest_links([
+			vrchat("Onlivfe", &[]),
+			same_platform,
+			resonite("onlivfe"),
+			unrelated,
+		]);
+
+		assert_eq!(suggestions.len(), 2, "{suggestions:?}");
+		for suggestion in &suggestions {
+			assert!(suggestion.link.contains(&resonite("").account_id));
+			assert!(matches!(
+				suggestion.reasons.as_slice(),
+				[LinkReason::SimilarName { .. }]
+			));
+		}
+	}
+
+	#[test]
+	fn suggestions_merge_candidates_and_sort_by_confidence() {
+		let cvr = candidate(
+			PlatformType::ChilloutVR,
+			"c1644b5b-3ca4-45b4-97c6-a2a0de70d469",
+			"Someone",
+			&["https://example.com/onlivfe"],
+		);
+
+		let suggestions = suggest_links([
+			vrchat("Onlivfe", &[]),
+			resonite("Onlivfe"),
+			cvr,
+			// The same account with what else is known about it
+			vrchat("Someone else", &["example.com/onlivfe/"]),
+		]);
+
+		let vrchat_id = vrchat("", &[]).account_id;
+		let links: Vec<&AccountLink> =
+			suggestions.iter().map(|suggestion| &suggestion.link).collect();
+		assert_eq!(links.len(), 2, "{suggestions:?}");
+		assert!(links.iter().all(|link| link.contains(&vrchat_id)));
+		assert!(matches!(
+			suggestions[0].reasons.as_slice(),
+			[LinkReason::SharedBioLinks { links }] if links.len() == 1
+		));
+		assert!(suggestions[0].confidence > suggestions[1].confidence);
+	}
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{CircleId, PlatformAccountId, Profile};
+
+/// A profile that was moved to the trash, along with what's needed to restore
+/// it, see
+/// [`OnlivfeStore::trash_profile`](crate::storage::OnlivfeStore::trash_profile)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedProfile {
+	/// The profile itself
+	pub profile: Profile,
+	/// The accounts that were linked to the profile
+	pub account_ids: Vec<PlatformAccountId>,
+	/// The circles that the profile was a member of
+	pub circle_ids: Vec<CircleId>,
+	/// When the profile was moved to the trash
+	pub trashed_at: OffsetDateTime,
+}
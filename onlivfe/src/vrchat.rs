@@ -22,10 +22,27 @@ impl From<super::PlatformDataMetadata<vrc::id::User>>
 }
 
 /// A VRC login request portion
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum LoginRequestPart {
 	/// Login with credentials
 	LoginRequest(vrc::query::Authenticating),
 	/// Continuing authentication with second factor
 	SecondFactor((vrc::id::User, vrc::query::VerifySecondFactor)),
 }
+
+// Manually implemented to keep the password and 2FA code out of logs
+impl std::fmt::Debug for LoginRequestPart {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::LoginRequest(authenticating) => f
+				.debug_struct("LoginRequest")
+				.field("username", &authenticating.username)
+				.finish_non_exhaustive(),
+			Self::SecondFactor((id, _)) => f
+				.debug_tuple("SecondFactor")
+				.field(id)
+				.field(&format_args!("<redacted>"))
+				.finish(),
+		}
+	}
+}
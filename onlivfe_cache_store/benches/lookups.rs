@@ -0,0 +1,56 @@
+//! Measures how profile & account mapping lookups scale with the amount of
+//! stored mappings.
+//!
+//! The time per lookup should stay roughly the same regardless of the amount,
+//! whereas it used to grow linearly with it.
+
+use std::time::Instant;
+
+use onlivfe::{PlatformAccountId, ProfileId, storage::OnlivfeStore};
+use onlivfe_cache_store::OnlivfeCacheStorageBackend;
+
+const SIZES: [u32; 4] = [10, 100, 1_000, 10_000];
+
+fn account_id(i: u32) -> PlatformAccountId {
+	let id = format!("usr_00000000-0000-4000-8000-{i:012x}");
+	PlatformAccountId::VRChat(id.parse().expect("the user ID should be valid"))
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+	let store = OnlivfeCacheStorageBackend::builder()
+		.in_memory()
+		.build()
+		.expect("the storage backend should be creatable");
+
+	for size in SIZES {
+		let profile_id = ProfileId::new();
+		let account_ids: Vec<PlatformAccountId> =
+			(0..size).map(account_id).collect();
+		store
+			.update_profile_account_ids(profile_id.clone(), account_ids.clone())
+			.await
+			.expect("storing the mappings should succeed");
+
+		let start = Instant::now();
+		for account_id in account_ids {
+			let profile_ids = store
+				.account_profile_ids(account_id)
+				.await
+				.expect("looking up the mappings should succeed");
+			assert_eq!(profile_ids, vec![profile_id.clone()]);
+		}
+		let elapsed = start.elapsed();
+
+		println!(
+			"{size:>6} mappings: {:>10.2?} total, {:>8.2?} per lookup",
+			elapsed,
+			elapsed / size
+		);
+
+		store
+			.delete_profile(profile_id)
+			.await
+			.expect("cleaning up the mappings should succeed");
+	}
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use onlivfe::{
+	Authentication,
+	PlatformAccountId,
+	encryption::{AuthCipher, AuthKey, KeyParams, Sealed},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{trace, warn};
+
+use crate::{Persistence, SkippedEntry, schema::AUTHENTICATIONS};
+
+/// Gets the cipher for the stored authentications, if they should be
+/// encrypted
+///
+/// # Errors
+///
+/// If the key is wrong, or if the authentications are encrypted but there's
+/// no key
+pub fn unlock(
+	key: Option<&AuthKey>, key_params: Option<&KeyParams>,
+) -> Result<Option<AuthCipher>, String> {
+	let cipher = match (key, key_params) {
+		(Some(key), Some(key_params)) => AuthCipher::unlock(key, key_params),
+		(Some(key), None) => AuthCipher::create(key),
+		(None, Some(_)) => {
+			return Err(
+				"The stored authentications are encrypted, but no key was given"
+					.to_owned(),
+			);
+		}
+		(None, None) => return Ok(None),
+	};
+
+	cipher.map(Some).map_err(|e| e.to_string())
+}
+
+/// Reads the stored authentications and the cipher for them, encrypting them
+/// if there's a key but they were stored in cleartext
+///
+/// # Errors
+///
+/// If the file couldn't be read, the key is wrong, or the encryption fails
+pub fn read(
+	persistence: &Persistence, key: Option<&AuthKey>,
+	skipped_entries: &mut Vec<SkippedEntry>,
+) -> Result<
+	(HashMap<PlatformAccountId, Authentication>, Option<AuthCipher>),
+	String,
+> {
+	let (entries, key_params) = persistence
+		.read(AUTHENTICATIONS.file_name, |bytes| {
+			AUTHENTICATIONS.parse_encrypted(bytes)
+		})?
+		.unwrap_or_default();
+	let cipher = unlock(key, key_params.as_ref())?;
+	let (authentications, mut skipped, had_cleartext) =
+		decode(cipher.as_ref(), entries);
+	skipped.iter_mut().for_each(redact);
+	skipped_entries.append(&mut skipped);
+	let authentications: HashMap<PlatformAccountId, Authentication> =
+		authentications.into_iter().map(|auth| (auth.id(), auth)).collect();
+	if cipher.is_some() && (had_cleartext || key_params.is_none()) {
+		trace!("Encrypting previously unencrypted authentications");
+		// The backup would still have them in cleartext
+		write(persistence, &authentications, cipher.as_ref())
+			.and_then(|()| persistence.remove_backup(AUTHENTICATIONS.file_name))
+			.map_err(|e| format!("Failed to encrypt authentications: {e}"))?;
+	}
+	if cipher.is_some() {
+		// It might be from before the encryption, and is unusable anyways
+		persistence
+			.remove_unusable(AUTHENTICATIONS.file_name)
+			.map_err(|e| format!("Failed to remove unusable authentications: {e}"))?;
+	}
+
+	Ok((authentications, cipher))
+}
+
+/// Leaves out the entry of a skipped authentication unless it's encrypted, as
+/// it would have the tokens in cleartext
+pub fn redact(skipped: &mut SkippedEntry) {
+	if skipped.file_name == AUTHENTICATIONS.file_name
+		&& Sealed::deserialize(&skipped.raw).is_err()
+	{
+		skipped.raw = Value::Null;
+	}
+}
+
+/// Decrypts the entries of the authentications file, skipping the ones that
+/// fail.
+///
+/// Also returns if there were cleartext entries that should be encrypted.
+pub fn decode(
+	cipher: Option<&AuthCipher>, entries: Vec<Value>,
+) -> (Vec<Authentication>, Vec<SkippedEntry>, bool) {
+	let Some(cipher) = cipher else {
+		let (decoded, skipped) = AUTHENTICATIONS.decode(entries);
+		return (decoded, skipped, false);
+	};
+
+	let mut decoded = Vec::with_capacity(entries.len());
+	let mut skipped = vec![];
+	let mut had_cleartext = false;
+	for raw in entries {
+		let result = Sealed::deserialize(&raw).map_or_else(
+			|_| {
+				had_cleartext = true;
+				Authentication::deserialize(&raw).map_err(|e| e.to_string())
+			},
+			|sealed| cipher.open(&sealed).map_err(|e| e.to_string()),
+		);
+		match result {
+			Ok(auth) => decoded.push(auth),
+			Err(e) => {
+				warn!("Skipping authentication that couldn't be loaded: {}", e);
+				skipped.push(SkippedEntry {
+					file_name: AUTHENTICATIONS.file_name.to_owned(),
+					reason: e,
+					raw,
+				});
+			}
+		}
+	}
+
+	(decoded, skipped, had_cleartext)
+}
+
+/// Writes the authentications file, encrypting the entries if there's a
+/// cipher
+pub fn write(
+	persistence: &Persistence,
+	authentications: &HashMap<PlatformAccountId, Authentication>,
+	cipher: Option<&AuthCipher>,
+) -> Result<(), std::io::Error> {
+	trace!("Going to write {} authentications", authentications.len());
+	let bytes = match cipher {
+		Some(cipher) => {
+			let sealed = authentications
+				.values()
+				.map(|auth| cipher.seal(auth))
+				.collect::<Result<Vec<Sealed>, _>>()
+				.map_err(|e| {
+					std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+				})?;
+			AUTHENTICATIONS.encode_encrypted(sealed, cipher.params())
+		}
+		None => AUTHENTICATIONS.encode(authentications.values().collect()),
+	}
+	.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+	persistence.write_file(AUTHENTICATIONS.file_name, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use onlivfe::storage::{OnlivfeStore, conformance::Samples};
+
+	use super::*;
+	use crate::OnlivfeCacheStorageBackend;
+
+	const TOKEN: &str = "authcookie_onlivfe-conformance";
+
+	fn key(passphrase: &str) -> AuthKey {
+		AuthKey::Passphrase(passphrase.to_owned())
+	}
+
+	fn open(
+		dir: &std::path::Path, key: Option<AuthKey>,
+	) -> Result<OnlivfeCacheStorageBackend, String> {
+		let builder = OnlivfeCacheStorageBackend::builder().path(dir);
+		match key {
+			Some(key) => builder.encrypt_authentications(key),
+			None => builder,
+		}
+		.build()
+	}
+
+	fn contains_token(path: &std::path::Path) -> bool {
+		std::fs::read_to_string(path).is_ok_and(|text| text.contains(TOKEN))
+	}
+
+	#[tokio::test]
+	async fn cleartext_authentications_are_encrypted_on_load() {
+		let dir = tempfile::tempdir().expect("a temporary directory");
+		let authentication = Samples::builtin().authentications.remove(0);
+		let store = open(dir.path(), None).expect("the store to be created");
+		store
+			.update_authentication(authentication.clone())
+			.await
+			.expect("storing an authentication");
+		// Rewritten so that there's a cleartext backup too
+		store
+			.update_authentication(authentication.clone())
+			.await
+			.expect("storing an authentication");
+		drop(store);
+		let auth_file = dir.path().join("auth.json");
+		let leftovers = [
+			dir.path().join("auth.json.bak"),
+			dir.path().join("auth.json.unusable"),
+			dir.path().join("skipped.json"),
+		];
+		let cleartext = std::fs::read(&auth_file).expect("the cleartext file");
+		std::fs::write(&leftovers[1], &cleartext).expect("an unusable file");
+		let skipped = vec![SkippedEntry {
+			file_name: AUTHENTICATIONS.file_name.to_owned(),
+			reason: "Previously skipped".to_owned(),
+			raw: serde_json::to_value(&authentication).unwrap(),
+		}];
+		std::fs::write(&leftovers[2], serde_json::to_vec(&skipped).unwrap())
+			.expect("a skipped entries file");
+		assert!(contains_token(&auth_file));
+		assert!(leftovers.iter().all(|path| contains_token(path)));
+
+		let store = open(dir.path(), Some(key("passphrase")))
+			.expect("the store to be opened with a key");
+		assert_eq!(
+			store.authentications().await.expect("the authentications").len(),
+			1
+		);
+		drop(store);
+
+		assert!(!contains_token(&auth_file));
+		for path in &leftovers {
+			assert!(!contains_token(path), "{} had the token", path.display());
+		}
+		assert!(
+			open(dir.path(), None).is_err(),
+			"the store shouldn't open without the key anymore"
+		);
+	}
+
+	#[tokio::test]
+	async fn rekeyed_authentications_need_new_key() {
+		let dir = tempfile::tempdir().expect("a temporary directory");
+		let authentication = Samples::builtin().authentications.remove(0);
+		let store =
+			open(dir.path(), Some(key("old"))).expect("the store to be created");
+		store
+			.update_authentication(authentication)
+			.await
+			.expect("storing an authentication");
+		store
+			.rekey_authentications(Some(key("new")))
+			.await
+			.expect("re-keying the authentications");
+		drop(store);
+
+		assert!(open(dir.path(), Some(key("old"))).is_err());
+		assert!(!dir.path().join("auth.json.bak").exists());
+		let store = open(dir.path(), Some(key("new")))
+			.expect("the store to be opened with the new key");
+		assert_eq!(
+			store.authentications().await.expect("the authentications").len(),
+			1
+		);
+
+		store
+			.rekey_authentications(None)
+			.await
+			.expect("decrypting the authentications");
+		drop(store);
+		assert!(contains_token(&dir.path().join("auth.json")));
+		let store =
+			open(dir.path(), None).expect("the store to be opened without a key");
+		assert_eq!(
+			store.authentications().await.expect("the authentications").len(),
+			1
+		);
+	}
+}
@@ -0,0 +1,232 @@
+//! Applying the persisted parts of a batch, so that either all or none of them
+//! are stored
+
+use std::collections::{HashMap, HashSet};
+
+use onlivfe::{
+	Authentication,
+	CircleId,
+	CircleMember,
+	PlatformAccountId,
+	Profile,
+	ProfileChange,
+	ProfileId,
+	storage::{EntityId, StoreBatch, StoreChange},
+};
+use tokio::sync::RwLockWriteGuard;
+use tracing::{error, trace};
+
+use crate::{OnlivfeCacheStorageBackend, insert_all, mappings::Mappings};
+
+/// The persisted data that batches change, locked for applying one
+pub struct Persisted<'a> {
+	pub profiles: RwLockWriteGuard<'a, HashMap<ProfileId, Profile>>,
+	pub mappings: RwLockWriteGuard<'a, Mappings>,
+	pub circle_members:
+		RwLockWriteGuard<'a, HashMap<CircleId, Vec<CircleMember>>>,
+	pub authentications:
+		RwLockWriteGuard<'a, HashMap<PlatformAccountId, Authentication>>,
+}
+
+/// The persisted data from before a batch, for undoing it if storing it
+/// fails.
+///
+/// Only the data that the batch changes is kept, which is also what gets
+/// written.
+struct Previous {
+	profiles: Option<HashMap<ProfileId, Profile>>,
+	mappings: Option<Mappings>,
+	circle_members: Option<HashMap<CircleId, Vec<CircleMember>>>,
+	authentications: Option<HashMap<PlatformAccountId, Authentication>>,
+}
+
+/// What applying the persisted parts of a batch did
+#[derive(Default)]
+pub struct Applied {
+	/// The profiles that replaced existing ones
+	pub profiles: Vec<ProfileId>,
+	/// The authentications that replaced existing ones
+	pub authentications: Vec<PlatformAccountId>,
+	/// The changes of the mappings, circle members and deletions
+	pub changes: Vec<StoreChange>,
+	/// The changes to record as revisions of the profiles
+	pub revisions: Vec<(ProfileId, ProfileChange)>,
+}
+
+/// Sets the members of a circle, keeping only the first position of each one
+fn set_circle_members(
+	circle_members: &mut HashMap<CircleId, Vec<CircleMember>>,
+	circle_id: CircleId, mut members: Vec<CircleMember>,
+) {
+	let mut seen = HashSet::new();
+	members.retain(|member| seen.insert(member.clone()));
+	if members.is_empty() {
+		circle_members.remove(&circle_id);
+	} else {
+		circle_members.insert(circle_id, members);
+	}
+}
+
+impl Persisted<'_> {
+	fn previous(&self, batch: &StoreBatch) -> Previous {
+		let deletes = !batch.deleted_profiles.is_empty();
+		Previous {
+			profiles: (deletes || !batch.profiles.is_empty())
+				.then(|| self.profiles.clone()),
+			mappings: (deletes || !batch.profile_account_ids.is_empty())
+				.then(|| self.mappings.clone()),
+			circle_members: (deletes || !batch.circle_members.is_empty())
+				.then(|| self.circle_members.clone()),
+			authentications: (!batch.authentications.is_empty())
+				.then(|| self.authentications.clone()),
+		}
+	}
+
+	fn restore(&mut self, previous: &Previous) {
+		if let Some(profiles) = &previous.profiles {
+			self.profiles.clone_from(profiles);
+		}
+		if let Some(mappings) = &previous.mappings {
+			self.mappings.clone_from(mappings);
+		}
+		if let Some(circle_members) = &previous.circle_members {
+			self.circle_members.clone_from(circle_members);
+		}
+		if let Some(authentications) = &previous.authentications {
+			self.authentications.clone_from(authentications);
+		}
+	}
+
+	/// Applies the batch in memory only
+	fn apply(&mut self, batch: StoreBatch, previous: &Previous) -> Applied {
+		let deleted: HashSet<ProfileId> =
+			batch.deleted_profiles.iter().cloned().collect();
+		let batch_profile_ids: HashSet<ProfileId> =
+			batch.profiles.iter().map(|p| p.sharing_id.clone()).collect();
+		let mut applied = Applied {
+			profiles: insert_all(&mut self.profiles, batch.profiles, |p| {
+				p.sharing_id.clone()
+			}),
+			authentications: insert_all(
+				&mut self.authentications,
+				batch.authentications,
+				Authentication::id,
+			),
+			..Applied::default()
+		};
+		if let Some(previous_profiles) = &previous.profiles {
+			// The deleted ones get their revisions from being deleted
+			applied.revisions.extend(
+				batch_profile_ids
+					.into_iter()
+					.filter(|id| !deleted.contains(id))
+					.filter_map(|profile_id| {
+						let change = ProfileChange::details(
+							previous_profiles.get(&profile_id).cloned(),
+							self.profiles.get(&profile_id).cloned(),
+						)?;
+						Some((profile_id, change))
+					}),
+			);
+		}
+
+		for (profile_id, account_ids) in batch.profile_account_ids {
+			let previous_account_ids =
+				self.mappings.set_profile_accounts(&profile_id, account_ids.clone());
+			applied.changes.extend(StoreChange::profile_accounts_replaced(
+				&profile_id,
+				&previous_account_ids,
+				&account_ids,
+			));
+			let change = ProfileChange::accounts(
+				previous_account_ids,
+				self.mappings.account_ids(&profile_id),
+			);
+			applied.revisions.extend(change.map(|change| (profile_id, change)));
+		}
+		for (circle_id, members) in batch.circle_members {
+			set_circle_members(&mut self.circle_members, circle_id.clone(), members);
+			applied
+				.changes
+				.push(StoreChange::stored(EntityId::CircleMembers(circle_id), true));
+		}
+		for profile_id in &batch.deleted_profiles {
+			self.remove_profile(profile_id, &mut applied);
+		}
+
+		applied
+	}
+
+	/// Removes a profile along with its mappings and circle memberships
+	fn remove_profile(&mut self, profile_id: &ProfileId, applied: &mut Applied) {
+		let removed_profile = self.profiles.remove(profile_id);
+		let account_ids = self.mappings.set_profile_accounts(profile_id, vec![]);
+		let member = CircleMember::Profile(profile_id.clone());
+		let mut circle_ids = vec![];
+		for (circle_id, members) in self.circle_members.iter_mut() {
+			if members.contains(&member) {
+				members.retain(|m| *m != member);
+				circle_ids.push(circle_id.clone());
+			}
+		}
+		self.circle_members.retain(|_, members| !members.is_empty());
+
+		applied.changes.extend(StoreChange::profile_deleted(
+			profile_id,
+			&account_ids,
+			&circle_ids,
+		));
+		applied.revisions.extend(
+			[
+				ProfileChange::details(removed_profile, None),
+				ProfileChange::accounts(account_ids, vec![]),
+			]
+			.into_iter()
+			.flatten()
+			.map(|change| (profile_id.clone(), change)),
+		);
+	}
+}
+
+impl OnlivfeCacheStorageBackend {
+	/// Applies the profiles, authentications, mappings, circle members and
+	/// profile deletions of a batch, and persists them, undoing all of it if
+	/// persisting fails
+	pub(crate) async fn apply_persisted(
+		&self, data: &mut Persisted<'_>, batch: StoreBatch,
+	) -> Result<Applied, std::io::Error> {
+		let previous = data.previous(&batch);
+		let applied = data.apply(batch, &previous);
+		if let Err(e) = self.persist(data, &previous).await {
+			trace!("Undoing batch update");
+			data.restore(&previous);
+			// Some of the files might've been written already
+			if let Err(e) = self.persist(data, &previous).await {
+				error!("Failed to undo batch update on disk: {e}");
+			}
+			return Err(e);
+		}
+
+		Ok(applied)
+	}
+
+	/// Writes the files of the data that a batch changes
+	async fn persist(
+		&self, data: &Persisted<'_>, previous: &Previous,
+	) -> Result<(), std::io::Error> {
+		if previous.profiles.is_some() {
+			self.update_profiles(&data.profiles)?;
+		}
+		if previous.mappings.is_some() {
+			self.update_mappings(&data.mappings)?;
+		}
+		if previous.circle_members.is_some() {
+			self.update_circle_members(&data.circle_members)?;
+		}
+		if previous.authentications.is_some() {
+			self.update_auths(&data.authentications).await?;
+		}
+
+		Ok(())
+	}
+}
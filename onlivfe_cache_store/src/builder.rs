@@ -0,0 +1,142 @@
+use std::{path::PathBuf, time::Duration};
+
+use directories::ProjectDirs;
+use onlivfe::encryption::AuthKey;
+
+use crate::{
+	DEFAULT_MAX_PROFILE_REVISIONS,
+	DEFAULT_SNAPSHOT_INTERVAL,
+	OnlivfeCacheStorageBackend,
+	Persistence,
+	pictures,
+};
+
+/// Where the data of the cache storage backend is kept
+#[derive(Debug, Clone, Default)]
+enum Location {
+	/// Nowhere but in memory
+	#[default]
+	Memory,
+	/// In the app's system config directory
+	AppName(String),
+	/// In an explicitly chosen directory
+	Path(PathBuf),
+}
+
+/// Configures & creates an [`OnlivfeCacheStorageBackend`]
+///
+/// Defaults to keeping everything in memory only.
+///
+/// ```no_run
+/// use onlivfe_cache_store::OnlivfeCacheStorageBackend;
+///
+/// let store = OnlivfeCacheStorageBackend::builder()
+/// 	.path("./portable-data")
+/// 	.read_only(true)
+/// 	.build()?;
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct OnlivfeCacheStorageBackendBuilder {
+	location: Location,
+	read_only: bool,
+	auth_key: Option<AuthKey>,
+	max_profile_revisions: Option<usize>,
+	snapshot_interval: Option<Duration>,
+}
+
+impl OnlivfeCacheStorageBackendBuilder {
+	/// Stores the data in the system's config directory for the app
+	pub fn app_name(mut self, app_name: impl Into<String>) -> Self {
+		self.location = Location::AppName(app_name.into());
+		self
+	}
+
+	/// Stores the data in the directory, creating it if needed
+	pub fn path(mut self, root: impl Into<PathBuf>) -> Self {
+		self.location = Location::Path(root.into());
+		self
+	}
+
+	/// Never touches the filesystem, everything is lost when dropped
+	pub fn in_memory(mut self) -> Self {
+		self.location = Location::Memory;
+		self
+	}
+
+	/// Loads the data from disk, but keeps any changes in memory only
+	pub const fn read_only(mut self, read_only: bool) -> Self {
+		self.read_only = read_only;
+		self
+	}
+
+	/// Encrypts the persisted authentications with the key.
+	///
+	/// Previously unencrypted authentications get encrypted when loaded.
+	pub fn encrypt_authentications(mut self, key: AuthKey) -> Self {
+		self.auth_key = Some(key);
+		self
+	}
+
+	/// Keeps only the latest revisions of each profile, as all of them are
+	/// rewritten whenever one is recorded.
+	///
+	/// Defaults to [`DEFAULT_MAX_PROFILE_REVISIONS`].
+	pub const fn max_profile_revisions(mut self, max: usize) -> Self {
+		self.max_profile_revisions = Some(max);
+		self
+	}
+
+	/// Saves the cached platform data after a write when it was last saved at
+	/// least this long ago, so that not all of it is lost if the app crashes.
+	///
+	/// It's always saved when the storage backend is dropped too.
+	/// Defaults to [`DEFAULT_SNAPSHOT_INTERVAL`].
+	pub const fn snapshot_interval(mut self, interval: Duration) -> Self {
+		self.snapshot_interval = Some(interval);
+		self
+	}
+
+	/// Creates the storage backend, loading any previous data from disk
+	///
+	/// # Errors
+	///
+	/// If the directory couldn't be figured out or created,
+	/// if reading previous data from it fails,
+	/// or if the key for the encrypted authentications is wrong or missing
+	pub fn build(self) -> Result<OnlivfeCacheStorageBackend, String> {
+		OnlivfeCacheStorageBackend::load(
+			self.persistence()?,
+			self.auth_key.as_ref(),
+			self.max_profile_revisions.unwrap_or(DEFAULT_MAX_PROFILE_REVISIONS),
+			self.snapshot_interval.unwrap_or(DEFAULT_SNAPSHOT_INTERVAL),
+		)
+	}
+
+	/// Figures out how the data is persisted, creating the directory if needed
+	fn persistence(&self) -> Result<Persistence, String> {
+		let dir = match &self.location {
+			Location::Memory => return Ok(Persistence::Memory),
+			Location::AppName(app_name) => {
+				ProjectDirs::from("com", "Onlivfe", app_name)
+					.ok_or_else(|| {
+						"Failed to get system directory paths for storage".to_owned()
+					})?
+					.config_dir()
+					.to_path_buf()
+			}
+			Location::Path(path) => path.clone(),
+		};
+
+		if self.read_only {
+			return Ok(Persistence::ReadOnly(dir));
+		}
+
+		// Along with the directory itself
+		std::fs::create_dir_all(dir.join(pictures::DIR_NAME))
+			.map_err(|e| format!("Could not create config directory: {e}"))?;
+
+		Ok(Persistence::Disk(dir))
+	}
+}
@@ -0,0 +1,165 @@
+use std::{
+	ffi::OsString,
+	io::Write,
+	path::{Path, PathBuf},
+};
+
+use tracing::{error, warn};
+
+/// Appends an extension to the file name, `auth.json` becoming `auth.json.bak`
+fn with_added_extension(path: &Path, extension: &str) -> PathBuf {
+	let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+	file_name.push(".");
+	file_name.push(extension);
+	path.with_file_name(file_name)
+}
+
+/// Gets the path where the previous version of a file is kept
+fn backup_path(path: &Path) -> PathBuf { with_added_extension(path, "bak") }
+
+/// Gets the path where a corrupt version of a file is moved aside to
+fn unusable_path(path: &Path) -> PathBuf {
+	with_added_extension(path, "unusable")
+}
+
+/// Writes the file so that a crash never leaves it half written.
+///
+/// The bytes are first written & synced to a temporary file, the current
+/// version is moved to be the backup, and then the temporary file is renamed
+/// to take its place.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+	let temporary_path = with_added_extension(path, "tmp");
+	let mut file = std::fs::File::create(&temporary_path)?;
+	file.write_all(bytes)?;
+	file.sync_all()?;
+	drop(file);
+
+	if path.exists() {
+		std::fs::rename(path, backup_path(path))?;
+	}
+	std::fs::rename(&temporary_path, path)?;
+
+	sync_parent_dir(path)
+}
+
+/// Removes the previous version of a file, for when it shouldn't be kept
+pub fn remove_backup(path: &Path) -> std::io::Result<()> {
+	match std::fs::remove_file(backup_path(path)) {
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+		result => result,
+	}
+}
+
+/// Removes the corrupt version of a file that was moved aside, for when it
+/// shouldn't be kept
+pub fn remove_unusable(path: &Path) -> std::io::Result<()> {
+	match std::fs::remove_file(unusable_path(path)) {
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+		result => result,
+	}
+}
+
+/// Makes sure that the renames have hit the disk too
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+	match path.parent() {
+		Some(dir) => std::fs::File::open(dir)?.sync_all(),
+		None => Ok(()),
+	}
+}
+
+/// Directories can't be opened as files on other platforms
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+const fn sync_parent_dir(_path: &Path) -> std::io::Result<()> { Ok(()) }
+
+/// Reads & parses a file, returning `None` if it doesn't exist
+fn read_file<T>(
+	path: &Path, parse: &impl Fn(&[u8]) -> Result<T, String>,
+) -> Result<Option<T>, String> {
+	if !path.exists() {
+		return Ok(None);
+	}
+	let bytes = std::fs::read(path)
+		.map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+	let value = parse(&bytes)
+		.map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+
+	Ok(Some(value))
+}
+
+/// Reads & parses a file, falling back to its backup if the file is
+/// missing or corrupt, optionally also restoring the file from the backup.
+///
+/// Returns `None` if neither of them exist.
+///
+/// # Errors
+///
+/// If there's no usable version of the file
+pub fn read_with_backup<T>(
+	path: &Path, restore_from_backup: bool,
+	parse: impl Fn(&[u8]) -> Result<T, String>,
+) -> Result<Option<T>, String> {
+	let error = match read_file(path, &parse) {
+		Ok(Some(value)) => return Ok(Some(value)),
+		Ok(None) => None,
+		Err(e) => Some(e),
+	};
+
+	let backup_path = backup_path(path);
+	match read_file(&backup_path, &parse) {
+		Ok(Some(value)) => {
+			warn!(
+				"Using backup {}, as the file itself was not usable: {:?}",
+				backup_path.display(),
+				error
+			);
+			if !restore_from_backup {
+				return Ok(Some(value));
+			}
+			// So that the next write won't replace the good backup with a bad file,
+			// while still keeping the bad file around in case it can be recovered
+			if path.exists() {
+				if let Err(e) = std::fs::rename(path, unusable_path(path)) {
+					warn!("Failed to move aside unusable {}: {e}", path.display());
+				}
+			}
+			if let Err(e) = std::fs::copy(&backup_path, path) {
+				warn!("Failed to restore {} from backup: {e}", path.display());
+			}
+			Ok(Some(value))
+		}
+		Ok(None) => error.map_or(Ok(None), Err),
+		Err(backup_error) => {
+			error!("Both {} and its backup are corrupt", path.display());
+			Err(error.unwrap_or(backup_error))
+		}
+	}
+}
+
+/// Removes a file along with its backup, for when it's not needed anymore
+pub fn remove_with_backup(path: &Path) -> std::io::Result<()> {
+	match std::fs::remove_file(path) {
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+		_ => {}
+	}
+	remove_backup(path)
+}
+
+/// Lists the names of the files in a directory, which is empty if it doesn't
+/// exist
+pub fn file_names(dir: &Path) -> std::io::Result<Vec<String>> {
+	let entries = match std::fs::read_dir(dir) {
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+		result => result?,
+	};
+	let mut file_names = vec![];
+	for entry in entries {
+		let entry = entry?;
+		if entry.file_type()?.is_file() {
+			file_names.push(entry.file_name().to_string_lossy().into_owned());
+		}
+	}
+
+	Ok(file_names)
+}
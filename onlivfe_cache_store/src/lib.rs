@@ -16,135 +16,656 @@
 #![allow(clippy::multiple_crate_versions)]
 // The warnings are a bit too aggressive
 #![allow(clippy::significant_drop_tightening)]
-
 #![macro_use]
 extern crate tracing;
 
-// Pain, https://github.com/rust-lang/rust/issues/43244
-/// Drains items from the slice if they match the condition,
-/// returns the drained items
-fn drain_vec<I, F>(slice: &mut Vec<I>, cond: F) -> Vec<I>
-where
-	F: Fn(&I) -> bool,
-{
-	let mut removed = vec![];
-	let mut i = 0;
-	while i < slice.len() {
-		if cond(&mut slice[i]) {
-			removed.push(slice.remove(i));
-		} else {
-			i += 1;
-		}
-	}
-
-	removed
-}
+use std::{
+	collections::{HashMap, HashSet},
+	path::PathBuf,
+	sync::{
+		Mutex,
+		PoisonError,
+		atomic::{AtomicBool, Ordering},
+	},
+	time::{Duration, Instant},
+};
 
-use directories::ProjectDirs;
 use onlivfe::{
+	AccountLink,
 	Authentication,
 	Avatar,
 	AvatarId,
+	Circle,
+	CircleId,
+	CircleMember,
 	Instance,
 	InstanceId,
 	PlatformAccount,
 	PlatformAccountId,
 	PlatformFriend,
 	Profile,
+	ProfileChange,
 	ProfileId,
+	ProfilePicture,
+	ProfileRevision,
+	TrashedProfile,
 	World,
 	WorldId,
-	storage::OnlivfeStore,
+	encryption::{AuthCipher, AuthKey},
+	storage::{
+		BatchUpdated,
+		ChangeKind,
+		ChangeNotifier,
+		ChangeStream,
+		EntityId,
+		OnlivfeStore,
+		SearchHit,
+		StoreBatch,
+		StoreChange,
+		search,
+	},
 };
+use time::OffsetDateTime;
 use tokio::sync::RwLock;
 use tracing::{error, trace, warn};
 
-/// An in-memory only cache storage backend for onlivfe
+mod auth;
+mod batch;
+mod builder;
+pub use builder::OnlivfeCacheStorageBackendBuilder;
+mod files;
+mod mappings;
+use mappings::Mappings;
+mod migrate;
+pub use migrate::MigrationCounts;
+mod pictures;
+mod schema;
+use schema::Schema;
+pub use schema::SkippedEntry;
+mod snapshot;
+
+/// Where the entries that couldn't be loaded are kept
+const SKIPPED_ENTRIES_FILE_NAME: &str = "skipped.json";
+/// How many of the latest revisions of each profile are kept by default, see
+/// [`OnlivfeCacheStorageBackendBuilder::max_profile_revisions`]
+pub const DEFAULT_MAX_PROFILE_REVISIONS: usize = 100;
+/// How often the cached platform data is saved by default, see
+/// [`OnlivfeCacheStorageBackendBuilder::snapshot_interval`]
+pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_mins(5);
+
+/// How the cache storage backend persists its data
+#[derive(Debug, Clone)]
+enum Persistence {
+	/// Reads & writes the files in the directory
+	Disk(PathBuf),
+	/// Reads the files in the directory, but keeps changes in memory only
+	ReadOnly(PathBuf),
+	/// Never touches the filesystem
+	Memory,
+}
+
+impl Persistence {
+	/// Reads & parses a file from the storage directory, if there is one
+	fn read<T>(
+		&self, file_name: &str, parse: impl Fn(&[u8]) -> Result<T, String>,
+	) -> Result<Option<T>, String> {
+		match self {
+			Self::Disk(dir) => {
+				files::read_with_backup(&dir.join(file_name), true, parse)
+			}
+			Self::ReadOnly(dir) => {
+				files::read_with_backup(&dir.join(file_name), false, parse)
+			}
+			Self::Memory => Ok(None),
+		}
+	}
+
+	/// Reads the entries of a persisted JSON file, upgrading them from older
+	/// versions and skipping the ones that can't be parsed
+	fn read_entries<T: serde::de::DeserializeOwned>(
+		&self, schema: &Schema, skipped_entries: &mut Vec<SkippedEntry>,
+	) -> Result<Vec<T>, String> {
+		let Some(entries) =
+			self.read(schema.file_name, |bytes| schema.parse(bytes))?
+		else {
+			return Ok(vec![]);
+		};
+		let (entries, mut skipped) = schema.decode(entries);
+		skipped_entries.append(&mut skipped);
+
+		Ok(entries)
+	}
+
+	/// Writes a file in the storage directory,
+	/// keeping the previous version of it as a backup
+	fn write_file(
+		&self, file_name: &str, bytes: &[u8],
+	) -> Result<(), std::io::Error> {
+		let Self::Disk(dir) = self else {
+			trace!("Not persisting {} as storage is not writable", file_name);
+			return Ok(());
+		};
+		trace!("Writing {} bytes to {}", bytes.len(), file_name);
+		files::write_atomically(&dir.join(file_name), bytes)
+	}
+
+	/// Lists the files in a directory within the storage directory
+	fn file_names(&self, dir_name: &str) -> Result<Vec<String>, String> {
+		match self {
+			Self::Disk(dir) | Self::ReadOnly(dir) => {
+				files::file_names(&dir.join(dir_name))
+					.map_err(|e| format!("Failed to list {dir_name}: {e}"))
+			}
+			Self::Memory => Ok(vec![]),
+		}
+	}
+
+	/// Removes a file in the storage directory, along with its backup
+	fn remove_file(&self, file_name: &str) -> Result<(), std::io::Error> {
+		let Self::Disk(dir) = self else {
+			trace!("Not removing {} as storage is not writable", file_name);
+			return Ok(());
+		};
+		trace!("Removing {}", file_name);
+		files::remove_with_backup(&dir.join(file_name))
+	}
+
+	/// Removes the backup of a file in the storage directory
+	fn remove_backup(&self, file_name: &str) -> Result<(), std::io::Error> {
+		match self {
+			Self::Disk(dir) => files::remove_backup(&dir.join(file_name)),
+			Self::ReadOnly(_) | Self::Memory => Ok(()),
+		}
+	}
+
+	/// Removes the corrupt version of a file in the storage directory that was
+	/// moved aside when it was restored from its backup
+	fn remove_unusable(&self, file_name: &str) -> Result<(), std::io::Error> {
+		match self {
+			Self::Disk(dir) => files::remove_unusable(&dir.join(file_name)),
+			Self::ReadOnly(_) | Self::Memory => Ok(()),
+		}
+	}
+
+	const fn is_writable(&self) -> bool { matches!(self, Self::Disk(_)) }
+}
+
+/// An in-memory cache storage backend for onlivfe
+///
+/// Everything is kept in hash maps keyed by the platform IDs,
+/// with the profile to account mappings indexed both ways.
+/// Authentications, profiles, their pictures and revisions, the trash,
+/// circles, their mappings, and rejected links are optionally persisted on
+/// disk, see [`OnlivfeCacheStorageBackendBuilder`]. The rest of the cached
+/// platform data is persisted with [`OnlivfeCacheStorageBackend::save_cache`]
+/// and when dropped.
 ///
-/// Built for simplicity & quick iterating, not for efficiency.
-#[derive(Debug)]
+/// The persisted authentications can be encrypted with a passphrase or a key
+/// file, see [`OnlivfeCacheStorageBackendBuilder::encrypt_authentications`].
 pub struct OnlivfeCacheStorageBackend {
-	dirs: ProjectDirs,
-	profiles: RwLock<Vec<Profile>>,
-	accounts: RwLock<Vec<PlatformAccount>>,
-	friends: RwLock<Vec<PlatformFriend>>,
-	profiles_to_accounts: RwLock<Vec<(PlatformAccountId, ProfileId)>>,
-	authentications: RwLock<Vec<Authentication>>,
-	instances: RwLock<Vec<Instance>>,
-	worlds: RwLock<Vec<World>>,
-	avatars: RwLock<Vec<Avatar>>,
+	persistence: Persistence,
+	skipped_entries: Vec<SkippedEntry>,
+	/// If the cached platform data has changed since it was last saved
+	dirty: AtomicBool,
+	/// When saving the cached platform data was last attempted
+	last_snapshot: Mutex<Instant>,
+	/// How long to wait after the last save before saving again after a write
+	snapshot_interval: Duration,
+	/// Locked before the mappings and the circle members when they're needed
+	profiles: RwLock<HashMap<ProfileId, Profile>>,
+	trash: RwLock<HashMap<ProfileId, TrashedProfile>>,
+	accounts: RwLock<HashMap<PlatformAccountId, PlatformAccount>>,
+	friends: RwLock<HashMap<PlatformAccountId, PlatformFriend>>,
+	profiles_to_accounts: RwLock<Mappings>,
+	circles: RwLock<HashMap<CircleId, Circle>>,
+	/// Locked after the circles when both are needed
+	circle_members: RwLock<HashMap<CircleId, Vec<CircleMember>>>,
+	rejected_links: RwLock<HashSet<AccountLink>>,
+	/// Locked after everything else that's related to profiles
+	revisions: RwLock<HashMap<ProfileId, Vec<ProfileRevision>>>,
+	/// How many of the latest revisions of each profile are kept
+	max_revisions: usize,
+	pictures: RwLock<HashMap<ProfileId, ProfilePicture>>,
+	authentications: RwLock<HashMap<PlatformAccountId, Authentication>>,
+	/// Only locked while holding the lock of the authentications
+	auth_cipher: RwLock<Option<AuthCipher>>,
+	instances: RwLock<HashMap<InstanceId, Instance>>,
+	worlds: RwLock<HashMap<WorldId, World>>,
+	avatars: RwLock<HashMap<AvatarId, Avatar>>,
+	changes: ChangeNotifier,
+}
+
+impl std::fmt::Debug for OnlivfeCacheStorageBackend {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		// The skipped entries could have anything in them, and the rest is huge
+		f.debug_struct("OnlivfeCacheStorageBackend")
+			.field("persistence", &self.persistence)
+			.field("skipped_entries", &self.skipped_entries.len())
+			.field("max_revisions", &self.max_revisions)
+			.finish_non_exhaustive()
+	}
 }
 
 impl OnlivfeCacheStorageBackend {
-	/// Creates a new onlivfe cache storage backend
+	/// Creates a new onlivfe cache storage backend,
+	/// persisting data in the system's config directory for the app
+	///
+	/// Corrupt files are replaced by their backups from before the last write,
+	/// and single entries that can't be loaded are skipped, see
+	/// [`OnlivfeCacheStorageBackend::skipped_entries`].
 	///
 	/// # Errors
 	///
 	/// If reading previous data from disk fails
 	pub fn new(app_name: &str) -> Result<Self, String> {
-		let dirs =
-			ProjectDirs::from("com", "Onlivfe", app_name).ok_or_else(|| {
-				"Failed to get system directory paths for storage".to_owned()
-			})?;
-
-		std::fs::create_dir_all(dirs.config_dir()).map_err(|e| format!("Could not create config directory: {e}"))?;
-
-		let authentications = dirs.config_dir().join("auth.json");
-		let authentications: Vec<Authentication> = if authentications.exists() {
-			let authentications =
-				std::fs::File::open(authentications).map_err(|e| e.to_string())?;
-			serde_json::from_reader(authentications).map_err(|e| e.to_string())?
-		} else {
-			vec![]
-		};
+		Self::builder().app_name(app_name).build()
+	}
 
-		let profiles = dirs.config_dir().join("profiles.json");
-		let profiles: Vec<Profile> = if profiles.exists() {
-			let profiles =
-				std::fs::File::open(profiles).map_err(|e| e.to_string())?;
-			serde_json::from_reader(profiles).map_err(|e| e.to_string())?
-		} else {
-			vec![]
-		};
+	/// Creates a builder for configuring where and how the data is stored
+	pub fn builder() -> OnlivfeCacheStorageBackendBuilder {
+		OnlivfeCacheStorageBackendBuilder::default()
+	}
 
-		let profiles_to_accounts = dirs.config_dir().join("mappings.json");
+	fn load(
+		persistence: Persistence, auth_key: Option<&AuthKey>, max_revisions: usize,
+		snapshot_interval: Duration,
+	) -> Result<Self, String> {
+		let mut skipped_entries = vec![];
+		let (authentications, auth_cipher) =
+			auth::read(&persistence, auth_key, &mut skipped_entries)?;
+		let profiles: Vec<Profile> =
+			persistence.read_entries(&schema::PROFILES, &mut skipped_entries)?;
+		let trash: Vec<TrashedProfile> =
+			persistence.read_entries(&schema::TRASH, &mut skipped_entries)?;
 		let profiles_to_accounts: Vec<(PlatformAccountId, ProfileId)> =
-			if profiles_to_accounts.exists() {
-				let profiles_to_accounts = std::fs::File::open(profiles_to_accounts)
-					.map_err(|e| e.to_string())?;
-				serde_json::from_reader(profiles_to_accounts).map_err(|e| e.to_string())?
-			} else {
-				vec![]
-			};
-
-		trace!("Loaded storage backed with {} authentications, {} profiles, and {} mappings", authentications.len(), profiles.len(), profiles_to_accounts.len());
+			persistence.read_entries(&schema::MAPPINGS, &mut skipped_entries)?;
+		let circles: Vec<Circle> =
+			persistence.read_entries(&schema::CIRCLES, &mut skipped_entries)?;
+		let circle_members: Vec<(CircleId, Vec<CircleMember>)> = persistence
+			.read_entries(&schema::CIRCLE_MEMBERS, &mut skipped_entries)?;
+		let rejected_links: Vec<AccountLink> = persistence
+			.read_entries(&schema::REJECTED_LINKS, &mut skipped_entries)?;
+		let revisions: Vec<ProfileRevision> =
+			persistence.read_entries(&schema::REVISIONS, &mut skipped_entries)?;
+		let pictures = pictures::read_all(&persistence)?;
+		// It's just a cache, so rather start from scratch than fail
+		let cache = persistence
+			.read(snapshot::FILE_NAME, snapshot::decode)
+			.unwrap_or_else(|e| {
+				warn!("Discarding unusable cache snapshot: {e}");
+				None
+			})
+			.flatten()
+			.unwrap_or_default();
+
+		trace!(
+			"Loaded storage backed with {} authentications, {} profiles, {} mappings, and {} circles",
+			authentications.len(),
+			profiles.len(),
+			profiles_to_accounts.len(),
+			circles.len()
+		);
+		trace!(
+			"Loaded cache with {} accounts, {} friends, {} instances, {} worlds, and {} avatars",
+			cache.accounts.len(),
+			cache.friends.len(),
+			cache.instances.len(),
+			cache.worlds.len(),
+			cache.avatars.len()
+		);
 		let store = Self {
-			dirs,
-			accounts: RwLock::default(),
-			friends: RwLock::default(),
+			persistence,
+			skipped_entries,
+			dirty: AtomicBool::new(false),
+			last_snapshot: Mutex::new(Instant::now()),
+			snapshot_interval,
+			accounts: RwLock::new(
+				cache.accounts.into_iter().map(|acc| (acc.id(), acc)).collect(),
+			),
+			friends: RwLock::new(
+				cache.friends.into_iter().map(|fren| (fren.id(), fren)).collect(),
+			),
 			authentications: RwLock::new(authentications),
-			profiles: RwLock::new(profiles),
-			profiles_to_accounts: RwLock::new(profiles_to_accounts),
-			instances: RwLock::default(),
-			worlds: RwLock::default(),
-			avatars: RwLock::default(),
+			auth_cipher: RwLock::new(auth_cipher),
+			profiles: RwLock::new(
+				profiles
+					.into_iter()
+					.map(|profile| (profile.sharing_id.clone(), profile))
+					.collect(),
+			),
+			trash: RwLock::new(
+				trash
+					.into_iter()
+					.map(|trashed| (trashed.profile.sharing_id.clone(), trashed))
+					.collect(),
+			),
+			profiles_to_accounts: RwLock::new(Mappings::from_pairs(
+				profiles_to_accounts,
+			)),
+			circles: RwLock::new(
+				circles.into_iter().map(|circle| (circle.id.clone(), circle)).collect(),
+			),
+			circle_members: RwLock::new(circle_members.into_iter().collect()),
+			rejected_links: RwLock::new(rejected_links.into_iter().collect()),
+			revisions: RwLock::new(group_revisions(revisions, max_revisions)),
+			max_revisions,
+			pictures: RwLock::new(pictures),
+			instances: RwLock::new(
+				cache.instances.into_iter().map(|inst| (inst.id(), inst)).collect(),
+			),
+			worlds: RwLock::new(
+				cache.worlds.into_iter().map(|world| (world.id(), world)).collect(),
+			),
+			avatars: RwLock::new(
+				cache.avatars.into_iter().map(|avt| (avt.id(), avt)).collect(),
+			),
+			changes: ChangeNotifier::new(),
 		};
+		store.keep_skipped_entries();
 
 		Ok(store)
 	}
 
-	fn update_mappings(
-		&self, mappings: &Vec<(PlatformAccountId, ProfileId)>,
+	/// Entries of the persisted files that couldn't be loaded, for example due
+	/// to changes in the platforms' models.
+	///
+	/// They're also kept in `skipped.json`, as the next write would lose them.
+	#[must_use]
+	pub fn skipped_entries(&self) -> &[SkippedEntry] { &self.skipped_entries }
+
+	/// Adds the skipped entries to the ones skipped previously, leaving out the
+	/// cleartext authentications of previously skipped ones
+	fn keep_skipped_entries(&self) {
+		if !self.persistence.is_writable() {
+			return;
+		}
+
+		let mut all_skipped: Vec<SkippedEntry> =
+			match self.persistence.read(SKIPPED_ENTRIES_FILE_NAME, |bytes| {
+				serde_json::from_slice(bytes).map_err(|e| e.to_string())
+			}) {
+				Ok(previous) => previous.unwrap_or_default(),
+				Err(e) => {
+					error!("Failed to read previously skipped entries: {e}");
+					return;
+				}
+			};
+		let previous = all_skipped.clone();
+		all_skipped.iter_mut().for_each(auth::redact);
+		let redacted = all_skipped != previous;
+		for skipped in &self.skipped_entries {
+			if !all_skipped.contains(skipped) {
+				all_skipped.push(skipped.clone());
+			}
+		}
+		if all_skipped == previous {
+			return;
+		}
+
+		let result = serde_json::to_vec(&all_skipped)
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+			.and_then(|bytes| {
+				self.persistence.write_file(SKIPPED_ENTRIES_FILE_NAME, &bytes)
+			})
+			.and_then(|()| {
+				// The backup would still have the redacted authentications
+				if redacted {
+					self.persistence.remove_backup(SKIPPED_ENTRIES_FILE_NAME)
+				} else {
+					Ok(())
+				}
+			});
+		if let Err(e) = result {
+			error!("Failed to keep skipped entries: {e}");
+		}
+	}
+
+	/// Persists the cached platform data, such as friends and instances,
+	/// so that it's available right away after a restart.
+	///
+	/// Also happens automatically when the storage backend is dropped, and
+	/// after writes as configured with
+	/// [`OnlivfeCacheStorageBackendBuilder::snapshot_interval`].
+	///
+	/// # Errors
+	///
+	/// If writing the data to disk fails
+	pub async fn save_cache(&self) -> Result<(), std::io::Error> {
+		if !self.persistence.is_writable() {
+			return Ok(());
+		}
+
+		let accounts = self.accounts.read().await;
+		let friends = self.friends.read().await;
+		let instances = self.instances.read().await;
+		let worlds = self.worlds.read().await;
+		let avatars = self.avatars.read().await;
+		self.dirty.store(false, Ordering::Relaxed);
+		*self.lock_last_snapshot() = Instant::now();
+
+		let result = snapshot::encode(
+			accounts.values(),
+			friends.values(),
+			instances.values(),
+			worlds.values(),
+			avatars.values(),
+		)
+		.and_then(|bytes| self.persistence.write_file(snapshot::FILE_NAME, &bytes));
+		if result.is_err() {
+			self.dirty.store(true, Ordering::Relaxed);
+		}
+
+		result
+	}
+
+	/// Encrypts the stored authentications with a new key,
+	/// or stores them unencrypted if there's no key.
+	///
+	/// The backup of the authentications is removed, as it would still be
+	/// readable with the previous key.
+	///
+	/// # Errors
+	///
+	/// If the key file couldn't be read, or writing the authentications fails
+	pub async fn rekey_authentications(
+		&self, new_key: Option<AuthKey>,
+	) -> Result<(), std::io::Error> {
+		let authentications = self.authentications.write().await;
+		let mut auth_cipher = self.auth_cipher.write().await;
+
+		let new_cipher = new_key
+			.map(|key| AuthCipher::create(&key))
+			.transpose()
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+		auth::write(&self.persistence, &authentications, new_cipher.as_ref())?;
+		self.persistence.remove_backup(schema::AUTHENTICATIONS.file_name)?;
+		*auth_cipher = new_cipher;
+
+		trace!("Re-keyed authentications");
+
+		Ok(())
+	}
+
+	fn mark_dirty(&self) { self.dirty.store(true, Ordering::Relaxed); }
+
+	fn lock_last_snapshot(&self) -> std::sync::MutexGuard<'_, Instant> {
+		// An instant can't be left in an invalid state
+		self.last_snapshot.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Saves the cached platform data if it has changed and it's been long
+	/// enough since it was last saved.
+	///
+	/// Must be called without holding the locks of the platform data.
+	async fn save_cache_if_due(&self) {
+		if !self.dirty.load(Ordering::Relaxed)
+			|| self.lock_last_snapshot().elapsed() < self.snapshot_interval
+		{
+			return;
+		}
+
+		if let Err(e) = self.save_cache().await {
+			error!("Failed to save cache snapshot: {e}");
+		}
+	}
+
+	/// Serializes the entries into a versioned JSON file in the storage
+	/// directory
+	fn write_entries<T: serde::Serialize>(
+		&self, schema: &Schema, entries: Vec<T>,
+	) -> Result<(), std::io::Error> {
+		let bytes = schema
+			.encode(entries)
+			.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+		self.persistence.write_file(schema.file_name, &bytes)
+	}
+
+	fn update_mappings(&self, mappings: &Mappings) -> Result<(), std::io::Error> {
+		self.write_entries(&schema::MAPPINGS, mappings.to_pairs())
+	}
+
+	fn update_profiles(
+		&self, profiles: &HashMap<ProfileId, Profile>,
+	) -> Result<(), std::io::Error> {
+		trace!("Going to write {} profiles", profiles.len());
+		self.write_entries(&schema::PROFILES, profiles.values().collect())
+	}
+
+	fn update_trash(
+		&self, trash: &HashMap<ProfileId, TrashedProfile>,
+	) -> Result<(), std::io::Error> {
+		self.write_entries(&schema::TRASH, trash.values().collect())
+	}
+
+	fn update_circles(
+		&self, circles: &HashMap<CircleId, Circle>,
+	) -> Result<(), std::io::Error> {
+		self.write_entries(&schema::CIRCLES, circles.values().collect())
+	}
+
+	fn update_circle_members(
+		&self, circle_members: &HashMap<CircleId, Vec<CircleMember>>,
+	) -> Result<(), std::io::Error> {
+		self.write_entries(&schema::CIRCLE_MEMBERS, circle_members.iter().collect())
+	}
+
+	fn update_rejected_links(
+		&self, rejected_links: &HashSet<AccountLink>,
 	) -> Result<(), std::io::Error> {
-		let write_result = serde_json::to_vec::<Vec<(PlatformAccountId, ProfileId)>>(mappings).map(|bytes| {
-			trace!("Writing {} mapping bytes to disk", bytes.len());
-			std::fs::write(self.dirs.config_dir().join("mappings.json"), bytes)
-		});
+		self.write_entries(&schema::REJECTED_LINKS, rejected_links.iter().collect())
+	}
+
+	fn update_revisions(
+		&self, revisions: &HashMap<ProfileId, Vec<ProfileRevision>>,
+	) -> Result<(), std::io::Error> {
+		self
+			.write_entries(&schema::REVISIONS, revisions.values().flatten().collect())
+	}
+
+	/// Records the changes as new revisions of their profiles, dropping the
+	/// oldest ones past the limit.
+	///
+	/// Called once the changes themselves have been stored and notified about,
+	/// so an error with persisting the revisions is returned with the changes
+	/// kept.
+	async fn record_revisions(
+		&self, changes: Vec<(ProfileId, ProfileChange)>,
+	) -> Result<(), std::io::Error> {
+		if changes.is_empty() {
+			return Ok(());
+		}
+		let mut revisions = self.revisions.write().await;
+		let previous_revisions = revisions.clone();
+
+		let recorded_at = OffsetDateTime::now_utc();
+		for (profile_id, change) in changes {
+			let profile_revisions = revisions.entry(profile_id.clone()).or_default();
+			let number =
+				profile_revisions.last().map_or(0, |revision| revision.number) + 1;
+			profile_revisions.push(ProfileRevision {
+				profile_id,
+				number,
+				recorded_at,
+				change,
+			});
+			let excess = profile_revisions.len().saturating_sub(self.max_revisions);
+			profile_revisions.drain(..excess);
+		}
+		revisions.retain(|_, profile_revisions| !profile_revisions.is_empty());
+
+		if let Err(e) = self.update_revisions(&revisions) {
+			error!("Failed to record profile revisions: {e}");
+			*revisions = previous_revisions;
+			return Err(e);
+		}
+		Ok(())
+	}
+
+	async fn update_auths(
+		&self, authentications: &HashMap<PlatformAccountId, Authentication>,
+	) -> Result<(), std::io::Error> {
+		let auth_cipher = self.auth_cipher.read().await;
+		auth::write(&self.persistence, authentications, auth_cipher.as_ref())
+	}
+}
 
-		match write_result {
-			Ok(Ok(())) => Ok(()),
-			Ok(Err(e)) => Err(e),
-			Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)),
+/// Groups the revisions by their profiles in the order that they were made,
+/// keeping only the latest ones of each
+fn group_revisions(
+	revisions: Vec<ProfileRevision>, max_revisions: usize,
+) -> HashMap<ProfileId, Vec<ProfileRevision>> {
+	let mut revisions_by_profile: HashMap<ProfileId, Vec<ProfileRevision>> =
+		HashMap::new();
+	for revision in revisions {
+		revisions_by_profile
+			.entry(revision.profile_id.clone())
+			.or_default()
+			.push(revision);
+	}
+	for profile_revisions in revisions_by_profile.values_mut() {
+		profile_revisions.sort_by_key(|revision| revision.number);
+		let excess = profile_revisions.len().saturating_sub(max_revisions);
+		profile_revisions.drain(..excess);
+	}
+
+	revisions_by_profile
+}
+
+/// Inserts the entries into the map, returning the IDs of the ones that
+/// replaced entries that existed before, each once
+fn insert_all<Id: Clone + Eq + std::hash::Hash, T>(
+	map: &mut HashMap<Id, T>, entries: Vec<T>, id: impl Fn(&T) -> Id,
+) -> Vec<Id> {
+	let mut seen = HashSet::new();
+	let mut ids = vec![];
+	for entry in entries {
+		let entry_id = id(&entry);
+		let existed = map.insert(entry_id.clone(), entry).is_some();
+		// Later entries with the same ID replace the earlier ones from the batch
+		if seen.insert(entry_id.clone()) && existed {
+			ids.push(entry_id);
+		}
+	}
+
+	ids
+}
+
+impl Drop for OnlivfeCacheStorageBackend {
+	fn drop(&mut self) {
+		if !*self.dirty.get_mut() || !self.persistence.is_writable() {
+			return;
+		}
+
+		let result = snapshot::encode(
+			self.accounts.get_mut().values(),
+			self.friends.get_mut().values(),
+			self.instances.get_mut().values(),
+			self.worlds.get_mut().values(),
+			self.avatars.get_mut().values(),
+		)
+		.and_then(|bytes| self.persistence.write_file(snapshot::FILE_NAME, &bytes));
+		if let Err(e) = result {
+			error!("Failed to save cache snapshot: {e}");
 		}
 	}
 }
@@ -153,12 +674,110 @@ impl OnlivfeCacheStorageBackend {
 impl OnlivfeStore for OnlivfeCacheStorageBackend {
 	type Err = std::io::Error;
 
+	fn watch(&self) -> Option<ChangeStream> { Some(self.changes.watch()) }
+
+	async fn apply_batch(
+		&self, batch: StoreBatch,
+	) -> Result<BatchUpdated, Self::Err> {
+		let entity_ids =
+			if self.changes.is_watched() { batch.entity_ids() } else { vec![] };
+		let (platform_data, batch) = batch.split_platform_data();
+		// Always locked in the same order, to avoid deadlocks
+		let mut accounts = self.accounts.write().await;
+		let mut friends = self.friends.write().await;
+		let mut instances = self.instances.write().await;
+		let mut worlds = self.worlds.write().await;
+		let mut avatars = self.avatars.write().await;
+		let mut persisted = batch::Persisted {
+			profiles: self.profiles.write().await,
+			mappings: self.profiles_to_accounts.write().await,
+			circle_members: self.circle_members.write().await,
+			authentications: self.authentications.write().await,
+		};
+
+		// The persisted data is applied first, as only it can fail
+		let applied = self.apply_persisted(&mut persisted, batch).await?;
+		drop(persisted);
+
+		let has_platform_data = !platform_data.is_empty();
+		let updated = BatchUpdated {
+			accounts: insert_all(
+				&mut accounts,
+				platform_data.accounts,
+				PlatformAccount::id,
+			),
+			friends: insert_all(
+				&mut friends,
+				platform_data.friends,
+				PlatformFriend::id,
+			),
+			instances: insert_all(
+				&mut instances,
+				platform_data.instances,
+				Instance::id,
+			),
+			worlds: insert_all(&mut worlds, platform_data.worlds, World::id),
+			avatars: insert_all(&mut avatars, platform_data.avatars, Avatar::id),
+			profiles: applied.profiles,
+			authentications: applied.authentications,
+		};
+		if has_platform_data {
+			self.mark_dirty();
+		}
+		// Saving the cache needs them
+		drop((accounts, friends, instances, worlds, avatars));
+		trace!("Applied batch update");
+		self.changes.notify_batch(entity_ids, &updated);
+		self.changes.notify(applied.changes);
+		self.record_revisions(applied.revisions).await?;
+		if has_platform_data {
+			self.save_cache_if_due().await;
+		}
+
+		Ok(updated)
+	}
+
+	async fn search(
+		&self, query: &str, max: usize,
+	) -> Result<Vec<SearchHit>, Self::Err> {
+		let terms = search::terms(query);
+		if terms.is_empty() {
+			return Ok(vec![]);
+		}
+
+		let mut hits: Vec<SearchHit> = self
+			.profiles
+			.read()
+			.await
+			.values()
+			.filter_map(|profile| SearchHit::profile(&terms, profile))
+			.collect();
+		hits.extend(
+			self
+				.friends
+				.read()
+				.await
+				.values()
+				.filter_map(|friend| SearchHit::friend(&terms, friend)),
+		);
+		hits.extend(
+			self
+				.accounts
+				.read()
+				.await
+				.values()
+				.filter_map(|account| SearchHit::account(&terms, account)),
+		);
+
+		Ok(search::rank(hits, max))
+	}
+
 	async fn account_ids(
 		&self, max: usize,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
 		let accounts = self.accounts.read().await;
 		let accounts: Vec<PlatformAccountId> =
-			accounts.iter().take(max).map(PlatformAccount::id).collect();
+			accounts.keys().take(max).cloned().collect();
 		Ok(accounts)
 	}
 
@@ -166,7 +785,7 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 		&self, account_id: PlatformAccountId,
 	) -> Result<PlatformAccount, Self::Err> {
 		let accounts = self.accounts.read().await;
-		if let Some(account) = accounts.iter().find(|acc| account_id == acc.id()) {
+		if let Some(account) = accounts.get(&account_id) {
 			return Ok(account.clone());
 		}
 		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
@@ -176,50 +795,60 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 		&self, account_id: PlatformAccountId,
 	) -> Result<Vec<ProfileId>, Self::Err> {
 		let profiles_to_accounts = self.profiles_to_accounts.read().await;
-		let profile_ids: Vec<ProfileId> = profiles_to_accounts
-			.iter()
-			.filter(|map| account_id == map.0)
-			.map(|map| map.1.clone())
-			.collect();
-		Ok(profile_ids)
+		Ok(profiles_to_accounts.profile_ids(&account_id))
 	}
 
 	async fn update_account_profile_ids(
 		&self, account_id: PlatformAccountId, profile_ids: Vec<ProfileId>,
 	) -> Result<(), Self::Err> {
 		let mut profiles_to_accounts = self.profiles_to_accounts.write().await;
-		let removed_profile_ids =
-			drain_vec(&mut profiles_to_accounts, |(acc_id, _)| acc_id == &account_id)
-				.into_iter()
-				.filter(|(_, prof_id)| profile_ids.contains(prof_id))
-				.map(|(_, prof_id)| prof_id)
-				.collect::<Vec<ProfileId>>();
-		for profile_id in profile_ids {
-			profiles_to_accounts.push((account_id.clone(), profile_id));
+		let mut affected: Vec<(ProfileId, Vec<PlatformAccountId>)> = vec![];
+		for profile_id in profiles_to_accounts
+			.profile_ids(&account_id)
+			.into_iter()
+			.chain(profile_ids.iter().cloned())
+		{
+			if !affected.iter().any(|(id, _)| *id == profile_id) {
+				let account_ids = profiles_to_accounts.account_ids(&profile_id);
+				affected.push((profile_id, account_ids));
+			}
 		}
+		let previous_profile_ids = profiles_to_accounts
+			.set_account_profiles(&account_id, profile_ids.clone());
 
 		if let Err(e) = self.update_mappings(&profiles_to_accounts) {
-			for profile_id in removed_profile_ids {
-				profiles_to_accounts.push((account_id.clone(), profile_id));
-			}
+			trace!("Undoing account profile mappings update");
+			profiles_to_accounts
+				.set_account_profiles(&account_id, previous_profile_ids);
 			return Err(e);
 		}
+		let changes = affected
+			.into_iter()
+			.filter_map(|(profile_id, old)| {
+				let new = profiles_to_accounts.account_ids(&profile_id);
+				Some((profile_id, ProfileChange::accounts(old, new)?))
+			})
+			.collect();
 
-		Ok(())
+		self.changes.notify(StoreChange::account_profiles_replaced(
+			&account_id,
+			&previous_profile_ids,
+			&profile_ids,
+		));
+		self.record_revisions(changes).await
 	}
 
 	async fn update_account(
 		&self, account: PlatformAccount,
 	) -> Result<bool, Self::Err> {
 		let mut accounts = self.accounts.write().await;
-		if let Some(acc) = accounts.iter_mut().find(|acc| account.id() == acc.id())
-		{
-			*acc = account;
-			return Ok(true);
-		}
-
-		accounts.push(account);
-		Ok(false)
+		self.mark_dirty();
+		let id = account.id();
+		let existed = accounts.insert(id.clone(), account).is_some();
+		drop(accounts);
+		self.changes.notify_stored(EntityId::Account(id), existed);
+		self.save_cache_if_due().await;
+		Ok(existed)
 	}
 
 	async fn friend_ids(
@@ -227,7 +856,7 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
 		let friends = self.friends.read().await;
 		let friend_ids: Vec<PlatformAccountId> =
-			friends.iter().take(max).map(PlatformFriend::id).collect();
+			friends.keys().take(max).cloned().collect();
 		Ok(friend_ids)
 	}
 
@@ -235,7 +864,7 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 		&self, friend_id: PlatformAccountId,
 	) -> Result<PlatformFriend, Self::Err> {
 		let friends = self.friends.read().await;
-		if let Some(friend) = friends.iter().find(|fren| friend_id == fren.id()) {
+		if let Some(friend) = friends.get(&friend_id) {
 			return Ok(friend.clone());
 		}
 		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
@@ -245,14 +874,13 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 		&self, friend: PlatformFriend,
 	) -> Result<bool, Self::Err> {
 		let mut friends = self.friends.write().await;
-		if let Some(fren) = friends.iter_mut().find(|fren| friend.id() == fren.id())
-		{
-			*fren = friend;
-			return Ok(true);
-		}
-
-		friends.push(friend);
-		Ok(false)
+		self.mark_dirty();
+		let id = friend.id();
+		let existed = friends.insert(id.clone(), friend).is_some();
+		drop(friends);
+		self.changes.notify_stored(EntityId::Friend(id), existed);
+		self.save_cache_if_due().await;
+		Ok(existed)
 	}
 
 	async fn instance_ids(
@@ -260,7 +888,7 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 	) -> Result<Vec<InstanceId>, Self::Err> {
 		let instances = self.instances.read().await;
 		let instance_ids: Vec<InstanceId> =
-			instances.iter().take(max).map(Instance::id).collect();
+			instances.keys().take(max).cloned().collect();
 		Ok(instance_ids)
 	}
 
@@ -268,9 +896,7 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 		&self, instance_id: InstanceId,
 	) -> Result<Instance, Self::Err> {
 		let instances = self.instances.read().await;
-		if let Some(instance) =
-			instances.iter().find(|instance| instance_id == instance.id())
-		{
+		if let Some(instance) = instances.get(&instance_id) {
 			return Ok(instance.clone());
 		}
 		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
@@ -280,27 +906,24 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 		&self, instance: Instance,
 	) -> Result<bool, Self::Err> {
 		let mut instances = self.instances.write().await;
-		if let Some(acc) =
-			instances.iter_mut().find(|inst| instance.id() == inst.id())
-		{
-			*acc = instance;
-			return Ok(true);
-		}
-
-		instances.push(instance);
-		Ok(false)
+		self.mark_dirty();
+		let id = instance.id();
+		let existed = instances.insert(id.clone(), instance).is_some();
+		drop(instances);
+		self.changes.notify_stored(EntityId::Instance(id), existed);
+		self.save_cache_if_due().await;
+		Ok(existed)
 	}
 
 	async fn world_ids(&self, max: usize) -> Result<Vec<WorldId>, Self::Err> {
 		let worlds = self.worlds.read().await;
-		let world_ids: Vec<WorldId> =
-			worlds.iter().take(max).map(World::id).collect();
+		let world_ids: Vec<WorldId> = worlds.keys().take(max).cloned().collect();
 		Ok(world_ids)
 	}
 
 	async fn world(&self, world_id: WorldId) -> Result<World, Self::Err> {
 		let worlds = self.worlds.read().await;
-		if let Some(world) = worlds.iter().find(|world| world_id == world.id()) {
+		if let Some(world) = worlds.get(&world_id) {
 			return Ok(world.clone());
 		}
 		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
@@ -308,26 +931,24 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 
 	async fn update_world(&self, world: World) -> Result<bool, Self::Err> {
 		let mut worlds = self.worlds.write().await;
-		if let Some(acc) = worlds.iter_mut().find(|avt| world.id() == avt.id()) {
-			*acc = world;
-			return Ok(true);
-		}
-
-		worlds.push(world);
-		Ok(false)
+		self.mark_dirty();
+		let id = world.id();
+		let existed = worlds.insert(id.clone(), world).is_some();
+		drop(worlds);
+		self.changes.notify_stored(EntityId::World(id), existed);
+		self.save_cache_if_due().await;
+		Ok(existed)
 	}
 
 	async fn avatar_ids(&self, max: usize) -> Result<Vec<AvatarId>, Self::Err> {
 		let avatars = self.avatars.read().await;
-		let avatar_ids: Vec<AvatarId> =
-			avatars.iter().take(max).map(Avatar::id).collect();
+		let avatar_ids: Vec<AvatarId> = avatars.keys().take(max).cloned().collect();
 		Ok(avatar_ids)
 	}
 
 	async fn avatar(&self, avatar_id: AvatarId) -> Result<Avatar, Self::Err> {
 		let avatars = self.avatars.read().await;
-		if let Some(avatar) = avatars.iter().find(|avatar| avatar_id == avatar.id())
-		{
+		if let Some(avatar) = avatars.get(&avatar_id) {
 			return Ok(avatar.clone());
 		}
 		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
@@ -335,20 +956,38 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 
 	async fn update_avatar(&self, avatar: Avatar) -> Result<bool, Self::Err> {
 		let mut avatars = self.avatars.write().await;
-		if let Some(acc) = avatars.iter_mut().find(|avt| avatar.id() == avt.id()) {
-			*acc = avatar;
-			return Ok(true);
-		}
+		self.mark_dirty();
+		let id = avatar.id();
+		let existed = avatars.insert(id.clone(), avatar).is_some();
+		drop(avatars);
+		self.changes.notify_stored(EntityId::Avatar(id), existed);
+		self.save_cache_if_due().await;
+		Ok(existed)
+	}
 
-		avatars.push(avatar);
-		Ok(false)
+	async fn profile_ids(&self, max: usize) -> Result<Vec<ProfileId>, Self::Err> {
+		let profiles = self.profiles.read().await;
+		let profile_ids: Vec<ProfileId> =
+			profiles.keys().take(max).cloned().collect();
+		Ok(profile_ids)
+	}
+
+	async fn tagged_profile_ids(
+		&self, tag: &str, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		let profiles = self.profiles.read().await;
+		let profile_ids: Vec<ProfileId> = profiles
+			.values()
+			.filter(|profile| profile.has_tag(tag))
+			.take(max)
+			.map(|profile| profile.sharing_id.clone())
+			.collect();
+		Ok(profile_ids)
 	}
 
 	async fn profile(&self, profile_id: ProfileId) -> Result<Profile, Self::Err> {
 		let profiles = self.profiles.read().await;
-		if let Some(profile) =
-			profiles.iter().find(|profile| profile_id == profile.sharing_id)
-		{
+		if let Some(profile) = profiles.get(&profile_id) {
 			return Ok(profile.clone());
 		}
 		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
@@ -358,180 +997,361 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 		&self, profile_id: ProfileId,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
 		let profiles_to_accounts = self.profiles_to_accounts.read().await;
-		let platform_account_ids: Vec<PlatformAccountId> = profiles_to_accounts
-			.iter()
-			.filter(|map| profile_id == map.1)
-			.map(|map| map.0.clone())
-			.collect();
-		Ok(platform_account_ids)
+		Ok(profiles_to_accounts.account_ids(&profile_id))
 	}
 
 	async fn update_profile_account_ids(
 		&self, profile_id: ProfileId, account_ids: Vec<PlatformAccountId>,
 	) -> Result<(), Self::Err> {
-		let mut profiles_to_accounts = self.profiles_to_accounts.write().await;
-		let removed_account_ids =
-			drain_vec(&mut profiles_to_accounts, |(_, prof_id)| {
-				prof_id == &profile_id
-			})
-			.into_iter()
-			.filter(|(acc_id, _)| account_ids.contains(acc_id))
-			.map(|(acc_id, _)| acc_id)
-			.collect::<Vec<PlatformAccountId>>();
-		for account_id in account_ids {
-			profiles_to_accounts.push((account_id, profile_id.clone()));
-		}
+		let batch = StoreBatch {
+			profile_account_ids: vec![(profile_id, account_ids)],
+			..StoreBatch::default()
+		};
+		self.apply_batch(batch).await.map(|_| ())
+	}
 
-		if let Err(e) = self.update_mappings(&profiles_to_accounts) {
-			for account_id in removed_account_ids {
-				profiles_to_accounts.push((account_id, profile_id.clone()));
-			}
+	async fn update_profile(&self, profile: Profile) -> Result<bool, Self::Err> {
+		let profile_id = profile.sharing_id.clone();
+		let mut profiles = self.profiles.write().await;
+
+		let previous_profile = profiles.insert(profile_id.clone(), profile.clone());
+		let swapped = previous_profile.is_some();
+
+		// Undo the operation before returning, as we want same state on disk and in
+		// cache always
+		if let Err(e) = self.update_profiles(&profiles) {
+			trace!("Undoing profile update");
+			match previous_profile {
+				Some(previous_profile) => profiles.insert(profile_id, previous_profile),
+				None => profiles.remove(&profile_id),
+			};
 			return Err(e);
 		}
+		let change = ProfileChange::details(previous_profile, Some(profile));
 
-		Ok(())
+		trace!("Fully updated profiles");
+		self.changes.notify_stored(EntityId::Profile(profile_id.clone()), swapped);
+		self
+			.record_revisions(
+				change.map(|change| (profile_id, change)).into_iter().collect(),
+			)
+			.await?;
+
+		Ok(swapped)
 	}
 
-	async fn update_profile(
-		&self, mut profile: Profile,
-	) -> Result<bool, Self::Err> {
-		let profile_id = profile.sharing_id.clone();
-		let mut profiles = self.profiles.write().await;
+	async fn delete_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		let batch = StoreBatch {
+			deleted_profiles: vec![profile_id],
+			..StoreBatch::default()
+		};
+		self.apply_batch(batch).await.map(|_| ())
+	}
 
-		let mut swapped_profile = None;
+	async fn trashed_profile_ids(
+		&self, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		let trash = self.trash.read().await;
+		Ok(trash.keys().take(max).cloned().collect())
+	}
 
-		if let Some(auth) =
-			profiles.iter_mut().find(|profile| profile.sharing_id == profile_id)
-		{
-			trace!("Swapping profile");
-			std::mem::swap(&mut *auth, &mut profile);
-			swapped_profile = Some(profile);
-		} else {
-			trace!("Adding profile");
-			profiles.push(profile);
+	async fn trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<TrashedProfile, Self::Err> {
+		let trash = self.trash.read().await;
+		if let Some(trashed) = trash.get(&profile_id) {
+			return Ok(trashed.clone());
 		}
+		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
+	}
 
-		trace!("Going to write {} ", profiles.len());
-		let write_result = serde_json::to_vec::<Vec<Profile>>(&*profiles).map(|bytes| {
-			trace!("Writing {} auth bytes to disk", bytes.len());
-			std::fs::write(self.dirs.config_dir().join("profiles.json"), bytes)
-		});
+	async fn update_trashed_profile(
+		&self, trashed: TrashedProfile,
+	) -> Result<bool, Self::Err> {
+		let profile_id = trashed.profile.sharing_id.clone();
+		let mut trash = self.trash.write().await;
 
-		let swapped = swapped_profile.is_some();
+		let previous = trash.insert(profile_id.clone(), trashed);
+		let swapped = previous.is_some();
 
-		// Undo the operation before returning, as we want same state on disk and in
-		// cache always
-		let before_exit = || {
-			if let Some(mut swapped_profile) = swapped_profile {
-				trace!("Undoing profile swap");
-				if let Some(prof) =
-					profiles.iter_mut().find(|profile| profile.sharing_id == profile_id)
-				{
-					std::mem::swap(&mut *prof, &mut swapped_profile);
-				} else {
-					error!("This should be impossible, couldn't undo profile swap");
-				}
+		if let Err(e) = self.update_trash(&trash) {
+			trace!("Undoing trashed profile update");
+			match previous {
+				Some(previous) => trash.insert(profile_id, previous),
+				None => trash.remove(&profile_id),
 			};
+			return Err(e);
+		}
+
+		self.changes.notify_stored(EntityId::TrashedProfile(profile_id), swapped);
+
+		Ok(swapped)
+	}
+
+	async fn delete_trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		let mut trash = self.trash.write().await;
+
+		let Some(removed) = trash.remove(&profile_id) else {
+			return Ok(());
 		};
+		if let Err(e) = self.update_trash(&trash) {
+			trace!("Undoing trashed profile removal");
+			trash.insert(profile_id, removed);
+			return Err(e);
+		}
 
-		match write_result {
-			Ok(Ok(())) => {}
-			Ok(Err(e)) => {
-				before_exit();
-				return Err(e);
-			}
-			Err(e) => {
-				before_exit();
-				return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
-			}
+		self.changes.notify([StoreChange {
+			entity: EntityId::TrashedProfile(profile_id),
+			kind: ChangeKind::Deleted,
+		}]);
+
+		Ok(())
+	}
+
+	async fn profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<Vec<ProfileRevision>, Self::Err> {
+		let revisions = self.revisions.read().await;
+		Ok(revisions.get(&profile_id).cloned().unwrap_or_default())
+	}
+
+	async fn update_profile_revisions(
+		&self, profile_id: ProfileId, mut revisions: Vec<ProfileRevision>,
+	) -> Result<(), Self::Err> {
+		let mut all_revisions = self.revisions.write().await;
+
+		for revision in &mut revisions {
+			revision.profile_id = profile_id.clone();
+		}
+		revisions.sort_by_key(|revision| revision.number);
+		let excess = revisions.len().saturating_sub(self.max_revisions);
+		revisions.drain(..excess);
+		let previous = if revisions.is_empty() {
+			all_revisions.remove(&profile_id)
+		} else {
+			all_revisions.insert(profile_id.clone(), revisions)
 		};
 
-		trace!("Fully updated profiles");
+		if let Err(e) = self.update_revisions(&all_revisions) {
+			trace!("Undoing profile revisions update");
+			match previous {
+				Some(previous) => all_revisions.insert(profile_id, previous),
+				None => all_revisions.remove(&profile_id),
+			};
+			return Err(e);
+		}
 
-		Ok(swapped)
+		Ok(())
 	}
 
-	async fn delete_profile(
+	async fn delete_profile_revisions(
 		&self, profile_id: ProfileId,
 	) -> Result<(), Self::Err> {
-		let mut profiles_to_accounts = self.profiles_to_accounts.write().await;
-		let removed_account_ids =
-			drain_vec(&mut profiles_to_accounts, |(_, prof_id)| {
-				prof_id == &profile_id
-			})
-			.into_iter()
-			.map(|(acc_id, _)| acc_id)
-			.collect::<Vec<PlatformAccountId>>();
+		let mut revisions = self.revisions.write().await;
 
-		if let Err(e) = self.update_mappings(&profiles_to_accounts) {
-			for account_id in removed_account_ids {
-				profiles_to_accounts.push((account_id, profile_id.clone()));
-			}
+		let Some(removed) = revisions.remove(&profile_id) else {
+			return Ok(());
+		};
+		if let Err(e) = self.update_revisions(&revisions) {
+			trace!("Undoing profile revisions removal");
+			revisions.insert(profile_id, removed);
 			return Err(e);
 		}
 
 		Ok(())
 	}
 
-	async fn authentications(&self) -> Result<Vec<Authentication>, Self::Err> {
-		Ok(self.authentications.read().await.clone())
+	async fn profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<ProfilePicture, Self::Err> {
+		let pictures = self.pictures.read().await;
+		if let Some(picture) = pictures.get(&profile_id) {
+			return Ok(picture.clone());
+		}
+		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
 	}
 
-	async fn update_authentication(
-		&self, mut authentication: Authentication,
+	async fn update_profile_picture(
+		&self, picture: ProfilePicture,
 	) -> Result<bool, Self::Err> {
-		let auth_id = authentication.id();
-		let mut authentications = self.authentications.write().await;
+		let profile_id = picture.profile_id.clone();
+		let mut pictures = self.pictures.write().await;
 
-		let mut swapped_auth = None;
+		// Only the picture's own file is written, so nothing needs undoing
+		let bytes = pictures::encode(&picture)?;
+		self.persistence.write_file(&pictures::file_name(&profile_id), &bytes)?;
+		let swapped = pictures.insert(profile_id.clone(), picture).is_some();
 
-		if let Some(auth) =
-			authentications.iter_mut().find(|auth| auth.id() == auth_id)
-		{
-			trace!("Swapping authentication");
-			std::mem::swap(&mut *auth, &mut authentication);
-			swapped_auth = Some(authentication);
-		} else {
-			trace!("Adding authentication");
-			authentications.push(authentication);
+		self.changes.notify_stored(EntityId::ProfilePicture(profile_id), swapped);
+
+		Ok(swapped)
+	}
+
+	async fn delete_profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		let mut pictures = self.pictures.write().await;
+
+		if !pictures.contains_key(&profile_id) {
+			return Ok(());
 		}
+		self.persistence.remove_file(&pictures::file_name(&profile_id))?;
+		pictures.remove(&profile_id);
 
-		trace!("Going to write {} authentications", authentications.len());
-		let write_result = serde_json::to_vec::<Vec<Authentication>>(&authentications).map(|bytes| {
-			trace!("Writing {} auth bytes to disk", bytes.len());
-			std::fs::write(self.dirs.config_dir().join("auth.json"), bytes)
-		});
+		self.changes.notify([StoreChange {
+			entity: EntityId::ProfilePicture(profile_id),
+			kind: ChangeKind::Deleted,
+		}]);
 
-		let swapped = swapped_auth.is_some();
+		Ok(())
+	}
+
+	async fn circle_ids(&self, max: usize) -> Result<Vec<CircleId>, Self::Err> {
+		let circles = self.circles.read().await;
+		let circle_ids: Vec<CircleId> = circles.keys().take(max).cloned().collect();
+		Ok(circle_ids)
+	}
+
+	async fn circle(&self, circle_id: CircleId) -> Result<Circle, Self::Err> {
+		let circles = self.circles.read().await;
+		if let Some(circle) = circles.get(&circle_id) {
+			return Ok(circle.clone());
+		}
+		Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Not found"))
+	}
+
+	async fn update_circle(&self, circle: Circle) -> Result<bool, Self::Err> {
+		let circle_id = circle.id.clone();
+		let mut circles = self.circles.write().await;
+
+		let previous_circle = circles.insert(circle_id.clone(), circle);
+		let swapped = previous_circle.is_some();
 
 		// Undo the operation before returning, as we want same state on disk and in
 		// cache always
-		let before_exit = || {
-			if let Some(mut swapped_auth) = swapped_auth {
-				trace!("Undoing auth swap");
-				if let Some(auth) =
-					authentications.iter_mut().find(|auth| auth.id() == auth_id)
-				{
-					std::mem::swap(&mut *auth, &mut swapped_auth);
-				} else {
-					error!("This should be impossible, couldn't undo auth swap");
-				}
-			}
-		};
+		if let Err(e) = self.update_circles(&circles) {
+			trace!("Undoing circle update");
+			match previous_circle {
+				Some(previous_circle) => circles.insert(circle_id, previous_circle),
+				None => circles.remove(&circle_id),
+			};
+			return Err(e);
+		}
 
-		match write_result {
-			Ok(Ok(())) => {}
-			Ok(Err(e)) => {
-				before_exit();
-				return Err(e);
-			}
-			Err(e) => {
-				before_exit();
-				return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+		self.changes.notify_stored(EntityId::Circle(circle_id), swapped);
+
+		Ok(swapped)
+	}
+
+	async fn delete_circle(&self, circle_id: CircleId) -> Result<(), Self::Err> {
+		let mut circles = self.circles.write().await;
+		let mut circle_members = self.circle_members.write().await;
+
+		let removed_circle = circles.remove(&circle_id);
+		let removed_members = circle_members.remove(&circle_id);
+
+		let result = self
+			.update_circles(&circles)
+			.and_then(|()| self.update_circle_members(&circle_members));
+		if let Err(e) = result {
+			trace!("Undoing circle removal");
+			circles.extend(removed_circle.map(|circle| (circle_id.clone(), circle)));
+			circle_members
+				.extend(removed_members.map(|members| (circle_id.clone(), members)));
+			// The circles might've been written already
+			if let Err(e) = self.update_circles(&circles) {
+				error!("Failed to undo circle removal on disk: {e}");
 			}
+			return Err(e);
+		}
+
+		self.changes.notify(StoreChange::circle_deleted(&circle_id));
+
+		Ok(())
+	}
+
+	async fn circle_members(
+		&self, circle_id: CircleId,
+	) -> Result<Vec<CircleMember>, Self::Err> {
+		let circle_members = self.circle_members.read().await;
+		Ok(circle_members.get(&circle_id).cloned().unwrap_or_default())
+	}
+
+	async fn update_circle_members(
+		&self, circle_id: CircleId, members: Vec<CircleMember>,
+	) -> Result<(), Self::Err> {
+		let batch = StoreBatch {
+			circle_members: vec![(circle_id, members)],
+			..StoreBatch::default()
 		};
+		self.apply_batch(batch).await.map(|_| ())
+	}
+
+	async fn member_circle_ids(
+		&self, member: CircleMember,
+	) -> Result<Vec<CircleId>, Self::Err> {
+		let circle_members = self.circle_members.read().await;
+		Ok(
+			circle_members
+				.iter()
+				.filter(|(_, members)| members.contains(&member))
+				.map(|(circle_id, _)| circle_id.clone())
+				.collect(),
+		)
+	}
+
+	async fn rejected_links(&self) -> Result<Vec<AccountLink>, Self::Err> {
+		Ok(self.rejected_links.read().await.iter().cloned().collect())
+	}
+
+	async fn reject_link(&self, link: AccountLink) -> Result<bool, Self::Err> {
+		let mut rejected_links = self.rejected_links.write().await;
+		if rejected_links.contains(&link) {
+			return Ok(true);
+		}
+
+		rejected_links.insert(link.clone());
+		if let Err(e) = self.update_rejected_links(&rejected_links) {
+			trace!("Undoing link rejection");
+			rejected_links.remove(&link);
+			return Err(e);
+		}
+
+		self.changes.notify_stored(EntityId::RejectedLink(link), false);
+
+		Ok(false)
+	}
+
+	async fn authentications(&self) -> Result<Vec<Authentication>, Self::Err> {
+		Ok(self.authentications.read().await.values().cloned().collect())
+	}
+
+	async fn update_authentication(
+		&self, authentication: Authentication,
+	) -> Result<bool, Self::Err> {
+		let auth_id = authentication.id();
+		let mut authentications = self.authentications.write().await;
+
+		let previous_auth = authentications.insert(auth_id.clone(), authentication);
+		let swapped = previous_auth.is_some();
+
+		// Undo the operation before returning, as we want same state on disk and in
+		// cache always
+		if let Err(e) = self.update_auths(&authentications).await {
+			trace!("Undoing auth update");
+			match previous_auth {
+				Some(previous_auth) => authentications.insert(auth_id, previous_auth),
+				None => authentications.remove(&auth_id),
+			};
+			return Err(e);
+		}
 
 		trace!("Fully added authentication");
+		self.changes.notify_stored(EntityId::Authentication(auth_id), swapped);
 
 		Ok(swapped)
 	}
@@ -541,39 +1361,23 @@ impl OnlivfeStore for OnlivfeCacheStorageBackend {
 	) -> Result<bool, Self::Err> {
 		let mut authentications = self.authentications.write().await;
 
-		let removed_auth = authentications
-			.iter()
-			.position(|auth| auth.id() == id)
-			.map(|index| authentications.swap_remove(index));
-
-		trace!("Going to write {} authentications", authentications.len());
-		let write_result = serde_json::to_vec::<Vec<Authentication>>(&*authentications).map(|bytes| {
-			trace!("Writing {} auth bytes to disk", bytes.len());
-			std::fs::write(self.dirs.config_dir().join("auth.json"), bytes)
-		});
+		let Some(removed_auth) = authentications.remove(&id) else {
+			return Ok(false);
+		};
 
 		// Undo the operation before returning, as we want same state on disk and in
 		// cache always
-		let before_exit = || {
-			if let Some(removed_auth) = removed_auth {
-				trace!("Undoing auth removal");
-				authentications.push(removed_auth);
-			}
-		};
-
-		match write_result {
-			Ok(Ok(())) => {}
-			Ok(Err(e)) => {
-				before_exit();
-				return Err(e);
-			}
-			Err(e) => {
-				before_exit();
-				return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
-			}
-		};
+		if let Err(e) = self.update_auths(&authentications).await {
+			trace!("Undoing auth removal");
+			authentications.insert(id, removed_auth);
+			return Err(e);
+		}
 
 		trace!("Fully removed authentication");
+		self.changes.notify([StoreChange {
+			entity: EntityId::Authentication(id),
+			kind: ChangeKind::Deleted,
+		}]);
 
 		Ok(true)
 	}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use onlivfe::{PlatformAccountId, ProfileId};
+
+/// The profile to account mappings, indexed both ways for quick lookups
+#[derive(Debug, Clone, Default)]
+pub struct Mappings {
+	by_account: HashMap<PlatformAccountId, Vec<ProfileId>>,
+	by_profile: HashMap<ProfileId, Vec<PlatformAccountId>>,
+}
+
+impl Mappings {
+	/// Builds the indexes from the stored pairs
+	pub fn from_pairs(pairs: Vec<(PlatformAccountId, ProfileId)>) -> Self {
+		let mut mappings = Self::default();
+		for (account_id, profile_id) in pairs {
+			mappings.insert(account_id, profile_id);
+		}
+
+		mappings
+	}
+
+	/// Flattens the indexes back into pairs that can be stored
+	pub fn to_pairs(&self) -> Vec<(PlatformAccountId, ProfileId)> {
+		self
+			.by_account
+			.iter()
+			.flat_map(|(account_id, profile_ids)| {
+				profile_ids
+					.iter()
+					.map(|profile_id| (account_id.clone(), profile_id.clone()))
+			})
+			.collect()
+	}
+
+	/// Gets the amount of mappings
+	pub fn len(&self) -> usize { self.by_account.values().map(Vec::len).sum() }
+
+	/// Gets the profile IDs of an account
+	pub fn profile_ids(&self, account_id: &PlatformAccountId) -> Vec<ProfileId> {
+		self.by_account.get(account_id).cloned().unwrap_or_default()
+	}
+
+	/// Gets the account IDs of a profile
+	pub fn account_ids(&self, profile_id: &ProfileId) -> Vec<PlatformAccountId> {
+		self.by_profile.get(profile_id).cloned().unwrap_or_default()
+	}
+
+	/// Iterates over the profiles that have accounts, along with the accounts
+	pub fn profiles(
+		&self,
+	) -> impl Iterator<Item = (&ProfileId, &[PlatformAccountId])> {
+		self
+			.by_profile
+			.iter()
+			.map(|(profile_id, account_ids)| (profile_id, account_ids.as_slice()))
+	}
+
+	/// Replaces the profiles of an account, returning the previous ones
+	pub fn set_account_profiles(
+		&mut self, account_id: &PlatformAccountId, profile_ids: Vec<ProfileId>,
+	) -> Vec<ProfileId> {
+		let previous = self.by_account.remove(account_id).unwrap_or_default();
+		for profile_id in &previous {
+			self.unlink_account_from(profile_id, account_id);
+		}
+		for profile_id in profile_ids {
+			self.insert(account_id.clone(), profile_id);
+		}
+
+		previous
+	}
+
+	/// Replaces the accounts of a profile, returning the previous ones
+	pub fn set_profile_accounts(
+		&mut self, profile_id: &ProfileId, account_ids: Vec<PlatformAccountId>,
+	) -> Vec<PlatformAccountId> {
+		let previous = self.by_profile.remove(profile_id).unwrap_or_default();
+		for account_id in &previous {
+			self.unlink_profile_from(account_id, profile_id);
+		}
+		for account_id in account_ids {
+			self.insert(account_id, profile_id.clone());
+		}
+
+		previous
+	}
+
+	fn insert(&mut self, account_id: PlatformAccountId, profile_id: ProfileId) {
+		let profile_ids = self.by_account.entry(account_id.clone()).or_default();
+		if profile_ids.contains(&profile_id) {
+			return;
+		}
+		profile_ids.push(profile_id.clone());
+		self.by_profile.entry(profile_id).or_default().push(account_id);
+	}
+
+	fn unlink_account_from(
+		&mut self, profile_id: &ProfileId, account_id: &PlatformAccountId,
+	) {
+		if let Some(account_ids) = self.by_profile.get_mut(profile_id) {
+			account_ids.retain(|id| id != account_id);
+			if account_ids.is_empty() {
+				self.by_profile.remove(profile_id);
+			}
+		}
+	}
+
+	fn unlink_profile_from(
+		&mut self, account_id: &PlatformAccountId, profile_id: &ProfileId,
+	) {
+		if let Some(profile_ids) = self.by_account.get_mut(account_id) {
+			profile_ids.retain(|id| id != profile_id);
+			if profile_ids.is_empty() {
+				self.by_account.remove(account_id);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn account_id(i: u32) -> PlatformAccountId {
+		let id = format!("usr_00000000-0000-4000-8000-{i:012x}");
+		PlatformAccountId::VRChat(id.parse().unwrap())
+	}
+
+	/// Both of the indexes should always have the same mappings in them
+	fn assert_consistent(mappings: &Mappings) {
+		let mut by_account = mappings.to_pairs();
+		let mut by_profile: Vec<(PlatformAccountId, ProfileId)> = mappings
+			.profiles()
+			.flat_map(|(profile_id, account_ids)| {
+				account_ids.iter().map(|id| (id.clone(), profile_id.clone()))
+			})
+			.collect();
+		by_account.sort_by_key(|(a, p)| (a.id_as_string(), p.to_string()));
+		by_profile.sort_by_key(|(a, p)| (a.id_as_string(), p.to_string()));
+		assert_eq!(by_account, by_profile);
+	}
+
+	#[test]
+	fn lookups_use_both_indexes() {
+		let profile_id = ProfileId::new();
+		let account_ids: Vec<PlatformAccountId> =
+			(0..1_000).map(account_id).collect();
+		let mut mappings = Mappings::default();
+		mappings.set_profile_accounts(&profile_id, account_ids.clone());
+
+		assert_eq!(mappings.len(), 1_000);
+		assert_eq!(mappings.by_account.len(), 1_000);
+		assert_eq!(mappings.by_profile.len(), 1);
+		for account_id in &account_ids {
+			assert_eq!(mappings.profile_ids(account_id), vec![profile_id.clone()]);
+		}
+		assert_eq!(mappings.account_ids(&profile_id), account_ids);
+		assert_consistent(&mappings);
+	}
+
+	#[test]
+	fn replacing_mappings_unlinks_the_previous_ones() {
+		let (first, second) = (ProfileId::new(), ProfileId::new());
+		let mut mappings = Mappings::from_pairs(vec![
+			(account_id(1), first.clone()),
+			(account_id(2), first.clone()),
+			(account_id(1), second.clone()),
+		]);
+
+		let previous = mappings.set_account_profiles(&account_id(1), vec![]);
+		assert_eq!(previous.len(), 2);
+		assert_eq!(mappings.account_ids(&first), vec![account_id(2)]);
+		assert!(mappings.account_ids(&second).is_empty());
+		assert!(!mappings.by_profile.contains_key(&second));
+		assert_consistent(&mappings);
+
+		mappings.set_profile_accounts(&first, vec![]);
+		assert_eq!(mappings.len(), 0);
+		assert!(mappings.by_account.is_empty());
+		assert_consistent(&mappings);
+	}
+}
@@ -0,0 +1,256 @@
+use std::collections::{HashMap, HashSet};
+
+use onlivfe::{
+	Authentication,
+	Circle,
+	CircleId,
+	CircleMember,
+	PlatformAccountId,
+	Profile,
+	ProfileId,
+	ProfilePicture,
+	TrashedProfile,
+	storage::OnlivfeStore,
+};
+use tokio::sync::RwLockReadGuard;
+use tracing::{error, trace, warn};
+
+use crate::{OnlivfeCacheStorageBackend, mappings::Mappings};
+
+/// The amounts of data that were migrated to another storage backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MigrationCounts {
+	/// Platform authentications
+	pub authentications: usize,
+	/// Profiles
+	pub profiles: usize,
+	/// Profile to account mappings
+	pub mappings: usize,
+	/// Circles along with their members
+	pub circles: usize,
+	/// Profiles in the trash
+	pub trashed_profiles: usize,
+	/// Locally stored profile pictures
+	pub profile_pictures: usize,
+}
+
+impl OnlivfeCacheStorageBackend {
+	/// Copies the authentications, profiles, their mappings and pictures, the
+	/// trash, and the circles into another
+	/// storage backend, such as the DB one, so that upgrading doesn't require
+	/// logging in again.
+	///
+	/// Everything is read back from the other storage backend afterwards to
+	/// verify that it was all migrated.
+	/// Nothing is written to this one, so building it as
+	/// [read only](crate::OnlivfeCacheStorageBackendBuilder::read_only) leaves
+	/// the original files untouched.
+	/// The cached platform data and the
+	/// [skipped entries](Self::skipped_entries) are not migrated.
+	///
+	/// The authentications are decrypted for the migration, so if they're
+	/// encrypted, the other storage backend should encrypt them too, for
+	/// example by creating the DB one with `new_encrypted`.
+	///
+	/// # Errors
+	///
+	/// If storing into the other storage backend fails, or if some of the data
+	/// is missing from it afterwards
+	pub async fn migrate_into<S: OnlivfeStore>(
+		&self, target: &S,
+	) -> Result<MigrationCounts, String> {
+		// Locked in the same order as when writing, to avoid deadlocks
+		let data = MigratedData {
+			profiles: self.profiles.read().await,
+			profiles_to_accounts: self.profiles_to_accounts.read().await,
+			trash: self.trash.read().await,
+			circles: self.circles.read().await,
+			circle_members: self.circle_members.read().await,
+			pictures: self.pictures.read().await,
+			authentications: self.authentications.read().await,
+		};
+
+		data.copy_into(target).await?;
+		trace!("Migrated everything, verifying");
+		let migrated = data.count_migrated(target).await?;
+		let expected = data.counts();
+		if migrated != expected {
+			error!("Migration verification failed, {migrated:?} != {expected:?}");
+			return Err(format!(
+				"Only {} of {} authentications, {} of {} profiles, {} of {} \
+				 mappings, {} of {} circles, {} of {} trashed profiles, and {} of \
+				 {} profile pictures were migrated",
+				migrated.authentications,
+				expected.authentications,
+				migrated.profiles,
+				expected.profiles,
+				migrated.mappings,
+				expected.mappings,
+				migrated.circles,
+				expected.circles,
+				migrated.trashed_profiles,
+				expected.trashed_profiles,
+				migrated.profile_pictures,
+				expected.profile_pictures
+			));
+		}
+
+		Ok(migrated)
+	}
+}
+
+/// The data that's migrated, locked for the whole migration
+struct MigratedData<'a> {
+	authentications:
+		RwLockReadGuard<'a, HashMap<PlatformAccountId, Authentication>>,
+	profiles: RwLockReadGuard<'a, HashMap<ProfileId, Profile>>,
+	profiles_to_accounts: RwLockReadGuard<'a, Mappings>,
+	trash: RwLockReadGuard<'a, HashMap<ProfileId, TrashedProfile>>,
+	circles: RwLockReadGuard<'a, HashMap<CircleId, Circle>>,
+	circle_members: RwLockReadGuard<'a, HashMap<CircleId, Vec<CircleMember>>>,
+	pictures: RwLockReadGuard<'a, HashMap<ProfileId, ProfilePicture>>,
+}
+
+impl MigratedData<'_> {
+	/// Stores all of the data into the other storage backend
+	async fn copy_into<S: OnlivfeStore>(&self, target: &S) -> Result<(), String> {
+		for authentication in self.authentications.values() {
+			target
+				.update_authentication(authentication.clone())
+				.await
+				.map_err(|e| format!("Failed to migrate authentication: {e}"))?;
+		}
+		for profile in self.profiles.values() {
+			target
+				.update_profile(profile.clone())
+				.await
+				.map_err(|e| format!("Failed to migrate profile: {e}"))?;
+		}
+		for (profile_id, account_ids) in self.profiles_to_accounts.profiles() {
+			target
+				.update_profile_account_ids(profile_id.clone(), account_ids.to_vec())
+				.await
+				.map_err(|e| format!("Failed to migrate profile's accounts: {e}"))?;
+		}
+		for trashed in self.trash.values() {
+			target
+				.update_trashed_profile(trashed.clone())
+				.await
+				.map_err(|e| format!("Failed to migrate trashed profile: {e}"))?;
+		}
+		for circle in self.circles.values() {
+			target
+				.update_circle(circle.clone())
+				.await
+				.map_err(|e| format!("Failed to migrate circle: {e}"))?;
+			let members =
+				self.circle_members.get(&circle.id).cloned().unwrap_or_default();
+			target
+				.update_circle_members(circle.id.clone(), members)
+				.await
+				.map_err(|e| format!("Failed to migrate circle's members: {e}"))?;
+		}
+		for picture in self.pictures.values() {
+			target
+				.update_profile_picture(picture.clone())
+				.await
+				.map_err(|e| format!("Failed to migrate profile picture: {e}"))?;
+		}
+
+		Ok(())
+	}
+
+	/// Counts how much of the data the other storage backend has afterwards
+	async fn count_migrated<S: OnlivfeStore>(
+		&self, target: &S,
+	) -> Result<MigrationCounts, String> {
+		let mut migrated = MigrationCounts::default();
+		let migrated_authentications = target
+			.authentications()
+			.await
+			.map_err(|e| format!("Failed to verify authentications: {e}"))?;
+		migrated.authentications = self
+			.authentications
+			.values()
+			.filter(|auth| migrated_authentications.contains(auth))
+			.count();
+		for profile in self.profiles.values() {
+			match target.profile(profile.sharing_id.clone()).await {
+				Ok(migrated_profile) if &migrated_profile == profile => {
+					migrated.profiles += 1;
+				}
+				Ok(_) => warn!("Migrated profile {} differs", profile.sharing_id),
+				Err(e) => warn!("Migrated profile {} missing: {e}", profile.sharing_id),
+			}
+		}
+		for (profile_id, account_ids) in self.profiles_to_accounts.profiles() {
+			let migrated_account_ids: HashSet<_> = target
+				.profile_account_ids(profile_id.clone())
+				.await
+				.map_err(|e| format!("Failed to verify profile's accounts: {e}"))?
+				.into_iter()
+				.collect();
+			migrated.mappings += account_ids
+				.iter()
+				.filter(|account_id| migrated_account_ids.contains(account_id))
+				.count();
+		}
+
+		for trashed in self.trash.values() {
+			let profile_id = &trashed.profile.sharing_id;
+			match target.trashed_profile(profile_id.clone()).await {
+				Ok(migrated_trashed) if &migrated_trashed == trashed => {
+					migrated.trashed_profiles += 1;
+				}
+				Ok(_) => warn!("Migrated trashed profile {profile_id} differs"),
+				Err(e) => warn!("Migrated trashed profile {profile_id} missing: {e}"),
+			}
+		}
+		for circle in self.circles.values() {
+			let migrated_circle = match target.circle(circle.id.clone()).await {
+				Ok(migrated_circle) => migrated_circle,
+				Err(e) => {
+					warn!("Migrated circle {} missing: {e}", circle.id);
+					continue;
+				}
+			};
+			let migrated_members = target
+				.circle_members(circle.id.clone())
+				.await
+				.map_err(|e| format!("Failed to verify circle's members: {e}"))?;
+			let members =
+				self.circle_members.get(&circle.id).map_or(&[][..], Vec::as_slice);
+			if &migrated_circle == circle && migrated_members == members {
+				migrated.circles += 1;
+			} else {
+				warn!("Migrated circle {} differs", circle.id);
+			}
+		}
+		for picture in self.pictures.values() {
+			let profile_id = &picture.profile_id;
+			match target.profile_picture(profile_id.clone()).await {
+				Ok(migrated_picture) if &migrated_picture == picture => {
+					migrated.profile_pictures += 1;
+				}
+				Ok(_) => warn!("Migrated picture of profile {profile_id} differs"),
+				Err(e) => {
+					warn!("Migrated picture of profile {profile_id} missing: {e}");
+				}
+			}
+		}
+
+		Ok(migrated)
+	}
+
+	/// The amounts of data there are to migrate
+	fn counts(&self) -> MigrationCounts {
+		MigrationCounts {
+			authentications: self.authentications.len(),
+			profiles: self.profiles.len(),
+			mappings: self.profiles_to_accounts.len(),
+			circles: self.circles.len(),
+			trashed_profiles: self.trash.len(),
+			profile_pictures: self.pictures.len(),
+		}
+	}
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use onlivfe::{ProfileId, ProfilePicture};
+use time::OffsetDateTime;
+use tracing::error;
+
+use crate::Persistence;
+
+/// The directory that the profile pictures are stored in, one file each
+pub const DIR_NAME: &str = "pictures";
+/// The extension of the profile picture files
+const EXTENSION: &str = ".bin";
+
+/// Identifies the file as an onlivfe profile picture
+const MAGIC: [u8; 4] = *b"OLVP";
+
+/// Should be bumped whenever the layout of the file changes
+const VERSION: u16 = 1;
+
+/// The pictures are kept as their own files, as they'd bloat the JSON files
+#[derive(BorshSerialize, BorshDeserialize)]
+struct PictureFile {
+	magic: [u8; 4],
+	version: u16,
+	profile_id: String,
+	content_type: String,
+	/// Nanoseconds since the UNIX epoch
+	stored_at: i128,
+	bytes: Vec<u8>,
+	thumbnail: Vec<u8>,
+}
+
+/// The path of a profile's picture, relative to the storage directory
+pub fn file_name(profile_id: &ProfileId) -> String {
+	format!("{DIR_NAME}/{profile_id}{EXTENSION}")
+}
+
+/// If the file in the pictures directory is a picture, and not for example a
+/// backup of one
+pub fn is_picture_file(file_name: &str) -> bool {
+	file_name.ends_with(EXTENSION)
+}
+
+/// Serializes a profile picture into its file
+pub fn encode(picture: &ProfilePicture) -> Result<Vec<u8>, std::io::Error> {
+	borsh::to_vec(&PictureFile {
+		magic: MAGIC,
+		version: VERSION,
+		profile_id: picture.profile_id.to_string(),
+		content_type: picture.content_type.clone(),
+		stored_at: picture.stored_at.unix_timestamp_nanos(),
+		bytes: picture.bytes.clone(),
+		thumbnail: picture.thumbnail.clone(),
+	})
+}
+
+/// Parses a profile picture from its file
+///
+/// # Errors
+///
+/// If the bytes aren't a profile picture of the current version
+pub fn decode(bytes: &[u8]) -> Result<ProfilePicture, String> {
+	let stored = PictureFile::try_from_slice(bytes)
+		.map_err(|e| format!("Invalid profile picture: {e}"))?;
+	if stored.magic != MAGIC {
+		return Err("Not a profile picture".to_owned());
+	}
+	if stored.version != VERSION {
+		return Err(format!(
+			"Profile picture of version {}, expected {}",
+			stored.version, VERSION
+		));
+	}
+
+	Ok(ProfilePicture {
+		profile_id: stored.profile_id.parse().map_err(|e| format!("{e}"))?,
+		content_type: stored.content_type,
+		bytes: stored.bytes,
+		thumbnail: stored.thumbnail,
+		stored_at: OffsetDateTime::from_unix_timestamp_nanos(stored.stored_at)
+			.map_err(|e| format!("Invalid profile picture timestamp: {e}"))?,
+	})
+}
+
+/// Reads all the stored profile pictures, skipping the unusable ones
+///
+/// # Errors
+///
+/// If the pictures couldn't be listed
+pub fn read_all(
+	persistence: &Persistence,
+) -> Result<HashMap<ProfileId, ProfilePicture>, String> {
+	let mut pictures = HashMap::new();
+	for file_name in persistence.file_names(DIR_NAME)? {
+		if !is_picture_file(&file_name) {
+			continue;
+		}
+		let file_name = format!("{DIR_NAME}/{file_name}");
+		// Left on disk, so that it can still be recovered
+		match persistence.read(&file_name, decode) {
+			Ok(Some(picture)) => {
+				pictures.insert(picture.profile_id.clone(), picture);
+			}
+			Ok(None) => {}
+			Err(e) => error!("Skipping unusable profile picture: {e}"),
+		}
+	}
+
+	Ok(pictures)
+}
@@ -0,0 +1,189 @@
+use onlivfe::{Profile, encryption::KeyParams};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tracing::{trace, warn};
+
+/// Upgrades the raw entries of a file from one version to the next one
+type Upgrade = fn(Vec<Value>) -> Result<Vec<Value>, String>;
+
+/// The on-disk format of a persisted JSON file
+#[derive(Serialize, Deserialize)]
+struct Envelope<T, P> {
+	version: usize,
+	/// What's needed to decrypt the entries, if they're encrypted
+	#[serde(skip_serializing_if = "Option::is_none")]
+	encryption: Option<P>,
+	entries: Vec<T>,
+}
+
+/// Describes a persisted JSON file, and how to upgrade it from older versions
+#[derive(Debug, Clone, Copy)]
+pub struct Schema {
+	pub file_name: &'static str,
+	/// The upgrade steps, indexed by the version they upgrade from.
+	///
+	/// So the current version is the amount of steps, and a change to the
+	/// format of the entries, including changes to the platform models, should
+	/// be handled by adding a new step to the end.
+	upgrades: &'static [Upgrade],
+}
+
+/// Files used to be plain lists of entries, without the envelope
+#[allow(clippy::unnecessary_wraps)]
+const fn from_unversioned(entries: Vec<Value>) -> Result<Vec<Value>, String> {
+	Ok(entries)
+}
+
+/// Profiles got more details, which are filled in with their defaults
+#[allow(clippy::unnecessary_wraps)]
+fn profile_details(entries: Vec<Value>) -> Result<Vec<Value>, String> {
+	Ok(
+		entries
+			.into_iter()
+			.map(|raw| {
+				// Unparseable entries are left for decoding to skip
+				serde_json::from_value::<Profile>(raw.clone())
+					.and_then(serde_json::to_value)
+					.unwrap_or(raw)
+			})
+			.collect(),
+	)
+}
+
+/// The platform authentications
+///
+/// Upgrades only see the encrypted form of the entries when the
+/// authentications are encrypted.
+pub const AUTHENTICATIONS: Schema =
+	Schema { file_name: "auth.json", upgrades: &[from_unversioned] };
+/// The profiles
+pub const PROFILES: Schema = Schema {
+	file_name: "profiles.json",
+	upgrades: &[from_unversioned, profile_details],
+};
+/// The profile to account mappings
+pub const MAPPINGS: Schema =
+	Schema { file_name: "mappings.json", upgrades: &[from_unversioned] };
+/// The circles
+pub const CIRCLES: Schema = Schema { file_name: "circles.json", upgrades: &[] };
+/// The members of the circles
+pub const CIRCLE_MEMBERS: Schema =
+	Schema { file_name: "circle_members.json", upgrades: &[] };
+/// The profiles in the trash
+pub const TRASH: Schema = Schema { file_name: "trash.json", upgrades: &[] };
+/// The recorded changes of the profiles
+pub const REVISIONS: Schema =
+	Schema { file_name: "revisions.json", upgrades: &[] };
+/// The account links that were rejected from being suggested
+pub const REJECTED_LINKS: Schema =
+	Schema { file_name: "rejected_links.json", upgrades: &[] };
+
+/// An entry that couldn't be loaded, and was skipped
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedEntry {
+	/// The file that the entry was in
+	pub file_name: String,
+	/// Why the entry couldn't be loaded
+	pub reason: String,
+	/// The entry itself, so that it can be recovered.
+	///
+	/// Null for authentications that weren't encrypted, as they'd have the
+	/// tokens in cleartext.
+	pub raw: Value,
+}
+
+impl Schema {
+	/// The version that the file is written with
+	pub const fn version(&self) -> usize { self.upgrades.len() }
+
+	/// Serializes the entries with the current version
+	pub fn encode<T: Serialize>(
+		&self, entries: Vec<T>,
+	) -> Result<Vec<u8>, serde_json::Error> {
+		serde_json::to_vec(&Envelope::<T, &KeyParams> {
+			version: self.version(),
+			encryption: None,
+			entries,
+		})
+	}
+
+	/// Serializes the encrypted entries with the current version, along with
+	/// the parameters needed for decrypting them
+	pub fn encode_encrypted<T: Serialize>(
+		&self, entries: Vec<T>, key_params: &KeyParams,
+	) -> Result<Vec<u8>, serde_json::Error> {
+		serde_json::to_vec(&Envelope {
+			version: self.version(),
+			encryption: Some(key_params),
+			entries,
+		})
+	}
+
+	/// Parses the file, and upgrades the raw entries to the current version
+	///
+	/// # Errors
+	///
+	/// If the file is not valid, or is from a newer version
+	pub fn parse(&self, bytes: &[u8]) -> Result<Vec<Value>, String> {
+		self.parse_encrypted(bytes).map(|(entries, _)| entries)
+	}
+
+	/// Parses the file like [`Schema::parse`], also returning the parameters
+	/// needed for decrypting the entries if they're encrypted
+	///
+	/// # Errors
+	///
+	/// If the file is not valid, or is from a newer version
+	pub fn parse_encrypted(
+		&self, bytes: &[u8],
+	) -> Result<(Vec<Value>, Option<KeyParams>), String> {
+		let (version, key_params, mut entries) =
+			match serde_json::from_slice(bytes).map_err(|e| e.to_string())? {
+				Value::Array(entries) => (0, None, entries),
+				envelope => {
+					let envelope: Envelope<Value, KeyParams> =
+						serde_json::from_value(envelope).map_err(|e| e.to_string())?;
+					(envelope.version, envelope.encryption, envelope.entries)
+				}
+			};
+
+		if version > self.version() {
+			return Err(format!(
+				"File version {version} is newer than the supported {}",
+				self.version()
+			));
+		}
+
+		for (from_version, upgrade) in
+			self.upgrades.iter().enumerate().skip(version)
+		{
+			trace!("Upgrading {} from version {}", self.file_name, from_version);
+			entries = upgrade(entries)?;
+		}
+
+		Ok((entries, key_params))
+	}
+
+	/// Deserializes the entries one by one, skipping the ones that fail
+	pub fn decode<T: DeserializeOwned>(
+		&self, entries: Vec<Value>,
+	) -> (Vec<T>, Vec<SkippedEntry>) {
+		let mut decoded = Vec::with_capacity(entries.len());
+		let mut skipped = vec![];
+		for raw in entries {
+			match T::deserialize(&raw) {
+				Ok(entry) => decoded.push(entry),
+				Err(e) => {
+					warn!("Skipping unparseable entry in {}: {}", self.file_name, e);
+					skipped.push(SkippedEntry {
+						file_name: self.file_name.to_owned(),
+						reason: e.to_string(),
+						raw,
+					});
+				}
+			}
+		}
+
+		(decoded, skipped)
+	}
+}
@@ -0,0 +1,128 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use onlivfe::{Avatar, Instance, PlatformAccount, PlatformFriend, World};
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{trace, warn};
+
+/// The file that the cached platform data is stored in
+pub const FILE_NAME: &str = "cache.bin";
+
+/// Identifies the file as an onlivfe cache snapshot
+const MAGIC: [u8; 4] = *b"OLVC";
+
+/// Should be bumped whenever the layout of the snapshot changes
+const VERSION: u16 = 1;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct Header {
+	magic: [u8; 4],
+	version: u16,
+	/// Seconds since the UNIX epoch
+	saved_at: u64,
+}
+
+/// The platform models only implement serde, so each entry is JSON within the
+/// borsh framing.
+///
+/// Which also allows skipping single entries that don't match the current
+/// models anymore, without losing everything else.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct Entries {
+	accounts: Vec<Vec<u8>>,
+	friends: Vec<Vec<u8>>,
+	instances: Vec<Vec<u8>>,
+	worlds: Vec<Vec<u8>>,
+	avatars: Vec<Vec<u8>>,
+}
+
+/// The cached platform data that is kept across restarts
+#[derive(Debug, Default)]
+pub struct Snapshot {
+	pub accounts: Vec<PlatformAccount>,
+	pub friends: Vec<PlatformFriend>,
+	pub instances: Vec<Instance>,
+	pub worlds: Vec<World>,
+	pub avatars: Vec<Avatar>,
+}
+
+fn encode_entries<'a, T: Serialize + 'a>(
+	items: impl Iterator<Item = &'a T>,
+) -> Result<Vec<Vec<u8>>, std::io::Error> {
+	items
+		.map(|item| {
+			serde_json::to_vec(item)
+				.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+		})
+		.collect()
+}
+
+fn decode_entries<T: DeserializeOwned>(
+	kind: &str, entries: Vec<Vec<u8>>,
+) -> Vec<T> {
+	entries
+		.into_iter()
+		.filter_map(|entry| match serde_json::from_slice(&entry) {
+			Ok(item) => Some(item),
+			Err(e) => {
+				warn!("Skipping cached {} that couldn't be parsed: {}", kind, e);
+				None
+			}
+		})
+		.collect()
+}
+
+/// Serializes the cached platform data into the snapshot format
+pub fn encode<'a>(
+	accounts: impl Iterator<Item = &'a PlatformAccount>,
+	friends: impl Iterator<Item = &'a PlatformFriend>,
+	instances: impl Iterator<Item = &'a Instance>,
+	worlds: impl Iterator<Item = &'a World>,
+	avatars: impl Iterator<Item = &'a Avatar>,
+) -> Result<Vec<u8>, std::io::Error> {
+	let saved_at = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map_or(0, |duration| duration.as_secs());
+	let header = Header { magic: MAGIC, version: VERSION, saved_at };
+	let entries = Entries {
+		accounts: encode_entries(accounts)?,
+		friends: encode_entries(friends)?,
+		instances: encode_entries(instances)?,
+		worlds: encode_entries(worlds)?,
+		avatars: encode_entries(avatars)?,
+	};
+
+	let mut bytes = borsh::to_vec(&header)?;
+	entries.serialize(&mut bytes)?;
+	Ok(bytes)
+}
+
+/// Parses the snapshot, returning `None` if it's from an incompatible version
+///
+/// # Errors
+///
+/// If the bytes aren't a snapshot
+pub fn decode(mut bytes: &[u8]) -> Result<Option<Snapshot>, String> {
+	let header = Header::deserialize(&mut bytes)
+		.map_err(|e| format!("Invalid cache snapshot header: {e}"))?;
+	if header.magic != MAGIC {
+		return Err("Not a cache snapshot".to_owned());
+	}
+	if header.version != VERSION {
+		warn!(
+			"Ignoring cache snapshot of version {}, expected {}",
+			header.version, VERSION
+		);
+		return Ok(None);
+	}
+
+	let entries = Entries::try_from_slice(bytes)
+		.map_err(|e| format!("Invalid cache snapshot: {e}"))?;
+	trace!("Loading cache snapshot saved at {}", header.saved_at);
+
+	Ok(Some(Snapshot {
+		accounts: decode_entries("account", entries.accounts),
+		friends: decode_entries("friend", entries.friends),
+		instances: decode_entries("instance", entries.instances),
+		worlds: decode_entries("world", entries.worlds),
+		avatars: decode_entries("avatar", entries.avatars),
+	}))
+}
@@ -0,0 +1,120 @@
+use onlivfe::{
+	Profile,
+	ProfileRevision,
+	storage::{
+		LayeredStore,
+		OnlivfeStore,
+		WritePolicy,
+		conformance::{self, Samples},
+	},
+};
+use onlivfe_cache_store::OnlivfeCacheStorageBackend;
+
+#[tokio::test]
+async fn conformance_in_memory() {
+	let store = OnlivfeCacheStorageBackend::builder()
+		.in_memory()
+		.build()
+		.expect("the cache storage backend to be created");
+
+	conformance::run(&store, &Samples::builtin()).await;
+}
+
+#[tokio::test]
+async fn conformance_on_disk() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let store = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.build()
+		.expect("the cache storage backend to be created");
+
+	conformance::run(&store, &Samples::builtin()).await;
+}
+
+#[tokio::test]
+async fn only_latest_revisions_are_kept() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let builder = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.max_profile_revisions(2);
+	let store = builder.clone().build().expect("the store to be created");
+
+	let mut profile = Profile::new();
+	let profile_id = profile.sharing_id.clone();
+	for nick in ["First", "Second", "Third"] {
+		profile.nick = Some(nick.to_owned());
+		store
+			.update_profile(profile.clone())
+			.await
+			.expect("the profile to be stored");
+	}
+	let numbers = |revisions: Vec<ProfileRevision>| -> Vec<u64> {
+		revisions.iter().map(|revision| revision.number).collect()
+	};
+	let revisions = store
+		.profile_revisions(profile_id.clone())
+		.await
+		.expect("the revisions to be retrieved");
+	assert_eq!(numbers(revisions), vec![2, 3]);
+	drop(store);
+
+	let reloaded =
+		builder.max_profile_revisions(1).build().expect("the store to be loaded");
+	let revisions = reloaded
+		.profile_revisions(profile_id)
+		.await
+		.expect("the revisions to be retrieved");
+	assert_eq!(numbers(revisions), vec![3], "Excess revisions should be dropped");
+}
+
+#[tokio::test]
+async fn conformance_layered() {
+	for policy in
+		[WritePolicy::WriteThrough, WritePolicy::Batched { max_pending: 2 }]
+	{
+		let dir = tempfile::tempdir().expect("a temporary directory");
+		let fast = OnlivfeCacheStorageBackend::builder()
+			.in_memory()
+			.build()
+			.expect("the fast layer to be created");
+		let durable = OnlivfeCacheStorageBackend::builder()
+			.path(dir.path())
+			.build()
+			.expect("the durable layer to be created");
+		let store = LayeredStore::load(fast, durable, policy)
+			.await
+			.expect("the layered storage backend to be created");
+
+		conformance::run(&store, &Samples::builtin()).await;
+	}
+}
+
+#[tokio::test]
+async fn layered_writes_reach_durable_layer_when_flushed() {
+	let durable = OnlivfeCacheStorageBackend::builder()
+		.in_memory()
+		.build()
+		.expect("the durable layer to be created");
+	let fast = OnlivfeCacheStorageBackend::builder()
+		.in_memory()
+		.build()
+		.expect("the fast layer to be created");
+	let store = LayeredStore::load(
+		fast,
+		durable,
+		WritePolicy::Batched { max_pending: usize::MAX },
+	)
+	.await
+	.expect("the layered storage backend to be created");
+	let account = Samples::builtin().accounts.remove(0);
+
+	store.update_account(account.clone()).await.expect("storing an account");
+	assert!(store.account(account.id()).await.is_ok());
+	assert!(store.durable().account(account.id()).await.is_err());
+
+	store.flush().await.expect("flushing the queued writes");
+	assert_eq!(
+		store.durable().account(account.id()).await.expect("the account"),
+		account
+	);
+}
@@ -0,0 +1,223 @@
+use std::{path::Path, time::Duration};
+
+use onlivfe::{
+	Profile,
+	storage::{OnlivfeStore, StoreBatch, conformance::Samples},
+};
+use onlivfe_cache_store::OnlivfeCacheStorageBackend;
+
+fn open(dir: &Path) -> OnlivfeCacheStorageBackend {
+	OnlivfeCacheStorageBackend::builder()
+		.path(dir)
+		.build()
+		.expect("the storage backend to be loaded")
+}
+
+fn named(nick: &str) -> Profile {
+	let mut profile = Profile::new();
+	profile.nick = Some(nick.to_owned());
+	profile
+}
+
+#[tokio::test]
+async fn corrupt_file_is_replaced_by_backup() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let store = open(dir.path());
+	let first = named("First");
+	store.update_profile(first.clone()).await.expect("storing a profile");
+	store.update_profile(named("Second")).await.expect("storing a profile");
+	drop(store);
+
+	let profiles_file = dir.path().join("profiles.json");
+	let backup_file = dir.path().join("profiles.json.bak");
+	assert!(backup_file.exists(), "the previous version should be kept");
+	assert!(!dir.path().join("profiles.json.tmp").exists());
+	std::fs::write(&profiles_file, b"{ half written").expect("corrupting");
+
+	// Read only uses the backup, but leaves the files as they are
+	let read_only = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.read_only(true)
+		.build()
+		.expect("the storage backend to be loaded from the backup");
+	assert_eq!(
+		read_only.profiles(usize::MAX).await.unwrap(),
+		vec![first.clone()]
+	);
+	drop(read_only);
+	assert_eq!(std::fs::read(&profiles_file).unwrap(), b"{ half written");
+
+	let store = open(dir.path());
+	assert_eq!(store.profiles(usize::MAX).await.unwrap(), vec![first]);
+	assert_eq!(
+		std::fs::read(dir.path().join("profiles.json.unusable")).unwrap(),
+		b"{ half written",
+		"the corrupt file should be kept around"
+	);
+	assert_eq!(
+		std::fs::read(&profiles_file).unwrap(),
+		std::fs::read(&backup_file).unwrap()
+	);
+}
+
+#[tokio::test]
+async fn builder_modes_persist_as_configured() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let persisted = named("Persisted");
+	let store = open(dir.path());
+	store.update_profile(persisted.clone()).await.expect("storing a profile");
+	drop(store);
+	let files_before = std::fs::read(dir.path().join("profiles.json")).unwrap();
+
+	let read_only = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.read_only(true)
+		.build()
+		.expect("the read only storage backend to be loaded");
+	assert_eq!(
+		read_only.profiles(usize::MAX).await.unwrap(),
+		vec![persisted.clone()]
+	);
+	let in_memory_only = named("Not persisted");
+	read_only
+		.update_profile(in_memory_only.clone())
+		.await
+		.expect("storing a profile in memory");
+	assert!(read_only.profile(in_memory_only.sharing_id.clone()).await.is_ok());
+	drop(read_only);
+	assert_eq!(
+		std::fs::read(dir.path().join("profiles.json")).unwrap(),
+		files_before
+	);
+
+	let in_memory = OnlivfeCacheStorageBackend::builder()
+		.in_memory()
+		.build()
+		.expect("the in-memory storage backend to be created");
+	assert!(in_memory.profiles(usize::MAX).await.unwrap().is_empty());
+
+	let store = open(dir.path());
+	assert_eq!(store.profiles(usize::MAX).await.unwrap(), vec![persisted]);
+}
+
+#[tokio::test]
+async fn old_files_are_upgraded_and_bad_entries_skipped() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let profile = named("Unversioned");
+	let unparseable = serde_json::json!({ "unparseable": "entry-marker" });
+	// Files used to be plain lists of entries
+	std::fs::write(
+		dir.path().join("profiles.json"),
+		serde_json::to_vec(&serde_json::json!([profile, unparseable])).unwrap(),
+	)
+	.expect("writing an unversioned file");
+
+	let store = open(dir.path());
+	assert_eq!(store.profiles(usize::MAX).await.unwrap(), vec![profile]);
+	let skipped = store.skipped_entries();
+	assert_eq!(skipped.len(), 1);
+	assert_eq!(skipped[0].file_name, "profiles.json");
+	assert_eq!(skipped[0].raw, unparseable);
+	assert!(
+		!format!("{store:?}").contains("entry-marker"),
+		"the skipped entries shouldn't be debug printed"
+	);
+	drop(store);
+
+	let kept: serde_json::Value = serde_json::from_slice(
+		&std::fs::read(dir.path().join("skipped.json")).unwrap(),
+	)
+	.unwrap();
+	assert_eq!(kept[0]["raw"], unparseable);
+}
+
+#[tokio::test]
+async fn files_from_newer_versions_are_not_loaded() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	std::fs::write(
+		dir.path().join("profiles.json"),
+		br#"{ "version": 1000, "entries": [] }"#,
+	)
+	.expect("writing a file from the future");
+
+	assert!(
+		OnlivfeCacheStorageBackend::builder().path(dir.path()).build().is_err()
+	);
+}
+
+#[tokio::test]
+async fn failed_batch_is_rolled_back() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let store = open(dir.path());
+	// Makes writing the authentications fail after the profiles are written
+	std::fs::create_dir(dir.path().join("auth.json.tmp"))
+		.expect("blocking the authentications file");
+	let mut samples = Samples::builtin();
+	let profile = named("Rolled back");
+	let batch = StoreBatch {
+		accounts: vec![samples.accounts.remove(0)],
+		profiles: vec![profile.clone()],
+		authentications: vec![samples.authentications.remove(0)],
+		..StoreBatch::default()
+	};
+
+	assert!(store.apply_batch(batch).await.is_err());
+	assert!(store.profile(profile.sharing_id.clone()).await.is_err());
+	assert!(store.account_ids(usize::MAX).await.unwrap().is_empty());
+	assert!(store.authentications().await.unwrap().is_empty());
+	drop(store);
+
+	std::fs::remove_dir(dir.path().join("auth.json.tmp")).unwrap();
+	let store = open(dir.path());
+	assert!(store.profiles(usize::MAX).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn cache_is_saved_and_loaded() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let account = Samples::builtin().accounts.remove(0);
+	let store = open(dir.path());
+	store.update_account(account.clone()).await.expect("storing an account");
+	assert!(
+		!dir.path().join("cache.bin").exists(),
+		"the cache shouldn't be saved right after loading"
+	);
+
+	store.save_cache().await.expect("saving the cache");
+	let reloaded = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.read_only(true)
+		.build()
+		.expect("the storage backend to be loaded");
+	assert_eq!(reloaded.account(account.id()).await.unwrap(), account);
+}
+
+#[tokio::test]
+async fn cache_is_saved_after_writes() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let mut samples = Samples::builtin();
+	let store = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.snapshot_interval(Duration::ZERO)
+		.build()
+		.expect("the storage backend to be created");
+	let friend = samples.friends.remove(0);
+	store.update_friend(friend.clone()).await.expect("storing a friend");
+	let batch = StoreBatch {
+		accounts: vec![samples.accounts.remove(0)],
+		..StoreBatch::default()
+	};
+	store.apply_batch(batch.clone()).await.expect("applying a batch");
+
+	// Without dropping the store, as if the app crashed
+	let reloaded = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.read_only(true)
+		.build()
+		.expect("the storage backend to be loaded");
+	assert_eq!(reloaded.friend(friend.id()).await.unwrap(), friend);
+	assert_eq!(
+		reloaded.account(batch.accounts[0].id()).await.unwrap(),
+		batch.accounts[0]
+	);
+}
@@ -0,0 +1,74 @@
+//! Migrates the authentications, profiles and their mappings of the cache
+//! storage backend into the DB storage backend, leaving the original files
+//! untouched:
+//!
+//! ```sh
+//! cargo run -p onlivfe_db_store --example migrate_cache_store -- \
+//!     <cache store directory> <database URL>
+//! ```
+//!
+//! If the authentications are encrypted, the key is read from the file in the
+//! `ONLIVFE_AUTH_KEY_FILE` environment variable, or the passphrase from the
+//! `ONLIVFE_AUTH_PASSPHRASE` one.
+//! They're then encrypted with the same key in the database too.
+
+use onlivfe::encryption::AuthKey;
+use onlivfe_cache_store::OnlivfeCacheStorageBackend;
+use onlivfe_db_store::OnlivfeDatabaseStorageBackend;
+
+/// Gets the key for the encrypted authentications from the environment
+fn auth_key() -> Result<Option<AuthKey>, String> {
+	match (
+		std::env::var_os("ONLIVFE_AUTH_KEY_FILE"),
+		std::env::var("ONLIVFE_AUTH_PASSPHRASE"),
+	) {
+		(Some(_), Ok(_)) => Err(
+			"Only one of ONLIVFE_AUTH_KEY_FILE and ONLIVFE_AUTH_PASSPHRASE can be \
+			 set"
+				.to_owned(),
+		),
+		(Some(path), Err(_)) => Ok(Some(AuthKey::KeyFile(path.into()))),
+		(None, Ok(passphrase)) => Ok(Some(AuthKey::Passphrase(passphrase))),
+		(None, Err(_)) => Ok(None),
+	}
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), String> {
+	let mut args = std::env::args().skip(1);
+	let (Some(cache_dir), Some(db_url)) = (args.next(), args.next()) else {
+		return Err(
+			"Usage: migrate_cache_store <cache store directory> <database URL>"
+				.to_owned(),
+		);
+	};
+
+	let auth_key = auth_key()?;
+	let mut builder =
+		OnlivfeCacheStorageBackend::builder().path(cache_dir).read_only(true);
+	if let Some(auth_key) = &auth_key {
+		builder = builder.encrypt_authentications(auth_key.clone());
+	}
+	let cache_store = builder.build()?;
+	if !cache_store.skipped_entries().is_empty() {
+		eprintln!(
+			"Not migrating {} entries that couldn't be loaded",
+			cache_store.skipped_entries().len()
+		);
+	}
+
+	// The decrypted authentications mustn't end up in cleartext in the DB
+	let db_store = match &auth_key {
+		Some(auth_key) => {
+			OnlivfeDatabaseStorageBackend::new_encrypted(&db_url, auth_key).await?
+		}
+		None => OnlivfeDatabaseStorageBackend::new(&db_url).await?,
+	};
+	let migrated = cache_store.migrate_into(&db_store).await?;
+	println!(
+		"Migrated {} authentications, {} profiles, and {} mappings",
+		migrated.authentications, migrated.profiles, migrated.mappings
+	);
+
+	Ok(())
+}
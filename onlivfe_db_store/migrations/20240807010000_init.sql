@@ -4,7 +4,7 @@ CREATE TABLE profiles(
 	nick TEXT,
 	notes TEXT,
 	pfp_href TEXT,
-	created_at DATETIME NOT NULL DEFAULT DATETIME('now')
+	created_at DATETIME NOT NULL DEFAULT (DATETIME('now'))
 );
 
 CREATE TABLE platform_accounts(
@@ -12,7 +12,7 @@ CREATE TABLE platform_accounts(
 	platform_type TEXT NOT NULL
 	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
 	platform_id TEXT NOT NULL,
-	connection_created_at DATETIME NOT NULL DEFAULT DATETIME('now'),
+	connection_created_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
 
 	PRIMARY KEY(profile_pk, platform_type, platform_id),
 	FOREIGN KEY(profile_pk) REFERENCES profiles(profile_pk)
@@ -23,7 +23,7 @@ CREATE TABLE vrchat_accounts(
 	GENERATED ALWAYS AS ("vrchat") VIRTUAL,
 	vrchat_user_id TEXT NOT NULL,
 	cache_requester_vrchat_user_id TEXT,
-	cache_stored_at DATETIME NOT NULL DEFAULT DATETIME('now'),
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
 
 	FOREIGN KEY(platform_account_type, vrchat_user_id)
 	REFERENCES platform_accounts(platform_type, platform_id)
@@ -31,10 +31,10 @@ CREATE TABLE vrchat_accounts(
 
 CREATE TABLE chilloutvr_accounts(
 	platform_account_type TEXT NOT NULL
-	GENERATED ALWAYS AS "chilloutvr" VIRTUAL,
+	GENERATED ALWAYS AS ("chilloutvr") VIRTUAL,
 	chilloutvr_user_id TEXT NOT NULL,
 	cache_requester_chilloutvr_user_id TEXT,
-	cache_stored_at DATETIME NOT NULL DEFAULT DATETIME('now'),
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
 
 	FOREIGN KEY(platform_account_type, chilloutvr_user_id)
 	REFERENCES platform_accounts(platform_type, platform_id)
@@ -45,7 +45,7 @@ CREATE TABLE resonite_accounts(
 	GENERATED ALWAYS AS ("resonite") VIRTUAL,
 	resonite_user_id TEXT NOT NULL,
 	cache_requester_resonite_user_id TEXT,
-	cache_stored_at DATETIME NOT NULL DEFAULT DATETIME('now'),
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
 
 	name TEXT,
 	registered_at DATETIME,
@@ -0,0 +1,94 @@
+-- Replaces the first draft of the schema, which split accounts into tables per
+-- platform, with the profile mappings and the platform data as JSON
+
+DROP TABLE resonite_accounts;
+DROP TABLE chilloutvr_accounts;
+DROP TABLE vrchat_accounts;
+DROP TABLE platform_accounts;
+DROP TABLE profiles;
+
+CREATE TABLE profiles(
+	sharing_id TEXT PRIMARY KEY NOT NULL,
+	nick TEXT,
+	notes TEXT,
+	pfp_url TEXT,
+	created_at DATETIME NOT NULL DEFAULT (DATETIME('now'))
+);
+
+-- Not referencing the profiles, as accounts can be mapped to profiles before
+-- the profiles themselves are stored
+CREATE TABLE profile_accounts(
+	sharing_id TEXT NOT NULL,
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	platform_id TEXT NOT NULL,
+	connection_created_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
+
+	PRIMARY KEY(sharing_id, platform_type, platform_id)
+);
+
+CREATE INDEX profile_accounts_by_account
+ON profile_accounts(platform_type, platform_id);
+
+-- The platform data is stored as the JSON of the onlivfe models,
+-- as the platforms' models change too often for proper columns
+
+CREATE TABLE platform_accounts(
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	platform_id TEXT NOT NULL,
+	data TEXT NOT NULL,
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
+
+	PRIMARY KEY(platform_type, platform_id)
+);
+
+CREATE TABLE platform_friends(
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	platform_id TEXT NOT NULL,
+	data TEXT NOT NULL,
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
+
+	PRIMARY KEY(platform_type, platform_id)
+);
+
+CREATE TABLE instances(
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	platform_id TEXT NOT NULL,
+	data TEXT NOT NULL,
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
+
+	PRIMARY KEY(platform_type, platform_id)
+);
+
+CREATE TABLE worlds(
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	platform_id TEXT NOT NULL,
+	data TEXT NOT NULL,
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
+
+	PRIMARY KEY(platform_type, platform_id)
+);
+
+CREATE TABLE avatars(
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	platform_id TEXT NOT NULL,
+	data TEXT NOT NULL,
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
+
+	PRIMARY KEY(platform_type, platform_id)
+);
+
+CREATE TABLE authentications(
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	platform_id TEXT NOT NULL,
+	data TEXT NOT NULL,
+	cache_stored_at DATETIME NOT NULL DEFAULT (DATETIME('now')),
+
+	PRIMARY KEY(platform_type, platform_id)
+);
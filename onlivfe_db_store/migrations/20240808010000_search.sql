@@ -0,0 +1,16 @@
+-- The searchable names of the profiles, friends and accounts.
+--
+-- The platform data is JSON, so the names of the friends and accounts are
+-- kept up to date by the storage backend rather than by triggers.
+CREATE VIRTUAL TABLE search_index USING fts5(
+	entity_kind UNINDEXED,
+	platform_type UNINDEXED,
+	entity_id UNINDEXED,
+	name,
+	details,
+	tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO search_index(entity_kind, platform_type, entity_id, name, details)
+SELECT 'profile', '', sharing_id, COALESCE(nick, ''), COALESCE(notes, '')
+FROM profiles;
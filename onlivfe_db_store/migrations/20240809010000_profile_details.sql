@@ -0,0 +1,20 @@
+ALTER TABLE profiles ADD COLUMN pronouns TEXT;
+-- As `YYYY-MM-DD`, or `--MM-DD` when the year is not known
+ALTER TABLE profiles ADD COLUMN birthday TEXT;
+ALTER TABLE profiles ADD COLUMN time_zone TEXT;
+-- A JSON object of the free-form fields
+ALTER TABLE profiles ADD COLUMN custom_fields TEXT NOT NULL DEFAULT '{}';
+ALTER TABLE profiles ADD COLUMN favorite BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE profiles ADD COLUMN updated_at DATETIME;
+
+UPDATE profiles SET updated_at = created_at;
+
+CREATE TABLE profile_tags(
+	sharing_id TEXT NOT NULL,
+	tag TEXT NOT NULL,
+
+	PRIMARY KEY(sharing_id, tag)
+);
+
+CREATE INDEX profile_tags_by_tag
+ON profile_tags(tag COLLATE NOCASE);
@@ -0,0 +1,24 @@
+CREATE TABLE circles(
+	circle_id TEXT PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL,
+	-- As `#rrggbb`
+	color TEXT,
+	position INTEGER NOT NULL DEFAULT 0
+);
+
+-- Not referencing the circles, just like the profile accounts
+CREATE TABLE circle_members(
+	circle_id TEXT NOT NULL,
+	member_kind TEXT NOT NULL
+	CHECK(member_kind IN ('profile', 'account')),
+	-- Empty for profiles
+	platform_type TEXT NOT NULL
+	CHECK(platform_type IN ('', 'vrchat', 'chilloutvr', 'resonite')),
+	member_id TEXT NOT NULL,
+	position INTEGER NOT NULL,
+
+	PRIMARY KEY(circle_id, member_kind, platform_type, member_id)
+);
+
+CREATE INDEX circle_members_by_member
+ON circle_members(member_kind, platform_type, member_id);
@@ -0,0 +1,16 @@
+-- The accounts are in the order that `AccountLink` keeps them in
+CREATE TABLE rejected_links(
+	first_platform_type TEXT NOT NULL
+	CHECK(first_platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	first_platform_id TEXT NOT NULL,
+	second_platform_type TEXT NOT NULL
+	CHECK(second_platform_type IN ('vrchat', 'chilloutvr', 'resonite')),
+	second_platform_id TEXT NOT NULL,
+
+	PRIMARY KEY(
+		first_platform_type,
+		first_platform_id,
+		second_platform_type,
+		second_platform_id
+	)
+);
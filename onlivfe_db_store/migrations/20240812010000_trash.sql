@@ -0,0 +1,7 @@
+-- The trashed profiles are stored as the JSON of the onlivfe models, as they
+-- are only ever restored as a whole
+CREATE TABLE trashed_profiles(
+	sharing_id TEXT PRIMARY KEY NOT NULL,
+	data TEXT NOT NULL,
+	trashed_at DATETIME NOT NULL
+);
@@ -0,0 +1,10 @@
+-- The changes are stored as the JSON of the onlivfe models, and are kept
+-- after the profile is deleted
+CREATE TABLE profile_revisions(
+	sharing_id TEXT NOT NULL,
+	number INTEGER NOT NULL,
+	recorded_at DATETIME NOT NULL,
+	change TEXT NOT NULL,
+
+	PRIMARY KEY(sharing_id, number)
+);
@@ -0,0 +1,8 @@
+-- Kept when the profile is deleted, so that trashed profiles keep theirs
+CREATE TABLE profile_pictures(
+	sharing_id TEXT PRIMARY KEY NOT NULL,
+	content_type TEXT NOT NULL,
+	bytes BLOB NOT NULL,
+	thumbnail BLOB NOT NULL,
+	stored_at DATETIME NOT NULL
+);
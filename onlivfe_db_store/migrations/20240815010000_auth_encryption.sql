@@ -0,0 +1,6 @@
+-- The parameters of the key that the authentications are encrypted with,
+-- which only exist if they're encrypted
+CREATE TABLE auth_key_params(
+	id INTEGER PRIMARY KEY NOT NULL CHECK(id = 1),
+	params TEXT NOT NULL
+);
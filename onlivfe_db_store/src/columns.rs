@@ -0,0 +1,181 @@
+use onlivfe::{
+	Birthday,
+	CircleId,
+	CircleMember,
+	Color,
+	PlatformAccountId,
+	PlatformType,
+	ProfileId,
+	storage::{EntityId, SearchHit},
+};
+use serde::de::DeserializeOwned;
+
+/// The names of the tables that store platform data,
+/// all of which are keyed by the `platform_type` and `platform_id` columns
+pub mod tables {
+	pub const ACCOUNTS: &str = "platform_accounts";
+	pub const FRIENDS: &str = "platform_friends";
+	pub const INSTANCES: &str = "instances";
+	pub const WORLDS: &str = "worlds";
+	pub const AVATARS: &str = "avatars";
+	pub const AUTHENTICATIONS: &str = "authentications";
+}
+
+/// The values of the `entity_kind` column of the search index
+pub mod search_kinds {
+	pub const PROFILE: &str = "profile";
+	pub const ACCOUNT: &str = "account";
+	pub const FRIEND: &str = "friend";
+}
+
+/// The values of the `member_kind` column of the circle members
+pub mod member_kinds {
+	pub const PROFILE: &str = "profile";
+	pub const ACCOUNT: &str = "account";
+}
+
+/// The value of the `platform_type` column for a platform
+pub const fn platform_type(platform: PlatformType) -> &'static str {
+	match platform {
+		PlatformType::VRChat => "vrchat",
+		PlatformType::ChilloutVR => "chilloutvr",
+		PlatformType::Resonite => "resonite",
+	}
+}
+
+/// Recreates a platform specific ID from the `platform_type` and
+/// `platform_id` columns
+///
+/// # Errors
+///
+/// If the columns don't form a valid ID
+pub fn platform_id<Id: DeserializeOwned>(
+	platform_type: &str, platform_id: &str,
+) -> Result<Id, sqlx::Error> {
+	let platform: PlatformType =
+		platform_type.parse().map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+	serde_json::from_value(
+		serde_json::json!({ "platform": platform, "id": platform_id }),
+	)
+	.map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// Parses the value of a `sharing_id` column
+///
+/// # Errors
+///
+/// If the column is not a valid profile ID
+pub fn profile_id(sharing_id: &str) -> Result<ProfileId, sqlx::Error> {
+	sharing_id.parse().map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// Parses the value of a `circle_id` column
+///
+/// # Errors
+///
+/// If the column is not a valid circle ID
+pub fn circle_id(circle_id: &str) -> Result<CircleId, sqlx::Error> {
+	circle_id.parse().map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// Parses the value of a `color` column
+///
+/// # Errors
+///
+/// If the column is not a valid color
+pub fn color(color: Option<String>) -> Result<Option<Color>, sqlx::Error> {
+	color
+		.map(|color| {
+			color.parse().map_err(|e: String| sqlx::Error::Decode(e.into()))
+		})
+		.transpose()
+}
+
+/// The values of the `member_kind`, `platform_type` and `member_id` columns
+/// for a circle member
+pub fn circle_member_columns(
+	member: &CircleMember,
+) -> (&'static str, &'static str, String) {
+	match member {
+		CircleMember::Profile(profile_id) => {
+			(member_kinds::PROFILE, "", profile_id.to_string())
+		}
+		CircleMember::Account(account_id) => (
+			member_kinds::ACCOUNT,
+			platform_type(account_id.platform()),
+			account_id.id_as_string(),
+		),
+	}
+}
+
+/// Recreates a circle member from the `member_kind`, `platform_type` and
+/// `member_id` columns
+///
+/// # Errors
+///
+/// If the columns don't form a valid member
+pub fn circle_member(
+	member_kind: &str, platform_type: &str, member_id: &str,
+) -> Result<CircleMember, sqlx::Error> {
+	match member_kind {
+		member_kinds::PROFILE => Ok(CircleMember::Profile(profile_id(member_id)?)),
+		member_kinds::ACCOUNT => {
+			Ok(CircleMember::Account(platform_id(platform_type, member_id)?))
+		}
+		_ => Err(sqlx::Error::Decode(
+			format!("Unknown circle member kind {member_kind}").into(),
+		)),
+	}
+}
+
+/// Parses the value of a `birthday` column
+///
+/// # Errors
+///
+/// If the column is not a valid birthday
+pub fn birthday(
+	birthday: Option<String>,
+) -> Result<Option<Birthday>, sqlx::Error> {
+	birthday
+		.map(|birthday| {
+			birthday.parse().map_err(|e: String| sqlx::Error::Decode(e.into()))
+		})
+		.transpose()
+}
+
+/// Recreates a search hit from the columns of the search index
+///
+/// # Errors
+///
+/// If the columns don't form a valid hit
+pub fn search_hit(
+	entity_kind: &str, platform_type: &str, entity_id: &str, name: String,
+	bm25: f64,
+) -> Result<SearchHit, sqlx::Error> {
+	let (entity, platform) = match entity_kind {
+		search_kinds::PROFILE => (EntityId::Profile(profile_id(entity_id)?), None),
+		search_kinds::ACCOUNT => {
+			let id: PlatformAccountId = platform_id(platform_type, entity_id)?;
+			let platform = id.platform();
+			(EntityId::Account(id), Some(platform))
+		}
+		search_kinds::FRIEND => {
+			let id: PlatformAccountId = platform_id(platform_type, entity_id)?;
+			let platform = id.platform();
+			(EntityId::Friend(id), Some(platform))
+		}
+		_ => {
+			return Err(sqlx::Error::Decode(
+				format!("Unknown search entity kind {entity_kind}").into(),
+			));
+		}
+	};
+
+	Ok(SearchHit {
+		entity,
+		platform,
+		name: (!name.is_empty()).then_some(name),
+		// Lower is better with BM25
+		score: -bm25,
+	})
+}
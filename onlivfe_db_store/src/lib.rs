@@ -15,28 +15,61 @@
 // Not much can be done about it :/
 #![allow(clippy::multiple_crate_versions)]
 
+use std::collections::{BTreeMap, HashSet};
+
+use columns::{member_kinds, search_kinds, tables};
 use onlivfe::{
+	AccountLink,
 	Authentication,
 	Avatar,
 	AvatarId,
+	Circle,
+	CircleId,
+	CircleMember,
 	Instance,
 	InstanceId,
 	PlatformAccount,
 	PlatformAccountId,
 	PlatformFriend,
+	PlatformType,
 	Profile,
+	ProfileChange,
 	ProfileId,
+	ProfilePicture,
+	ProfileRevision,
+	TrashedProfile,
 	World,
 	WorldId,
-	storage::OnlivfeStore,
+	encryption::{AuthCipher, AuthKey, KeyParams, Sealed},
+	storage::{
+		BatchUpdated,
+		ChangeKind,
+		ChangeNotifier,
+		ChangeStream,
+		EntityId,
+		OnlivfeStore,
+		SearchHit,
+		StoreBatch,
+		StoreChange,
+		search,
+	},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sqlx::{
+	SqliteConnection,
+	types::{Json, time::OffsetDateTime},
 };
 
+mod columns;
+
 #[derive(Debug)]
 /// A database backend storage for onlivfe
 pub struct OnlivfeDatabaseStorageBackend {
 	/// The main database connection pool
-	#[allow(dead_code)]
 	db: sqlx::SqlitePool,
+	/// Encrypts the stored authentications, if they should be
+	auth_cipher: Option<AuthCipher>,
+	changes: ChangeNotifier,
 }
 
 impl OnlivfeDatabaseStorageBackend {
@@ -44,8 +77,29 @@ impl OnlivfeDatabaseStorageBackend {
 	///
 	/// # Errors
 	///
-	/// If the storage connection setup fails
+	/// If the storage connection setup fails, or if the stored authentications
+	/// are encrypted
 	pub async fn new(db_url: &str) -> Result<Self, String> {
+		Self::connect(db_url, None).await
+	}
+
+	/// Creates a new onlivfe core interface, encrypting the stored
+	/// authentications with the key.
+	///
+	/// Previously unencrypted authentications get encrypted when connecting.
+	///
+	/// # Errors
+	///
+	/// If the storage connection setup fails, or if the key is wrong
+	pub async fn new_encrypted(
+		db_url: &str, key: &AuthKey,
+	) -> Result<Self, String> {
+		Self::connect(db_url, Some(key)).await
+	}
+
+	async fn connect(
+		db_url: &str, auth_key: Option<&AuthKey>,
+	) -> Result<Self, String> {
 		use sqlx::{
 			Executor,
 			sqlite::{SqlitePool, SqlitePoolOptions},
@@ -55,8 +109,7 @@ impl OnlivfeDatabaseStorageBackend {
 			.max_connections(
 				(num_cpus::get() - 1)
 					.try_into()
-					.map(|v| if v < 1 { 1u32 } else { v })
-					.unwrap_or(4),
+					.map_or(4, |v| if v < 1 { 1u32 } else { v }),
 			)
 			.min_connections(1)
 			.connect(db_url)
@@ -76,145 +129,1473 @@ impl OnlivfeDatabaseStorageBackend {
 			.await
 			.map_err(|e| "Failed to migrate DB: ".to_string() + &e.to_string())?;
 
-		Ok(Self { db })
+		let auth_cipher = encrypt_authentications(&db, auth_key).await?;
+		let store = Self { db, auth_cipher, changes: ChangeNotifier::new() };
+		store.index_missing_names().await.map_err(|e| {
+			"Failed to update the search index: ".to_string() + &e.to_string()
+		})?;
+
+		Ok(store)
+	}
+
+	/// Adds the friends and accounts that are stored but not searchable,
+	/// as the search index is newer than them
+	async fn index_missing_names(&self) -> Result<(), sqlx::Error> {
+		// Read before the transaction, as it might hold the only connection
+		let accounts = self
+			.unindexed::<PlatformAccount>(tables::ACCOUNTS, search_kinds::ACCOUNT)
+			.await?;
+		let friends = self
+			.unindexed::<PlatformFriend>(tables::FRIENDS, search_kinds::FRIEND)
+			.await?;
+
+		let mut tx = self.db.begin().await?;
+		for account in accounts {
+			index_name(
+				&mut tx,
+				search_kinds::ACCOUNT,
+				&account.id(),
+				account.display_name(),
+			)
+			.await?;
+		}
+		for friend in friends {
+			index_name(
+				&mut tx,
+				search_kinds::FRIEND,
+				&friend.id(),
+				friend.display_name(),
+			)
+			.await?;
+		}
+
+		tx.commit().await
+	}
+
+	/// Gets the platform data of a table that's missing from the search index
+	async fn unindexed<T: DeserializeOwned + Send + Unpin + 'static>(
+		&self, table: &str, entity_kind: &str,
+	) -> Result<Vec<T>, sqlx::Error> {
+		let rows: Vec<(Json<T>,)> = sqlx::query_as(&format!(
+			"SELECT data FROM {table} AS t WHERE NOT EXISTS (
+				SELECT 1 FROM search_index AS s WHERE s.entity_kind = ?
+				AND s.platform_type = t.platform_type AND s.entity_id = t.platform_id
+			)"
+		))
+		.bind(entity_kind)
+		.fetch_all(&self.db)
+		.await?;
+
+		Ok(rows.into_iter().map(|(Json(data),)| data).collect())
+	}
+}
+
+impl OnlivfeDatabaseStorageBackend {
+	/// Lists the IDs of the platform data stored in a table
+	async fn platform_ids<Id: DeserializeOwned>(
+		&self, table: &str, max: usize,
+	) -> Result<Vec<Id>, sqlx::Error> {
+		let rows: Vec<(String, String)> = sqlx::query_as(&format!(
+			"SELECT platform_type, platform_id FROM {table} ORDER BY rowid LIMIT ?"
+		))
+		.bind(i64::try_from(max).unwrap_or(i64::MAX))
+		.fetch_all(&self.db)
+		.await?;
+
+		rows
+			.into_iter()
+			.map(|(platform_type, platform_id)| {
+				columns::platform_id(&platform_type, &platform_id)
+			})
+			.collect()
+	}
+
+	/// Gets platform data from a table by its ID
+	async fn platform_data<T: DeserializeOwned + Send + Unpin + 'static>(
+		&self, table: &str, platform: PlatformType, platform_id: String,
+	) -> Result<T, sqlx::Error> {
+		let (Json(data),): (Json<T>,) = sqlx::query_as(&format!(
+			"SELECT data FROM {table} WHERE platform_type = ? AND platform_id = ?"
+		))
+		.bind(columns::platform_type(platform))
+		.bind(platform_id)
+		.fetch_one(&self.db)
+		.await?;
+
+		Ok(data)
+	}
+
+	/// Serializes an authentication for storing, encrypting it if needed
+	fn authentication_data(
+		&self, authentication: &Authentication,
+	) -> Result<serde_json::Value, sqlx::Error> {
+		let data = match &self.auth_cipher {
+			Some(cipher) => serde_json::to_value(
+				cipher
+					.seal(authentication)
+					.map_err(|e| sqlx::Error::Encode(Box::new(e)))?,
+			),
+			None => serde_json::to_value(authentication),
+		};
+
+		data.map_err(|e| sqlx::Error::Encode(Box::new(e)))
+	}
+
+	/// Parses a stored authentication, decrypting it if needed
+	fn parse_authentication(
+		&self, data: &serde_json::Value,
+	) -> Result<Authentication, sqlx::Error> {
+		match &self.auth_cipher {
+			Some(cipher) => {
+				let sealed = Sealed::deserialize(data)
+					.map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+				cipher.open(&sealed).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+			}
+			None => Authentication::deserialize(data)
+				.map_err(|e| sqlx::Error::Decode(Box::new(e))),
+		}
+	}
+
+	/// Stores platform data into a table, returning if it replaced existing data
+	async fn update_platform_data<T: Serialize + Sync>(
+		&self, table: &str, platform: PlatformType, platform_id: String, data: &T,
+	) -> Result<bool, sqlx::Error> {
+		let mut tx = self.db.begin().await?;
+		let existed =
+			upsert_platform_data(&mut tx, table, platform, platform_id, data).await?;
+		tx.commit().await?;
+
+		Ok(existed)
+	}
+}
+
+/// Gets the cipher for the stored authentications if there's a key, encrypting
+/// the ones that were stored in cleartext
+///
+/// # Errors
+///
+/// If the key is wrong, if the authentications are encrypted but there's no
+/// key, or if encrypting them fails
+async fn encrypt_authentications(
+	db: &sqlx::SqlitePool, key: Option<&AuthKey>,
+) -> Result<Option<AuthCipher>, String> {
+	let encrypt_err =
+		|e: sqlx::Error| format!("Failed to encrypt authentications: {e}");
+
+	let params: Option<(Json<KeyParams>,)> =
+		sqlx::query_as("SELECT params FROM auth_key_params")
+			.fetch_optional(db)
+			.await
+			.map_err(encrypt_err)?;
+	let cipher = match (key, params) {
+		(Some(key), Some((Json(params),))) => AuthCipher::unlock(key, &params),
+		(Some(key), None) => AuthCipher::create(key),
+		(None, Some(_)) => {
+			return Err(
+				"The stored authentications are encrypted, but no key was given"
+					.to_owned(),
+			);
+		}
+		(None, None) => return Ok(None),
+	}
+	.map_err(|e| e.to_string())?;
+
+	let rows: Vec<(String, String, Json<serde_json::Value>)> = sqlx::query_as(
+		"SELECT platform_type, platform_id, data FROM authentications",
+	)
+	.fetch_all(db)
+	.await
+	.map_err(encrypt_err)?;
+	let mut tx = db.begin().await.map_err(encrypt_err)?;
+	sqlx::query(
+		"INSERT OR IGNORE INTO auth_key_params(id, params) VALUES (1, ?)",
+	)
+	.bind(Json(cipher.params()))
+	.execute(&mut *tx)
+	.await
+	.map_err(encrypt_err)?;
+	let mut encrypted = 0;
+	for (platform_type, platform_id, Json(data)) in rows {
+		if Sealed::deserialize(&data).is_ok() {
+			continue;
+		}
+		let sealed = Authentication::deserialize(&data)
+			.map_err(|e| e.to_string())
+			.and_then(|auth| cipher.seal(&auth).map_err(|e| e.to_string()))
+			.map_err(|e| {
+				format!("Failed to encrypt authentication {platform_id}: {e}")
+			})?;
+		sqlx::query(
+			"UPDATE authentications SET data = ?
+			WHERE platform_type = ? AND platform_id = ?",
+		)
+		.bind(Json(sealed))
+		.bind(platform_type)
+		.bind(platform_id)
+		.execute(&mut *tx)
+		.await
+		.map_err(encrypt_err)?;
+		encrypted += 1;
+	}
+	tx.commit().await.map_err(encrypt_err)?;
+
+	if encrypted > 0 {
+		// So that the cleartext isn't left around in the freed pages,
+		// or in the write-ahead log
+		sqlx::query("VACUUM").execute(db).await.map_err(encrypt_err)?;
+		sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+			.execute(db)
+			.await
+			.map_err(encrypt_err)?;
+	}
+
+	Ok(Some(cipher))
+}
+
+/// Stores platform data into a table within a transaction,
+/// returning if it replaced existing data
+async fn upsert_platform_data<T: Serialize + Sync>(
+	conn: &mut SqliteConnection, table: &str, platform: PlatformType,
+	platform_id: String, data: &T,
+) -> Result<bool, sqlx::Error> {
+	let existed = sqlx::query(&format!(
+		"SELECT 1 FROM {table} WHERE platform_type = ? AND platform_id = ?"
+	))
+	.bind(columns::platform_type(platform))
+	.bind(&platform_id)
+	.fetch_optional(&mut *conn)
+	.await?
+	.is_some();
+
+	sqlx::query(&format!(
+		"INSERT INTO {table}(platform_type, platform_id, data) VALUES (?, ?, ?)
+		ON CONFLICT(platform_type, platform_id) DO UPDATE SET
+		data = excluded.data, cache_stored_at = DATETIME('now')"
+	))
+	.bind(columns::platform_type(platform))
+	.bind(&platform_id)
+	.bind(Json(data))
+	.execute(&mut *conn)
+	.await?;
+
+	Ok(existed)
+}
+
+/// Gets a profile, if it's stored
+async fn stored_profile(
+	conn: &mut SqliteConnection, profile_id: &ProfileId,
+) -> Result<Option<Profile>, sqlx::Error> {
+	let sharing_id = profile_id.to_string();
+	#[allow(clippy::type_complexity)]
+	let row: Option<(
+		Option<String>,
+		Option<String>,
+		Option<String>,
+		Option<String>,
+		Option<String>,
+		Option<String>,
+		Json<BTreeMap<String, String>>,
+		bool,
+		OffsetDateTime,
+		Option<OffsetDateTime>,
+	)> = sqlx::query_as(
+		"SELECT nick, notes, pfp_url, pronouns, birthday, time_zone,
+		custom_fields, favorite, created_at, updated_at
+		FROM profiles WHERE sharing_id = ?",
+	)
+	.bind(&sharing_id)
+	.fetch_optional(&mut *conn)
+	.await?;
+	let Some((
+		nick,
+		notes,
+		pfp_url,
+		pronouns,
+		birthday,
+		time_zone,
+		Json(custom_fields),
+		favorite,
+		created_at,
+		updated_at,
+	)) = row
+	else {
+		return Ok(None);
+	};
+	let tags: Vec<(String,)> =
+		sqlx::query_as("SELECT tag FROM profile_tags WHERE sharing_id = ?")
+			.bind(&sharing_id)
+			.fetch_all(&mut *conn)
+			.await?;
+
+	Ok(Some(Profile {
+		sharing_id: profile_id.clone(),
+		nick,
+		notes,
+		pfp_url,
+		tags: tags.into_iter().map(|(tag,)| tag).collect(),
+		custom_fields,
+		pronouns,
+		birthday: columns::birthday(birthday)?,
+		time_zone,
+		favorite,
+		created_at,
+		updated_at: updated_at.unwrap_or(created_at),
+	}))
+}
+
+/// Stores a profile within a transaction, returning if it replaced an
+/// existing one
+async fn upsert_profile(
+	conn: &mut SqliteConnection, profile: Profile,
+) -> Result<bool, sqlx::Error> {
+	let profile_id = profile.sharing_id.clone();
+	let sharing_id = profile_id.to_string();
+
+	let previous = stored_profile(conn, &profile_id).await?;
+	let existed = previous.is_some();
+	let change = ProfileChange::details(previous, Some(profile.clone()));
+
+	sqlx::query(
+		"INSERT INTO profiles(sharing_id, nick, notes, pfp_url, pronouns, birthday,
+		time_zone, custom_fields, favorite, created_at, updated_at)
+		VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+		ON CONFLICT(sharing_id) DO UPDATE SET
+		nick = excluded.nick, notes = excluded.notes, pfp_url = excluded.pfp_url,
+		pronouns = excluded.pronouns, birthday = excluded.birthday,
+		time_zone = excluded.time_zone, custom_fields = excluded.custom_fields,
+		favorite = excluded.favorite, created_at = excluded.created_at,
+		updated_at = excluded.updated_at",
+	)
+	.bind(&sharing_id)
+	.bind(&profile.nick)
+	.bind(&profile.notes)
+	.bind(profile.pfp_url)
+	.bind(profile.pronouns)
+	.bind(profile.birthday.map(|birthday| birthday.to_string()))
+	.bind(profile.time_zone)
+	.bind(Json(&profile.custom_fields))
+	.bind(profile.favorite)
+	.bind(profile.created_at)
+	.bind(profile.updated_at)
+	.execute(&mut *conn)
+	.await?;
+
+	sqlx::query("DELETE FROM profile_tags WHERE sharing_id = ?")
+		.bind(&sharing_id)
+		.execute(&mut *conn)
+		.await?;
+	for tag in &profile.tags {
+		sqlx::query("INSERT INTO profile_tags(sharing_id, tag) VALUES (?, ?)")
+			.bind(&sharing_id)
+			.bind(tag)
+			.execute(&mut *conn)
+			.await?;
 	}
+
+	index_for_search(
+		conn,
+		search_kinds::PROFILE,
+		"",
+		&sharing_id,
+		profile.nick.as_deref().unwrap_or_default(),
+		profile.notes.as_deref().unwrap_or_default(),
+	)
+	.await?;
+
+	if let Some(change) = change {
+		record_revision(conn, &profile_id, change).await?;
+	}
+
+	Ok(existed)
+}
+
+/// Records a change as the next revision of a profile within a transaction
+async fn record_revision(
+	conn: &mut SqliteConnection, profile_id: &ProfileId, change: ProfileChange,
+) -> Result<(), sqlx::Error> {
+	let sharing_id = profile_id.to_string();
+
+	sqlx::query(
+		"INSERT INTO profile_revisions(sharing_id, number, recorded_at, change)
+		SELECT ?, COALESCE(MAX(number), 0) + 1, ?, ?
+		FROM profile_revisions WHERE sharing_id = ?",
+	)
+	.bind(&sharing_id)
+	.bind(OffsetDateTime::now_utc())
+	.bind(Json(change))
+	.bind(&sharing_id)
+	.execute(&mut *conn)
+	.await?;
+
+	Ok(())
+}
+
+/// Replaces the searchable texts of something within a transaction
+async fn index_for_search(
+	conn: &mut SqliteConnection, entity_kind: &str, platform_type: &str,
+	entity_id: &str, name: &str, details: &str,
+) -> Result<(), sqlx::Error> {
+	sqlx::query(
+		"DELETE FROM search_index
+		WHERE entity_kind = ? AND platform_type = ? AND entity_id = ?",
+	)
+	.bind(entity_kind)
+	.bind(platform_type)
+	.bind(entity_id)
+	.execute(&mut *conn)
+	.await?;
+
+	sqlx::query(
+		"INSERT INTO search_index(entity_kind, platform_type, entity_id, name, details)
+		VALUES (?, ?, ?, ?, ?)",
+	)
+	.bind(entity_kind)
+	.bind(platform_type)
+	.bind(entity_id)
+	.bind(name)
+	.bind(details)
+	.execute(&mut *conn)
+	.await?;
+
+	Ok(())
+}
+
+/// Makes the display name of a friend or an account searchable within a
+/// transaction
+async fn index_name(
+	conn: &mut SqliteConnection, entity_kind: &str, id: &PlatformAccountId,
+	name: &str,
+) -> Result<(), sqlx::Error> {
+	index_for_search(
+		conn,
+		entity_kind,
+		columns::platform_type(id.platform()),
+		&id.id_as_string(),
+		name,
+		"",
+	)
+	.await
+}
+
+/// Gets the IDs of the profiles that an account is linked to
+async fn mapped_profile_ids(
+	conn: &mut SqliteConnection, account_id: &PlatformAccountId,
+) -> Result<Vec<ProfileId>, sqlx::Error> {
+	let rows: Vec<(String,)> = sqlx::query_as(
+		"SELECT sharing_id FROM profile_accounts
+		WHERE platform_type = ? AND platform_id = ? ORDER BY rowid",
+	)
+	.bind(columns::platform_type(account_id.platform()))
+	.bind(account_id.id_as_string())
+	.fetch_all(&mut *conn)
+	.await?;
+
+	rows.iter().map(|(sharing_id,)| columns::profile_id(sharing_id)).collect()
+}
+
+/// Gets the IDs of the accounts that are linked to a profile
+async fn mapped_account_ids(
+	conn: &mut SqliteConnection, profile_id: &ProfileId,
+) -> Result<Vec<PlatformAccountId>, sqlx::Error> {
+	let rows: Vec<(String, String)> = sqlx::query_as(
+		"SELECT platform_type, platform_id FROM profile_accounts
+		WHERE sharing_id = ? ORDER BY rowid",
+	)
+	.bind(profile_id.to_string())
+	.fetch_all(&mut *conn)
+	.await?;
+
+	rows
+		.into_iter()
+		.map(|(platform_type, platform_id)| {
+			columns::platform_id(&platform_type, &platform_id)
+		})
+		.collect()
+}
+
+/// Replaces the accounts linked to a profile within a transaction, returning
+/// the changes to notify about
+async fn replace_profile_accounts(
+	conn: &mut SqliteConnection, profile_id: &ProfileId,
+	account_ids: &[PlatformAccountId],
+) -> Result<Vec<StoreChange>, sqlx::Error> {
+	let sharing_id = profile_id.to_string();
+	let previous_account_ids = mapped_account_ids(conn, profile_id).await?;
+
+	sqlx::query("DELETE FROM profile_accounts WHERE sharing_id = ?")
+		.bind(&sharing_id)
+		.execute(&mut *conn)
+		.await?;
+	for account_id in account_ids {
+		sqlx::query(
+			"INSERT OR IGNORE INTO profile_accounts(sharing_id, platform_type, platform_id)
+			VALUES (?, ?, ?)",
+		)
+		.bind(&sharing_id)
+		.bind(columns::platform_type(account_id.platform()))
+		.bind(account_id.id_as_string())
+		.execute(&mut *conn)
+		.await?;
+	}
+	let new_account_ids = mapped_account_ids(conn, profile_id).await?;
+	if let Some(change) =
+		ProfileChange::accounts(previous_account_ids.clone(), new_account_ids)
+	{
+		record_revision(conn, profile_id, change).await?;
+	}
+
+	Ok(StoreChange::profile_accounts_replaced(
+		profile_id,
+		&previous_account_ids,
+		account_ids,
+	))
+}
+
+/// Replaces the members of a circle within a transaction
+async fn replace_circle_members(
+	conn: &mut SqliteConnection, circle_id: &CircleId, members: &[CircleMember],
+) -> Result<(), sqlx::Error> {
+	let id = circle_id.to_string();
+
+	sqlx::query("DELETE FROM circle_members WHERE circle_id = ?")
+		.bind(&id)
+		.execute(&mut *conn)
+		.await?;
+	for (position, member) in members.iter().enumerate() {
+		let (member_kind, platform_type, member_id) =
+			columns::circle_member_columns(member);
+		// Only the first position of a member counts
+		sqlx::query(
+			"INSERT OR IGNORE INTO circle_members(circle_id, member_kind,
+			platform_type, member_id, position) VALUES (?, ?, ?, ?, ?)",
+		)
+		.bind(&id)
+		.bind(member_kind)
+		.bind(platform_type)
+		.bind(member_id)
+		.bind(i64::try_from(position).unwrap_or(i64::MAX))
+		.execute(&mut *conn)
+		.await?;
+	}
+
+	Ok(())
+}
+
+/// Deletes a profile along with its mappings and circle memberships within a
+/// transaction, returning the changes to notify about
+async fn remove_profile(
+	conn: &mut SqliteConnection, profile_id: &ProfileId,
+) -> Result<Vec<StoreChange>, sqlx::Error> {
+	let sharing_id = profile_id.to_string();
+	let previous = stored_profile(conn, profile_id).await?;
+	let account_ids = mapped_account_ids(conn, profile_id).await?;
+	let circle_rows: Vec<(String,)> = sqlx::query_as(
+		"SELECT circle_id FROM circle_members
+		WHERE member_kind = ? AND platform_type = '' AND member_id = ?",
+	)
+	.bind(member_kinds::PROFILE)
+	.bind(&sharing_id)
+	.fetch_all(&mut *conn)
+	.await?;
+	let circle_ids = circle_rows
+		.iter()
+		.map(|(circle_id,)| columns::circle_id(circle_id))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	sqlx::query("DELETE FROM profile_accounts WHERE sharing_id = ?")
+		.bind(&sharing_id)
+		.execute(&mut *conn)
+		.await?;
+	sqlx::query("DELETE FROM profiles WHERE sharing_id = ?")
+		.bind(&sharing_id)
+		.execute(&mut *conn)
+		.await?;
+	sqlx::query("DELETE FROM profile_tags WHERE sharing_id = ?")
+		.bind(&sharing_id)
+		.execute(&mut *conn)
+		.await?;
+	sqlx::query(
+		"DELETE FROM circle_members
+		WHERE member_kind = ? AND platform_type = '' AND member_id = ?",
+	)
+	.bind(member_kinds::PROFILE)
+	.bind(&sharing_id)
+	.execute(&mut *conn)
+	.await?;
+	sqlx::query(
+		"DELETE FROM search_index WHERE entity_kind = ? AND entity_id = ?",
+	)
+	.bind(search_kinds::PROFILE)
+	.bind(&sharing_id)
+	.execute(&mut *conn)
+	.await?;
+	let changes = [
+		ProfileChange::details(previous, None),
+		ProfileChange::accounts(account_ids.clone(), vec![]),
+	];
+	for change in changes.into_iter().flatten() {
+		record_revision(conn, profile_id, change).await?;
+	}
+
+	Ok(StoreChange::profile_deleted(profile_id, &account_ids, &circle_ids))
+}
+
+/// Stores all of the platform data in a table within a transaction,
+/// returning the IDs of the ones that replaced data that existed before, each
+/// once
+macro_rules! upsert_all {
+	($tx:ident, $table:expr, $entries:expr $(, $search_kind:expr)?) => {{
+		let mut seen = HashSet::new();
+		let mut ids = vec![];
+		for entry in $entries {
+			let id = entry.id();
+			let first = seen.insert(id.clone());
+			let existed = upsert_platform_data(
+				&mut $tx,
+				$table,
+				id.platform(),
+				id.id_as_string(),
+				&entry,
+			)
+			.await?;
+			$(
+				index_name(&mut $tx, $search_kind, &id, entry.display_name()).await?;
+			)?
+			if existed && first {
+				ids.push(id);
+			}
+		}
+		ids
+	}};
 }
 
 #[async_trait::async_trait]
 impl OnlivfeStore for OnlivfeDatabaseStorageBackend {
 	type Err = sqlx::Error;
 
+	fn watch(&self) -> Option<ChangeStream> { Some(self.changes.watch()) }
+
+	async fn apply_batch(
+		&self, batch: StoreBatch,
+	) -> Result<BatchUpdated, Self::Err> {
+		let entity_ids =
+			if self.changes.is_watched() { batch.entity_ids() } else { vec![] };
+		let authentication_data = batch
+			.authentications
+			.iter()
+			.map(|auth| Ok((auth.id(), self.authentication_data(auth)?)))
+			.collect::<Result<Vec<_>, sqlx::Error>>()?;
+		let mut tx = self.db.begin().await?;
+
+		// Later entries with the same ID replace the earlier ones from the batch,
+		// so only the first ones tell if they existed before it
+		let mut seen = HashSet::new();
+		let mut profiles = vec![];
+		for profile in batch.profiles {
+			let id = profile.sharing_id.clone();
+			let first = seen.insert(id.clone());
+			if upsert_profile(&mut tx, profile).await? && first {
+				profiles.push(id);
+			}
+		}
+		let mut seen = HashSet::new();
+		let mut authentications = vec![];
+		for (id, data) in authentication_data {
+			let first = seen.insert(id.clone());
+			let existed = upsert_platform_data(
+				&mut tx,
+				tables::AUTHENTICATIONS,
+				id.platform(),
+				id.id_as_string(),
+				&data,
+			)
+			.await?;
+			if existed && first {
+				authentications.push(id);
+			}
+		}
+		let updated = BatchUpdated {
+			accounts: upsert_all!(
+				tx,
+				tables::ACCOUNTS,
+				batch.accounts,
+				search_kinds::ACCOUNT
+			),
+			friends: upsert_all!(
+				tx,
+				tables::FRIENDS,
+				batch.friends,
+				search_kinds::FRIEND
+			),
+			instances: upsert_all!(tx, tables::INSTANCES, batch.instances),
+			worlds: upsert_all!(tx, tables::WORLDS, batch.worlds),
+			avatars: upsert_all!(tx, tables::AVATARS, batch.avatars),
+			profiles,
+			authentications,
+		};
+		let mut changes = vec![];
+		for (profile_id, account_ids) in &batch.profile_account_ids {
+			changes.extend(
+				replace_profile_accounts(&mut tx, profile_id, account_ids).await?,
+			);
+		}
+		for (circle_id, members) in batch.circle_members {
+			replace_circle_members(&mut tx, &circle_id, &members).await?;
+			changes
+				.push(StoreChange::stored(EntityId::CircleMembers(circle_id), true));
+		}
+		for profile_id in &batch.deleted_profiles {
+			changes.extend(remove_profile(&mut tx, profile_id).await?);
+		}
+
+		tx.commit().await?;
+
+		self.changes.notify_batch(entity_ids, &updated);
+		self.changes.notify(changes);
+		Ok(updated)
+	}
+
+	async fn search(
+		&self, query: &str, max: usize,
+	) -> Result<Vec<SearchHit>, Self::Err> {
+		// Every term needs to match the start of a word, as it's likely still
+		// being typed, and the terms are alphanumeric so they can't break out of
+		// the quotes
+		let fts_query = search::terms(query)
+			.iter()
+			.map(|term| format!("\"{term}\"*"))
+			.collect::<Vec<_>>()
+			.join(" ");
+		if fts_query.is_empty() {
+			return Ok(vec![]);
+		}
+
+		// Matches in the names are worth more than ones in the notes
+		let rows: Vec<(String, String, String, String, f64)> = sqlx::query_as(
+			"SELECT entity_kind, platform_type, entity_id, name,
+			bm25(search_index, 0.0, 0.0, 0.0, 2.0, 1.0) AS score
+			FROM search_index WHERE search_index MATCH ? ORDER BY score LIMIT ?",
+		)
+		.bind(fts_query)
+		.bind(i64::try_from(max).unwrap_or(i64::MAX))
+		.fetch_all(&self.db)
+		.await?;
+
+		rows
+			.into_iter()
+			.map(|(entity_kind, platform_type, entity_id, name, score)| {
+				columns::search_hit(
+					&entity_kind,
+					&platform_type,
+					&entity_id,
+					name,
+					score,
+				)
+			})
+			.collect()
+	}
+
 	async fn account_ids(
-		&self, _max: usize,
+		&self, max: usize,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
-		todo!();
+		self.platform_ids(tables::ACCOUNTS, max).await
 	}
 
 	async fn account(
-		&self, _account_id: PlatformAccountId,
+		&self, account_id: PlatformAccountId,
 	) -> Result<PlatformAccount, Self::Err> {
-		todo!();
+		self
+			.platform_data(
+				tables::ACCOUNTS,
+				account_id.platform(),
+				account_id.id_as_string(),
+			)
+			.await
 	}
 
 	async fn account_profile_ids(
-		&self, _account_id: PlatformAccountId,
+		&self, account_id: PlatformAccountId,
 	) -> Result<Vec<ProfileId>, Self::Err> {
-		todo!();
+		mapped_profile_ids(&mut *self.db.acquire().await?, &account_id).await
 	}
 
 	async fn update_account_profile_ids(
-		&self, _account_id: PlatformAccountId, _profile_ids: Vec<ProfileId>,
+		&self, account_id: PlatformAccountId, profile_ids: Vec<ProfileId>,
 	) -> Result<(), Self::Err> {
-		todo!();
+		let platform_type = columns::platform_type(account_id.platform());
+		let platform_id = account_id.id_as_string();
+		let mut tx = self.db.begin().await?;
+		let previous_profile_ids = mapped_profile_ids(&mut tx, &account_id).await?;
+		let mut affected: Vec<(ProfileId, Vec<PlatformAccountId>)> = vec![];
+		for profile_id in previous_profile_ids.iter().chain(&profile_ids) {
+			if !affected.iter().any(|(id, _)| id == profile_id) {
+				let account_ids = mapped_account_ids(&mut tx, profile_id).await?;
+				affected.push((profile_id.clone(), account_ids));
+			}
+		}
+
+		sqlx::query(
+			"DELETE FROM profile_accounts WHERE platform_type = ? AND platform_id = ?",
+		)
+		.bind(platform_type)
+		.bind(&platform_id)
+		.execute(&mut *tx)
+		.await?;
+		for profile_id in &profile_ids {
+			sqlx::query(
+				"INSERT OR IGNORE INTO profile_accounts(sharing_id, platform_type, platform_id)
+				VALUES (?, ?, ?)",
+			)
+			.bind(profile_id.to_string())
+			.bind(platform_type)
+			.bind(&platform_id)
+			.execute(&mut *tx)
+			.await?;
+		}
+		for (profile_id, old) in affected {
+			let new = mapped_account_ids(&mut tx, &profile_id).await?;
+			if let Some(change) = ProfileChange::accounts(old, new) {
+				record_revision(&mut tx, &profile_id, change).await?;
+			}
+		}
+
+		tx.commit().await?;
+
+		self.changes.notify(StoreChange::account_profiles_replaced(
+			&account_id,
+			&previous_profile_ids,
+			&profile_ids,
+		));
+		Ok(())
 	}
 
 	async fn update_account(
-		&self, _account: PlatformAccount,
+		&self, account: PlatformAccount,
 	) -> Result<bool, Self::Err> {
-		todo!();
+		let id = account.id();
+		let mut tx = self.db.begin().await?;
+		let existed = upsert_platform_data(
+			&mut tx,
+			tables::ACCOUNTS,
+			id.platform(),
+			id.id_as_string(),
+			&account,
+		)
+		.await?;
+		index_name(&mut tx, search_kinds::ACCOUNT, &id, account.display_name())
+			.await?;
+		tx.commit().await?;
+
+		self.changes.notify_stored(EntityId::Account(id), existed);
+		Ok(existed)
 	}
 
 	async fn friend_ids(
-		&self, _max: usize,
+		&self, max: usize,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
-		todo!();
+		self.platform_ids(tables::FRIENDS, max).await
 	}
 
 	async fn friend(
-		&self, _friend_id: PlatformAccountId,
+		&self, friend_id: PlatformAccountId,
 	) -> Result<PlatformFriend, Self::Err> {
-		todo!();
+		self
+			.platform_data(
+				tables::FRIENDS,
+				friend_id.platform(),
+				friend_id.id_as_string(),
+			)
+			.await
 	}
 
 	async fn update_friend(
-		&self, _friend: PlatformFriend,
+		&self, friend: PlatformFriend,
 	) -> Result<bool, Self::Err> {
-		todo!();
+		let id = friend.id();
+		let mut tx = self.db.begin().await?;
+		let existed = upsert_platform_data(
+			&mut tx,
+			tables::FRIENDS,
+			id.platform(),
+			id.id_as_string(),
+			&friend,
+		)
+		.await?;
+		index_name(&mut tx, search_kinds::FRIEND, &id, friend.display_name())
+			.await?;
+		tx.commit().await?;
+
+		self.changes.notify_stored(EntityId::Friend(id), existed);
+		Ok(existed)
 	}
 
 	async fn instance_ids(
-		&self, _max: usize,
+		&self, max: usize,
 	) -> Result<Vec<InstanceId>, Self::Err> {
-		todo!();
+		self.platform_ids(tables::INSTANCES, max).await
 	}
 
 	async fn instance(
-		&self, _instance_id: InstanceId,
+		&self, instance_id: InstanceId,
 	) -> Result<Instance, Self::Err> {
-		todo!();
+		self
+			.platform_data(
+				tables::INSTANCES,
+				instance_id.platform(),
+				instance_id.id_as_string(),
+			)
+			.await
 	}
 
 	async fn update_instance(
-		&self, _instance: Instance,
+		&self, instance: Instance,
 	) -> Result<bool, Self::Err> {
-		todo!();
+		let id = instance.id();
+		let existed = self
+			.update_platform_data(
+				tables::INSTANCES,
+				id.platform(),
+				id.id_as_string(),
+				&instance,
+			)
+			.await?;
+
+		self.changes.notify_stored(EntityId::Instance(id), existed);
+		Ok(existed)
 	}
 
-	async fn world_ids(&self, _max: usize) -> Result<Vec<WorldId>, Self::Err> {
-		todo!();
+	async fn world_ids(&self, max: usize) -> Result<Vec<WorldId>, Self::Err> {
+		self.platform_ids(tables::WORLDS, max).await
 	}
 
-	async fn world(&self, _world_id: WorldId) -> Result<World, Self::Err> {
-		todo!();
+	async fn world(&self, world_id: WorldId) -> Result<World, Self::Err> {
+		self
+			.platform_data(
+				tables::WORLDS,
+				world_id.platform(),
+				world_id.id_as_string(),
+			)
+			.await
 	}
 
-	async fn update_world(&self, _world: World) -> Result<bool, Self::Err> {
-		todo!();
+	async fn update_world(&self, world: World) -> Result<bool, Self::Err> {
+		let id = world.id();
+		let existed = self
+			.update_platform_data(
+				tables::WORLDS,
+				id.platform(),
+				id.id_as_string(),
+				&world,
+			)
+			.await?;
+
+		self.changes.notify_stored(EntityId::World(id), existed);
+		Ok(existed)
 	}
 
-	async fn avatar_ids(&self, _max: usize) -> Result<Vec<AvatarId>, Self::Err> {
-		todo!();
+	async fn avatar_ids(&self, max: usize) -> Result<Vec<AvatarId>, Self::Err> {
+		self.platform_ids(tables::AVATARS, max).await
+	}
+
+	async fn avatar(&self, avatar_id: AvatarId) -> Result<Avatar, Self::Err> {
+		self
+			.platform_data(
+				tables::AVATARS,
+				avatar_id.platform(),
+				avatar_id.id_as_string(),
+			)
+			.await
 	}
 
-	async fn avatar(&self, _avatar_id: AvatarId) -> Result<Avatar, Self::Err> {
-		todo!();
+	async fn update_avatar(&self, avatar: Avatar) -> Result<bool, Self::Err> {
+		let id = avatar.id();
+		let existed = self
+			.update_platform_data(
+				tables::AVATARS,
+				id.platform(),
+				id.id_as_string(),
+				&avatar,
+			)
+			.await?;
+
+		self.changes.notify_stored(EntityId::Avatar(id), existed);
+		Ok(existed)
 	}
 
-	async fn update_avatar(&self, _world: Avatar) -> Result<bool, Self::Err> {
-		todo!();
+	async fn profile_ids(&self, max: usize) -> Result<Vec<ProfileId>, Self::Err> {
+		let rows: Vec<(String,)> =
+			sqlx::query_as("SELECT sharing_id FROM profiles ORDER BY rowid LIMIT ?")
+				.bind(i64::try_from(max).unwrap_or(i64::MAX))
+				.fetch_all(&self.db)
+				.await?;
+
+		rows.iter().map(|(sharing_id,)| columns::profile_id(sharing_id)).collect()
 	}
 
-	async fn profile(
-		&self, _profile_id: ProfileId,
-	) -> Result<Profile, Self::Err> {
-		todo!();
+	async fn tagged_profile_ids(
+		&self, tag: &str, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		let rows: Vec<(String,)> = sqlx::query_as(
+			"SELECT sharing_id FROM profile_tags WHERE tag = ? COLLATE NOCASE
+			GROUP BY sharing_id ORDER BY MIN(rowid) LIMIT ?",
+		)
+		.bind(tag)
+		.bind(i64::try_from(max).unwrap_or(i64::MAX))
+		.fetch_all(&self.db)
+		.await?;
+
+		rows.iter().map(|(sharing_id,)| columns::profile_id(sharing_id)).collect()
+	}
+
+	async fn profile(&self, profile_id: ProfileId) -> Result<Profile, Self::Err> {
+		stored_profile(&mut *self.db.acquire().await?, &profile_id)
+			.await?
+			.ok_or(sqlx::Error::RowNotFound)
 	}
 
 	async fn profile_account_ids(
-		&self, _profile_id: ProfileId,
+		&self, profile_id: ProfileId,
 	) -> Result<Vec<PlatformAccountId>, Self::Err> {
-		todo!();
+		mapped_account_ids(&mut *self.db.acquire().await?, &profile_id).await
 	}
 
 	async fn update_profile_account_ids(
-		&self, _profile_id: ProfileId, _account_ids: Vec<PlatformAccountId>,
+		&self, profile_id: ProfileId, account_ids: Vec<PlatformAccountId>,
 	) -> Result<(), Self::Err> {
-		todo!();
+		let batch = StoreBatch {
+			profile_account_ids: vec![(profile_id, account_ids)],
+			..StoreBatch::default()
+		};
+		self.apply_batch(batch).await.map(|_| ())
 	}
 
-	async fn update_profile(&self, _profile: Profile) -> Result<bool, Self::Err> {
-		todo!();
+	async fn update_profile(&self, profile: Profile) -> Result<bool, Self::Err> {
+		let profile_id = profile.sharing_id.clone();
+		let mut tx = self.db.begin().await?;
+		let existed = upsert_profile(&mut tx, profile).await?;
+		tx.commit().await?;
+
+		self.changes.notify_stored(EntityId::Profile(profile_id), existed);
+		Ok(existed)
 	}
 
 	async fn delete_profile(
-		&self, _profile_id: ProfileId,
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		let batch = StoreBatch {
+			deleted_profiles: vec![profile_id],
+			..StoreBatch::default()
+		};
+		self.apply_batch(batch).await.map(|_| ())
+	}
+
+	async fn trashed_profile_ids(
+		&self, max: usize,
+	) -> Result<Vec<ProfileId>, Self::Err> {
+		let rows: Vec<(String,)> = sqlx::query_as(
+			"SELECT sharing_id FROM trashed_profiles ORDER BY trashed_at LIMIT ?",
+		)
+		.bind(i64::try_from(max).unwrap_or(i64::MAX))
+		.fetch_all(&self.db)
+		.await?;
+
+		rows.iter().map(|(sharing_id,)| columns::profile_id(sharing_id)).collect()
+	}
+
+	async fn trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<TrashedProfile, Self::Err> {
+		let (Json(trashed),): (Json<TrashedProfile>,) =
+			sqlx::query_as("SELECT data FROM trashed_profiles WHERE sharing_id = ?")
+				.bind(profile_id.to_string())
+				.fetch_one(&self.db)
+				.await?;
+
+		Ok(trashed)
+	}
+
+	async fn update_trashed_profile(
+		&self, trashed: TrashedProfile,
+	) -> Result<bool, Self::Err> {
+		let profile_id = trashed.profile.sharing_id.clone();
+		let sharing_id = profile_id.to_string();
+		let mut tx = self.db.begin().await?;
+		let existed =
+			sqlx::query("SELECT 1 FROM trashed_profiles WHERE sharing_id = ?")
+				.bind(&sharing_id)
+				.fetch_optional(&mut *tx)
+				.await?
+				.is_some();
+
+		sqlx::query(
+			"INSERT INTO trashed_profiles(sharing_id, data, trashed_at) VALUES (?, ?, ?)
+			ON CONFLICT(sharing_id) DO UPDATE SET
+			data = excluded.data, trashed_at = excluded.trashed_at",
+		)
+		.bind(&sharing_id)
+		.bind(Json(&trashed))
+		.bind(trashed.trashed_at)
+		.execute(&mut *tx)
+		.await?;
+
+		tx.commit().await?;
+
+		self.changes.notify_stored(EntityId::TrashedProfile(profile_id), existed);
+		Ok(existed)
+	}
+
+	async fn delete_trashed_profile(
+		&self, profile_id: ProfileId,
 	) -> Result<(), Self::Err> {
-		todo!();
+		let result =
+			sqlx::query("DELETE FROM trashed_profiles WHERE sharing_id = ?")
+				.bind(profile_id.to_string())
+				.execute(&self.db)
+				.await?;
+
+		if result.rows_affected() > 0 {
+			self.changes.notify([StoreChange {
+				entity: EntityId::TrashedProfile(profile_id),
+				kind: ChangeKind::Deleted,
+			}]);
+		}
+		Ok(())
+	}
+
+	async fn profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<Vec<ProfileRevision>, Self::Err> {
+		let rows: Vec<(i64, OffsetDateTime, Json<ProfileChange>)> = sqlx::query_as(
+			"SELECT number, recorded_at, change FROM profile_revisions
+			WHERE sharing_id = ? ORDER BY number",
+		)
+		.bind(profile_id.to_string())
+		.fetch_all(&self.db)
+		.await?;
+
+		rows
+			.into_iter()
+			.map(|(number, recorded_at, Json(change))| {
+				Ok(ProfileRevision {
+					profile_id: profile_id.clone(),
+					number: u64::try_from(number)
+						.map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+					recorded_at,
+					change,
+				})
+			})
+			.collect()
+	}
+
+	async fn update_profile_revisions(
+		&self, profile_id: ProfileId, revisions: Vec<ProfileRevision>,
+	) -> Result<(), Self::Err> {
+		let sharing_id = profile_id.to_string();
+		let mut tx = self.db.begin().await?;
+
+		sqlx::query("DELETE FROM profile_revisions WHERE sharing_id = ?")
+			.bind(&sharing_id)
+			.execute(&mut *tx)
+			.await?;
+		for revision in revisions {
+			sqlx::query(
+				"INSERT INTO profile_revisions(sharing_id, number, recorded_at, change)
+				VALUES (?, ?, ?, ?)",
+			)
+			.bind(&sharing_id)
+			.bind(i64::try_from(revision.number).unwrap_or(i64::MAX))
+			.bind(revision.recorded_at)
+			.bind(Json(revision.change))
+			.execute(&mut *tx)
+			.await?;
+		}
+
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn delete_profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		sqlx::query("DELETE FROM profile_revisions WHERE sharing_id = ?")
+			.bind(profile_id.to_string())
+			.execute(&self.db)
+			.await?;
+
+		Ok(())
+	}
+
+	async fn profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<ProfilePicture, Self::Err> {
+		let (content_type, bytes, thumbnail, stored_at): (
+			String,
+			Vec<u8>,
+			Vec<u8>,
+			OffsetDateTime,
+		) = sqlx::query_as(
+			"SELECT content_type, bytes, thumbnail, stored_at FROM profile_pictures
+			WHERE sharing_id = ?",
+		)
+		.bind(profile_id.to_string())
+		.fetch_one(&self.db)
+		.await?;
+
+		Ok(ProfilePicture { profile_id, content_type, bytes, thumbnail, stored_at })
+	}
+
+	async fn update_profile_picture(
+		&self, picture: ProfilePicture,
+	) -> Result<bool, Self::Err> {
+		let profile_id = picture.profile_id.clone();
+		let sharing_id = profile_id.to_string();
+		let mut tx = self.db.begin().await?;
+		let existed =
+			sqlx::query("SELECT 1 FROM profile_pictures WHERE sharing_id = ?")
+				.bind(&sharing_id)
+				.fetch_optional(&mut *tx)
+				.await?
+				.is_some();
+
+		sqlx::query(
+			"INSERT INTO profile_pictures(sharing_id, content_type, bytes, thumbnail,
+			stored_at) VALUES (?, ?, ?, ?, ?)
+			ON CONFLICT(sharing_id) DO UPDATE SET
+			content_type = excluded.content_type, bytes = excluded.bytes,
+			thumbnail = excluded.thumbnail, stored_at = excluded.stored_at",
+		)
+		.bind(&sharing_id)
+		.bind(picture.content_type)
+		.bind(picture.bytes)
+		.bind(picture.thumbnail)
+		.bind(picture.stored_at)
+		.execute(&mut *tx)
+		.await?;
+
+		tx.commit().await?;
+
+		self.changes.notify_stored(EntityId::ProfilePicture(profile_id), existed);
+		Ok(existed)
+	}
+
+	async fn delete_profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<(), Self::Err> {
+		let result =
+			sqlx::query("DELETE FROM profile_pictures WHERE sharing_id = ?")
+				.bind(profile_id.to_string())
+				.execute(&self.db)
+				.await?;
+
+		if result.rows_affected() > 0 {
+			self.changes.notify([StoreChange {
+				entity: EntityId::ProfilePicture(profile_id),
+				kind: ChangeKind::Deleted,
+			}]);
+		}
+		Ok(())
+	}
+
+	async fn circle_ids(&self, max: usize) -> Result<Vec<CircleId>, Self::Err> {
+		let rows: Vec<(String,)> =
+			sqlx::query_as("SELECT circle_id FROM circles ORDER BY rowid LIMIT ?")
+				.bind(i64::try_from(max).unwrap_or(i64::MAX))
+				.fetch_all(&self.db)
+				.await?;
+
+		rows.iter().map(|(circle_id,)| columns::circle_id(circle_id)).collect()
+	}
+
+	async fn circle(&self, circle_id: CircleId) -> Result<Circle, Self::Err> {
+		let (name, color, position): (String, Option<String>, u32) =
+			sqlx::query_as(
+				"SELECT name, color, position FROM circles WHERE circle_id = ?",
+			)
+			.bind(circle_id.to_string())
+			.fetch_one(&self.db)
+			.await?;
+
+		Ok(Circle { id: circle_id, name, color: columns::color(color)?, position })
+	}
+
+	async fn update_circle(&self, circle: Circle) -> Result<bool, Self::Err> {
+		let id = circle.id.to_string();
+		let mut tx = self.db.begin().await?;
+		let existed = sqlx::query("SELECT 1 FROM circles WHERE circle_id = ?")
+			.bind(&id)
+			.fetch_optional(&mut *tx)
+			.await?
+			.is_some();
+
+		sqlx::query(
+			"INSERT INTO circles(circle_id, name, color, position) VALUES (?, ?, ?, ?)
+			ON CONFLICT(circle_id) DO UPDATE SET
+			name = excluded.name, color = excluded.color, position = excluded.position",
+		)
+		.bind(&id)
+		.bind(circle.name)
+		.bind(circle.color.map(|color| color.to_string()))
+		.bind(circle.position)
+		.execute(&mut *tx)
+		.await?;
+
+		tx.commit().await?;
+
+		self.changes.notify_stored(EntityId::Circle(circle.id), existed);
+		Ok(existed)
+	}
+
+	async fn delete_circle(&self, circle_id: CircleId) -> Result<(), Self::Err> {
+		let id = circle_id.to_string();
+		let mut tx = self.db.begin().await?;
+
+		sqlx::query("DELETE FROM circle_members WHERE circle_id = ?")
+			.bind(&id)
+			.execute(&mut *tx)
+			.await?;
+		sqlx::query("DELETE FROM circles WHERE circle_id = ?")
+			.bind(&id)
+			.execute(&mut *tx)
+			.await?;
+
+		tx.commit().await?;
+
+		self.changes.notify(StoreChange::circle_deleted(&circle_id));
+		Ok(())
+	}
+
+	async fn circle_members(
+		&self, circle_id: CircleId,
+	) -> Result<Vec<CircleMember>, Self::Err> {
+		let rows: Vec<(String, String, String)> = sqlx::query_as(
+			"SELECT member_kind, platform_type, member_id FROM circle_members
+			WHERE circle_id = ? ORDER BY position",
+		)
+		.bind(circle_id.to_string())
+		.fetch_all(&self.db)
+		.await?;
+
+		rows
+			.into_iter()
+			.map(|(member_kind, platform_type, member_id)| {
+				columns::circle_member(&member_kind, &platform_type, &member_id)
+			})
+			.collect()
+	}
+
+	async fn update_circle_members(
+		&self, circle_id: CircleId, members: Vec<CircleMember>,
+	) -> Result<(), Self::Err> {
+		let batch = StoreBatch {
+			circle_members: vec![(circle_id, members)],
+			..StoreBatch::default()
+		};
+		self.apply_batch(batch).await.map(|_| ())
+	}
+
+	async fn member_circle_ids(
+		&self, member: CircleMember,
+	) -> Result<Vec<CircleId>, Self::Err> {
+		let (member_kind, platform_type, member_id) =
+			columns::circle_member_columns(&member);
+		let rows: Vec<(String,)> = sqlx::query_as(
+			"SELECT circle_id FROM circle_members
+			WHERE member_kind = ? AND platform_type = ? AND member_id = ?",
+		)
+		.bind(member_kind)
+		.bind(platform_type)
+		.bind(member_id)
+		.fetch_all(&self.db)
+		.await?;
+
+		rows.iter().map(|(circle_id,)| columns::circle_id(circle_id)).collect()
+	}
+
+	async fn rejected_links(&self) -> Result<Vec<AccountLink>, Self::Err> {
+		let rows: Vec<(String, String, String, String)> = sqlx::query_as(
+			"SELECT first_platform_type, first_platform_id, second_platform_type,
+			second_platform_id FROM rejected_links ORDER BY rowid",
+		)
+		.fetch_all(&self.db)
+		.await?;
+
+		rows
+			.into_iter()
+			.map(|(first_type, first_id, second_type, second_id)| {
+				Ok(AccountLink::new(
+					columns::platform_id(&first_type, &first_id)?,
+					columns::platform_id(&second_type, &second_id)?,
+				))
+			})
+			.collect()
+	}
+
+	async fn reject_link(&self, link: AccountLink) -> Result<bool, Self::Err> {
+		let [first, second] = link.account_ids();
+		let result = sqlx::query(
+			"INSERT OR IGNORE INTO rejected_links(first_platform_type,
+			first_platform_id, second_platform_type, second_platform_id)
+			VALUES (?, ?, ?, ?)",
+		)
+		.bind(columns::platform_type(first.platform()))
+		.bind(first.id_as_string())
+		.bind(columns::platform_type(second.platform()))
+		.bind(second.id_as_string())
+		.execute(&self.db)
+		.await?;
+		let existed = result.rows_affected() == 0;
+
+		if !existed {
+			self.changes.notify_stored(EntityId::RejectedLink(link), false);
+		}
+		Ok(existed)
 	}
 
 	async fn authentications(&self) -> Result<Vec<Authentication>, Self::Err> {
-		todo!();
+		let rows: Vec<(Json<serde_json::Value>,)> =
+			sqlx::query_as("SELECT data FROM authentications ORDER BY rowid")
+				.fetch_all(&self.db)
+				.await?;
+
+		rows.iter().map(|(Json(data),)| self.parse_authentication(data)).collect()
 	}
 
 	async fn update_authentication(
-		&self, _authentication: Authentication,
+		&self, authentication: Authentication,
 	) -> Result<bool, Self::Err> {
-		todo!();
+		let id = authentication.id();
+		let data = self.authentication_data(&authentication)?;
+		let existed = self
+			.update_platform_data(
+				tables::AUTHENTICATIONS,
+				id.platform(),
+				id.id_as_string(),
+				&data,
+			)
+			.await?;
+
+		self.changes.notify_stored(EntityId::Authentication(id), existed);
+		Ok(existed)
 	}
 
 	async fn remove_authentication(
-		&self, _id: PlatformAccountId,
+		&self, id: PlatformAccountId,
 	) -> Result<bool, Self::Err> {
-		todo!();
+		let result = sqlx::query(
+			"DELETE FROM authentications WHERE platform_type = ? AND platform_id = ?",
+		)
+		.bind(columns::platform_type(id.platform()))
+		.bind(id.id_as_string())
+		.execute(&self.db)
+		.await?;
+
+		let removed = result.rows_affected() > 0;
+		if removed {
+			self.changes.notify([StoreChange {
+				entity: EntityId::Authentication(id),
+				kind: ChangeKind::Deleted,
+			}]);
+		}
+		Ok(removed)
 	}
 }
@@ -0,0 +1,131 @@
+use onlivfe::{
+	CircleId,
+	CircleMember,
+	Profile,
+	encryption::AuthKey,
+	storage::{
+		OnlivfeStore,
+		StoreBatch,
+		conformance::{self, Samples},
+	},
+};
+use onlivfe_db_store::OnlivfeDatabaseStorageBackend;
+
+#[tokio::test]
+async fn conformance() {
+	// In-memory databases are per connection, so a file is used instead
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let store = OnlivfeDatabaseStorageBackend::new(&db_url(&dir))
+		.await
+		.expect("the DB storage backend to be created");
+
+	conformance::run(&store, &Samples::builtin()).await;
+}
+
+fn db_url(dir: &tempfile::TempDir) -> String {
+	format!("sqlite://{}?mode=rwc", dir.path().join("onlivfe.db").display())
+}
+
+#[tokio::test]
+async fn encrypted_conformance() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let key = AuthKey::Passphrase("passphrase".to_owned());
+	let store = OnlivfeDatabaseStorageBackend::new_encrypted(&db_url(&dir), &key)
+		.await
+		.expect("the DB storage backend to be created");
+
+	conformance::run(&store, &Samples::builtin()).await;
+}
+
+#[tokio::test]
+async fn cleartext_authentications_are_encrypted() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let db_url = db_url(&dir);
+	let authentication = Samples::builtin().authentications.remove(0);
+	let store = OnlivfeDatabaseStorageBackend::new(&db_url)
+		.await
+		.expect("the DB storage backend to be created");
+	store
+		.update_authentication(authentication)
+		.await
+		.expect("storing an authentication");
+	drop(store);
+
+	let key = AuthKey::Passphrase("passphrase".to_owned());
+	let store = OnlivfeDatabaseStorageBackend::new_encrypted(&db_url, &key)
+		.await
+		.expect("the DB storage backend to be opened with a key");
+	assert_eq!(
+		store.authentications().await.expect("the authentications").len(),
+		1
+	);
+	drop(store);
+
+	let db = sqlx::SqlitePool::connect(&db_url).await.expect("the DB");
+	let stored: Vec<(String,)> =
+		sqlx::query_as("SELECT data FROM authentications")
+			.fetch_all(&db)
+			.await
+			.expect("the stored authentications");
+	assert_eq!(stored.len(), 1);
+	assert!(!stored[0].0.contains("authcookie_onlivfe-conformance"));
+	db.close().await;
+	assert!(
+		OnlivfeDatabaseStorageBackend::new(&db_url).await.is_err(),
+		"the DB shouldn't open without the key anymore"
+	);
+	let wrong_key = AuthKey::Passphrase("wrong".to_owned());
+	assert!(
+		OnlivfeDatabaseStorageBackend::new_encrypted(&db_url, &wrong_key)
+			.await
+			.is_err()
+	);
+}
+
+#[tokio::test]
+async fn failed_batch_is_rolled_back() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let db_url = db_url(&dir);
+	let store = OnlivfeDatabaseStorageBackend::new(&db_url)
+		.await
+		.expect("the DB storage backend to be created");
+	// Makes storing the circle members, which are written last, fail after
+	// everything else
+	let db = sqlx::SqlitePool::connect(&db_url).await.expect("the DB");
+	sqlx::query(
+		"CREATE TRIGGER fail_circle_members BEFORE INSERT ON circle_members
+		BEGIN SELECT RAISE(ABORT, 'failing on purpose'); END",
+	)
+	.execute(&db)
+	.await
+	.expect("creating a failing trigger");
+	db.close().await;
+	let mut samples = Samples::builtin();
+	let mut profile = Profile::new();
+	profile.nick = Some("Rolled back".to_owned());
+	let account = samples.accounts.remove(0);
+	let circle_id = CircleId::new();
+	let batch = StoreBatch {
+		accounts: vec![account.clone()],
+		friends: vec![samples.friends.remove(0)],
+		profiles: vec![profile.clone()],
+		authentications: vec![samples.authentications.remove(0)],
+		profile_account_ids: vec![(profile.sharing_id.clone(), vec![account.id()])],
+		circle_members: vec![(
+			circle_id.clone(),
+			vec![CircleMember::Profile(profile.sharing_id.clone())],
+		)],
+		..StoreBatch::default()
+	};
+
+	assert!(store.apply_batch(batch).await.is_err());
+	assert!(store.profile(profile.sharing_id.clone()).await.is_err());
+	assert!(store.account_ids(usize::MAX).await.unwrap().is_empty());
+	assert!(store.friend_ids(usize::MAX).await.unwrap().is_empty());
+	assert!(store.authentications().await.unwrap().is_empty());
+	assert!(
+		store.profile_account_ids(profile.sharing_id).await.unwrap().is_empty()
+	);
+	assert!(store.account_profile_ids(account.id()).await.unwrap().is_empty());
+	assert!(store.circle_members(circle_id).await.unwrap().is_empty());
+}
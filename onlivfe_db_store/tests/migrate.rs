@@ -0,0 +1,138 @@
+use onlivfe::{
+	Circle,
+	CircleMember,
+	Profile,
+	ProfilePicture,
+	encryption::AuthKey,
+	storage::{OnlivfeStore, conformance::Samples},
+};
+use onlivfe_cache_store::{MigrationCounts, OnlivfeCacheStorageBackend};
+use onlivfe_db_store::OnlivfeDatabaseStorageBackend;
+
+#[tokio::test]
+async fn migrate_into_other_store() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let source = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.build()
+		.expect("the source storage backend to be created");
+	let mut profile = Profile::new();
+	profile.nick = Some("Migrated".to_owned());
+	let authentication = Samples::builtin().authentications.remove(0);
+	source.update_profile(profile.clone()).await.expect("storing a profile");
+	source
+		.update_profile_account_ids(
+			profile.sharing_id.clone(),
+			vec![authentication.id()],
+		)
+		.await
+		.expect("storing a mapping");
+	source
+		.update_authentication(authentication.clone())
+		.await
+		.expect("storing an authentication");
+	let circle = Circle::new("Migrated");
+	let members = vec![CircleMember::Profile(profile.sharing_id.clone())];
+	source.update_circle(circle.clone()).await.expect("storing a circle");
+	source
+		.update_circle_members(circle.id.clone(), members.clone())
+		.await
+		.expect("storing circle members");
+	let picture = ProfilePicture {
+		profile_id: profile.sharing_id.clone(),
+		content_type: "image/png".to_owned(),
+		bytes: vec![0x89, b'P', b'N', b'G'],
+		thumbnail: vec![0x89, b'P', b'N', b'G'],
+		stored_at: sqlx::types::time::OffsetDateTime::now_utc(),
+	};
+	source
+		.update_profile_picture(picture.clone())
+		.await
+		.expect("storing a profile picture");
+	drop(source);
+	let picture_file_name = format!("pictures/{}.bin", profile.sharing_id);
+	let read_files = || {
+		[
+			"auth.json",
+			"profiles.json",
+			"mappings.json",
+			"circles.json",
+			"circle_members.json",
+			picture_file_name.as_str(),
+		]
+		.map(|file_name| std::fs::read(dir.path().join(file_name)).ok())
+	};
+	let original_files = read_files();
+
+	let source = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.read_only(true)
+		.build()
+		.expect("the source storage backend to be loaded");
+	let target = OnlivfeDatabaseStorageBackend::new("sqlite::memory:")
+		.await
+		.expect("the target storage backend to be created");
+	let migrated =
+		source.migrate_into(&target).await.expect("migration to succeed");
+
+	assert_eq!(
+		migrated,
+		MigrationCounts {
+			authentications: 1,
+			profiles: 1,
+			mappings: 1,
+			circles: 1,
+			trashed_profiles: 0,
+			profile_pictures: 1
+		}
+	);
+	assert_eq!(
+		target.profile(profile.sharing_id.clone()).await.expect("the profile"),
+		profile
+	);
+	assert_eq!(
+		target.authentications().await.expect("the authentications"),
+		vec![authentication]
+	);
+	assert_eq!(
+		target.circle_members(circle.id).await.expect("the circle's members"),
+		members
+	);
+	assert_eq!(
+		target
+			.profile_picture(profile.sharing_id.clone())
+			.await
+			.expect("the profile picture"),
+		picture
+	);
+	assert_eq!(read_files(), original_files, "Originals should be untouched");
+}
+
+#[tokio::test]
+async fn migrate_encrypted_authentications() {
+	let dir = tempfile::tempdir().expect("a temporary directory");
+	let key = AuthKey::Passphrase("passphrase".to_owned());
+	let source = OnlivfeCacheStorageBackend::builder()
+		.path(dir.path())
+		.encrypt_authentications(key.clone())
+		.build()
+		.expect("the source storage backend to be created");
+	let authentication = Samples::builtin().authentications.remove(0);
+	source
+		.update_authentication(authentication.clone())
+		.await
+		.expect("storing an authentication");
+	let target =
+		OnlivfeDatabaseStorageBackend::new_encrypted("sqlite::memory:", &key)
+			.await
+			.expect("the target storage backend to be created");
+
+	let migrated =
+		source.migrate_into(&target).await.expect("migration to succeed");
+
+	assert_eq!(migrated.authentications, 1);
+	assert_eq!(
+		target.authentications().await.expect("the authentications"),
+		vec![authentication]
+	);
+}
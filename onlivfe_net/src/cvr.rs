@@ -10,14 +10,15 @@ use chilloutvr::{
 	query::{self, AuthType},
 };
 
-use crate::OnlivfeApiClient;
+use crate::{OnlivfeApiClient, expiry::QueryError};
 
 impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn logout_chilloutvr(
 		&self, id: &id::User,
 	) -> Result<(), String> {
-		if let Some(client) = self.cvr.write().await.remove(id) {
+		let client = self.cvr.write().await.remove(id);
+		if let Some(client) = client {
 			// CVR Does not seem to have a logout endpoint
 			drop(client);
 			return Ok(());
@@ -29,16 +30,16 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn instance_chilloutvr(
 		&self, id: &id::User, instance_id: id::Instance,
-	) -> Result<ExtendedInstanceDetails, String> {
+	) -> Result<ExtendedInstanceDetails, QueryError> {
 		trace!("Fetching CVR instance {:?} as {:?}", instance_id, id);
 		let rw_lock_guard = self.cvr.read().await;
-		let api = rw_lock_guard
-			.get(id)
-			.ok_or_else(|| "CVR API not authenticated".to_owned())?;
+		let api = rw_lock_guard.get(id).ok_or_else(|| {
+			QueryError::Other("CVR API not authenticated".to_owned())
+		})?;
 		let query = query::Instance { instance_id };
 		let instance_resp = api.query(query).await.map_err(|e| {
 			warn!("Instance query failed: {:?}", &e);
-			"CVR instance query failed".to_owned()
+			QueryError::from_api(&e, "CVR instance query failed")
 		})?;
 
 		Ok(instance_resp.data)
@@ -47,16 +48,16 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn user_chilloutvr(
 		&self, get_as: &id::User, user_id: id::User,
-	) -> Result<UserDetails, String> {
+	) -> Result<UserDetails, QueryError> {
 		trace!("Fetching CVR user {:?} as {:?}", user_id, get_as);
 		let rw_lock_guard = self.cvr.read().await;
-		let api = rw_lock_guard
-			.get(get_as)
-			.ok_or_else(|| "CVR API not authenticated".to_owned())?;
+		let api = rw_lock_guard.get(get_as).ok_or_else(|| {
+			QueryError::Other("CVR API not authenticated".to_owned())
+		})?;
 		let query = query::UserDetails { user_id };
 		let user_resp = api.query(query).await.map_err(|e| {
 			warn!("User query failed: {:?}", &e);
-			"CVR user query failed".to_owned()
+			QueryError::from_api(&e, "CVR user query failed")
 		})?;
 
 		Ok(user_resp.data)
@@ -65,39 +66,46 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn friends_chilloutvr(
 		&self, id: &id::User,
-	) -> Result<Vec<Friend>, String> {
+	) -> Result<Vec<Friend>, QueryError> {
 		trace!("Fetching CVR friends as {:?}", id);
 		let rw_lock_guard = self.cvr.read().await;
-		let api = rw_lock_guard
-			.get(id)
-			.ok_or_else(|| "CVR API not authenticated".to_owned())?;
+		let api = rw_lock_guard.get(id).ok_or_else(|| {
+			QueryError::Other("CVR API not authenticated".to_owned())
+		})?;
 		let query = query::FriendList();
 		let friends_resp = api.query(query).await.map_err(|e| {
 			warn!("Friends query failed: {:?}", &e);
-			"CVR friends query failed".to_owned()
+			QueryError::from_api(&e, "CVR friends query failed")
 		})?;
 
 		Ok(friends_resp.data.0)
 	}
 
-	#[instrument]
+	// The credentials contain passwords or access keys
+	#[instrument(skip(auth))]
 	pub(crate) async fn login_chilloutvr(
 		&self, possible_existing: Option<id::User>,
-		auth: impl Into<AuthType> + std::fmt::Debug + Send,
-	) -> Result<(id::User, chilloutvr::query::SavedLoginCredentials), String> {
+		auth: impl Into<AuthType> + Send,
+	) -> Result<(id::User, chilloutvr::query::SavedLoginCredentials), QueryError>
+	{
 		let mut api_config = ApiConfiguration::new(self.user_agent.clone());
 		// TODO: Add a configuration option
 		api_config.mature_content_enabled = true;
 
-		let mut rw_lock_guard = self.cvr.write().await;
-		let api =
-			match possible_existing.and_then(|id| rw_lock_guard.remove(&id)) {
-				Some(api) => api.downgrade().await,
-				None => UnauthenticatedCVR::new(api_config),
-			}
-			.map_err(|_| {
-				"Internal error, Resonite API client creation failed".to_string()
-			})?;
+		// Not locked during the queries, so that the other accounts can be used
+		let existing = match possible_existing {
+			Some(id) => self.cvr.write().await.remove(&id),
+			None => None,
+		};
+		let api = match existing {
+			Some(api) => api.downgrade().await,
+			None => UnauthenticatedCVR::new(api_config),
+		}
+		.map_err(|_| {
+			QueryError::Other(
+				"Internal error, CVR API client creation failed".to_owned(),
+			)
+		})?;
 
 		let auth_req: AuthType = auth.into();
 		let user_auth = api
@@ -105,7 +113,7 @@ impl OnlivfeApiClient {
 			.await
 			.map_err(|e| {
 				warn!("Login failure: {:?}", &e);
-				"CVR authentication failed".to_owned()
+				QueryError::from_api(&e, "CVR authentication failed")
 			})?
 			.data;
 		trace!("Auth request for {:?} was successful", &user_auth.user_id);
@@ -115,11 +123,13 @@ impl OnlivfeApiClient {
 			query::SavedLoginCredentials::from(user_auth),
 		);
 		let api: AuthenticatedCVR = api.upgrade(creds.clone()).map_err(|_| {
-			"Internal error, authenticated CVR API client's creation failed"
-				.to_owned()
+			QueryError::Other(
+				"Internal error, authenticated CVR API client's creation failed"
+					.to_owned(),
+			)
 		})?;
 
-		rw_lock_guard.insert(id.clone(), api);
+		self.cvr.write().await.insert(id.clone(), api);
 		Ok((id, creds))
 	}
 }
@@ -0,0 +1,168 @@
+use std::future::Future;
+
+use onlivfe::{Authentication, PlatformAccountId};
+
+use crate::OnlivfeApiClient;
+
+/// The HTTP status that the platforms respond with when a session has expired
+const UNAUTHORIZED: u16 = 401;
+
+/// An error with a query that was made as an authenticated account, with a
+/// user facing message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+	/// The platform didn't accept the session anymore
+	Unauthorized(String),
+	/// Any other error, such as the platform not being reachable
+	Other(String),
+}
+
+/// An API client's error that might've been caused by a HTTP status
+pub trait ApiErrorStatus {
+	/// The HTTP status that the platform responded with, if it did
+	fn status(&self) -> Option<u16>;
+}
+
+impl ApiErrorStatus for racal::reqwest::ApiError {
+	fn status(&self) -> Option<u16> {
+		match self {
+			Self::Reqwest(e) => e.status().map(|status| status.as_u16()),
+			Self::Serde(_) => None,
+		}
+	}
+}
+
+impl ApiErrorStatus for racal_resonite::reqwest::ApiError {
+	fn status(&self) -> Option<u16> {
+		match self {
+			Self::Reqwest(e) => e.status().map(|status| status.as_u16()),
+			Self::Serde(_) => None,
+		}
+	}
+}
+
+impl QueryError {
+	/// Wraps an API error's user facing message, telling apart if it was caused
+	/// by the session having expired
+	pub(crate) fn from_api(e: &impl ApiErrorStatus, message: &str) -> Self {
+		if e.status() == Some(UNAUTHORIZED) {
+			Self::Unauthorized(message.to_owned())
+		} else {
+			Self::Other(message.to_owned())
+		}
+	}
+}
+
+impl std::fmt::Display for QueryError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Unauthorized(e) | Self::Other(e) => f.write_str(e),
+		}
+	}
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<QueryError> for String {
+	fn from(e: QueryError) -> Self {
+		match e {
+			QueryError::Unauthorized(e) | QueryError::Other(e) => e,
+		}
+	}
+}
+
+impl OnlivfeApiClient {
+	/// Runs a query, and if the session turns out to have expired, logs in
+	/// again with the latest authentication and retries it once.
+	///
+	/// If the platform doesn't accept the authentication or the new session
+	/// either, the account is marked as needing a login. Other errors, such as
+	/// the platform not being reachable, are returned without that, so that
+	/// logging in can be tried again later.
+	pub(crate) async fn retry_unauthorized<T, Fut>(
+		&self, id: &PlatformAccountId, query: impl Fn() -> Fut + Send + Sync,
+	) -> Result<T, String>
+	where
+		Fut: Future<Output = Result<T, QueryError>> + Send,
+	{
+		let e = match query().await {
+			Err(QueryError::Unauthorized(e)) => e,
+			result => return result.map_err(String::from),
+		};
+		warn!("Session of {id:?} was not accepted, logging in again");
+
+		let auth = self.authentications.read().await.get(id).cloned();
+		let Some(auth) = auth else {
+			self.mark_needs_login(id).await;
+			return Err(e);
+		};
+		let auth = match self.renew_authentication(auth).await {
+			Ok(auth) => auth,
+			Err(QueryError::Unauthorized(reauth_e)) => {
+				warn!("Logging in again as {id:?} was not accepted: {reauth_e}");
+				self.mark_needs_login(id).await;
+				return Err(e);
+			}
+			Err(QueryError::Other(reauth_e)) => {
+				warn!("Logging in again as {id:?} failed: {reauth_e}");
+				return Err(reauth_e);
+			}
+		};
+		self.renewed.write().await.insert(id.clone(), auth);
+
+		match query().await {
+			Err(QueryError::Unauthorized(e)) => {
+				warn!("Session of {id:?} was not accepted even after logging in");
+				self.mark_needs_login(id).await;
+				Err(e)
+			}
+			result => result.map_err(String::from),
+		}
+	}
+
+	/// Keeps the latest authentication of an account, for logging in again if
+	/// the session expires
+	pub(crate) async fn remember_authentication(&self, auth: Authentication) {
+		let id = auth.id();
+		self.needs_login.write().await.remove(&id);
+		self.authentications.write().await.insert(id, auth);
+	}
+
+	/// Forgets everything about an account's authentication
+	pub(crate) async fn forget_authentication(&self, id: &PlatformAccountId) {
+		self.needs_login.write().await.remove(id);
+		self.renewed.write().await.remove(id);
+		self.authentications.write().await.remove(id);
+	}
+
+	/// Removes the client of an account whose session couldn't be renewed, so
+	/// that it's not used anymore until it's logged in to again
+	async fn mark_needs_login(&self, id: &PlatformAccountId) {
+		error!("Session of {id:?} expired, it needs to be logged in to again");
+		match id {
+			PlatformAccountId::VRChat(id) => {
+				self.vrc.write().await.remove(id);
+			}
+			PlatformAccountId::ChilloutVR(id) => {
+				self.cvr.write().await.remove(id);
+			}
+			PlatformAccountId::Resonite(id) => {
+				self.resonite.write().await.remove(id);
+			}
+		}
+		self.authentications.write().await.remove(id);
+		self.needs_login.write().await.insert(id.clone());
+	}
+
+	/// The accounts whose sessions expired mid-use and couldn't be renewed, and
+	/// so need to be logged in to again
+	pub async fn accounts_needing_login(&self) -> Vec<PlatformAccountId> {
+		self.needs_login.read().await.iter().cloned().collect()
+	}
+
+	/// Takes the authentications that were renewed after their sessions
+	/// expired mid-use, which should be stored for future use
+	pub async fn take_renewed_authentications(&self) -> Vec<Authentication> {
+		self.renewed.write().await.drain().map(|(_, auth)| auth).collect()
+	}
+}
@@ -20,7 +20,7 @@
 #[macro_use]
 extern crate tracing;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use ::resonite::api_client::AuthenticatedResonite;
 use chilloutvr::api_client::AuthenticatedCVR;
@@ -30,6 +30,7 @@ use onlivfe::{
 	InstanceId,
 	LoginCredentials,
 	LoginError,
+	MAX_PICTURE_BYTES,
 	PlatformAccount,
 	PlatformAccountId,
 	PlatformDataAndMetadata,
@@ -41,38 +42,96 @@ use tokio::sync::RwLock;
 use vrchat::VRChatClientState;
 
 mod cvr;
+mod expiry;
 mod resonite;
 mod vrchat;
 
+pub use expiry::QueryError;
+
+/// The hosts of the platforms' CDNs, which images are downloaded from
+const IMAGE_HOSTS: &[&str] = &[
+	// VRChat
+	"api.vrchat.cloud",
+	"assets.vrchat.com",
+	"files.vrchat.cloud",
+	// ChilloutVR
+	"files.abidata.io",
+	// Resonite
+	"assets.resonite.com",
+];
+/// How many redirects are followed when downloading an image
+const MAX_IMAGE_REDIRECTS: usize = 5;
+
+/// If an image can be downloaded from the URL, as it's from one of the
+/// platforms' CDNs over HTTPS
+fn is_image_url(url: &reqwest::Url) -> bool {
+	url.scheme() == "https"
+		&& url.host_str().is_some_and(|host| IMAGE_HOSTS.contains(&host))
+}
+
 /// An unified API client interface for the different platforms
 pub struct OnlivfeApiClient {
 	user_agent: String,
-	/// The VRChat API client
+	/// For fetching things other than from the platforms' APIs, following
+	/// redirects only to [`IMAGE_HOSTS`]
+	http: reqwest::Client,
+	/// The `VRChat` API client
 	vrc: RwLock<HashMap<vrc::id::User, VRChatClientState>>,
-	/// The ChilloutVR API client
+	/// The `ChilloutVR` API client
 	cvr: RwLock<HashMap<chilloutvr::id::User, AuthenticatedCVR>>,
 	/// The Resonite API client
 	resonite: RwLock<HashMap<::resonite::id::User, AuthenticatedResonite>>,
+	/// The latest authentications of the accounts, for logging in again if
+	/// their sessions expire mid-use
+	authentications: RwLock<HashMap<PlatformAccountId, Authentication>>,
+	/// Authentications that were renewed mid-use, but haven't been taken to be
+	/// stored yet
+	renewed: RwLock<HashMap<PlatformAccountId, Authentication>>,
+	/// Accounts whose sessions expired mid-use and couldn't be renewed
+	needs_login: RwLock<HashSet<PlatformAccountId>>,
 }
 
 impl std::fmt::Debug for OnlivfeApiClient {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("OnlivfeApiClient")
 			.field("user_agent", &self.user_agent)
-			.finish()
+			.finish_non_exhaustive()
 	}
 }
 
 impl OnlivfeApiClient {
 	/// Creates a new API client
-	#[must_use]
-	pub fn new(user_agent: String) -> Self {
-		Self {
+	///
+	/// # Errors
+	///
+	/// If the HTTP client for downloading images couldn't be created
+	pub fn new(user_agent: String) -> Result<Self, String> {
+		let http = reqwest::Client::builder()
+			.redirect(reqwest::redirect::Policy::custom(|attempt| {
+				if attempt.previous().len() >= MAX_IMAGE_REDIRECTS {
+					attempt.error("Too many redirects")
+				} else if is_image_url(attempt.url()) {
+					attempt.follow()
+				} else {
+					attempt.error("Redirected outside of the platforms' CDNs")
+				}
+			}))
+			.build()
+			.map_err(|e| {
+				error!("Failed to create the HTTP client: {e}");
+				format!("Failed to create the HTTP client: {e}")
+			})?;
+
+		Ok(Self {
 			vrc: RwLock::default(),
 			cvr: RwLock::default(),
 			resonite: RwLock::default(),
+			authentications: RwLock::default(),
+			renewed: RwLock::default(),
+			needs_login: RwLock::default(),
+			http,
 			user_agent,
-		}
+		})
 	}
 
 	/// Gets the fully authenticated user ID's from the clients for a platform
@@ -114,6 +173,7 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub async fn logout(&self, id: &PlatformAccountId) -> Result<(), String> {
 		trace!("Logging out of {:?}", id);
+		self.forget_authentication(id).await;
 		match id {
 			PlatformAccountId::VRChat(id) => self.logout_vrchat(id).await?,
 			PlatformAccountId::ChilloutVR(id) => self.logout_chilloutvr(id).await?,
@@ -136,7 +196,7 @@ impl OnlivfeApiClient {
 	pub async fn login(
 		&self, auth: LoginCredentials,
 	) -> Result<Authentication, LoginError> {
-		Ok(match auth {
+		let auth = match auth {
 			LoginCredentials::VRChat(auth) => {
 				let (user_id, auth) =
 					self.login_vrchat(*auth).await.map_err(|(second_factor, err)| {
@@ -154,7 +214,7 @@ impl OnlivfeApiClient {
 				let (user_id, auth) = self
 					.login_chilloutvr(None, *auth)
 					.await
-					.map_err(LoginError::Error)?;
+					.map_err(|e| LoginError::Error(e.into()))?;
 				Authentication::ChilloutVR(PlatformDataAndMetadata::new_now(
 					Box::new(auth),
 					user_id,
@@ -168,7 +228,10 @@ impl OnlivfeApiClient {
 					user_id,
 				))
 			}
-		})
+		};
+		self.remember_authentication(auth.clone()).await;
+
+		Ok(auth)
 	}
 
 	/// Retrieves the friends list from a platform
@@ -185,7 +248,7 @@ impl OnlivfeApiClient {
 		match get_as {
 			PlatformAccountId::VRChat(id) => Ok(
 				self
-					.friends_vrchat(id)
+					.retry_unauthorized(get_as, || self.friends_vrchat(id))
 					.await?
 					.into_iter()
 					.map(|friend| {
@@ -198,7 +261,7 @@ impl OnlivfeApiClient {
 			),
 			PlatformAccountId::ChilloutVR(id) => Ok(
 				self
-					.friends_chilloutvr(id)
+					.retry_unauthorized(get_as, || self.friends_chilloutvr(id))
 					.await?
 					.into_iter()
 					.map(|friend| {
@@ -211,7 +274,7 @@ impl OnlivfeApiClient {
 			),
 			PlatformAccountId::Resonite(id) => Ok(
 				self
-					.contacts_resonite(id)
+					.retry_unauthorized(get_as, || self.contacts_resonite(id))
 					.await?
 					.into_iter()
 					.map(|friend| {
@@ -239,14 +302,24 @@ impl OnlivfeApiClient {
 				let PlatformAccountId::VRChat(get_as) = get_as else {
 					return Err("Auth and platform types don't match!".to_owned());
 				};
-				let instance = self.instance_vrchat(&get_as, instance_id).await?;
+				let id = PlatformAccountId::from(get_as.clone());
+				let instance = self
+					.retry_unauthorized(&id, || {
+						self.instance_vrchat(&get_as, instance_id.clone())
+					})
+					.await?;
 				Ok(Instance::VRChat(PlatformDataAndMetadata::new_now(instance, get_as)))
 			}
 			InstanceId::ChilloutVR(instance_id) => {
 				let PlatformAccountId::ChilloutVR(get_as) = get_as else {
 					return Err("Auth and platform types don't match!".to_owned());
 				};
-				let instance = self.instance_chilloutvr(&get_as, instance_id).await?;
+				let id = PlatformAccountId::from(get_as.clone());
+				let instance = self
+					.retry_unauthorized(&id, || {
+						self.instance_chilloutvr(&get_as, instance_id.clone())
+					})
+					.await?;
 				Ok(Instance::ChilloutVR(PlatformDataAndMetadata::new_now(
 					instance, get_as,
 				)))
@@ -255,7 +328,12 @@ impl OnlivfeApiClient {
 				let PlatformAccountId::Resonite(get_as) = get_as else {
 					return Err("Auth and platform types don't match!".to_owned());
 				};
-				let instance = self.instance_resonite(&get_as, instance_id).await?;
+				let id = PlatformAccountId::from(get_as.clone());
+				let instance = self
+					.retry_unauthorized(&id, || {
+						self.instance_resonite(&get_as, instance_id.clone())
+					})
+					.await?;
 				Ok(Instance::Resonite(PlatformDataAndMetadata::new_now(
 					instance, get_as,
 				)))
@@ -277,7 +355,12 @@ impl OnlivfeApiClient {
 				let PlatformAccountId::VRChat(get_as) = get_as else {
 					return Err("Auth and platform types don't match!".to_owned());
 				};
-				let account = self.user_vrchat(&get_as, account_id).await?;
+				let id = PlatformAccountId::from(get_as.clone());
+				let account = self
+					.retry_unauthorized(&id, || {
+						self.user_vrchat(&get_as, account_id.clone())
+					})
+					.await?;
 				Ok(PlatformAccount::VRChat(PlatformDataAndMetadata::new_now(
 					Box::new(account),
 					get_as,
@@ -287,7 +370,12 @@ impl OnlivfeApiClient {
 				let PlatformAccountId::ChilloutVR(get_as) = get_as else {
 					return Err("Auth and platform types don't match!".to_owned());
 				};
-				let account = self.user_chilloutvr(&get_as, account_id).await?;
+				let id = PlatformAccountId::from(get_as.clone());
+				let account = self
+					.retry_unauthorized(&id, || {
+						self.user_chilloutvr(&get_as, account_id.clone())
+					})
+					.await?;
 				Ok(PlatformAccount::ChilloutVR(PlatformDataAndMetadata::new_now(
 					Box::new(account),
 					get_as,
@@ -297,7 +385,12 @@ impl OnlivfeApiClient {
 				let PlatformAccountId::Resonite(get_as) = get_as else {
 					return Err("Auth and platform types don't match!".to_owned());
 				};
-				let account = self.user_resonite(&get_as, account_id).await?;
+				let id = PlatformAccountId::from(get_as.clone());
+				let account = self
+					.retry_unauthorized(&id, || {
+						self.user_resonite(&get_as, account_id.clone())
+					})
+					.await?;
 				Ok(PlatformAccount::Resonite(PlatformDataAndMetadata::new_now(
 					Box::new(account),
 					get_as,
@@ -306,39 +399,135 @@ impl OnlivfeApiClient {
 		}
 	}
 
+	/// Downloads an image, such as the thumbnail of an avatar, from one of the
+	/// platforms' CDNs
+	///
+	/// # Errors
+	///
+	/// If the URL isn't an HTTPS one of the platforms' CDNs, the request
+	/// failed, or the image is larger than [`MAX_PICTURE_BYTES`]
+	#[instrument]
+	pub async fn download_image(&self, url: &str) -> Result<Vec<u8>, String> {
+		let url = reqwest::Url::parse(url)
+			.map_err(|e| format!("Invalid image URL: {e}"))?;
+		if !is_image_url(&url) {
+			return Err(format!("Not downloading an image from {url}"));
+		}
+
+		let too_large = |len| format!("Image is too large, {len} bytes");
+		let mut response = self
+			.http
+			.get(url)
+			.header(reqwest::header::USER_AGENT, &self.user_agent)
+			.send()
+			.await
+			.and_then(reqwest::Response::error_for_status)
+			.map_err(|e| format!("Failed to download image: {e}"))?;
+		if let Some(len) = response.content_length() {
+			if len > MAX_PICTURE_BYTES as u64 {
+				return Err(too_large(len));
+			}
+		}
+
+		// The length might not be known up front
+		let mut bytes = vec![];
+		while let Some(chunk) = response
+			.chunk()
+			.await
+			.map_err(|e| format!("Failed to download image: {e}"))?
+		{
+			bytes.extend_from_slice(&chunk);
+			if bytes.len() > MAX_PICTURE_BYTES {
+				return Err(too_large(bytes.len() as u64));
+			}
+		}
+
+		Ok(bytes)
+	}
+
+	/// Keeps an authenticated session alive, extending it on platforms that
+	/// support it and otherwise checking that it's still valid
+	///
+	/// Falls back to a full [`Self::reauthenticate`] if the API doesn't have a
+	/// client for the account yet.
+	///
+	/// # Errors
+	///
+	/// If the session couldn't be refreshed, telling apart if the platform
+	/// didn't accept it anymore
+	#[instrument]
+	pub async fn refresh_session(
+		&self, auth: Authentication,
+	) -> Result<Authentication, QueryError> {
+		if !self.authenticated_clients(auth.platform()).await.contains(&auth.id()) {
+			return self.renew_authentication(auth).await;
+		}
+
+		let auth = match auth {
+			Authentication::VRChat(mut auth) => {
+				self.validate_vrchat(&auth.metadata.updated_by).await?;
+				auth.metadata.updated_at = OffsetDateTime::now_utc();
+				Authentication::VRChat(auth)
+			}
+			// The access keys don't expire, so logging in again is the only check
+			auth @ Authentication::ChilloutVR(_) => {
+				self.renew_authentication(auth).await?
+			}
+			Authentication::Resonite(mut auth) => {
+				self.extend_auth_resonite(&auth.data.user_id).await?;
+				auth.metadata.updated_at = OffsetDateTime::now_utc();
+				Authentication::Resonite(auth)
+			}
+		};
+		self.remember_authentication(auth.clone()).await;
+
+		Ok(auth)
+	}
+
 	/// Used to restore authentication for example on app startup
 	///
 	/// # Errors
 	///
 	/// If an error happened with the authentication check/extension/login/etc
-	#[instrument]
 	pub async fn reauthenticate(
 		&self, auth: Authentication,
 	) -> Result<Authentication, String> {
-		match auth {
+		self.renew_authentication(auth).await.map_err(String::from)
+	}
+
+	/// Logs in again with an authentication, telling apart if the platform
+	/// didn't accept it anymore
+	#[instrument]
+	async fn renew_authentication(
+		&self, auth: Authentication,
+	) -> Result<Authentication, QueryError> {
+		let auth = match auth {
 			Authentication::VRChat(auth) => {
 				let current_account = self
 					.reauthenticate_vrchat(&auth.metadata.updated_by, *auth.data.clone())
 					.await?;
-				Ok(Authentication::VRChat(PlatformDataAndMetadata::new_now(
+				Authentication::VRChat(PlatformDataAndMetadata::new_now(
 					Box::new(*auth.data),
 					current_account.base.id,
-				)))
+				))
 			}
 			Authentication::ChilloutVR(auth) => {
 				let (id, new_auth) = self
 					.login_chilloutvr(Some(auth.metadata.updated_by), *auth.data)
 					.await?;
-				Ok(Authentication::ChilloutVR(PlatformDataAndMetadata::new_now(
+				Authentication::ChilloutVR(PlatformDataAndMetadata::new_now(
 					Box::new(new_auth),
 					id,
-				)))
+				))
 			}
 			Authentication::Resonite(mut auth) => {
 				self.reauthenticate_resonite((*auth.data).clone()).await?;
 				auth.metadata.updated_at = OffsetDateTime::now_utc();
-				Ok(Authentication::Resonite(auth))
+				Authentication::Resonite(auth)
 			}
-		}
+		};
+		self.remember_authentication(auth.clone()).await;
+
+		Ok(auth)
 	}
 }
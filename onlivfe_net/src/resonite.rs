@@ -15,14 +15,15 @@ use resonite::{
 	},
 };
 
-use crate::OnlivfeApiClient;
+use crate::{OnlivfeApiClient, expiry::QueryError};
 
 impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn logout_resonite(
 		&self, id: &id::User,
 	) -> Result<(), String> {
-		if let Some(_client) = self.resonite.write().await.remove(id) {
+		let client = self.resonite.write().await.remove(id);
+		if let Some(_client) = client {
 			// TODO: Logout request
 			//client.query(query::DestroyUserSession).await.map_err(|e| {
 			//	error!("Logout as {:?} failed: {:?}", id, e);
@@ -35,42 +36,32 @@ impl OnlivfeApiClient {
 	}
 
 	// Auth contains user ID so not passing it in here unlike other clients
-	#[instrument]
+	#[instrument(skip(auth), fields(user_id = ?auth.user_id))]
 	pub(crate) async fn reauthenticate_resonite(
 		&self, auth: Authentication,
-	) -> Result<(), String> {
+	) -> Result<(), QueryError> {
 		trace!("Reauthentcating as {:?}", &auth.user_id);
-		if let Some(api) = self.resonite.read().await.get(&auth.user_id) {
+		let id = auth.user_id.clone();
+		// An existing client would still use the old session, so it's recreated,
+		// but only replaced once logging in succeeds. Not locked during the query,
+		// so that the other accounts can be used
+		let api = AuthenticatedResonite::new(self.user_agent.clone(), auth)
+			.map_err(|e| {
+				error!("Creating Resonite API client as {id:?} failed: {e:?}");
+				QueryError::Other(
+					"Internal error, Resonite API client creation failed".to_owned(),
+				)
+			})?;
+
+		api.query(query::ExtendUserSession).await.map_err(|e| {
 			warn!(
-				"Already had authenticated client for reauthentication as {:?}",
-				auth.user_id
+				"Reauthentication via user session extension check as {:?} failed: {:?}",
+				&id, e
 			);
-			api.query(query::ExtendUserSession).await.map_err(|e| {
-				warn!(
-					"Reauthentication via user session extension check as {:?} failed: {:?}",
-					auth.user_id, e
-				);
-				"Reauthentication failed".to_owned()
-			})?;
-		} else {
-			let id = auth.user_id.clone();
-			let mut rw_lock_guard = self.resonite.write().await;
-			let api = AuthenticatedResonite::new(self.user_agent.clone(), auth)
-				.map_err(|e| {
-					error!("Creating Resonite API client as {id:?} failed: {e:?}");
-					"Internal error, Resonite API client creation failed".to_string()
-				})?;
-
-			api.query(query::ExtendUserSession).await.map_err(|e| {
-				warn!(
-					"Reauthentication via user session extension check as {:?} failed: {:?}",
-					&id, e
-				);
-				"Reauthentication failed".to_owned()
-			})?;
+			QueryError::from_api(&e, "Reauthentication failed")
+		})?;
 
-			rw_lock_guard.insert(id, api);
-		}
+		self.resonite.write().await.insert(id, api);
 
 		Ok(())
 	}
@@ -78,14 +69,14 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn extend_auth_resonite(
 		&self, id: &id::User,
-	) -> Result<(), String> {
+	) -> Result<(), QueryError> {
 		let rw_lock_guard = self.resonite.read().await;
-		let api = rw_lock_guard
-			.get(id)
-			.ok_or_else(|| "Resonite API not authenticated".to_owned())?;
+		let api = rw_lock_guard.get(id).ok_or_else(|| {
+			QueryError::Other("Resonite API not authenticated".to_owned())
+		})?;
 		api.query(query::ExtendUserSession).await.map_err(|e| {
 			warn!("User session extension as {:?} failed: {:?}", id, e);
-			"User session extension failed".to_owned()
+			QueryError::from_api(&e, "User session extension failed")
 		})?;
 
 		Ok(())
@@ -94,16 +85,16 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn instance_resonite(
 		&self, id: &id::User, session_id: id::Session,
-	) -> Result<SessionInfo, String> {
+	) -> Result<SessionInfo, QueryError> {
 		trace!("Fetching instance {:?} as {:?}", session_id, id);
 		let rw_lock_guard = self.resonite.read().await;
-		let api = rw_lock_guard
-			.get(id)
-			.ok_or_else(|| "Resonite API not authenticated".to_owned())?;
+		let api = rw_lock_guard.get(id).ok_or_else(|| {
+			QueryError::Other("Resonite API not authenticated".to_owned())
+		})?;
 		let query = query::SessionInfo { session_id };
 		let session = api.query(query).await.map_err(|e| {
 			warn!("Instance query failed: {:?}", &e);
-			"Resonite instance query failed".to_owned()
+			QueryError::from_api(&e, "Resonite instance query failed")
 		})?;
 
 		Ok(session)
@@ -112,16 +103,16 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn user_resonite(
 		&self, get_as: &id::User, user_id: id::User,
-	) -> Result<User, String> {
+	) -> Result<User, QueryError> {
 		trace!("Fetching user {:?} as {:?}", user_id, get_as);
 		let rw_lock_guard = self.resonite.read().await;
-		let api = rw_lock_guard
-			.get(get_as)
-			.ok_or_else(|| "Resonite API not authenticated".to_owned())?;
+		let api = rw_lock_guard.get(get_as).ok_or_else(|| {
+			QueryError::Other("Resonite API not authenticated".to_owned())
+		})?;
 		let query = query::UserInfo::new(user_id);
 		let user = api.query(query).await.map_err(|e| {
 			warn!("User query failed: {:?}", &e);
-			"Resonite user query failed".to_owned()
+			QueryError::from_api(&e, "Resonite user query failed")
 		})?;
 
 		Ok(user)
@@ -130,33 +121,34 @@ impl OnlivfeApiClient {
 	#[instrument]
 	pub(crate) async fn contacts_resonite(
 		&self, id: &id::User,
-	) -> Result<Vec<Contact>, String> {
+	) -> Result<Vec<Contact>, QueryError> {
 		trace!("Fetching Contacts as {:?}", id);
 		let rw_lock_guard = self.resonite.read().await;
-		let api = rw_lock_guard
-			.get(id)
-			.ok_or_else(|| "Resonite API not authenticated".to_owned())?;
+		let api = rw_lock_guard.get(id).ok_or_else(|| {
+			QueryError::Other("Resonite API not authenticated".to_owned())
+		})?;
 		let query = query::Contacts;
 		let contacts = api.query(query).await.map_err(|e| {
 			warn!("Contacts query failed: {:?}", &e);
-			"Resonite Contacts query failed".to_owned()
+			QueryError::from_api(&e, "Resonite Contacts query failed")
 		})?;
 
 		Ok(contacts)
 	}
 
-	#[instrument]
+	// The credentials contain a password or a session token
+	#[instrument(skip(auth))]
 	pub(crate) async fn login_resonite(
 		&self, auth: UserSessionQueryWithHeaders,
 	) -> Result<(id::User, query::Authentication), String> {
 		trace!("Trying to login as {:?}", auth.body.identifier);
-		let mut rw_lock_guard = self.resonite.write().await;
+		// Not locked during the query, so that the other accounts can be used
 		let api = match &auth.body.identifier {
 			LoginCredentialsIdentifier::OwnerID(owner_id_str) => {
-				id::User::try_from(owner_id_str.clone())
-					.map(|user_id| rw_lock_guard.remove(&user_id))
-					.ok()
-					.flatten()
+				match id::User::try_from(owner_id_str.clone()) {
+					Ok(user_id) => self.resonite.write().await.remove(&user_id),
+					Err(_) => None,
+				}
 			}
 			_ => None,
 		};
@@ -188,7 +180,7 @@ impl OnlivfeApiClient {
 				"Internal error, authenticated Resonite API client's creation failed"
 					.to_owned()
 			})?;
-		rw_lock_guard.insert(user_id.clone(), api);
+		self.resonite.write().await.insert(user_id.clone(), api);
 		Ok((user_id, auth))
 	}
 }
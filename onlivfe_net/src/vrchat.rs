@@ -7,7 +7,7 @@ use vrc::{
 	query::{self, Logout},
 };
 
-use crate::OnlivfeApiClient;
+use crate::{OnlivfeApiClient, expiry::QueryError};
 
 pub enum VRChatClientState {
 	/// Has authentication cookie saved from login but no 2FA cookie
@@ -41,7 +41,8 @@ impl OnlivfeApiClient {
 	pub(crate) async fn logout_vrchat(
 		&self, id: &id::User,
 	) -> Result<(), String> {
-		if let Some(client) = self.vrc.write().await.remove(id) {
+		let client = self.vrc.write().await.remove(id);
+		if let Some(client) = client {
 			trace!("Logging out of {:?}", id);
 			return client.logout(id).await;
 		}
@@ -49,39 +50,65 @@ impl OnlivfeApiClient {
 		Ok(())
 	}
 
-	#[instrument]
+	// The auth contains the session tokens
+	#[instrument(skip(auth))]
 	pub(crate) async fn reauthenticate_vrchat(
 		&self, id: &id::User, auth: query::Authentication,
-	) -> Result<CurrentAccount, String> {
+	) -> Result<CurrentAccount, QueryError> {
 		trace!("Reauthentcating as {:?}", id);
-		let mut rw_lock_guard = self.vrc.write().await;
-
-		// We're keeping the lock for this whole function anyways
-		#[allow(clippy::significant_drop_in_scrutinee)]
-		let api = match rw_lock_guard.remove(id) {
+		// Not locked during the query, so that the other accounts can be used
+		let existing = self.vrc.write().await.remove(id);
+		let api = match existing {
 			None => AuthenticatedVRC::new(self.user_agent.clone(), auth),
 			Some(VRChatClientState::Authenticated(api)) => api.recreate(auth),
 			Some(VRChatClientState::Authenticating(api)) => api.0.recreate(auth),
 		}
 		.map_err(|_| {
-			"Internal error, VRChat API client creation failed".to_string()
+			QueryError::Other(
+				"Internal error, VRChat API client creation failed".to_owned(),
+			)
 		})?;
 
 		let current_user: CurrentAccount =
 			api.query(query::GetCurrentUser).await.map_err(|e| {
 				warn!("Reauthentication query failed: {:?}", &e);
-				"Reauthentication failed".to_owned()
+				QueryError::from_api(&e, "Reauthentication failed")
 			})?;
 
-		rw_lock_guard.insert(id.clone(), VRChatClientState::Authenticated(api));
+		self
+			.vrc
+			.write()
+			.await
+			.insert(id.clone(), VRChatClientState::Authenticated(api));
 
 		Ok(current_user)
 	}
 
+	#[instrument]
+	pub(crate) async fn validate_vrchat(
+		&self, id: &id::User,
+	) -> Result<(), QueryError> {
+		trace!("Validating session of {:?}", id);
+		let rw_lock_guard = self.vrc.read().await;
+		let api = rw_lock_guard.get(id);
+		match api {
+			Some(VRChatClientState::Authenticated(api)) => {
+				let _: CurrentAccount =
+					api.query(query::GetCurrentUser).await.map_err(|e| {
+						warn!("Session validation query failed: {:?}", &e);
+						QueryError::from_api(&e, "VRChat session validation failed")
+					})?;
+
+				Ok(())
+			}
+			_ => Err(QueryError::Other("VRChat API not authenticated".to_owned())),
+		}
+	}
+
 	#[instrument]
 	pub(crate) async fn instance_vrchat(
 		&self, id: &id::User, instance_id: id::WorldInstance,
-	) -> Result<Instance, String> {
+	) -> Result<Instance, QueryError> {
 		trace!("Fetching instance {:?} as {:?}", instance_id, id);
 		let rw_lock_guard = self.vrc.read().await;
 		let api = rw_lock_guard.get(id);
@@ -90,19 +117,19 @@ impl OnlivfeApiClient {
 				let query = query::Instance { id: instance_id };
 				let instance = api.query(query).await.map_err(|e| {
 					warn!("Instance query failed: {:?}", &e);
-					"VRChat instance query failed".to_owned()
+					QueryError::from_api(&e, "VRChat instance query failed")
 				})?;
 
 				Ok(instance)
 			}
-			_ => Err("VRChat API not authenticated".to_string()),
+			_ => Err(QueryError::Other("VRChat API not authenticated".to_owned())),
 		}
 	}
 
 	#[instrument]
 	pub(crate) async fn user_vrchat(
 		&self, get_as: &id::User, user_id: id::User,
-	) -> Result<AnyUser, String> {
+	) -> Result<AnyUser, QueryError> {
 		trace!("Fetching user {:?} as {:?}", user_id, get_as);
 		let rw_lock_guard = self.vrc.read().await;
 		let api = rw_lock_guard.get(get_as);
@@ -111,19 +138,19 @@ impl OnlivfeApiClient {
 				let query = query::User { id: user_id };
 				let user = api.query(query).await.map_err(|e| {
 					warn!("User query failed: {:?}", &e);
-					"VRChat user query failed".to_owned()
+					QueryError::from_api(&e, "VRChat user query failed")
 				})?;
 
 				Ok(user)
 			}
-			_ => Err("VRChat API not authenticated".to_string()),
+			_ => Err(QueryError::Other("VRChat API not authenticated".to_owned())),
 		}
 	}
 
 	#[instrument]
 	pub(crate) async fn friends_vrchat(
 		&self, id: &id::User,
-	) -> Result<Vec<Friend>, String> {
+	) -> Result<Vec<Friend>, QueryError> {
 		trace!("Fetching friends as {:?}", id);
 		let rw_lock_guard = self.vrc.read().await;
 		let api = rw_lock_guard.get(id);
@@ -133,11 +160,11 @@ impl OnlivfeApiClient {
 				query.pagination.limit = 100;
 				let friends = api.query(query).await.map_err(|e| {
 					warn!("Friends query failed: {:?}", &e);
-					"VRChat friends query failed".to_owned()
+					QueryError::from_api(&e, "VRChat friends query failed")
 				})?;
 				Ok(friends)
 			}
-			_ => Err("VRChat API not authenticated".to_string()),
+			_ => Err(QueryError::Other("VRChat API not authenticated".to_owned())),
 		}
 	}
 
@@ -206,8 +233,7 @@ impl OnlivfeApiClient {
 			}
 			onlivfe::vrchat::LoginRequestPart::SecondFactor((id, second_factor)) => {
 				trace!("Continuing login for {:?}", id);
-				let mut rw_lock_guard = self.vrc.write().await;
-				let state = rw_lock_guard.remove(&id).ok_or_else(|| {
+				let state = self.vrc.write().await.remove(&id).ok_or_else(|| {
 					(None, "VRC authentication not in progress for user".to_owned())
 				})?;
 				let VRChatClientState::Authenticating(api_state) = state else {
@@ -0,0 +1,865 @@
+//! Backups of everything that's stored, for moving to a new machine
+
+use std::collections::{HashMap, HashSet};
+
+use onlivfe::{
+	AccountLink,
+	Authentication,
+	Avatar,
+	Circle,
+	CircleId,
+	CircleMember,
+	Instance,
+	LocalPfpUri,
+	PlatformAccount,
+	PlatformAccountId,
+	PlatformFriend,
+	Profile,
+	ProfileId,
+	ProfilePicture,
+	ProfileRevision,
+	TrashedProfile,
+	World,
+	encryption::{AuthCipher, AuthKey, KeyParams, Sealed},
+	storage::{OnlivfeStore, StoreBatch},
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{PickFirst, Same, base64::Base64, serde_as};
+use time::OffsetDateTime;
+
+use crate::{Onlivfe, ProfileImportCounts};
+
+/// The version of the archive format that is written, older ones can still be
+/// restored
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// How to include the platform authentications in an archive
+#[derive(Debug, Clone)]
+pub enum ArchiveSecrets {
+	/// Leave them out, requiring logging in again after restoring
+	Exclude,
+	/// Include them unencrypted, so anyone with the archive can use them
+	Plain,
+	/// Include them encrypted with a key, which is needed for restoring them
+	Encrypted(AuthKey),
+}
+
+/// The amounts of data that were restored from an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ArchiveImportCounts {
+	/// Profiles that didn't exist before
+	pub profiles_created: usize,
+	/// Existing profiles that got details filled in from the archive
+	pub profiles_merged: usize,
+	/// Accounts that were newly linked to profiles
+	pub mappings: usize,
+	/// Accounts, friends, instances, worlds and avatars that were newer in the
+	/// archive
+	pub platform_data: usize,
+	/// Platform authentications that didn't exist before
+	pub authentications: usize,
+	/// Circles that didn't exist before
+	pub circles_created: usize,
+	/// Members that were newly added to circles
+	pub circle_members: usize,
+	/// Profiles in the trash that weren't stored before
+	pub trashed_profiles: usize,
+	/// Pictures of profiles that didn't have one stored
+	pub pictures: usize,
+	/// Revisions of profiles that were new to this device
+	pub revisions: usize,
+	/// Account links that weren't rejected before
+	pub rejected_links: usize,
+}
+
+/// Just the version, to check it before trying to read the rest
+#[derive(Deserialize)]
+struct ArchiveHeader {
+	version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Archive {
+	version: u32,
+	#[serde(with = "time::serde::rfc3339")]
+	created_at: OffsetDateTime,
+	profiles: Vec<Profile>,
+	profile_accounts: Vec<ArchivedProfileAccounts>,
+	accounts: Vec<PlatformAccount>,
+	friends: Vec<PlatformFriend>,
+	instances: Vec<Instance>,
+	worlds: Vec<World>,
+	avatars: Vec<Avatar>,
+	authentications: ArchivedAuthentications,
+	#[serde(default)]
+	circles: Vec<ArchivedCircle>,
+	#[serde(default)]
+	trash: Vec<TrashedProfile>,
+	#[serde(default)]
+	pictures: Vec<ArchivedPicture>,
+	#[serde(default)]
+	revisions: Vec<ProfileRevision>,
+	#[serde(default)]
+	rejected_links: Vec<AccountLink>,
+}
+
+// Base64 is a lot smaller than arrays of numbers, which the first archives had
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchivedPicture {
+	profile_id: ProfileId,
+	content_type: String,
+	#[serde_as(as = "PickFirst<(Base64, Same)>")]
+	bytes: Vec<u8>,
+	#[serde_as(as = "PickFirst<(Base64, Same)>")]
+	thumbnail: Vec<u8>,
+	#[serde(with = "time::serde::rfc3339")]
+	stored_at: OffsetDateTime,
+}
+
+impl From<ProfilePicture> for ArchivedPicture {
+	fn from(picture: ProfilePicture) -> Self {
+		Self {
+			profile_id: picture.profile_id,
+			content_type: picture.content_type,
+			bytes: picture.bytes,
+			thumbnail: picture.thumbnail,
+			stored_at: picture.stored_at,
+		}
+	}
+}
+
+impl From<ArchivedPicture> for ProfilePicture {
+	fn from(picture: ArchivedPicture) -> Self {
+		Self {
+			profile_id: picture.profile_id,
+			content_type: picture.content_type,
+			bytes: picture.bytes,
+			thumbnail: picture.thumbnail,
+			stored_at: picture.stored_at,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchivedCircle {
+	#[serde(flatten)]
+	circle: Circle,
+	members: Vec<CircleMember>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchivedProfileAccounts {
+	profile_id: ProfileId,
+	account_ids: Vec<PlatformAccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ArchivedAuthentications {
+	Excluded,
+	Plain { entries: Vec<Authentication> },
+	Encrypted { key_params: KeyParams, entries: Vec<Sealed> },
+}
+
+/// Keeps the platform data that's missing from the store or newer than the
+/// stored copy of it
+macro_rules! keep_newer {
+	($archived:expr, $stored:expr) => {{
+		let stored: HashMap<_, _> =
+			$stored.into_iter().map(|v| (v.id(), v.metadata().updated_at)).collect();
+		$archived
+			.into_iter()
+			.filter(|v| {
+				!stored
+					.get(&v.id())
+					.is_some_and(|updated_at| v.metadata().updated_at <= *updated_at)
+			})
+			.collect::<Vec<_>>()
+	}};
+}
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Writes everything that's stored into a single versioned archive, which
+	/// can be restored with [`import_archive`](Self::import_archive) on
+	/// another machine.
+	///
+	/// # Errors
+	///
+	/// If reading from the store, encrypting the authentications or
+	/// serializing the archive failed
+	pub async fn export_archive(
+		&self, secrets: ArchiveSecrets,
+	) -> Result<Vec<u8>, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to read data for an archive: {e}");
+			"Failed to read stored data".to_string()
+		};
+
+		let profiles =
+			self.store.profiles(usize::MAX).await.map_err(storage_err)?;
+		let mut profile_accounts = Vec::with_capacity(profiles.len());
+		for profile in &profiles {
+			let profile_id = profile.sharing_id.clone();
+			let account_ids = self
+				.store
+				.profile_account_ids(profile_id.clone())
+				.await
+				.map_err(storage_err)?;
+			if !account_ids.is_empty() {
+				profile_accounts
+					.push(ArchivedProfileAccounts { profile_id, account_ids });
+			}
+		}
+
+		let stored_circles =
+			self.store.circles(usize::MAX).await.map_err(storage_err)?;
+		let mut circles = Vec::with_capacity(stored_circles.len());
+		for circle in stored_circles {
+			let members = self
+				.store
+				.circle_members(circle.id.clone())
+				.await
+				.map_err(storage_err)?;
+			circles.push(ArchivedCircle { circle, members });
+		}
+
+		let trash =
+			self.store.trashed_profiles(usize::MAX).await.map_err(storage_err)?;
+		let (pictures, revisions) = self
+			.pictures_and_revisions(&profiles, &trash)
+			.await
+			.map_err(storage_err)?;
+
+		let authentications = match secrets {
+			ArchiveSecrets::Exclude => ArchivedAuthentications::Excluded,
+			ArchiveSecrets::Plain => ArchivedAuthentications::Plain {
+				entries: self.store.authentications().await.map_err(storage_err)?,
+			},
+			ArchiveSecrets::Encrypted(key) => {
+				let cipher = AuthCipher::create(&key)
+					.map_err(|e| format!("Failed to set up encryption: {e}"))?;
+				let entries = self
+					.store
+					.authentications()
+					.await
+					.map_err(storage_err)?
+					.iter()
+					.map(|auth| cipher.seal(auth))
+					.collect::<Result<_, _>>()
+					.map_err(|e| format!("Failed to encrypt authentications: {e}"))?;
+				ArchivedAuthentications::Encrypted {
+					key_params: cipher.params().clone(),
+					entries,
+				}
+			}
+		};
+
+		let archive = Archive {
+			version: ARCHIVE_VERSION,
+			created_at: OffsetDateTime::now_utc(),
+			profiles,
+			profile_accounts,
+			accounts: self.store.accounts(usize::MAX).await.map_err(storage_err)?,
+			friends: self.store.friends(usize::MAX).await.map_err(storage_err)?,
+			instances: self.store.instances(usize::MAX).await.map_err(storage_err)?,
+			worlds: self.store.worlds(usize::MAX).await.map_err(storage_err)?,
+			avatars: self.store.avatars(usize::MAX).await.map_err(storage_err)?,
+			authentications,
+			circles,
+			trash,
+			pictures,
+			revisions,
+			rejected_links: self.store.rejected_links().await.map_err(storage_err)?,
+		};
+
+		serde_json::to_vec(&archive)
+			.map_err(|e| format!("Failed to serialize archive: {e}"))
+	}
+
+	/// Restores an archive from [`export_archive`](Self::export_archive),
+	/// merging it into what's already stored.
+	///
+	/// Existing profiles get the details that they're missing filled in and
+	/// the accounts linked to them in the archive added, instead of being
+	/// duplicated or replaced.
+	/// Platform data is only restored if it's newer than the stored copy, and
+	/// authentications only for accounts that aren't logged in already.
+	/// Existing circles keep their details, and only get the members that
+	/// they're missing added.
+	/// Trashed profiles are only restored into the trash if they're not stored
+	/// already.
+	/// Pictures are only restored for profiles that don't have one stored, and
+	/// revisions only for profiles that are new to this device.
+	///
+	/// # Errors
+	///
+	/// If the archive is invalid or from a newer version, if the
+	/// authentications are encrypted and the key is missing or wrong, or if
+	/// storing the data failed
+	pub async fn import_archive(
+		&self, archive: &[u8], key: Option<&AuthKey>,
+	) -> Result<ArchiveImportCounts, String> {
+		let archive = parse_archive(archive)?;
+
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to restore an archive: {e}");
+			"Failed to restore the archive".to_string()
+		};
+
+		let authentications = open_authentications(archive.authentications, key)?;
+		let stored_auth_ids: HashSet<PlatformAccountId> = self
+			.store
+			.authentications()
+			.await
+			.map_err(storage_err)?
+			.iter()
+			.map(Authentication::id)
+			.collect();
+		// Checked before restoring the profiles, as that records revisions too
+		let revisions = self
+			.new_profile_revisions(archive.revisions)
+			.await
+			.map_err(storage_err)?;
+
+		let mut profile_counts = ProfileImportCounts::default();
+		let profiles = self
+			.merge_with_stored_profiles(archive.profiles, &mut profile_counts)
+			.await
+			.map_err(storage_err)?;
+
+		let batch = StoreBatch {
+			accounts: keep_newer!(
+				archive.accounts,
+				self.store.accounts(usize::MAX).await.map_err(storage_err)?
+			),
+			friends: keep_newer!(
+				archive.friends,
+				self.store.friends(usize::MAX).await.map_err(storage_err)?
+			),
+			instances: keep_newer!(
+				archive.instances,
+				self.store.instances(usize::MAX).await.map_err(storage_err)?
+			),
+			worlds: keep_newer!(
+				archive.worlds,
+				self.store.worlds(usize::MAX).await.map_err(storage_err)?
+			),
+			avatars: keep_newer!(
+				archive.avatars,
+				self.store.avatars(usize::MAX).await.map_err(storage_err)?
+			),
+			profiles,
+			authentications: authentications
+				.into_iter()
+				.filter(|auth| !stored_auth_ids.contains(&auth.id()))
+				.collect(),
+			..StoreBatch::default()
+		};
+		let platform_data = batch.accounts.len()
+			+ batch.friends.len()
+			+ batch.instances.len()
+			+ batch.worlds.len()
+			+ batch.avatars.len();
+		let authentications = batch.authentications.len();
+		self.store.apply_batch(batch).await.map_err(storage_err)?;
+
+		for ArchivedProfileAccounts { profile_id, account_ids } in
+			archive.profile_accounts
+		{
+			profile_counts.mappings += self
+				.link_profile_accounts(profile_id, account_ids)
+				.await
+				.map_err(storage_err)?;
+		}
+
+		let (circles_created, circle_members) =
+			self.restore_circles(archive.circles).await.map_err(storage_err)?;
+		let trashed_profiles =
+			self.restore_trash(archive.trash).await.map_err(storage_err)?;
+		let pictures =
+			self.restore_pictures(archive.pictures).await.map_err(storage_err)?;
+		let revisions =
+			self.restore_revisions(revisions).await.map_err(storage_err)?;
+		let rejected_links = self
+			.restore_rejected_links(archive.rejected_links)
+			.await
+			.map_err(storage_err)?;
+
+		let counts = ArchiveImportCounts {
+			profiles_created: profile_counts.profiles_created,
+			profiles_merged: profile_counts.profiles_merged,
+			mappings: profile_counts.mappings,
+			platform_data,
+			authentications,
+			circles_created,
+			circle_members,
+			trashed_profiles,
+			pictures,
+			revisions,
+			rejected_links,
+		};
+		Ok(counts)
+	}
+
+	/// Gets the locally stored pictures that the profiles use, and the
+	/// revisions of the profiles
+	async fn pictures_and_revisions(
+		&self, profiles: &[Profile], trash: &[TrashedProfile],
+	) -> Result<(Vec<ArchivedPicture>, Vec<ProfileRevision>), StorageBackend::Err>
+	{
+		let mut revised_ids = HashSet::new();
+		let mut revisions = vec![];
+		let mut picture_ids = HashSet::new();
+		for profile in
+			profiles.iter().chain(trash.iter().map(|trashed| &trashed.profile))
+		{
+			// Profiles can be both stored and in the trash
+			if revised_ids.insert(profile.sharing_id.clone()) {
+				revisions.extend(
+					self.store.profile_revisions(profile.sharing_id.clone()).await?,
+				);
+			}
+			if let Some(uri) = profile
+				.pfp_url
+				.as_deref()
+				.and_then(|url| url.parse::<LocalPfpUri>().ok())
+			{
+				picture_ids.insert(uri.profile_id);
+			}
+		}
+		let mut pictures = Vec::with_capacity(picture_ids.len());
+		for profile_id in picture_ids {
+			// Not finding one is the only way to tell that it's missing, and a
+			// missing picture shouldn't prevent backing up everything else
+			match self.store.profile_picture(profile_id.clone()).await {
+				Ok(picture) => pictures.push(ArchivedPicture::from(picture)),
+				Err(e) => {
+					warn!("Not archiving missing picture of {profile_id:?}: {e}");
+				}
+			}
+		}
+
+		Ok((pictures, revisions))
+	}
+
+	/// Picks the archived revisions of the profiles that are new to this
+	/// device, grouped by the profiles
+	async fn new_profile_revisions(
+		&self, archived: Vec<ProfileRevision>,
+	) -> Result<HashMap<ProfileId, Vec<ProfileRevision>>, StorageBackend::Err> {
+		let mut known_ids: HashSet<ProfileId> =
+			self.store.profile_ids(usize::MAX).await?.into_iter().collect();
+		known_ids.extend(self.store.trashed_profile_ids(usize::MAX).await?);
+
+		let mut archived_by_profile: HashMap<ProfileId, Vec<ProfileRevision>> =
+			HashMap::new();
+		for revision in archived {
+			if !known_ids.contains(&revision.profile_id) {
+				archived_by_profile
+					.entry(revision.profile_id.clone())
+					.or_default()
+					.push(revision);
+			}
+		}
+		let mut revisions = HashMap::new();
+		for (profile_id, archived) in archived_by_profile {
+			// Deleted profiles might still have their revisions
+			if self.store.profile_revisions(profile_id.clone()).await?.is_empty() {
+				revisions.insert(profile_id, archived);
+			}
+		}
+
+		Ok(revisions)
+	}
+
+	/// Replaces the revisions of the profiles with the archived ones, returning
+	/// how many were restored
+	async fn restore_revisions(
+		&self, revisions: HashMap<ProfileId, Vec<ProfileRevision>>,
+	) -> Result<usize, StorageBackend::Err> {
+		let mut restored = 0;
+		for (profile_id, revisions) in revisions {
+			restored += revisions.len();
+			self.store.update_profile_revisions(profile_id, revisions).await?;
+		}
+
+		Ok(restored)
+	}
+
+	/// Rejects the account links, returning how many weren't rejected before
+	async fn restore_rejected_links(
+		&self, links: Vec<AccountLink>,
+	) -> Result<usize, StorageBackend::Err> {
+		let mut rejected = 0;
+		for link in links {
+			if !self.store.reject_link(link).await? {
+				rejected += 1;
+			}
+		}
+
+		Ok(rejected)
+	}
+
+	/// Restores the circles that don't exist and the members that existing
+	/// ones are missing, returning how many of each were added
+	async fn restore_circles(
+		&self, circles: Vec<ArchivedCircle>,
+	) -> Result<(usize, usize), StorageBackend::Err> {
+		let stored_circle_ids: HashSet<CircleId> =
+			self.store.circle_ids(usize::MAX).await?.into_iter().collect();
+		let mut circles_created = 0;
+		let mut circle_members = 0;
+		for ArchivedCircle { circle, members } in circles {
+			let circle_id = circle.id.clone();
+			let mut stored_members = if stored_circle_ids.contains(&circle_id) {
+				self.store.circle_members(circle_id.clone()).await?
+			} else {
+				self.store.update_circle(circle).await?;
+				circles_created += 1;
+				vec![]
+			};
+			let previous_len = stored_members.len();
+			for member in members {
+				if !stored_members.contains(&member) {
+					stored_members.push(member);
+				}
+			}
+			if stored_members.len() > previous_len {
+				circle_members += stored_members.len() - previous_len;
+				self.store.update_circle_members(circle_id, stored_members).await?;
+			}
+		}
+
+		Ok((circles_created, circle_members))
+	}
+
+	/// Restores the trashed profiles that aren't stored in any way, returning
+	/// how many were restored
+	async fn restore_trash(
+		&self, trash: Vec<TrashedProfile>,
+	) -> Result<usize, StorageBackend::Err> {
+		let mut stored_profile_ids: HashSet<ProfileId> =
+			self.store.profile_ids(usize::MAX).await?.into_iter().collect();
+		stored_profile_ids
+			.extend(self.store.trashed_profile_ids(usize::MAX).await?);
+		let mut trashed_profiles = 0;
+		for trashed in trash {
+			if stored_profile_ids.contains(&trashed.profile.sharing_id) {
+				continue;
+			}
+			self.store.update_trashed_profile(trashed).await?;
+			trashed_profiles += 1;
+		}
+
+		Ok(trashed_profiles)
+	}
+
+	/// Restores the pictures of the profiles that don't have one stored,
+	/// returning how many were restored
+	async fn restore_pictures(
+		&self, pictures: Vec<ArchivedPicture>,
+	) -> Result<usize, StorageBackend::Err> {
+		let mut restored = 0;
+		for picture in pictures {
+			// Not finding one is the only way to tell that it's missing
+			if self.store.profile_picture(picture.profile_id.clone()).await.is_ok() {
+				continue;
+			}
+			self.store.update_profile_picture(picture.into()).await?;
+			restored += 1;
+		}
+
+		Ok(restored)
+	}
+}
+
+/// Parses an archive, checking first that it's not from a newer version
+fn parse_archive(bytes: &[u8]) -> Result<Archive, String> {
+	let ArchiveHeader { version } = serde_json::from_slice(bytes)
+		.map_err(|e| format!("Not a valid archive: {e}"))?;
+	if version > ARCHIVE_VERSION {
+		return Err(format!(
+			"Archive version {version} is newer than the supported version \
+			 {ARCHIVE_VERSION}"
+		));
+	}
+
+	serde_json::from_slice(bytes).map_err(|e| format!("Not a valid archive: {e}"))
+}
+
+/// Decrypts the archived authentications if needed
+fn open_authentications(
+	archived: ArchivedAuthentications, key: Option<&AuthKey>,
+) -> Result<Vec<Authentication>, String> {
+	match archived {
+		ArchivedAuthentications::Excluded => Ok(vec![]),
+		ArchivedAuthentications::Plain { entries } => Ok(entries),
+		ArchivedAuthentications::Encrypted { key_params, entries } => {
+			let key = key.ok_or_else(|| {
+				"The archive's authentications are encrypted, but no key was given"
+					.to_string()
+			})?;
+			let cipher = AuthCipher::unlock(key, &key_params)
+				.map_err(|e| format!("Failed to unlock authentications: {e}"))?;
+			entries
+				.iter()
+				.map(|sealed| cipher.open(sealed))
+				.collect::<Result<_, _>>()
+				.map_err(|e| format!("Failed to decrypt authentications: {e}"))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use onlivfe_cache_store::OnlivfeCacheStorageBackend;
+
+	use super::*;
+
+	fn account_id(i: u32) -> PlatformAccountId {
+		let id = format!("usr_00000000-0000-4000-8000-{i:012x}");
+		PlatformAccountId::VRChat(id.parse().expect("the user ID to be valid"))
+	}
+
+	fn png() -> Vec<u8> {
+		let mut bytes = std::io::Cursor::new(vec![]);
+		image::RgbImage::new(2, 2)
+			.write_to(&mut bytes, image::ImageFormat::Png)
+			.expect("encoding a PNG to succeed");
+		bytes.into_inner()
+	}
+
+	/// Stores a bit of everything that's archived, returning the profile that
+	/// has a picture and is in a circle
+	async fn store_everything(
+		onlivfe: &Onlivfe<OnlivfeCacheStorageBackend>,
+	) -> Profile {
+		let store = &onlivfe.store;
+		let profile = Profile {
+			nick: Some("Archived".to_owned()),
+			tags: ["Friend".to_owned()].into(),
+			..Profile::new()
+		};
+		let profile_id = profile.sharing_id.clone();
+		store.update_profile(profile).await.expect("storing a profile");
+		store
+			.update_profile_account_ids(
+				profile_id.clone(),
+				vec![account_id(1), account_id(2)],
+			)
+			.await
+			.expect("linking accounts");
+		let profile = onlivfe
+			.set_profile_picture(profile_id.clone(), png())
+			.await
+			.expect("setting a picture");
+
+		let circle = Circle::new("Archived");
+		store.update_circle(circle.clone()).await.expect("storing a circle");
+		store
+			.update_circle_members(circle.id, vec![CircleMember::Profile(profile_id)])
+			.await
+			.expect("storing circle members");
+
+		let trashed = Profile::new();
+		store.update_profile(trashed.clone()).await.expect("storing a profile");
+		onlivfe.trash_profile(trashed.sharing_id).await.expect("trashing it");
+
+		profile
+	}
+
+	async fn export(onlivfe: &Onlivfe<OnlivfeCacheStorageBackend>) -> Vec<u8> {
+		onlivfe
+			.export_archive(ArchiveSecrets::Exclude)
+			.await
+			.expect("exporting an archive to succeed")
+	}
+
+	#[tokio::test]
+	async fn archive_round_trips() {
+		let source = Onlivfe::in_memory();
+		let profile = store_everything(&source).await;
+		let profile_id = profile.sharing_id.clone();
+		let archive = export(&source).await;
+
+		let target = Onlivfe::in_memory();
+		let counts = target
+			.import_archive(&archive, None)
+			.await
+			.expect("importing the archive to succeed");
+		assert_eq!(
+			ArchiveImportCounts { revisions: 0, ..counts },
+			ArchiveImportCounts {
+				profiles_created: 1,
+				mappings: 2,
+				circles_created: 1,
+				circle_members: 1,
+				trashed_profiles: 1,
+				pictures: 1,
+				..ArchiveImportCounts::default()
+			}
+		);
+
+		let (source, target) = (&source.store, &target.store);
+		assert_eq!(
+			target.profile(profile_id.clone()).await.expect("the profile"),
+			profile
+		);
+		assert_eq!(
+			target.profile_ids(usize::MAX).await.expect("the profiles"),
+			vec![profile_id.clone()]
+		);
+		assert_eq!(
+			target.profile_account_ids(profile_id.clone()).await.unwrap(),
+			source.profile_account_ids(profile_id.clone()).await.unwrap()
+		);
+		assert_eq!(
+			target.profile_picture(profile_id.clone()).await.unwrap(),
+			source.profile_picture(profile_id.clone()).await.unwrap()
+		);
+		assert_eq!(
+			target.profile_revisions(profile_id.clone()).await.unwrap(),
+			source.profile_revisions(profile_id).await.unwrap()
+		);
+		assert_eq!(
+			target.trashed_profiles(usize::MAX).await.unwrap(),
+			source.trashed_profiles(usize::MAX).await.unwrap()
+		);
+		let circles = source.circles(usize::MAX).await.unwrap();
+		assert_eq!(target.circles(usize::MAX).await.unwrap(), circles);
+		for circle in circles {
+			assert_eq!(
+				target.circle_members(circle.id.clone()).await.unwrap(),
+				source.circle_members(circle.id).await.unwrap()
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn importing_again_creates_no_duplicates() {
+		let onlivfe = Onlivfe::in_memory();
+		let profile = store_everything(&onlivfe).await;
+		let profile_id = profile.sharing_id.clone();
+		let archive = export(&onlivfe).await;
+
+		let counts = onlivfe
+			.import_archive(&archive, None)
+			.await
+			.expect("importing the archive to succeed");
+		assert_eq!(counts, ArchiveImportCounts::default());
+
+		let store = &onlivfe.store;
+		assert_eq!(
+			store.profile_ids(usize::MAX).await.unwrap(),
+			vec![profile_id.clone()]
+		);
+		assert_eq!(store.trashed_profile_ids(usize::MAX).await.unwrap().len(), 1);
+		assert_eq!(
+			store.profile_account_ids(profile_id.clone()).await.unwrap(),
+			vec![account_id(1), account_id(2)]
+		);
+		for account_id in [account_id(1), account_id(2)] {
+			assert_eq!(
+				store.account_profile_ids(account_id).await.unwrap(),
+				vec![profile_id.clone()]
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn import_merges_into_existing_profiles() {
+		let source = Onlivfe::in_memory();
+		let profile = store_everything(&source).await;
+		let profile_id = profile.sharing_id.clone();
+		let archive = export(&source).await;
+
+		// The same profile, with other details and accounts
+		let target = Onlivfe::in_memory();
+		let existing = Profile {
+			nick: Some("Existing".to_owned()),
+			tags: std::collections::BTreeSet::new(),
+			pfp_url: None,
+			..profile
+		};
+		target.store.update_profile(existing).await.expect("storing a profile");
+		target
+			.store
+			.update_profile_account_ids(
+				profile_id.clone(),
+				vec![account_id(3), account_id(2)],
+			)
+			.await
+			.expect("linking accounts");
+
+		let counts = target
+			.import_archive(&archive, None)
+			.await
+			.expect("importing the archive to succeed");
+		assert_eq!(counts.profiles_created, 0);
+		assert_eq!(counts.profiles_merged, 1);
+		assert_eq!(counts.mappings, 1);
+
+		let store = &target.store;
+		assert_eq!(
+			store.profile_ids(usize::MAX).await.unwrap(),
+			vec![profile_id.clone()]
+		);
+		let merged = store.profile(profile_id.clone()).await.unwrap();
+		assert_eq!(merged.nick.as_deref(), Some("Existing"));
+		assert_eq!(merged.tags, ["Friend".to_owned()].into());
+		assert_eq!(
+			store.profile_account_ids(profile_id.clone()).await.unwrap(),
+			vec![account_id(3), account_id(2), account_id(1)]
+		);
+		for account_id in [account_id(1), account_id(2), account_id(3)] {
+			assert_eq!(
+				store.account_profile_ids(account_id).await.unwrap(),
+				vec![profile_id.clone()]
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn pictures_are_archived_as_base64() {
+		let onlivfe = Onlivfe::in_memory();
+		let profile = store_everything(&onlivfe).await;
+		let archive = export(&onlivfe).await;
+
+		let mut json: serde_json::Value =
+			serde_json::from_slice(&archive).expect("the archive to be JSON");
+		let picture = &mut json["pictures"][0];
+		assert!(picture["bytes"].is_string() && picture["thumbnail"].is_string());
+
+		// The first archives had arrays of numbers, which still work
+		let bytes =
+			onlivfe.store.profile_picture(profile.sharing_id).await.unwrap();
+		picture["bytes"] = serde_json::json!(bytes.bytes);
+		picture["thumbnail"] = serde_json::json!(bytes.thumbnail);
+		let archive = serde_json::to_vec(&json).expect("serializing the archive");
+		let counts = Onlivfe::in_memory()
+			.import_archive(&archive, None)
+			.await
+			.expect("importing the archive to succeed");
+		assert_eq!(counts.pictures, 1);
+	}
+
+	#[tokio::test]
+	async fn missing_pictures_are_left_out() {
+		let onlivfe = Onlivfe::in_memory();
+		let profile = store_everything(&onlivfe).await;
+		onlivfe
+			.store
+			.delete_profile_picture(profile.sharing_id)
+			.await
+			.expect("deleting the picture");
+
+		let archive = parse_archive(&export(&onlivfe).await).expect("the archive");
+		assert!(archive.pictures.is_empty());
+		assert_eq!(archive.profiles.len(), 1);
+	}
+}
@@ -0,0 +1,172 @@
+//! Friend circles, grouping peeps across the platforms
+
+use std::collections::HashSet;
+
+use onlivfe::{
+	Circle,
+	CircleId,
+	CircleMember,
+	PlatformAccountId,
+	PlatformFriend,
+	storage::OnlivfeStore,
+};
+
+use crate::Onlivfe;
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Gets all of the circles, in the order that they're shown in
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the circles
+	pub async fn circles(&self) -> Result<Vec<Circle>, String> {
+		let mut circles = self.store.circles(usize::MAX).await.map_err(|e| {
+			error!("Failed to get circles from storage: {e:?}");
+			"Failed to retrieve circles".to_string()
+		})?;
+		circles.sort_by_key(|circle| circle.position);
+
+		Ok(circles)
+	}
+
+	/// Updates or creates a circle, returning if an existing one was updated
+	///
+	/// # Errors
+	///
+	/// If something failed with storing the circle
+	pub async fn update_circle(&self, circle: Circle) -> Result<bool, String> {
+		let id = circle.id.clone();
+		let existed = self.store.update_circle(circle).await.map_err(|e| {
+			error!("Failed to update circle {id:?}: {e:?}");
+			"Failed to update circle".to_string()
+		})?;
+
+		Ok(existed)
+	}
+
+	/// Deletes a circle, which doesn't affect its members
+	///
+	/// # Errors
+	///
+	/// If something failed with deleting the circle
+	pub async fn delete_circle(&self, circle_id: CircleId) -> Result<(), String> {
+		let id = circle_id.clone();
+		self.store.delete_circle(circle_id).await.map_err(|e| {
+			error!("Failed to delete circle {id:?}: {e:?}");
+			"Failed to delete circle".to_string()
+		})
+	}
+
+	/// Gets the members of a circle, in the order that they're shown in
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the members
+	pub async fn circle_members(
+		&self, circle_id: CircleId,
+	) -> Result<Vec<CircleMember>, String> {
+		let id = circle_id.clone();
+		self.store.circle_members(circle_id).await.map_err(|e| {
+			error!("Failed to get members of circle {id:?}: {e:?}");
+			"Failed to retrieve circle members".to_string()
+		})
+	}
+
+	/// Replaces the members of a circle, in the order that they're shown in
+	///
+	/// # Errors
+	///
+	/// If something failed with storing the members
+	pub async fn update_circle_members(
+		&self, circle_id: CircleId, members: Vec<CircleMember>,
+	) -> Result<(), String> {
+		let id = circle_id.clone();
+		self.store.update_circle_members(circle_id, members).await.map_err(|e| {
+			error!("Failed to update members of circle {id:?}: {e:?}");
+			"Failed to update circle members".to_string()
+		})
+	}
+
+	/// Gets the circles that a profile or an account is directly in
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the circles
+	pub async fn member_circles(
+		&self, member: CircleMember,
+	) -> Result<Vec<Circle>, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to get the circles of {member:?}: {e:?}");
+			"Failed to retrieve circles".to_string()
+		};
+
+		let circle_ids = self
+			.store
+			.member_circle_ids(member.clone())
+			.await
+			.map_err(storage_err)?;
+		let mut circles = Vec::with_capacity(circle_ids.len());
+		for circle_id in circle_ids {
+			circles.push(self.store.circle(circle_id).await.map_err(storage_err)?);
+		}
+		circles.sort_by_key(|circle| circle.position);
+
+		Ok(circles)
+	}
+
+	/// Gets the friends in a circle that are currently online on any platform.
+	///
+	/// Profiles in the circle include all of the accounts linked to them.
+	/// Friends whose status isn't known, like ones on platforms that only tell
+	/// it over a real-time connection, are left out.
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the members or friends
+	pub async fn online_circle_members(
+		&self, circle_id: CircleId,
+	) -> Result<Vec<PlatformFriend>, String> {
+		let id = circle_id.clone();
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to get online members of circle {id:?}: {e:?}");
+			"Failed to retrieve online circle members".to_string()
+		};
+
+		let members =
+			self.store.circle_members(circle_id).await.map_err(storage_err)?;
+		let mut account_ids: Vec<PlatformAccountId> = vec![];
+		for member in members {
+			match member {
+				CircleMember::Account(account_id) => account_ids.push(account_id),
+				CircleMember::Profile(profile_id) => account_ids.extend(
+					self
+						.store
+						.profile_account_ids(profile_id)
+						.await
+						.map_err(storage_err)?,
+				),
+			}
+		}
+
+		let friend_ids: HashSet<PlatformAccountId> = self
+			.store
+			.friend_ids(usize::MAX)
+			.await
+			.map_err(storage_err)?
+			.into_iter()
+			.collect();
+		let mut seen = HashSet::new();
+		let mut online = vec![];
+		for account_id in account_ids {
+			if !friend_ids.contains(&account_id) || !seen.insert(account_id.clone()) {
+				continue;
+			}
+			let friend = self.store.friend(account_id).await.map_err(storage_err)?;
+			if friend.is_online() == Some(true) {
+				online.push(friend);
+			}
+		}
+
+		Ok(online)
+	}
+}
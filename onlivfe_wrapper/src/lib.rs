@@ -34,12 +34,27 @@ use onlivfe::{
 	PlatformType,
 	Profile,
 	ProfileId,
+	TrashedProfile,
 };
 // TODO: Make re-exports needless
 pub use onlivfe_cache_store;
 pub use onlivfe_net;
 use strum::IntoEnumIterator;
 
+mod archive;
+pub use archive::{ARCHIVE_VERSION, ArchiveImportCounts, ArchiveSecrets};
+mod circles;
+mod merging;
+mod pictures;
+mod reauth;
+pub use reauth::{ReauthOptions, ReauthProgress};
+mod revisions;
+mod sessions;
+pub use sessions::{SessionEvent, SessionKeeper, SessionKeeperOptions};
+mod sharing;
+pub use sharing::ProfileImportCounts;
+mod suggestions;
+
 /// Initializes some static global parts of the core, setting up logging &
 /// loading env configs and such
 ///
@@ -72,7 +87,7 @@ pub fn init(
 		#[cfg(debug_assertions)]
 		dotenvy::dotenv()?;
 		tracing_subscriber::fmt()
-    	.pretty()
+			.pretty()
 			.with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
 			.try_init()?;
 		trace!("Initialized tracing");
@@ -94,8 +109,8 @@ const USER_AGENT: &str = concat!(
 pub enum ReauthError {
 	/// Retrieving the accounts from storage failed
 	Storage(String),
-	/// Some accounts failed to authenticate
-	FailedToAuthenticate(Vec<(String, PlatformAccountId)>)
+	/// Some accounts failed to authenticate or timed out
+	FailedToAuthenticate(Vec<(String, PlatformAccountId)>),
 }
 
 #[derive(Debug)]
@@ -115,16 +130,19 @@ pub struct Onlivfe<StorageBackend: onlivfe::storage::OnlivfeStore> {
 
 impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 	/// Creates a new onlivfe client
-	/// 
-	/// Remember to call `re_authenticate` after the creation to setup the API client properly!
+	///
+	/// Remember to call `re_authenticate` after the creation to setup the API
+	/// client properly, and `keep_sessions_alive` to keep the sessions from
+	/// expiring!
 	///
 	/// # Errors
 	///
-	/// If there were issues initializing API clients due to an invalid user agent
+	/// If there were issues initializing API clients, such as due to an invalid
+	/// user agent
 	pub fn new(store: StorageBackend) -> Result<Self, String> {
 		Ok(Self {
 			store: Arc::new(store),
-			api: Arc::new(onlivfe_net::OnlivfeApiClient::new(USER_AGENT.to_owned())),
+			api: Arc::new(onlivfe_net::OnlivfeApiClient::new(USER_AGENT.to_owned())?),
 		})
 	}
 
@@ -144,41 +162,21 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 		Ok(ids)
 	}
 
-	/// Re-authenticates the API based on the storage.
-	/// 
+	/// Re-authenticates the API based on the storage, see
+	/// [`Self::re_authenticate_with`] for concurrency & progress reporting.
+	///
 	/// Returns re-authenticated IDs
 	///
 	/// # Errors
 	///
-	/// If even getting the auths from storage failed, or if some account auths failed
+	/// If even getting the auths from storage failed, or if some account auths
+	/// failed
 	pub async fn re_authenticate(
-		&self, include_already_in_api: bool
+		&self, include_already_in_api: bool,
 	) -> Result<Vec<PlatformAccountId>, ReauthError> {
-		let mut to_authenticate = self.store.authentications().await.map_err(|e| ReauthError::Storage(e.to_string()))?;
-		let mut api_ids: Vec<PlatformAccountId> = vec![];
-		for platform in PlatformType::iter() {
-			api_ids.append(&mut self.api.authenticated_clients(platform).await);
-		}
-
-		if !include_already_in_api {
-			to_authenticate.retain(|v| !api_ids.contains(&v.id()));
-		}
-		let to_authenticate_ids = to_authenticate.iter().map(onlivfe::Authentication::id).collect();
-
-		let mut errors = vec![];
-		// TODO: Parallel
-		for auth in to_authenticate {
-			let id = auth.id();
-			if let Err(e) = self.restore_login(auth).await {
-				errors.push((e, id));
-			}
-		}
-
-		if errors.is_empty() {
-			Ok(to_authenticate_ids)
-		} else {
-			Err(ReauthError::FailedToAuthenticate(errors))
-		}
+		let options =
+			ReauthOptions { include_already_in_api, ..Default::default() };
+		self.re_authenticate_with(options, |_| {}).await
 	}
 
 	/// Logs in to a platform
@@ -256,7 +254,10 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 			< time::OffsetDateTime::now_utc() - time::Duration::MINUTE
 		{
 			let api = self.api.clone();
-			match api.friends(&get_as).await {
+			let result = api.friends(&get_as).await;
+			// The query might've renewed the session
+			self.store_renewed_authentications().await;
+			match result {
 				Ok(friends) => {
 					if let Some(friend_from_api) =
 						friends.iter().find(|friend| friend.id() == friend_id)
@@ -271,7 +272,7 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 				Err(e) => {
 					error!("Failed to fetch friends: {e}");
 				}
-			};
+			}
 		}
 
 		friend.ok_or_else(|| "Friend not found".to_owned())
@@ -300,7 +301,10 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 			< time::OffsetDateTime::now_utc() - time::Duration::MINUTE
 		{
 			let api = self.api.clone();
-			match api.friends(id).await {
+			let result = api.friends(id).await;
+			// The query might've renewed the session
+			self.store_renewed_authentications().await;
+			match result {
 				Ok(friends) => {
 					if let Err(e) = store.update_friends(friends).await {
 						error!("Failed to store fetched friend: {e}");
@@ -311,7 +315,7 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 					error!("Failed to fetch friends: {e}");
 					return Err(e);
 				}
-			};
+			}
 		}
 
 		Ok(friends)
@@ -340,7 +344,10 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 			< time::OffsetDateTime::now_utc() - time::Duration::MINUTE
 		{
 			let api = self.api.clone();
-			match api.platform_account(get_as, account_id).await {
+			let result = api.platform_account(get_as, account_id).await;
+			// The query might've renewed the session
+			self.store_renewed_authentications().await;
+			match result {
 				Ok(account) => {
 					let mut found_account = Some(account.clone());
 					std::mem::swap(&mut found_account, &mut platform_account);
@@ -351,7 +358,7 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 				Err(e) => {
 					error!("Failed to fetch platform account: {e}");
 				}
-			};
+			}
 		}
 
 		platform_account.ok_or_else(|| "Platform account not found".to_owned())
@@ -382,7 +389,10 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 	/// # Errors
 	///
 	/// If something failed with updating the profile
-	pub async fn update_profile(&self, profile: Profile) -> Result<bool, String> {
+	pub async fn update_profile(
+		&self, mut profile: Profile,
+	) -> Result<bool, String> {
+		profile.updated_at = time::OffsetDateTime::now_utc();
 		let id = profile.sharing_id.clone();
 		let was_new = self.store.update_profile(profile).await.map_err(|e| {
 			error!("Failed to update profile {id:?}: {e:?}");
@@ -392,6 +402,51 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 		Ok(was_new)
 	}
 
+	/// Gets the profiles that have a tag, ignoring ASCII case
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the profiles
+	pub async fn tagged_profiles(
+		&self, tag: &str,
+	) -> Result<Vec<Profile>, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to get profiles tagged {tag:?} from storage: {e:?}");
+			"Failed to retrieve tagged profiles".to_string()
+		};
+
+		let profile_ids = self
+			.store
+			.tagged_profile_ids(tag, usize::MAX)
+			.await
+			.map_err(storage_err)?;
+		let mut profiles = Vec::with_capacity(profile_ids.len());
+		for profile_id in profile_ids {
+			let profile =
+				self.store.profile(profile_id).await.map_err(storage_err)?;
+			profiles.push(profile);
+		}
+
+		Ok(profiles)
+	}
+
+	/// Gets all of the tags that the profiles have, for suggesting and
+	/// filtering by them
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the profiles
+	pub async fn profile_tags(&self) -> Result<Vec<String>, String> {
+		let profiles = self.store.profiles(usize::MAX).await.map_err(|e| {
+			error!("Failed to get profiles from storage: {e:?}");
+			"Failed to retrieve profiles".to_string()
+		})?;
+		let tags: std::collections::BTreeSet<String> =
+			profiles.into_iter().flat_map(|profile| profile.tags).collect();
+
+		Ok(tags.into_iter().collect())
+	}
+
 	/// Gets the profile's mapped accounts
 	///
 	/// # Errors
@@ -470,7 +525,11 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 		Ok(())
 	}
 
-	/// Removes a profile fully.
+	/// Removes a profile fully, along with its account mappings, circle
+	/// memberships, picture, and revisions.
+	///
+	/// See [`trash_profile`](Self::trash_profile) for removing it in a way
+	/// that can be undone.
 	///
 	/// # Errors
 	///
@@ -478,11 +537,113 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 	pub async fn delete_profile(
 		&self, profile_id: ProfileId,
 	) -> Result<(), String> {
-		let id = profile_id.clone();
-		self.store.delete_profile(profile_id).await.map_err(|e| {
-			error!("Failed to delete profile {id:?}: {e:?}");
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to delete profile {profile_id:?}: {e:?}");
 			"Failed to update profile".to_string()
-		})?;
+		};
+
+		self.store.delete_profile(profile_id.clone()).await.map_err(storage_err)?;
+		// Unless a copy of the profile in the trash still needs it
+		let trashed_ids =
+			self.store.trashed_profile_ids(usize::MAX).await.map_err(storage_err)?;
+		if !trashed_ids.contains(&profile_id) {
+			self
+				.store
+				.delete_profile_picture(profile_id.clone())
+				.await
+				.map_err(storage_err)?;
+			self
+				.store
+				.delete_profile_revisions(profile_id.clone())
+				.await
+				.map_err(storage_err)?;
+		}
+
+		Ok(())
+	}
+
+	/// Moves a profile to the trash, from where it can be restored with
+	/// [`restore_profile`](Self::restore_profile)
+	///
+	/// # Errors
+	///
+	/// If something failed with moving the profile
+	pub async fn trash_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<TrashedProfile, String> {
+		let id = profile_id.clone();
+		self.store.trash_profile(profile_id).await.map_err(|e| {
+			error!("Failed to trash profile {id:?}: {e:?}");
+			"Failed to move profile to the trash".to_string()
+		})
+	}
+
+	/// Gets the profiles in the trash, most recently trashed first
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the profiles
+	pub async fn trashed_profiles(&self) -> Result<Vec<TrashedProfile>, String> {
+		let mut trashed =
+			self.store.trashed_profiles(usize::MAX).await.map_err(|e| {
+				error!("Failed to get trashed profiles from storage: {e:?}");
+				"Failed to retrieve trashed profiles".to_string()
+			})?;
+		trashed.sort_by_key(|trashed| std::cmp::Reverse(trashed.trashed_at));
+
+		Ok(trashed)
+	}
+
+	/// Restores a profile from the trash, along with its account mappings and
+	/// circle memberships
+	///
+	/// # Errors
+	///
+	/// If the profile isn't in the trash, or something failed with restoring it
+	pub async fn restore_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<Profile, String> {
+		let id = profile_id.clone();
+		self.store.restore_profile(profile_id).await.map_err(|e| {
+			error!("Failed to restore profile {id:?}: {e:?}");
+			"Failed to restore profile".to_string()
+		})
+	}
+
+	/// Removes a profile from the trash for good, along with its picture and
+	/// revisions
+	///
+	/// # Errors
+	///
+	/// If something failed with removing the profile
+	pub async fn delete_trashed_profile(
+		&self, profile_id: ProfileId,
+	) -> Result<(), String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to delete trashed profile {profile_id:?}: {e:?}");
+			"Failed to delete profile from the trash".to_string()
+		};
+
+		self
+			.store
+			.delete_trashed_profile(profile_id.clone())
+			.await
+			.map_err(storage_err)?;
+		// Unless the profile was created again with the same ID, like by importing
+		let profile_ids =
+			self.store.profile_ids(usize::MAX).await.map_err(storage_err)?;
+		if !profile_ids.contains(&profile_id) {
+			self
+				.store
+				.delete_profile_picture(profile_id.clone())
+				.await
+				.map_err(storage_err)?;
+			self
+				.store
+				.delete_profile_revisions(profile_id.clone())
+				.await
+				.map_err(storage_err)?;
+		}
 
 		Ok(())
 	}
@@ -509,7 +670,10 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 			< time::OffsetDateTime::now_utc() - time::Duration::MINUTE
 		{
 			let api = self.api.clone();
-			match api.instance(get_as, instance_id).await {
+			let result = api.instance(get_as, instance_id).await;
+			// The query might've renewed the session
+			self.store_renewed_authentications().await;
+			match result {
 				Ok(instance_from_api) => {
 					let mut found_instance = Some(instance_from_api.clone());
 					std::mem::swap(&mut found_instance, &mut instance);
@@ -520,9 +684,21 @@ impl<StorageBackend: onlivfe::storage::OnlivfeStore> Onlivfe<StorageBackend> {
 				Err(e) => {
 					error!("Failed to fetch instance: {e}");
 				}
-			};
+			}
 		}
 
 		instance.ok_or_else(|| "Instance not found".to_owned())
 	}
 }
+
+#[cfg(test)]
+impl Onlivfe<onlivfe_cache_store::OnlivfeCacheStorageBackend> {
+	/// Creates a client with an empty store that's only kept in memory
+	pub(crate) fn in_memory() -> Self {
+		let store = onlivfe_cache_store::OnlivfeCacheStorageBackend::builder()
+			.in_memory()
+			.build()
+			.expect("the storage backend to be created");
+		Self::new(store).expect("the client to be created")
+	}
+}
@@ -0,0 +1,161 @@
+//! Merging profiles of the same peep, and splitting profiles of multiple peeps
+
+use std::collections::HashSet;
+
+use onlivfe::{
+	CircleMember,
+	LocalPfpUri,
+	PlatformAccountId,
+	Profile,
+	ProfileId,
+	storage::{OnlivfeStore, StoreBatch},
+};
+use time::OffsetDateTime;
+
+use crate::Onlivfe;
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Merges a profile into another one of the same peep, returning the
+	/// merged profile.
+	///
+	/// The details are combined with [`Profile::merge`], the accounts,
+	/// circle memberships, and the picture if it's used, of the merged profile
+	/// are moved over, and it's deleted, all in one batch besides the picture.
+	///
+	/// # Errors
+	///
+	/// If the profiles are the same, or something failed with retrieving or
+	/// storing them
+	pub async fn merge_profiles(
+		&self, into: ProfileId, from: ProfileId,
+	) -> Result<Profile, String> {
+		if into == from {
+			return Err("Can't merge a profile into itself".to_string());
+		}
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to merge profile {from:?} into {into:?}: {e:?}");
+			"Failed to merge profiles".to_string()
+		};
+
+		let mut profile =
+			self.store.profile(into.clone()).await.map_err(storage_err)?;
+		let merged = self.store.profile(from.clone()).await.map_err(storage_err)?;
+		profile.merge(&merged);
+		profile.updated_at = OffsetDateTime::now_utc();
+		if profile
+			.pfp_url
+			.as_deref()
+			.and_then(|url| url.parse::<LocalPfpUri>().ok())
+			.is_some_and(|uri| uri.profile_id == from)
+		{
+			let mut picture =
+				self.store.profile_picture(from.clone()).await.map_err(storage_err)?;
+			picture.profile_id = into.clone();
+			profile.pfp_url = Some(picture.uri().to_string());
+			self.store.update_profile_picture(picture).await.map_err(storage_err)?;
+		}
+
+		let mut account_ids = self
+			.store
+			.profile_account_ids(into.clone())
+			.await
+			.map_err(storage_err)?;
+		for account_id in
+			self.store.profile_account_ids(from.clone()).await.map_err(storage_err)?
+		{
+			if !account_ids.contains(&account_id) {
+				account_ids.push(account_id);
+			}
+		}
+
+		let circle_ids = self
+			.store
+			.member_circle_ids(CircleMember::Profile(from.clone()))
+			.await
+			.map_err(storage_err)?;
+		let mut circle_members = Vec::with_capacity(circle_ids.len());
+		for circle_id in circle_ids {
+			let mut members = self
+				.store
+				.circle_members(circle_id.clone())
+				.await
+				.map_err(storage_err)?;
+			for member in &mut members {
+				if *member == CircleMember::Profile(from.clone()) {
+					*member = CircleMember::Profile(into.clone());
+				}
+			}
+			// Storing keeps the first position if both were members
+			circle_members.push((circle_id, members));
+		}
+
+		// All at once, so that nothing is lost if storing fails halfway through
+		let batch = StoreBatch {
+			profiles: vec![profile.clone()],
+			profile_account_ids: vec![(into.clone(), account_ids)],
+			circle_members,
+			deleted_profiles: vec![from.clone()],
+			..StoreBatch::default()
+		};
+		self.store.apply_batch(batch).await.map_err(storage_err)?;
+		self
+			.store
+			.delete_profile_picture(from.clone())
+			.await
+			.map_err(storage_err)?;
+
+		Ok(profile)
+	}
+
+	/// Moves accounts from a profile to a new one, for when a profile turns out
+	/// to be of multiple peeps, returning the new profile.
+	///
+	/// The new profile starts out without any details, and the circle
+	/// memberships of the original profile stay with it.
+	///
+	/// # Errors
+	///
+	/// If some of the accounts aren't linked to the profile, or something
+	/// failed with storing the profiles
+	pub async fn split_profile(
+		&self, profile_id: ProfileId, mut account_ids: Vec<PlatformAccountId>,
+	) -> Result<Profile, String> {
+		let mut seen = HashSet::new();
+		account_ids.retain(|account_id| seen.insert(account_id.clone()));
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to split profile {profile_id:?}: {e:?}");
+			"Failed to split profile".to_string()
+		};
+
+		let linked = self
+			.store
+			.profile_account_ids(profile_id.clone())
+			.await
+			.map_err(storage_err)?;
+		if let Some(account_id) =
+			account_ids.iter().find(|account_id| !linked.contains(account_id))
+		{
+			return Err(format!(
+				"Account {} is not linked to the profile",
+				account_id.id_as_string()
+			));
+		}
+
+		let profile = Profile::new();
+		let remaining = linked
+			.into_iter()
+			.filter(|account_id| !account_ids.contains(account_id))
+			.collect();
+		let batch = StoreBatch {
+			profiles: vec![profile.clone()],
+			profile_account_ids: vec![
+				(profile.sharing_id.clone(), account_ids),
+				(profile_id.clone(), remaining),
+			],
+			..StoreBatch::default()
+		};
+		self.store.apply_batch(batch).await.map_err(storage_err)?;
+
+		Ok(profile)
+	}
+}
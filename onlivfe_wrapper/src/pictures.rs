@@ -0,0 +1,160 @@
+//! Profile pictures that are stored locally, see [`LocalPfpUri`]
+
+use onlivfe::{
+	LocalPfpUri,
+	PlatformAccountId,
+	Profile,
+	ProfileId,
+	ProfilePicture,
+	THUMBNAIL_CONTENT_TYPE,
+	storage::OnlivfeStore,
+};
+use time::OffsetDateTime;
+
+use crate::Onlivfe;
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Stores a picture for a profile along with a thumbnail of it, and points
+	/// the profile's picture to it, returning the updated profile
+	///
+	/// # Errors
+	///
+	/// If the image isn't valid, or something failed with storing it
+	pub async fn set_profile_picture(
+		&self, profile_id: ProfileId, bytes: Vec<u8>,
+	) -> Result<Profile, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to set picture of profile {profile_id:?}: {e:?}");
+			"Failed to store profile picture".to_string()
+		};
+
+		let mut profile =
+			self.store.profile(profile_id.clone()).await.map_err(storage_err)?;
+		let picture = ProfilePicture::new(profile_id.clone(), bytes)
+			.map_err(|e| e.to_string())?;
+		profile.pfp_url = Some(picture.uri().to_string());
+		profile.updated_at = OffsetDateTime::now_utc();
+
+		// Pictures can't be stored in batches, so the picture is stored before
+		// the profile points to it, and put back as it was if the profile can't
+		// be stored, so that the profile never points to a missing picture
+		let previous = self.store.profile_picture(profile_id.clone()).await.ok();
+		self.store.update_profile_picture(picture).await.map_err(storage_err)?;
+		if let Err(e) = self.store.update_profile(profile.clone()).await {
+			let undone = match previous {
+				Some(previous) => {
+					self.store.update_profile_picture(previous).await.map(|_| ())
+				}
+				None => self.store.delete_profile_picture(profile_id.clone()).await,
+			};
+			if let Err(undo_e) = undone {
+				error!("Failed to undo picture of profile {profile_id:?}: {undo_e:?}");
+			}
+			return Err(storage_err(e));
+		}
+
+		Ok(profile)
+	}
+
+	/// Removes the locally stored picture of a profile, also clearing the
+	/// profile's picture if it pointed to it
+	///
+	/// # Errors
+	///
+	/// If something failed with removing the picture
+	pub async fn remove_profile_picture(
+		&self, profile_id: ProfileId,
+	) -> Result<(), String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to remove picture of profile {profile_id:?}: {e:?}");
+			"Failed to remove profile picture".to_string()
+		};
+
+		let mut profile =
+			self.store.profile(profile_id.clone()).await.map_err(storage_err)?;
+		if profile
+			.pfp_url
+			.as_deref()
+			.and_then(|url| url.parse::<LocalPfpUri>().ok())
+			.is_some_and(|uri| uri.profile_id == profile_id)
+		{
+			profile.pfp_url = None;
+			profile.updated_at = OffsetDateTime::now_utc();
+			self.store.update_profile(profile).await.map_err(storage_err)?;
+		}
+		self
+			.store
+			.delete_profile_picture(profile_id.clone())
+			.await
+			.map_err(storage_err)
+	}
+
+	/// Gets the content type and the bytes of the image that a
+	/// [`LocalPfpUri`] points to, for displaying it
+	///
+	/// # Errors
+	///
+	/// If the URI isn't a local one, or the picture isn't found
+	pub async fn local_picture(
+		&self, uri: &str,
+	) -> Result<(String, Vec<u8>), String> {
+		let uri: LocalPfpUri = uri.parse()?;
+		let profile_id = uri.profile_id.clone();
+		let picture =
+			self.store.profile_picture(profile_id).await.map_err(|e| {
+				error!("Failed to get picture {uri}: {e:?}");
+				"Failed to retrieve profile picture".to_string()
+			})?;
+
+		if uri.thumbnail {
+			Ok((THUMBNAIL_CONTENT_TYPE.to_owned(), picture.thumbnail))
+		} else {
+			Ok((picture.content_type, picture.bytes))
+		}
+	}
+
+	/// Copies the thumbnail of the current avatar of an account that's linked
+	/// to a profile into the profile's picture, returning the updated profile
+	///
+	/// # Errors
+	///
+	/// If the account isn't linked to the profile, it doesn't have an avatar
+	/// thumbnail, or something failed with downloading or storing it
+	pub async fn import_account_avatar_picture(
+		&self, profile_id: ProfileId, account_id: PlatformAccountId,
+	) -> Result<Profile, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!(
+				"Failed to import avatar of {account_id:?} into {profile_id:?}: {e:?}"
+			);
+			"Failed to import avatar picture".to_string()
+		};
+
+		let linked = self
+			.store
+			.profile_account_ids(profile_id.clone())
+			.await
+			.map_err(storage_err)?;
+		if !linked.contains(&account_id) {
+			return Err("Account is not linked to the profile".to_string());
+		}
+
+		// Either of them might be known, depending on if it's a friend
+		let url = match self.store.account(account_id.clone()).await {
+			Ok(account) => account.avatar_thumbnail_url().map(str::to_owned),
+			Err(_) => self
+				.store
+				.friend(account_id.clone())
+				.await
+				.map_err(storage_err)?
+				.avatar_thumbnail_url()
+				.map(str::to_owned),
+		};
+		let Some(url) = url else {
+			return Err("Account has no avatar thumbnail".to_string());
+		};
+
+		let bytes = self.api.download_image(&url).await?;
+		self.set_profile_picture(profile_id, bytes).await
+	}
+}
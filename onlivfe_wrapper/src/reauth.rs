@@ -0,0 +1,227 @@
+//! Re-authenticating multiple stored accounts concurrently
+
+use std::time::Duration;
+
+use futures::prelude::*;
+use onlivfe::{Authentication, PlatformAccountId, storage::OnlivfeStore};
+
+use crate::{Onlivfe, ReauthError};
+
+/// How re-authenticating multiple accounts is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReauthOptions {
+	/// If accounts that the API already has clients for should be
+	/// re-authenticated too
+	pub include_already_in_api: bool,
+	/// How many accounts are re-authenticated at the same time, at least one
+	pub concurrency: usize,
+	/// How long a single account's re-authentication can take before it's
+	/// given up on
+	pub timeout: Duration,
+}
+
+impl Default for ReauthOptions {
+	fn default() -> Self {
+		Self {
+			include_already_in_api: false,
+			concurrency: 4,
+			timeout: Duration::from_secs(30),
+		}
+	}
+}
+
+/// The result of re-authenticating a single account, reported as soon as it
+/// finishes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReauthProgress {
+	/// The account that finished
+	pub id: PlatformAccountId,
+	/// If the account was re-authenticated, or why it wasn't
+	pub result: Result<(), String>,
+	/// How many accounts have finished so far, including this one
+	pub finished: usize,
+	/// How many accounts are being re-authenticated in total
+	pub total: usize,
+}
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Re-authenticates the API based on the storage, multiple accounts at a
+	/// time, calling `on_progress` as each account finishes.
+	///
+	/// Returns re-authenticated IDs
+	///
+	/// # Errors
+	///
+	/// If even getting the auths from storage failed, or if some account auths
+	/// failed or timed out
+	pub async fn re_authenticate_with(
+		&self, options: ReauthOptions,
+		on_progress: impl FnMut(ReauthProgress) + Send,
+	) -> Result<Vec<PlatformAccountId>, ReauthError> {
+		let to_authenticate = self.reauth_candidates(options).await?;
+
+		re_authenticate_each(
+			to_authenticate,
+			options,
+			|auth| self.restore_login(auth),
+			on_progress,
+		)
+		.await
+	}
+
+	/// The stored authentications that should be re-authenticated
+	async fn reauth_candidates(
+		&self, options: ReauthOptions,
+	) -> Result<Vec<Authentication>, ReauthError> {
+		let mut to_authenticate = self
+			.store
+			.authentications()
+			.await
+			.map_err(|e| ReauthError::Storage(e.to_string()))?;
+
+		if !options.include_already_in_api {
+			let api_ids = self.authenticated_accounts().await.unwrap_or_default();
+			to_authenticate.retain(|v| !api_ids.contains(&v.id()));
+		}
+
+		Ok(to_authenticate)
+	}
+}
+
+/// Runs `login` for the authentications, multiple at a time, calling
+/// `on_progress` as each one finishes.
+///
+/// Returns the IDs that were logged in to.
+async fn re_authenticate_each<Login, LoginFuture>(
+	to_authenticate: Vec<Authentication>, options: ReauthOptions, login: Login,
+	mut on_progress: impl FnMut(ReauthProgress) + Send,
+) -> Result<Vec<PlatformAccountId>, ReauthError>
+where
+	Login: Fn(Authentication) -> LoginFuture + Send + Sync,
+	LoginFuture: Future<Output = Result<(), String>> + Send,
+{
+	let total = to_authenticate.len();
+
+	let mut attempts = stream::iter(to_authenticate)
+		.map(|auth| {
+			let id = auth.id();
+			let attempt = login(auth);
+			async move {
+				let result = tokio::time::timeout(options.timeout, attempt)
+					.await
+					.unwrap_or_else(|_| {
+						warn!("Re-authenticating {id:?} timed out");
+						Err(format!(
+							"Timed out after {} seconds",
+							options.timeout.as_secs_f32()
+						))
+					});
+				(id, result)
+			}
+		})
+		.buffer_unordered(options.concurrency.max(1));
+
+	let mut authenticated = vec![];
+	let mut errors = vec![];
+	let mut finished = 0;
+	while let Some((id, result)) = attempts.next().await {
+		finished += 1;
+		match &result {
+			Ok(()) => authenticated.push(id.clone()),
+			Err(e) => errors.push((e.clone(), id.clone())),
+		}
+		on_progress(ReauthProgress { id, result, finished, total });
+	}
+
+	if errors.is_empty() {
+		Ok(authenticated)
+	} else {
+		Err(ReauthError::FailedToAuthenticate(errors))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use onlivfe::PlatformDataAndMetadata;
+
+	use super::*;
+
+	fn authentication(i: u32) -> Authentication {
+		let id = format!("usr_00000000-0000-4000-8000-{i:012x}");
+		Authentication::VRChat(PlatformDataAndMetadata::new_now(
+			Box::new(vrc::query::Authentication {
+				token: format!("authcookie_onlivfe-reauth-{i}"),
+				second_factor_token: None,
+			}),
+			id.parse().expect("the user ID to be valid"),
+		))
+	}
+
+	/// Succeeds for the first account, fails for the second, and takes way too
+	/// long for the third
+	async fn login(auth: Authentication) -> Result<(), String> {
+		if auth.id() == authentication(2).id() {
+			return Err("Rejected".to_owned());
+		}
+		if auth.id() == authentication(3).id() {
+			tokio::time::sleep(Duration::from_hours(1)).await;
+		}
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn failures_and_timeouts_are_reported_along_with_successes() {
+		let options = ReauthOptions {
+			timeout: Duration::from_millis(50),
+			..Default::default()
+		};
+		let mut progress = vec![];
+
+		let result = re_authenticate_each(
+			(1..=3).map(authentication).collect(),
+			options,
+			login,
+			|p| progress.push(p),
+		)
+		.await;
+
+		let Err(ReauthError::FailedToAuthenticate(errors)) = result else {
+			panic!("Expected some accounts to fail, got {result:?}");
+		};
+		assert_eq!(
+			errors,
+			vec![
+				("Rejected".to_owned(), authentication(2).id()),
+				("Timed out after 0.05 seconds".to_owned(), authentication(3).id()),
+			]
+		);
+		assert_eq!(
+			progress.iter().map(|p| p.finished).collect::<Vec<_>>(),
+			vec![1, 2, 3]
+		);
+		assert!(progress.iter().all(|p| p.total == 3));
+		let succeeded = progress
+			.iter()
+			.find(|p| p.id == authentication(1).id())
+			.expect("progress of the succeeding account");
+		assert_eq!(succeeded.result, Ok(()));
+		// The timed out account is the slowest to finish
+		assert_eq!(progress[2].id, authentication(3).id());
+	}
+
+	#[tokio::test]
+	async fn successes_are_returned_when_nothing_fails() {
+		let result = re_authenticate_each(
+			vec![authentication(1), authentication(4)],
+			ReauthOptions { concurrency: 0, ..Default::default() },
+			login,
+			|_| {},
+		)
+		.await;
+
+		assert_eq!(
+			result.expect("re-authenticating to succeed"),
+			vec![authentication(1).id(), authentication(4).id()]
+		);
+	}
+}
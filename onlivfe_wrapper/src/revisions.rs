@@ -0,0 +1,83 @@
+//! The history of the changes to profiles, and rolling them back
+
+use onlivfe::{
+	Profile,
+	ProfileId,
+	ProfileRevision,
+	ProfileState,
+	profile_at_revision,
+	storage::{OnlivfeStore, StoreBatch},
+};
+use time::OffsetDateTime;
+
+use crate::Onlivfe;
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Gets the recorded changes to a profile and its accounts, oldest first
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the revisions
+	pub async fn profile_revisions(
+		&self, profile_id: ProfileId,
+	) -> Result<Vec<ProfileRevision>, String> {
+		let id = profile_id.clone();
+		self.store.profile_revisions(profile_id).await.map_err(|e| {
+			error!("Failed to get revisions of profile {id:?}: {e:?}");
+			"Failed to retrieve profile revisions".to_string()
+		})
+	}
+
+	/// Rolls a profile and its accounts back to how they were right after a
+	/// revision, returning the rolled back profile.
+	///
+	/// Also brings back deleted profiles. The rollback is recorded as new
+	/// revisions, so that it can be rolled back as well.
+	///
+	/// # Errors
+	///
+	/// If the profile didn't exist at the revision, or something failed with
+	/// retrieving the revisions or storing the profile
+	pub async fn rollback_profile(
+		&self, profile_id: ProfileId, number: u64,
+	) -> Result<Profile, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to roll back profile {profile_id:?} to {number}: {e:?}");
+			"Failed to roll back profile".to_string()
+		};
+
+		let revisions = self
+			.store
+			.profile_revisions(profile_id.clone())
+			.await
+			.map_err(storage_err)?;
+		if !revisions.iter().any(|revision| revision.number == number) {
+			return Err(format!("Profile has no revision {number}"));
+		}
+		let current = ProfileState {
+			// Deleted profiles aren't found
+			profile: self.store.profile(profile_id.clone()).await.ok(),
+			account_ids: self
+				.store
+				.profile_account_ids(profile_id.clone())
+				.await
+				.map_err(storage_err)?,
+		};
+		let ProfileState { profile: Some(mut profile), account_ids } =
+			profile_at_revision(current, &revisions, number)
+		else {
+			return Err(format!("Profile didn't exist at revision {number}"));
+		};
+
+		profile.updated_at = OffsetDateTime::now_utc();
+		// Together, so that the profile isn't left half rolled back
+		let batch = StoreBatch {
+			profiles: vec![profile.clone()],
+			profile_account_ids: vec![(profile_id.clone(), account_ids)],
+			..StoreBatch::default()
+		};
+		self.store.apply_batch(batch).await.map_err(storage_err)?;
+
+		Ok(profile)
+	}
+}
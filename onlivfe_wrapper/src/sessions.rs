@@ -0,0 +1,310 @@
+//! Keeping the sessions of the stored accounts alive in the background
+
+use std::{collections::HashMap, time::Duration};
+
+use onlivfe::{
+	Authentication,
+	PlatformAccountId,
+	PlatformType,
+	storage::OnlivfeStore,
+};
+use onlivfe_net::QueryError;
+use time::OffsetDateTime;
+
+use crate::Onlivfe;
+
+/// How often and how persistently sessions are refreshed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionKeeperOptions {
+	/// How often the sessions are checked for needing a refresh
+	pub check_interval: Duration,
+	/// How old a Resonite session can get before it's extended
+	pub resonite_extend_after: Duration,
+	/// How old a `VRChat` session can get before its cookies are re-validated
+	pub vrchat_validate_after: Duration,
+	/// How old a `ChilloutVR` session can get before it's re-validated
+	pub chilloutvr_validate_after: Duration,
+	/// How long a single refresh can take before it's counted as failed
+	pub timeout: Duration,
+	/// How many refreshes in a row the platform can reject the session before
+	/// it's considered to be expired
+	///
+	/// Refreshes failing otherwise, such as due to network errors or timeouts,
+	/// aren't counted, as they don't mean that the session has expired.
+	pub max_failures: u32,
+}
+
+impl Default for SessionKeeperOptions {
+	fn default() -> Self {
+		Self {
+			check_interval: Duration::from_mins(5),
+			resonite_extend_after: Duration::from_hours(1),
+			vrchat_validate_after: Duration::from_hours(6),
+			chilloutvr_validate_after: Duration::from_hours(24),
+			timeout: Duration::from_secs(30),
+			max_failures: 3,
+		}
+	}
+}
+
+impl SessionKeeperOptions {
+	/// How old a session of the platform can get before it's refreshed
+	#[must_use]
+	pub const fn refresh_after(&self, platform: PlatformType) -> Duration {
+		match platform {
+			PlatformType::VRChat => self.vrchat_validate_after,
+			PlatformType::ChilloutVR => self.chilloutvr_validate_after,
+			PlatformType::Resonite => self.resonite_extend_after,
+		}
+	}
+}
+
+/// Something that happened with keeping a session alive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+	/// The session was refreshed, and the refreshed authentication stored
+	Refreshed(PlatformAccountId),
+	/// Refreshing the session failed, but it'll be tried again
+	Failed {
+		/// The account of the session
+		id: PlatformAccountId,
+		/// Why the refresh failed
+		error: String,
+		/// How many refreshes in a row the platform has rejected the session,
+		/// which stays the same if it failed for another reason
+		failures: u32,
+	},
+	/// The session has expired and can't be refreshed, so the account needs to
+	/// be logged in to again
+	Expired {
+		/// The account of the session
+		id: PlatformAccountId,
+		/// Why the last refresh failed
+		error: String,
+	},
+}
+
+/// Keeps track of the sessions' refreshes between checks
+#[derive(Debug, Clone, Default)]
+pub struct SessionKeeper {
+	/// How the sessions are refreshed
+	pub options: SessionKeeperOptions,
+	/// The failed refreshes in a row, along with the authentication's update
+	/// time, so that a new login resets them
+	failures: HashMap<PlatformAccountId, (OffsetDateTime, u32)>,
+}
+
+impl SessionKeeper {
+	/// Creates a session keeper that hasn't seen any failures yet
+	#[must_use]
+	pub fn new(options: SessionKeeperOptions) -> Self {
+		Self { options, failures: HashMap::new() }
+	}
+
+	/// How many refreshes of the authentication have failed in a row
+	fn failures(&self, auth: &Authentication) -> u32 {
+		self
+			.failures
+			.get(&auth.id())
+			.filter(|(updated_at, _)| *updated_at == auth.metadata().updated_at)
+			.map_or(0, |(_, failures)| *failures)
+	}
+
+	/// If the authentication's session should be refreshed now
+	fn is_due(&self, auth: &Authentication, now: OffsetDateTime) -> bool {
+		if self.failures(auth) >= self.options.max_failures {
+			return false;
+		}
+		let age = now - auth.metadata().updated_at;
+		age >= self.options.refresh_after(auth.platform())
+	}
+}
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Refreshes the sessions that are old enough to need it, writing the
+	/// refreshed authentications back to the store
+	///
+	/// Sessions that have failed too many times in a row are reported as
+	/// expired once, and then left alone until they're logged in to again.
+	/// The sessions that were renewed mid-use by the API are stored too.
+	///
+	/// # Errors
+	///
+	/// If getting the authentications from storage failed
+	pub async fn refresh_sessions(
+		&self, keeper: &mut SessionKeeper,
+	) -> Result<Vec<SessionEvent>, String> {
+		self.store_renewed_authentications().await;
+
+		let auths = self.store.authentications().await.map_err(|e| {
+			error!("Failed to get authentications for refreshing: {e:?}");
+			"Failed to retrieve authentications".to_string()
+		})?;
+		// Forget the failures of accounts that have been logged out of
+		keeper.failures.retain(|id, _| auths.iter().any(|auth| &auth.id() == id));
+
+		let now = OffsetDateTime::now_utc();
+		let mut events = vec![];
+		let due: Vec<Authentication> =
+			auths.into_iter().filter(|auth| keeper.is_due(auth, now)).collect();
+		for auth in due {
+			let id = auth.id();
+			let updated_at = auth.metadata().updated_at;
+			let failures = keeper.failures(&auth);
+
+			let result = tokio::time::timeout(
+				keeper.options.timeout,
+				self.api.refresh_session(auth),
+			)
+			.await
+			.unwrap_or_else(|_| {
+				Err(QueryError::Other("Session refresh timed out".to_owned()))
+			});
+			let result = match result {
+				Ok(auth) => self.store.update_authentication(auth).await.map_err(|e| {
+					error!("Failed to update refreshed authentication: {e}");
+					QueryError::Other(e.to_string())
+				}),
+				Err(e) => Err(e),
+			};
+
+			match result {
+				Ok(_) => {
+					keeper.failures.remove(&id);
+					events.push(SessionEvent::Refreshed(id));
+				}
+				Err(error) => {
+					// Otherwise the session might still be fine, for example if the
+					// platform was just unreachable
+					let failures = if matches!(error, QueryError::Unauthorized(_)) {
+						failures + 1
+					} else {
+						failures
+					};
+					let error = String::from(error);
+					warn!(
+						"Refreshing session of {id:?} failed {failures} times: {error}"
+					);
+					keeper.failures.insert(id.clone(), (updated_at, failures));
+					if failures >= keeper.options.max_failures {
+						events.push(SessionEvent::Expired { id, error });
+					} else {
+						events.push(SessionEvent::Failed { id, error, failures });
+					}
+				}
+			}
+		}
+
+		Ok(events)
+	}
+
+	/// Stores the authentications that the API renewed after their sessions
+	/// expired mid-use
+	pub(crate) async fn store_renewed_authentications(&self) {
+		for auth in self.api.take_renewed_authentications().await {
+			if let Err(e) = self.store.update_authentication(auth).await {
+				error!("Failed to update renewed authentication: {e}");
+			}
+		}
+	}
+
+	/// The accounts whose sessions expired mid-use and couldn't be renewed, so
+	/// they need to be logged in to again
+	pub async fn accounts_needing_login(&self) -> Vec<PlatformAccountId> {
+		self.api.accounts_needing_login().await
+	}
+
+	/// Refreshes the sessions every [`SessionKeeperOptions::check_interval`],
+	/// calling `on_event` with what happened to them.
+	///
+	/// Never returns, so should be spawned or raced against shutting down.
+	pub async fn keep_sessions_alive(
+		&self, options: SessionKeeperOptions,
+		mut on_event: impl FnMut(SessionEvent) + Send,
+	) {
+		let mut keeper = SessionKeeper::new(options);
+		let mut interval = tokio::time::interval(options.check_interval);
+		interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+		loop {
+			interval.tick().await;
+			match self.refresh_sessions(&mut keeper).await {
+				Ok(events) => events.into_iter().for_each(&mut on_event),
+				Err(e) => error!("Failed to refresh sessions: {e}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use onlivfe::{PlatformDataAndMetadata, PlatformDataMetadata};
+
+	use super::*;
+
+	fn authentication(updated_at: OffsetDateTime) -> Authentication {
+		Authentication::VRChat(PlatformDataAndMetadata {
+			data: Box::new(vrc::query::Authentication {
+				token: "authcookie_onlivfe-sessions".to_owned(),
+				second_factor_token: None,
+			}),
+			metadata: PlatformDataMetadata {
+				updated_at,
+				updated_by: "usr_c1644b5b-3ca4-45b4-97c6-a2a0de70d469"
+					.parse()
+					.expect("the user ID to be valid"),
+			},
+		})
+	}
+
+	fn fail(keeper: &mut SessionKeeper, auth: &Authentication, failures: u32) {
+		keeper.failures.insert(auth.id(), (auth.metadata().updated_at, failures));
+	}
+
+	#[test]
+	fn sessions_are_due_once_old_enough() {
+		let keeper = SessionKeeper::default();
+		let now = OffsetDateTime::now_utc();
+		let refresh_after = keeper.options.refresh_after(PlatformType::VRChat);
+
+		assert!(keeper.is_due(&authentication(now - refresh_after), now));
+		assert!(keeper.is_due(&authentication(now - refresh_after * 2), now));
+		assert!(!keeper.is_due(&authentication(now), now));
+		assert!(!keeper.is_due(
+			&authentication(now - refresh_after + Duration::from_secs(1)),
+			now
+		));
+	}
+
+	#[test]
+	fn sessions_are_given_up_on_after_max_failures() {
+		let mut keeper = SessionKeeper::new(SessionKeeperOptions {
+			max_failures: 2,
+			..SessionKeeperOptions::default()
+		});
+		let now = OffsetDateTime::now_utc();
+		let auth = authentication(now - Duration::from_hours(24));
+
+		fail(&mut keeper, &auth, 1);
+		assert_eq!(keeper.failures(&auth), 1);
+		assert!(keeper.is_due(&auth, now));
+
+		fail(&mut keeper, &auth, 2);
+		assert_eq!(keeper.failures(&auth), 2);
+		assert!(!keeper.is_due(&auth, now));
+		assert!(!keeper.is_due(&auth, now + Duration::from_hours(24 * 365)));
+	}
+
+	#[test]
+	fn logging_in_again_resets_failures() {
+		let mut keeper = SessionKeeper::default();
+		let now = OffsetDateTime::now_utc();
+		let expired = authentication(now - Duration::from_hours(48));
+		let max_failures = keeper.options.max_failures;
+		fail(&mut keeper, &expired, max_failures);
+		assert!(!keeper.is_due(&expired, now));
+
+		let logged_in = authentication(now - Duration::from_hours(24));
+		assert_eq!(keeper.failures(&logged_in), 0);
+		assert!(keeper.is_due(&logged_in, now));
+	}
+}
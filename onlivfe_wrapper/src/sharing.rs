@@ -0,0 +1,327 @@
+//! Sharing profiles with other people, see [`ProfileBundle`]
+
+use std::collections::HashSet;
+
+use onlivfe::{
+	LocalPfpUri,
+	PlatformAccountId,
+	Profile,
+	ProfileBundle,
+	ProfileId,
+	SharedProfile,
+	storage::{OnlivfeStore, StoreBatch},
+};
+
+use crate::Onlivfe;
+
+/// The amounts of profiles & mappings that were imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProfileImportCounts {
+	/// Profiles that didn't exist before
+	pub profiles_created: usize,
+	/// Existing profiles that got details filled in
+	pub profiles_merged: usize,
+	/// Accounts that were newly linked to profiles
+	pub mappings: usize,
+}
+
+/// Removes the profile picture URL if it points to a locally stored picture,
+/// as those only work on the device that they were stored on
+fn remove_local_pfp_url(profile: &mut Profile) {
+	if profile
+		.pfp_url
+		.as_deref()
+		.is_some_and(|url| url.parse::<LocalPfpUri>().is_ok())
+	{
+		profile.pfp_url = None;
+	}
+}
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Merges profiles from elsewhere with the stored ones, returning the ones
+	/// that need to be stored.
+	///
+	/// Profiles that don't exist yet are stored as is, and existing ones only get
+	/// the details that they're missing filled in.
+	pub(crate) async fn merge_with_stored_profiles(
+		&self, incoming: Vec<Profile>, counts: &mut ProfileImportCounts,
+	) -> Result<Vec<Profile>, StorageBackend::Err> {
+		let stored_profile_ids: HashSet<ProfileId> =
+			self.store.profile_ids(usize::MAX).await?.into_iter().collect();
+
+		let mut profiles = Vec::with_capacity(incoming.len());
+		for incoming in incoming {
+			if !stored_profile_ids.contains(&incoming.sharing_id) {
+				counts.profiles_created += 1;
+				profiles.push(incoming);
+				continue;
+			}
+			let mut profile = self.store.profile(incoming.sharing_id.clone()).await?;
+			if profile.fill_missing_from(&incoming) {
+				counts.profiles_merged += 1;
+				profiles.push(profile);
+			}
+		}
+
+		Ok(profiles)
+	}
+
+	/// Links accounts to a profile in addition to the already linked ones,
+	/// returning how many weren't linked before
+	pub(crate) async fn link_profile_accounts(
+		&self, profile_id: ProfileId, account_ids: Vec<PlatformAccountId>,
+	) -> Result<usize, StorageBackend::Err> {
+		let mut linked = self.store.profile_account_ids(profile_id.clone()).await?;
+		let previous_len = linked.len();
+		for account_id in account_ids {
+			if !linked.contains(&account_id) {
+				linked.push(account_id);
+			}
+		}
+		let added = linked.len() - previous_len;
+		if added > 0 {
+			self.store.update_profile_account_ids(profile_id, linked).await?;
+		}
+
+		Ok(added)
+	}
+
+	/// Creates a [`ProfileBundle`] of profiles and the accounts linked to them,
+	/// for sharing with other people.
+	///
+	/// Notes are often personal, so they're only included if asked to, and
+	/// favorites are never included. Neither are locally stored pictures, as
+	/// their URIs only work on this device.
+	///
+	/// # Errors
+	///
+	/// If some of the profiles don't exist or reading them failed
+	pub async fn export_profiles(
+		&self, profile_ids: Vec<ProfileId>, include_notes: bool,
+	) -> Result<String, String> {
+		let mut profiles = Vec::with_capacity(profile_ids.len());
+		for profile_id in profile_ids {
+			let id = profile_id.clone();
+			let storage_err = |e: StorageBackend::Err| {
+				error!("Failed to get profile {id:?} for sharing: {e:?}");
+				"Failed to get profile for sharing".to_string()
+			};
+			let mut profile =
+				self.store.profile(profile_id.clone()).await.map_err(storage_err)?;
+			if !include_notes {
+				profile.notes = None;
+			}
+			profile.favorite = false;
+			remove_local_pfp_url(&mut profile);
+			let account_ids = self
+				.store
+				.profile_account_ids(profile_id)
+				.await
+				.map_err(storage_err)?;
+			profiles.push(SharedProfile { profile, account_ids });
+		}
+
+		ProfileBundle::new(profiles).serialize().map_err(|e| e.to_string())
+	}
+
+	/// Imports a [`ProfileBundle`] that someone shared.
+	///
+	/// Profiles that already exist, such as ones imported from the same person
+	/// before, are merged into instead of being duplicated. Locally stored
+	/// pictures of the sharer's device are left out.
+	///
+	/// # Errors
+	///
+	/// If the bundle is not valid or storing the profiles failed
+	pub async fn import_profiles(
+		&self, bundle: &str,
+	) -> Result<ProfileImportCounts, String> {
+		let bundle = ProfileBundle::parse(bundle).map_err(|e| e.to_string())?;
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to import shared profiles: {e:?}");
+			"Failed to import shared profiles".to_string()
+		};
+
+		let mut counts = ProfileImportCounts::default();
+		let (incoming, mappings): (Vec<_>, Vec<_>) = bundle
+			.profiles
+			.into_iter()
+			.map(|mut shared| {
+				remove_local_pfp_url(&mut shared.profile);
+				let mapping = (shared.profile.sharing_id.clone(), shared.account_ids);
+				(shared.profile, mapping)
+			})
+			.unzip();
+		let profiles = self
+			.merge_with_stored_profiles(incoming, &mut counts)
+			.await
+			.map_err(storage_err)?;
+		let batch = StoreBatch { profiles, ..StoreBatch::default() };
+		self.store.apply_batch(batch).await.map_err(storage_err)?;
+
+		for (profile_id, account_ids) in mappings {
+			counts.mappings += self
+				.link_profile_accounts(profile_id, account_ids)
+				.await
+				.map_err(storage_err)?;
+		}
+
+		Ok(counts)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use onlivfe_cache_store::OnlivfeCacheStorageBackend;
+
+	use super::*;
+
+	fn account_id(i: u32) -> PlatformAccountId {
+		let id = format!("usr_00000000-0000-4000-8000-{i:012x}");
+		PlatformAccountId::VRChat(id.parse().expect("the user ID to be valid"))
+	}
+
+	fn local_pfp_url(profile_id: &ProfileId) -> String {
+		LocalPfpUri { profile_id: profile_id.clone(), thumbnail: false }.to_string()
+	}
+
+	async fn store_profile(
+		onlivfe: &Onlivfe<OnlivfeCacheStorageBackend>, profile: Profile,
+		account_ids: Vec<PlatformAccountId>,
+	) {
+		let profile_id = profile.sharing_id.clone();
+		onlivfe.store.update_profile(profile).await.expect("storing a profile");
+		onlivfe
+			.store
+			.update_profile_account_ids(profile_id, account_ids)
+			.await
+			.expect("linking accounts");
+	}
+
+	#[tokio::test]
+	async fn exported_profiles_import_as_they_were_shared() {
+		let source = Onlivfe::in_memory();
+		let profile = Profile {
+			nick: Some("Shared".to_owned()),
+			notes: Some("Personal".to_owned()),
+			pfp_url: Some("https://example.com/pfp.png".to_owned()),
+			favorite: true,
+			..Profile::new()
+		};
+		let profile_id = profile.sharing_id.clone();
+		store_profile(&source, profile.clone(), vec![account_id(1)]).await;
+		let bundle = source
+			.export_profiles(vec![profile_id.clone()], false)
+			.await
+			.expect("exporting to succeed");
+
+		let target = Onlivfe::in_memory();
+		let counts =
+			target.import_profiles(&bundle).await.expect("importing to succeed");
+		assert_eq!(
+			counts,
+			ProfileImportCounts {
+				profiles_created: 1,
+				profiles_merged: 0,
+				mappings: 1
+			}
+		);
+		assert_eq!(
+			target.store.profile(profile_id.clone()).await.unwrap(),
+			Profile { notes: None, favorite: false, ..profile }
+		);
+		assert_eq!(
+			target.store.profile_account_ids(profile_id).await.unwrap(),
+			vec![account_id(1)]
+		);
+
+		let counts =
+			target.import_profiles(&bundle).await.expect("importing to succeed");
+		assert_eq!(counts, ProfileImportCounts::default());
+	}
+
+	#[tokio::test]
+	async fn local_pictures_are_not_exported() {
+		let onlivfe = Onlivfe::in_memory();
+		let profile = Profile::new();
+		let profile_id = profile.sharing_id.clone();
+		let profile =
+			Profile { pfp_url: Some(local_pfp_url(&profile_id)), ..profile };
+		store_profile(&onlivfe, profile, vec![]).await;
+
+		let bundle = onlivfe
+			.export_profiles(vec![profile_id], true)
+			.await
+			.expect("exporting to succeed");
+		let bundle = ProfileBundle::parse(&bundle).expect("the bundle to parse");
+		assert_eq!(bundle.profiles[0].profile.pfp_url, None);
+	}
+
+	#[tokio::test]
+	async fn local_pictures_are_not_imported() {
+		let profile = Profile::new();
+		let profile_id = profile.sharing_id.clone();
+		let profile =
+			Profile { pfp_url: Some(local_pfp_url(&profile_id)), ..profile };
+		let bundle =
+			ProfileBundle::new(vec![SharedProfile { profile, account_ids: vec![] }])
+				.serialize()
+				.expect("serializing to succeed");
+
+		let onlivfe = Onlivfe::in_memory();
+		onlivfe.import_profiles(&bundle).await.expect("importing to succeed");
+		assert_eq!(onlivfe.store.profile(profile_id).await.unwrap().pfp_url, None);
+	}
+
+	#[tokio::test]
+	async fn imports_fill_in_existing_profiles() {
+		let onlivfe = Onlivfe::in_memory();
+		let existing =
+			Profile { nick: Some("Existing".to_owned()), ..Profile::new() };
+		let profile_id = existing.sharing_id.clone();
+		store_profile(&onlivfe, existing, vec![account_id(1)]).await;
+		let incoming = Profile {
+			sharing_id: profile_id.clone(),
+			nick: Some("Incoming".to_owned()),
+			pronouns: Some("they/them".to_owned()),
+			..Profile::new()
+		};
+		let bundle = ProfileBundle::new(vec![SharedProfile {
+			profile: incoming,
+			account_ids: vec![account_id(1), account_id(2)],
+		}])
+		.serialize()
+		.expect("serializing to succeed");
+
+		let counts =
+			onlivfe.import_profiles(&bundle).await.expect("importing to succeed");
+		assert_eq!(
+			counts,
+			ProfileImportCounts {
+				profiles_created: 0,
+				profiles_merged: 1,
+				mappings: 1
+			}
+		);
+		let merged = onlivfe.store.profile(profile_id.clone()).await.unwrap();
+		assert_eq!(merged.nick.as_deref(), Some("Existing"));
+		assert_eq!(merged.pronouns.as_deref(), Some("they/them"));
+		assert_eq!(
+			onlivfe.store.profile_account_ids(profile_id).await.unwrap(),
+			vec![account_id(1), account_id(2)]
+		);
+	}
+
+	#[tokio::test]
+	async fn invalid_bundles_are_rejected() {
+		let onlivfe = Onlivfe::in_memory();
+		assert!(onlivfe.import_profiles("not a bundle").await.is_err());
+		assert!(
+			onlivfe
+				.import_profiles(r#"{"version": 4294967295, "profiles": []}"#)
+				.await
+				.is_err()
+		);
+		assert!(onlivfe.store.profile_ids(usize::MAX).await.unwrap().is_empty());
+	}
+}
@@ -0,0 +1,148 @@
+//! Suggesting which accounts belong to the same person, see [`suggest_links`]
+
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+
+use onlivfe::{
+	AccountLink,
+	LinkCandidate,
+	LinkSuggestion,
+	PlatformAccountId,
+	Profile,
+	ProfileId,
+	storage::OnlivfeStore,
+	suggest_links,
+};
+
+use crate::Onlivfe;
+
+impl<StorageBackend: OnlivfeStore> Onlivfe<StorageBackend> {
+	/// Suggests accounts on different platforms to link to the same profile,
+	/// based on the stored accounts and friends, most confident first.
+	///
+	/// Links that were rejected before, and accounts that are already linked
+	/// to the same profile, are left out.
+	///
+	/// # Errors
+	///
+	/// If something failed with retrieving the stored data
+	pub async fn link_suggestions(
+		&self, min_confidence: f32,
+	) -> Result<Vec<LinkSuggestion>, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to get data for link suggestions: {e:?}");
+			"Failed to retrieve data for link suggestions".to_string()
+		};
+
+		let accounts =
+			self.store.accounts(usize::MAX).await.map_err(storage_err)?;
+		let friends = self.store.friends(usize::MAX).await.map_err(storage_err)?;
+		let candidates = accounts
+			.iter()
+			.map(LinkCandidate::from)
+			.chain(friends.iter().map(LinkCandidate::from));
+		let rejected_links: HashSet<AccountLink> = self
+			.store
+			.rejected_links()
+			.await
+			.map_err(storage_err)?
+			.into_iter()
+			.collect();
+
+		let mut account_profiles: HashMap<PlatformAccountId, Vec<ProfileId>> =
+			HashMap::new();
+		let mut suggestions = vec![];
+		for suggestion in suggest_links(candidates) {
+			if suggestion.confidence < min_confidence {
+				// They're sorted by the confidence
+				break;
+			}
+			if rejected_links.contains(&suggestion.link) {
+				continue;
+			}
+			for account_id in suggestion.link.account_ids() {
+				if let Entry::Vacant(entry) = account_profiles.entry(account_id.clone())
+				{
+					entry.insert(
+						self
+							.store
+							.account_profile_ids(account_id.clone())
+							.await
+							.map_err(storage_err)?,
+					);
+				}
+			}
+			let [a, b] =
+				suggestion.link.account_ids().map(|id| &account_profiles[id]);
+			if a.iter().any(|profile_id| b.contains(profile_id)) {
+				continue;
+			}
+			suggestions.push(suggestion);
+		}
+
+		Ok(suggestions)
+	}
+
+	/// Links the accounts of a suggestion to the same profile, returning the
+	/// profile's ID.
+	///
+	/// The accounts are added to a profile that one of them is already linked
+	/// to, or to a new profile if neither of them is linked to one.
+	///
+	/// # Errors
+	///
+	/// If something failed with storing the profile or the links
+	pub async fn accept_link_suggestion(
+		&self, link: AccountLink,
+	) -> Result<ProfileId, String> {
+		let storage_err = |e: StorageBackend::Err| {
+			error!("Failed to accept link suggestion {link:?}: {e:?}");
+			"Failed to accept link suggestion".to_string()
+		};
+
+		let mut profile_id = None;
+		for account_id in link.account_ids() {
+			let profile_ids = self
+				.store
+				.account_profile_ids(account_id.clone())
+				.await
+				.map_err(storage_err)?;
+			if let Some(id) = profile_ids.into_iter().next() {
+				profile_id = Some(id);
+				break;
+			}
+		}
+		let profile_id = if let Some(profile_id) = profile_id {
+			profile_id
+		} else {
+			let profile = Profile::new();
+			let profile_id = profile.sharing_id.clone();
+			self.store.update_profile(profile).await.map_err(storage_err)?;
+			profile_id
+		};
+
+		let account_ids = link.account_ids().into_iter().cloned().collect();
+		self
+			.link_profile_accounts(profile_id.clone(), account_ids)
+			.await
+			.map_err(storage_err)?;
+
+		Ok(profile_id)
+	}
+
+	/// Rejects a link suggestion, so that it isn't suggested again
+	///
+	/// # Errors
+	///
+	/// If something failed with storing the rejection
+	pub async fn reject_link_suggestion(
+		&self, link: AccountLink,
+	) -> Result<(), String> {
+		let id = link.clone();
+		self.store.reject_link(link).await.map_err(|e| {
+			error!("Failed to reject link suggestion {id:?}: {e:?}");
+			"Failed to reject link suggestion".to_string()
+		})?;
+
+		Ok(())
+	}
+}